@@ -0,0 +1,101 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    body::{self, Body},
+    extract::{Path, State},
+    http::Request,
+    routing::post,
+    Router,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rpc_lb::{
+    algorithms::round_robin::{Chains, LoadBalancer, RoundRobin, RpcServer},
+    handlers::load_balancer::load_balancer,
+};
+use tokio::runtime::Runtime;
+
+const RESPONSE: &str = r#"{"jsonrpc":"2.0","result":"0xaa36a7","id":1}"#;
+
+// Serves a fixed JSON-RPC response on a local port and returns its url.
+async fn spawn_upstream() -> String {
+    let app = Router::new().route("/", post(|| async { RESPONSE }));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    url
+}
+
+// A balancer whose single chain never runs out of request limit.
+fn create_balancer(url: String, config: Chains) -> Arc<LoadBalancer> {
+    let server = RpcServer {
+        url,
+        request_limit: u32::MAX,
+        current_limit: u32::MAX,
+        ..Default::default()
+    };
+    let round_robin = Arc::new(RoundRobin::new(vec![server]));
+    let mut lb = LoadBalancer::new(Arc::new(HashMap::from([(
+        "sepolia".to_string(),
+        round_robin,
+    )])));
+    lb.chains = Arc::new(HashMap::from([("sepolia".to_string(), config)]));
+    Arc::new(lb)
+}
+
+async fn forward(lb: Arc<LoadBalancer>) {
+    let request = Request::builder()
+        .method("POST")
+        .uri("/sepolia")
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#,
+        ))
+        .unwrap();
+
+    let response = load_balancer(Path("sepolia".to_string()), State(lb), request)
+        .await
+        .unwrap();
+    body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+}
+
+fn forwarding(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let url = runtime.block_on(spawn_upstream());
+
+    let mut group = c.benchmark_group("forwarding");
+
+    let streamed = create_balancer(url.clone(), Chains::default());
+    group.bench_function("streamed", |b| {
+        b.to_async(&runtime).iter(|| forward(streamed.clone()))
+    });
+
+    // Mirroring needs the response body, which forces it to be buffered.
+    let buffered = create_balancer(
+        url.clone(),
+        Chains {
+            mirror_ratio: 1.0,
+            ..Default::default()
+        },
+    );
+    group.bench_function("buffered", |b| {
+        b.to_async(&runtime).iter(|| forward(buffered.clone()))
+    });
+
+    let opaque = create_balancer(
+        url,
+        Chains {
+            opaque: true,
+            ..Default::default()
+        },
+    );
+    group.bench_function("opaque", |b| {
+        b.to_async(&runtime).iter(|| forward(opaque.clone()))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, forwarding);
+criterion_main!(benches);
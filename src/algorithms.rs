@@ -1 +1,8 @@
+pub mod key_health;
+pub mod latency;
+pub mod rate_limiter;
 pub mod round_robin;
+pub mod routing;
+pub mod schedule;
+pub mod sharded;
+pub mod strategy;
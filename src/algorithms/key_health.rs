@@ -0,0 +1,193 @@
+use std::time::{Duration, Instant};
+
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::services::head::host_of;
+
+/// How long a key answering `401` or `403` is left out, it was most likely
+/// revoked or has expired.
+const REVOKED_QUARANTINE: Duration = Duration::from_secs(600);
+/// How long a rate limited key is left out when the provider did not send a
+/// `Retry-After`.
+const EXHAUSTED_QUARANTINE: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyState {
+    Healthy,
+    /// Rejected with `401` or `403`.
+    Revoked,
+    /// Rejected with `429`.
+    Exhausted,
+}
+
+/// Outcomes of the provider key a backend url carries.
+///
+/// A key the provider rejects is quarantined: its backend is skipped until
+/// the quarantine is over, after which the next response decides again.
+#[derive(Clone, Debug, Default)]
+pub struct KeyHealth {
+    pub unauthorized: u64,
+    pub forbidden: u64,
+    pub rate_limited: u64,
+    quarantine: Option<(KeyState, Instant)>,
+}
+
+impl KeyHealth {
+    pub fn record(&mut self, status: StatusCode, retry_after: Option<Duration>, now: Instant) {
+        let (state, duration) = match status {
+            StatusCode::UNAUTHORIZED => {
+                self.unauthorized += 1;
+                (KeyState::Revoked, REVOKED_QUARANTINE)
+            }
+            StatusCode::FORBIDDEN => {
+                self.forbidden += 1;
+                (KeyState::Revoked, REVOKED_QUARANTINE)
+            }
+            StatusCode::TOO_MANY_REQUESTS => {
+                self.rate_limited += 1;
+                (
+                    KeyState::Exhausted,
+                    retry_after.unwrap_or(EXHAUSTED_QUARANTINE),
+                )
+            }
+            _ => {
+                if status.is_success() {
+                    self.quarantine = None;
+                }
+                return;
+            }
+        };
+        self.quarantine = Some((state, now + duration));
+    }
+
+    pub fn state(&self, now: Instant) -> KeyState {
+        match self.quarantine {
+            Some((state, until)) if until > now => state,
+            _ => KeyState::Healthy,
+        }
+    }
+
+    pub fn is_quarantined(&self, now: Instant) -> bool {
+        self.state(now) != KeyState::Healthy
+    }
+
+    /// The state the key is quarantined in and for how much longer, if it is.
+    pub fn quarantine(&self, now: Instant) -> Option<(KeyState, Duration)> {
+        let (state, until) = self.quarantine?;
+        Some((state, until.checked_duration_since(now)?))
+    }
+
+    /// Quarantines the key as a response would have, for a quarantine
+    /// carried over from before a restart.
+    pub fn quarantine_for(&mut self, state: KeyState, duration: Duration, now: Instant) {
+        self.quarantine = Some((state, now + duration));
+    }
+
+    pub fn report(&self, index: usize, url: &str, now: Instant) -> KeyReport {
+        KeyReport {
+            index,
+            name: None,
+            host: host_of(url),
+            key: key_hint(url),
+            state: self.state(now),
+            quarantined_for_secs: self
+                .quarantine
+                .and_then(|(_, until)| until.checked_duration_since(now))
+                .map(|left| left.as_secs()),
+            unauthorized: self.unauthorized,
+            forbidden: self.forbidden,
+            rate_limited: self.rate_limited,
+        }
+    }
+}
+
+/// The status of one key, as served by `/{chain}/keys`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct KeyReport {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub host: String,
+    pub key: String,
+    pub state: KeyState,
+    pub quarantined_for_secs: Option<u64>,
+    pub unauthorized: u64,
+    pub forbidden: u64,
+    pub rate_limited: u64,
+}
+
+/// The seconds form of a `Retry-After` header.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// The last characters of the key in `url`, enough to tell keys apart
+/// without exposing them. Providers put the key in a path segment or query
+/// value, which is taken to be the longest one.
+pub fn key_hint(url: &str) -> String {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return String::new();
+    };
+    let segments = url.path().split('/').map(str::to_string);
+    let values = url.query_pairs().map(|(_, value)| value.into_owned());
+    let Some(key) = segments.chain(values).max_by_key(String::len) else {
+        return String::new();
+    };
+    let chars: Vec<char> = key.chars().collect();
+    let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("...{}", tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quarantine_and_recovery() {
+        let now = Instant::now();
+        let mut key = KeyHealth::default();
+
+        key.record(
+            StatusCode::TOO_MANY_REQUESTS,
+            Some(Duration::from_secs(5)),
+            now,
+        );
+        assert_eq!(key.state(now), KeyState::Exhausted);
+        assert!(!key.is_quarantined(now + Duration::from_secs(5)));
+
+        key.record(StatusCode::UNAUTHORIZED, None, now);
+        assert_eq!(key.state(now + Duration::from_secs(60)), KeyState::Revoked);
+        assert_eq!(
+            key.report(0, "https://a.io", now).quarantined_for_secs,
+            Some(600)
+        );
+
+        key.record(StatusCode::OK, None, now);
+        assert_eq!(key.state(now), KeyState::Healthy);
+        assert_eq!((key.unauthorized, key.rate_limited), (1, 1));
+    }
+
+    #[test]
+    fn test_key_hint() {
+        assert_eq!(
+            key_hint("https://eth-sepolia.g.alchemy.com/v2/abcdef123456"),
+            "...3456"
+        );
+        assert_eq!(
+            key_hint("https://rpc.io/sepolia?apikey=0123456789ab"),
+            "...89ab"
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Span of the latest samples percentiles are taken over.
+const WINDOW: Duration = Duration::from_secs(60);
+/// Samples are grouped in slots of this span, the oldest slot leaves the
+/// window as a whole.
+const SLOT: Duration = Duration::from_secs(10);
+/// Buckets per power of two of microseconds, so a bucket is at most 1/16
+/// (about 6%) wider than the values it holds.
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+/// Latency percentiles of a backend over the last minute, as served by
+/// `/{chain}/status`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub struct LatencyPercentiles {
+    pub samples: u64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// Latency samples of the last minute in HDR-style buckets: exact below
+/// 16µs, then 16 buckets per power of two, which keeps the memory bounded
+/// however many requests a backend serves.
+#[derive(Clone, Debug, Default)]
+pub struct LatencyHistogram {
+    /// Sample counts by bucket, per slot, oldest first.
+    slots: VecDeque<(Instant, BTreeMap<u16, u64>)>,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration, now: Instant) {
+        self.expire(now);
+        match self.slots.back_mut() {
+            Some((started, _)) if now < *started + SLOT => {}
+            _ => self.slots.push_back((now, BTreeMap::new())),
+        }
+        let (_, counts) = self.slots.back_mut().unwrap();
+        *counts.entry(bucket_of(latency)).or_insert(0) += 1;
+    }
+
+    /// The latency under which a `quantile` (0 to 1) of the window's samples
+    /// fall, in milliseconds. `None` without samples.
+    pub fn percentile(&self, quantile: f64, now: Instant) -> Option<f64> {
+        let counts = self.merged(now);
+        let total: u64 = counts.values().sum();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((quantile * total as f64).ceil() as u64).clamp(1, total);
+        let mut seen = 0;
+        counts
+            .iter()
+            .find(|(_, count)| {
+                seen += **count;
+                seen >= rank
+            })
+            .map(|(bucket, _)| bucket_midpoint_ms(*bucket))
+    }
+
+    pub fn percentiles(&self, now: Instant) -> Option<LatencyPercentiles> {
+        Some(LatencyPercentiles {
+            samples: self.merged(now).values().sum(),
+            p50_ms: self.percentile(0.5, now)?,
+            p90_ms: self.percentile(0.9, now)?,
+            p99_ms: self.percentile(0.99, now)?,
+        })
+    }
+
+    fn merged(&self, now: Instant) -> BTreeMap<u16, u64> {
+        let mut merged = BTreeMap::new();
+        for (_, counts) in self.live_slots(now) {
+            for (bucket, count) in counts {
+                *merged.entry(*bucket).or_insert(0) += count;
+            }
+        }
+        merged
+    }
+
+    fn live_slots(
+        &self,
+        now: Instant,
+    ) -> impl Iterator<Item = &(Instant, BTreeMap<u16, u64>)> + '_ {
+        self.slots
+            .iter()
+            .filter(move |(started, _)| *started + WINDOW > now)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self
+            .slots
+            .front()
+            .is_some_and(|(started, _)| *started + WINDOW <= now)
+        {
+            self.slots.pop_front();
+        }
+    }
+}
+
+fn bucket_of(latency: Duration) -> u16 {
+    let micros = latency.as_micros().min(u64::MAX as u128) as u64;
+    if micros < SUB_BUCKETS {
+        return micros as u16;
+    }
+    let shift = (63 - micros.leading_zeros()) - SUB_BUCKET_BITS;
+    let sub_bucket = (micros >> shift) - SUB_BUCKETS;
+    ((shift as u64 + 1) * SUB_BUCKETS + sub_bucket) as u16
+}
+
+/// The middle of the values `bucket` holds, in milliseconds.
+fn bucket_midpoint_ms(bucket: u16) -> f64 {
+    let bucket = bucket as u64;
+    if bucket < SUB_BUCKETS {
+        return bucket as f64 / 1000.0;
+    }
+    let shift = bucket / SUB_BUCKETS - 1;
+    let lowest = (SUB_BUCKETS + bucket % SUB_BUCKETS) << shift;
+    let width = 1u64 << shift;
+    (lowest as f64 + (width - 1) as f64 / 2.0) / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let now = Instant::now();
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.percentiles(now), None);
+
+        for ms in 1..=100 {
+            histogram.record(Duration::from_millis(ms), now);
+        }
+        let percentiles = histogram.percentiles(now).unwrap();
+        assert_eq!(percentiles.samples, 100);
+        for (value, expected) in [
+            (percentiles.p50_ms, 50.0),
+            (percentiles.p90_ms, 90.0),
+            (percentiles.p99_ms, 99.0),
+        ] {
+            assert!((value - expected).abs() / expected < 0.07, "{}", value);
+        }
+
+        // Slow samples of a later slot, the first slot then leaves the window.
+        let later = now + Duration::from_secs(30);
+        for _ in 0..100 {
+            histogram.record(Duration::from_millis(500), later);
+        }
+        let p50 = histogram.percentile(0.5, later).unwrap();
+        assert!(p50 < 200.0, "{}", p50);
+        let p50 = histogram.percentile(0.5, now + WINDOW).unwrap();
+        assert!((p50 - 500.0).abs() / 500.0 < 0.07, "{}", p50);
+        assert_eq!(histogram.percentiles(later + WINDOW), None);
+    }
+
+    #[test]
+    fn test_buckets() {
+        for micros in [0, 15, 16, 31, 32, 1_000, 123_456, 3_600_000_000] {
+            let latency = Duration::from_micros(micros);
+            let midpoint = bucket_midpoint_ms(bucket_of(latency)) * 1000.0;
+            let error = (midpoint - micros as f64).abs();
+            assert!(error <= micros as f64 / 32.0, "{} -> {}", micros, midpoint);
+        }
+    }
+}
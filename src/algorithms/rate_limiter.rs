@@ -0,0 +1,169 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use chrono::NaiveDateTime;
+
+use super::{round_robin::RpcServer, sharded::ShardedBudget};
+
+/// Decides whether a backend may take another request.
+///
+/// Every backend of a [`RoundRobin`](super::round_robin::RoundRobin) has one,
+/// the built-in [`TokenBucket`] unless another policy was plugged in with
+/// `RoundRobin::with_limiter`, e.g. one asking a company-internal quota
+/// service. Registering the same limiter for several backends shares it
+/// between them, as for backends using the same provider key.
+pub trait RateLimiter: Send + Sync + Debug {
+    /// Takes one request token, returns `false` when the backend is out.
+    fn consume(&self) -> bool;
+
+    /// Takes one token of an allowance only used once every backend's
+    /// `consume` failed. Limiters without one never grant it.
+    fn consume_burst(&self) -> bool {
+        false
+    }
+
+    /// Gives back a token of a request which never reached the backend.
+    fn refund(&self);
+
+    /// Starts a new limit window, called every refill interval with the time
+    /// in the chain's timezone.
+    fn refill(&self, now: NaiveDateTime);
+
+    /// Whether `consume` or `consume_burst` would currently succeed.
+    fn has_capacity(&self) -> bool;
+}
+
+/// The built-in limiter: the steady `request_limit` and `burst_limit` of a
+/// backend's [`RpcServer`], with the steady tokens leased to worker threads
+/// through a [`ShardedBudget`].
+#[derive(Debug)]
+pub struct TokenBucket {
+    servers: Arc<Vec<Mutex<RpcServer>>>,
+    budgets: Arc<Vec<ShardedBudget>>,
+    index: usize,
+}
+
+impl TokenBucket {
+    pub fn new(
+        servers: Arc<Vec<Mutex<RpcServer>>>,
+        budgets: Arc<Vec<ShardedBudget>>,
+        index: usize,
+    ) -> Self {
+        Self {
+            servers,
+            budgets,
+            index,
+        }
+    }
+
+    fn server(&self) -> &Mutex<RpcServer> {
+        &self.servers[self.index]
+    }
+
+    fn budget(&self) -> &ShardedBudget {
+        &self.budgets[self.index]
+    }
+}
+
+impl RateLimiter for TokenBucket {
+    /// Tokens leased to the calling worker are spent without locking. Once
+    /// they run out, a new lease is taken from the server's limit, and only
+    /// when that is used up too are tokens leased to other workers claimed.
+    fn consume(&self) -> bool {
+        let budget = self.budget();
+        if budget.take_local() {
+            return true;
+        }
+
+        let mut server = self.server().lock().unwrap();
+        let lease = budget
+            .lease_size(server.request_limit)
+            .min(server.current_limit);
+        if lease > 0 {
+            server.current_limit -= lease;
+            budget.deposit(lease - 1);
+            true
+        } else {
+            budget.steal()
+        }
+    }
+
+    fn consume_burst(&self) -> bool {
+        let mut server = self.server().lock().unwrap();
+        if server.current_burst > 0 {
+            server.current_burst -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn refund(&self) {
+        self.budget().deposit(1);
+    }
+
+    fn refill(&self, now: NaiveDateTime) {
+        let mut server = self.server().lock().unwrap();
+        // Reconcile the leases so unused tokens count towards the burst refill.
+        server.current_limit += self.budget().drain();
+        server.refill(now);
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.budget().has_tokens() || self.server().lock().unwrap().has_capacity()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+    use crate::algorithms::round_robin::RoundRobin;
+
+    /// Grants a fixed number of requests, like an external quota would.
+    #[derive(Debug, Default)]
+    struct Quota(AtomicU32);
+
+    impl RateLimiter for Quota {
+        fn consume(&self) -> bool {
+            self.0
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                    left.checked_sub(1)
+                })
+                .is_ok()
+        }
+
+        fn refund(&self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn refill(&self, _now: NaiveDateTime) {}
+
+        fn has_capacity(&self) -> bool {
+            self.0.load(Ordering::Relaxed) > 0
+        }
+    }
+
+    #[test]
+    fn test_custom_limiter() {
+        let url = "https://sepolia.drpc.org".to_string();
+        let server = RpcServer {
+            url: url.clone(),
+            request_limit: 100,
+            current_limit: 100,
+            ..Default::default()
+        };
+        let quota = Arc::new(Quota(AtomicU32::new(1)));
+        let round_robin = RoundRobin::new(vec![server]).with_limiter(&url, quota.clone());
+
+        assert_eq!(round_robin.get_next(), Some(url.clone()));
+        assert_eq!(round_robin.get_next(), None);
+
+        round_robin.refund(&url);
+        assert!(quota.has_capacity());
+        assert_eq!(round_robin.get_fastest(), Some(url));
+    }
+}
@@ -1,60 +1,1247 @@
 use std::{
     collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    net::IpAddr,
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use serde::Deserialize;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 use tokio::time;
 
+use super::{
+    key_health::{KeyHealth, KeyReport},
+    latency::{LatencyHistogram, LatencyPercentiles},
+    rate_limiter::{RateLimiter, TokenBucket},
+    routing::{HealthWeights, RoutingConfig, Strategy},
+    schedule::{self, LimitWindow},
+    sharded::{self, ShardedBudget},
+    strategy::{self, BalancingStrategy},
+};
+use crate::{
+    auth::{AuthConfig, SlaClass},
+    config::history::HistoryConfig,
+    listener::ListenerConfig,
+    metrics::{Metrics, MetricsConfig},
+    services::{
+        cache::{CacheControl, CachePolicy, ResponseCache},
+        cache_warming::CacheWarming,
+        chaos::{Chaos, Fault},
+        consumers::{ConsumerConfig, Consumers},
+        discovery::BackendSource,
+        filters::FilterPins,
+        gas_oracle::GasOracle,
+        geo::{ClientRegions, GeoIpConfig},
+        head::host_of,
+        headers::HeaderPolicy,
+        health::HealthCheck,
+        kill_switch::KillSwitch,
+        prewarm::Prewarm,
+        quota::QuotaWebhook,
+        registry::RegistryConfig,
+        replay::ReplayGuard,
+        request_log::{LogSampling, RequestLog},
+        response_guard,
+        startup::StartupMode,
+        tx_dedup::TxDedup,
+        tx_journal::{JournalConfig, TxJournal},
+        tx_rebroadcast::{RebroadcastConfig, TxTracker},
+        warm_state::{self, BackendSnapshot},
+    },
+    transport::{
+        self,
+        http::{HttpClientConfig, HttpTransport},
+        Sent, TransportError, UpstreamRequest, UpstreamTransport,
+    },
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+
 #[derive(Clone, Debug)]
 pub struct RoundRobin {
     pub urls: Arc<Vec<Mutex<RpcServer>>>,
     pub index: Arc<AtomicUsize>,
+    pub stats: Arc<Vec<Mutex<BackendStats>>>,
+    /// Urls of the servers in `urls`, readable without taking their locks.
+    pub endpoints: Arc<Vec<String>>,
+    /// Steady tokens of each server leased out to worker threads.
+    pub budgets: Arc<Vec<ShardedBudget>>,
+    /// Decides whether each server may take another request.
+    pub limiters: Arc<Vec<Arc<dyn RateLimiter>>>,
+    /// Carries requests to each server, picked by the scheme of its url.
+    pub transports: Arc<Vec<Arc<dyn UpstreamTransport>>>,
+    /// Timezone limit schedules are evaluated in, the host's when unset.
+    pub timezone: Option<Tz>,
+    /// Whether each server in `urls` is a fallback.
+    pub fallbacks: Arc<Vec<bool>>,
+    /// Region label of each server in `urls`.
+    pub regions: Arc<Vec<Option<String>>>,
+    /// Name of each server in `urls`, or its host when it has none.
+    pub labels: Arc<Vec<String>>,
+    /// Tags of each server in `urls`, see [`RoundRobin::get_tagged`].
+    pub tags: Arc<Vec<Vec<String>>>,
+    /// Share of `weighted_random` and `weighted_round_robin` picks of each
+    /// server in `urls`.
+    pub weights: Arc<Vec<u32>>,
+    /// Region whose servers are used before any other, set for chains
+    /// preferring the region of this replica.
+    pub local_region: Option<String>,
+    /// Set while requests spill over to the fallbacks.
+    spilled: Arc<AtomicBool>,
+    spilled_at: Arc<Mutex<Instant>>,
+    /// Minimum time spent on the fallbacks before failing back.
+    pub failback_after: Duration,
+    /// Cleared while a lazily started chain has no backend known to be usable.
+    ready: Arc<AtomicBool>,
+    /// p95 latency of the last minute above which servers are only used as a
+    /// last resort.
+    pub latency_budget_ms: Option<u64>,
+    /// Keep sending to a server until its limit is used up instead of
+    /// rotating after every pick.
+    pub drain_first: bool,
+    /// When `refill_limits` starts the next limit window.
+    next_refill: Arc<Mutex<Option<DateTime<Utc>>>>,
+    /// Start and length of the current refill window.
+    window: Arc<Mutex<Option<(Instant, Duration)>>>,
+    /// Move requests off servers on pace to run out of limit before the
+    /// window ends, while others are not.
+    pub predictive_spillover: bool,
+    /// Weights of the scores picking servers with [`Strategy::HealthWeighted`].
+    pub health_weights: HealthWeights,
+    /// Weight given to the newest sample in the latency moving average.
+    pub latency_smoothing: f64,
+    /// Requests sent to each server in `urls` and not answered yet.
+    pub in_flight: Arc<Vec<AtomicUsize>>,
+    /// Tokens a request costs on a server whose every attempt fails, see
+    /// [`RoundRobin::token_cost`].
+    pub error_cost: f64,
+    /// What picks the server of a request with each strategy.
+    pub balancers: Arc<HashMap<Strategy, Arc<dyn BalancingStrategy>>>,
+    /// Strategy replacing the chain's `algorithm` until the next reload, set
+    /// through `/admin/chains/{chain}/algorithm`.
+    algorithm: Arc<RwLock<Option<Strategy>>>,
+}
+
+/// Why a chain has no backend to send a request to, as reported to clients
+/// and in `rpc_lb_unavailable_total`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PoolStatus {
+    /// At least one backend can take a request.
+    Available,
+    /// The chain has no backends configured.
+    Empty,
+    /// Every backend fails its health check or has its key quarantined.
+    Unhealthy,
+    /// The backends left are out of tokens until the next limit window.
+    RateLimited { until: Option<DateTime<Utc>> },
 }
+
+impl PoolStatus {
+    /// The `reason` label of the status.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PoolStatus::Available => "upstream_errors",
+            PoolStatus::Empty => "no_backends",
+            PoolStatus::Unhealthy => "unhealthy",
+            PoolStatus::RateLimited { .. } => "rate_limited",
+        }
+    }
+}
+
+/// Refunds the token of a request on drop unless it was sent.
+struct RefundUnsent<'a> {
+    round_robin: &'a RoundRobin,
+    url: &'a str,
+    sent: &'a Sent,
+}
+
+impl Drop for RefundUnsent<'_> {
+    fn drop(&mut self) {
+        if !self.sent.is_marked() {
+            self.round_robin.refund(self.url);
+        }
+    }
+}
+
+/// Counts a request in the `in_flight` of its server until dropped.
+struct InFlight<'a>(Option<&'a AtomicUsize>);
+
+impl<'a> InFlight<'a> {
+    fn start(round_robin: &'a RoundRobin, url: &str) -> Self {
+        let count = round_robin
+            .endpoints
+            .iter()
+            .position(|endpoint| endpoint == url)
+            .map(|i| &round_robin.in_flight[i]);
+        if let Some(count) = count {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+        Self(count)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if let Some(count) = self.0 {
+            count.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Time spent on the fallbacks before the primaries are tried again, unless
+/// the chain sets `failback_secs`.
+const DEFAULT_FAILBACK: Duration = Duration::from_secs(30);
+
 impl RoundRobin {
     pub fn new(urls: Vec<RpcServer>) -> Self {
-        let urls = urls.into_iter().map(Mutex::new).collect();
+        let stats = urls.iter().map(|_| Mutex::default()).collect();
+        let endpoints = urls.iter().map(|server| server.url.clone()).collect();
+        let fallbacks = urls.iter().map(RpcServer::is_fallback).collect();
+        let regions = urls.iter().map(|server| server.region.clone()).collect();
+        let labels = urls.iter().map(RpcServer::label).collect();
+        let tags = urls.iter().map(|server| server.tags.clone()).collect();
+        let weights = urls
+            .iter()
+            .map(|server| server.weight.unwrap_or(1))
+            .collect();
+        let transports = urls.iter().map(transport::for_server).collect();
+        let budgets: Arc<Vec<ShardedBudget>> = Arc::new(
+            urls.iter()
+                .map(|_| ShardedBudget::new(sharded::shard_count()))
+                .collect(),
+        );
+        let urls: Arc<Vec<Mutex<RpcServer>>> = Arc::new(
+            urls.into_iter()
+                .map(|mut server| {
+                    server.current_burst = server.burst_limit;
+                    server.window_limit = server.current_limit;
+                    Mutex::new(server)
+                })
+                .collect(),
+        );
+        let limiters = (0..urls.len())
+            .map(|i| {
+                Arc::new(TokenBucket::new(urls.clone(), budgets.clone(), i)) as Arc<dyn RateLimiter>
+            })
+            .collect();
+        let endpoints_in_flight = (0..urls.len()).map(|_| AtomicUsize::new(0)).collect();
+        let balancers = Strategy::ALL
+            .into_iter()
+            .map(|strategy| (strategy, strategy::built_in(strategy)))
+            .collect();
         Self {
-            urls: Arc::new(urls),
+            urls,
             index: Arc::new(AtomicUsize::new(0)),
+            stats: Arc::new(stats),
+            endpoints: Arc::new(endpoints),
+            budgets,
+            limiters: Arc::new(limiters),
+            transports: Arc::new(transports),
+            timezone: None,
+            fallbacks: Arc::new(fallbacks),
+            regions: Arc::new(regions),
+            labels: Arc::new(labels),
+            tags: Arc::new(tags),
+            weights: Arc::new(weights),
+            local_region: None,
+            spilled: Arc::new(AtomicBool::new(false)),
+            spilled_at: Arc::new(Mutex::new(Instant::now())),
+            failback_after: DEFAULT_FAILBACK,
+            ready: Arc::new(AtomicBool::new(true)),
+            latency_budget_ms: None,
+            drain_first: false,
+            next_refill: Arc::new(Mutex::new(None)),
+            window: Arc::new(Mutex::new(None)),
+            predictive_spillover: false,
+            health_weights: HealthWeights::default(),
+            latency_smoothing: DEFAULT_LATENCY_SMOOTHING,
+            in_flight: Arc::new(endpoints_in_flight),
+            error_cost: 1.0,
+            balancers: Arc::new(balancers),
+            algorithm: Arc::default(),
+        }
+    }
+
+    pub fn with_timezone(mut self, timezone: Option<Tz>) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    pub fn with_failback(mut self, failback_after: Option<Duration>) -> Self {
+        self.failback_after = failback_after.unwrap_or(DEFAULT_FAILBACK);
+        self
+    }
+
+    /// Replaces the limiter of the servers at `url`, the built-in
+    /// [`TokenBucket`] by default.
+    pub fn with_limiter(mut self, url: &str, limiter: Arc<dyn RateLimiter>) -> Self {
+        let mut limiters = self.limiters.to_vec();
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
+            if endpoint == url {
+                limiters[i] = limiter.clone();
+            }
+        }
+        self.limiters = Arc::new(limiters);
+        self
+    }
+
+    /// Replaces what picks servers with `strategy`, the built-in
+    /// implementation by default.
+    pub fn with_balancer(
+        mut self,
+        strategy: Strategy,
+        balancer: Arc<dyn BalancingStrategy>,
+    ) -> Self {
+        Arc::make_mut(&mut self.balancers).insert(strategy, balancer);
+        self
+    }
+
+    /// Replaces the transport of the servers at `url`, e.g. with a gRPC
+    /// gateway client.
+    pub fn with_transport(mut self, url: &str, transport: Arc<dyn UpstreamTransport>) -> Self {
+        let mut transports = self.transports.to_vec();
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
+            if endpoint == url {
+                transports[i] = transport.clone();
+            }
+        }
+        self.transports = Arc::new(transports);
+        self
+    }
+
+    /// Sends the requests of the HTTP servers through a client of the chain's
+    /// own built from `config`, instead of the one shared by every chain.
+    pub fn with_http_client(mut self, config: &HttpClientConfig) -> Result<Self, String> {
+        let client = config.build(None)?;
+        let mut transports = self.transports.to_vec();
+        for (i, server) in self.urls.iter().enumerate() {
+            let server = server.lock().unwrap();
+            let scheme = server.url.split("://").next().unwrap_or_default();
+            if !matches!(scheme, "http" | "https") {
+                continue;
+            }
+            let client = match server.connect_to {
+                Some(address) => config.build(Some((&host_of(&server.url), address)))?,
+                None => client.clone(),
+            };
+            transports[i] = Arc::new(
+                HttpTransport::with_client(client, server.host_header.clone())
+                    .with_compression(server.compress_requests_over),
+            );
+        }
+        self.transports = Arc::new(transports);
+        Ok(self)
+    }
+
+    /// Only sends requests to servers outside `region` once every server in
+    /// it is out of limit.
+    pub fn with_local_region(mut self, region: Option<String>) -> Self {
+        self.local_region = region;
+        self
+    }
+
+    pub fn with_predictive_spillover(mut self, predictive_spillover: bool) -> Self {
+        self.predictive_spillover = predictive_spillover;
+        self
+    }
+
+    pub fn with_health_weights(mut self, health_weights: HealthWeights) -> Self {
+        self.health_weights = health_weights;
+        self
+    }
+
+    pub fn with_latency_smoothing(mut self, latency_smoothing: Option<f64>) -> Self {
+        self.latency_smoothing = latency_smoothing.unwrap_or(DEFAULT_LATENCY_SMOOTHING);
+        self
+    }
+
+    pub fn with_error_cost(mut self, error_cost: Option<f64>) -> Self {
+        self.error_cost = error_cost.unwrap_or(1.0);
+        self
+    }
+
+    pub fn with_drain_first(mut self, drain_first: bool) -> Self {
+        self.drain_first = drain_first;
+        self
+    }
+
+    pub fn with_latency_budget(mut self, latency_budget_ms: Option<u64>) -> Self {
+        self.latency_budget_ms = latency_budget_ms;
+        self
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Relaxed)
+    }
+
+    pub fn set_ready(&self, ready: bool) {
+        self.ready.store(ready, Ordering::Relaxed);
+    }
+
+    /// The strategy set at runtime in place of the chain's `algorithm`.
+    pub fn algorithm(&self) -> Option<Strategy> {
+        *self.algorithm.read().unwrap()
+    }
+
+    /// Switches the strategy of requests not routed by method or category
+    /// to `algorithm`, or back to the chain's configured one with `None`.
+    /// Requests already past selection keep theirs.
+    pub fn set_algorithm(&self, algorithm: Option<Strategy>) {
+        *self.algorithm.write().unwrap() = algorithm;
+    }
+
+    /// What picks servers with `strategy`.
+    pub fn balancer(&self, strategy: Strategy) -> Arc<dyn BalancingStrategy> {
+        self.balancers
+            .get(&strategy)
+            .cloned()
+            .unwrap_or_else(|| strategy::built_in(strategy))
+    }
+
+    /// Picks a server with `balancer` among those with limit left, in the
+    /// tiers and order of preference of the pool.
+    pub fn get_with(&self, balancer: &dyn BalancingStrategy) -> Option<String> {
+        self.pick(|fallback| {
+            self.by_preference(fallback, |eligible| balancer.get_next(self, eligible))
+        })
+    }
+
+    pub fn get_next(&self) -> Option<String> {
+        self.get_with(&*self.balancer(Strategy::RoundRobin))
+    }
+
+    /// Picks the server with the lowest observed latency among those with
+    /// limit left. Servers without a measurement yet are tried first.
+    pub fn get_fastest(&self) -> Option<String> {
+        self.get_with(&*self.balancer(Strategy::Latency))
+    }
+
+    /// Picks a server uniformly at random among those with limit left.
+    /// Leaves the shared index alone.
+    pub fn get_random(&self) -> Option<String> {
+        self.get_with(&*self.balancer(Strategy::Random))
+    }
+
+    /// Picks a server at random, each with a chance proportional to its
+    /// weight among those with limit left. Leaves the shared index alone.
+    pub fn get_weighted(&self) -> Option<String> {
+        self.get_with(&*self.balancer(Strategy::WeightedRandom))
+    }
+
+    /// Picks the server with the fewest requests in flight among those with
+    /// limit left. Leaves the shared index alone.
+    pub fn get_least_loaded(&self) -> Option<String> {
+        self.get_with(&*self.balancer(Strategy::LeastConn))
+    }
+
+    /// Picks the server with the best health score, see
+    /// [`RoundRobin::health_score`], among those with limit left.
+    pub fn get_healthiest(&self) -> Option<String> {
+        self.get_with(&*self.balancer(Strategy::HealthWeighted))
+    }
+
+    /// Takes the server at `url`, for requests only it can answer such as
+    /// polls of a filter installed on it.
+    pub fn get_pinned(&self, url: &str) -> Option<String> {
+        self.next_among(|i| self.endpoints[i] == url)
+    }
+
+    /// The name of the server at `url`, or its host, to refer to it in logs,
+    /// metrics and headers without exposing the key in its url.
+    pub fn label_of(&self, url: &str) -> String {
+        self.endpoints
+            .iter()
+            .position(|endpoint| endpoint == url)
+            .map_or_else(|| host_of(url), |i| self.labels[i].clone())
+    }
+
+    /// Whether any server has a region label, so client regions matter.
+    pub fn has_regions(&self) -> bool {
+        self.regions.iter().any(Option::is_some)
+    }
+
+    /// Picks the server the client hashed to `key` sticks to, among those
+    /// with limit left: each server is ranked by the hash of `key` with its
+    /// url, the highest ranked one which can take the request wins.
+    pub fn get_sticky(&self, key: u64) -> Option<String> {
+        self.pick(|fallback| {
+            self.by_preference(fallback, |eligible| self.sticky_among(key, eligible))
+        })
+    }
+
+    fn sticky_among(&self, key: u64, eligible: impl Fn(usize) -> bool) -> Option<String> {
+        let mut ranked: Vec<(u64, usize)> = (0..self.urls.len())
+            .filter(|&i| eligible(i))
+            .map(|i| {
+                let mut hasher = DefaultHasher::new();
+                (key, &self.endpoints[i]).hash(&mut hasher);
+                (hasher.finish(), i)
+            })
+            .collect();
+        ranked.sort_unstable_by(|a, b| b.cmp(a));
+        ranked.into_iter().find_map(|(_, i)| self.try_take(i))
+    }
+
+    /// Picks a primary server labelled `region` with `balancer`, falling back
+    /// to the fastest server of any region once none of them can take a
+    /// request.
+    pub fn get_in_region(&self, region: &str, balancer: &dyn BalancingStrategy) -> Option<String> {
+        let local = |i: usize| {
+            !self.fallbacks[i]
+                && !self.is_slow(i)
+                && self.regions[i]
+                    .as_deref()
+                    .is_some_and(|label| label.eq_ignore_ascii_case(region))
+        };
+        balancer
+            .get_next(self, &local)
+            .or_else(|| self.get_fastest())
+    }
+
+    /// Picks a server tagged `tag` with `balancer`. Unlike regions, requests
+    /// asking for a tag never go to other servers, e.g. historical reads only
+    /// archive nodes can answer.
+    pub fn get_tagged(&self, tag: &str, balancer: &dyn BalancingStrategy) -> Option<String> {
+        let tagged = |i: usize| self.tags[i].iter().any(|t| t == tag);
+        balancer.get_next(self, &tagged)
+    }
+
+    /// Takes a server through `take`, which is told whether to pick among the
+    /// fallbacks or the primaries.
+    ///
+    /// Primaries are preferred. Once none of them can take a request, the
+    /// pool spills over to the fallbacks and stays there until
+    /// [`RoundRobin::on_fallback`] sees the primaries recovered.
+    fn pick(&self, take: impl Fn(bool) -> Option<String>) -> Option<String> {
+        if !self.on_fallback() {
+            if let Some(url) = take(false) {
+                return Some(url);
+            }
+            if !self.fallbacks.contains(&true) {
+                return None;
+            }
+            println!("Primary backends exhausted, spilling over to fallbacks");
+            *self.spilled_at.lock().unwrap() = Instant::now();
+            self.spilled.store(true, Ordering::Relaxed);
+        }
+        take(true).or_else(|| take(false))
+    }
+
+    /// Whether requests go to the fallbacks. Failing back waits for
+    /// `failback_after` and for the primaries to have at least half of their
+    /// steady limit available, so the pool does not flap between the two.
+    fn on_fallback(&self) -> bool {
+        if !self.spilled.load(Ordering::Relaxed) {
+            return false;
+        }
+        let spilled_at = self.spilled_at.lock().unwrap();
+        if spilled_at.elapsed() < self.failback_after || !self.primaries_recovered() {
+            return true;
+        }
+        if self.spilled.swap(false, Ordering::Relaxed) {
+            println!("Primary backends recovered, failing back");
+        }
+        false
+    }
+
+    fn primaries_recovered(&self) -> bool {
+        let (mut available, mut limit) = (0u64, 0u64);
+        for (i, server) in self.urls.iter().enumerate() {
+            if self.fallbacks[i] {
+                continue;
+            }
+            let sidelined = self.is_sidelined(i);
+            let server = server.lock().unwrap();
+            limit += server.request_limit as u64;
+            if !sidelined {
+                available += server.current_limit as u64;
+            }
+        }
+        limit > 0 && available * 2 >= limit
+    }
+
+    /// Offers `take` the servers of a tier group by group: those in the
+    /// local region before the others, if there is one, and within each,
+    /// those over the latency budget only once the others can not take the
+    /// request, then likewise those on pace to run out of limit.
+    fn by_preference(
+        &self,
+        fallback: bool,
+        take: impl Fn(&dyn Fn(usize) -> bool) -> Option<String>,
+    ) -> Option<String> {
+        let localities: &[Option<bool>] = match self.local_region {
+            Some(_) => &[Some(true), Some(false)],
+            None => &[None],
+        };
+        let speeds: &[bool] = match self.latency_budget_ms {
+            Some(_) => &[false, true],
+            None => &[false],
+        };
+        let paces: &[bool] = match self.predictive_spillover {
+            true => &[false, true],
+            false => &[false],
+        };
+        for local in localities {
+            for slow in speeds {
+                for pressed in paces {
+                    let eligible = |i: usize| {
+                        self.fallbacks[i] == fallback
+                            && self.is_slow(i) == *slow
+                            && self.is_pressed(i) == *pressed
+                            && local.is_none_or(|local| self.is_local(i) == local)
+                    };
+                    if let Some(url) = take(&eligible) {
+                        return Some(url);
+                    }
+                }
+            }
         }
+        None
     }
 
-    pub fn get_next(&mut self) -> Option<String> {
+    /// Whether the server at `i` spends its limit faster than the refill
+    /// window allows, so at its current pace it runs out before the window
+    /// ends. Always false without `predictive_spillover`.
+    fn is_pressed(&self, i: usize) -> bool {
+        if !self.predictive_spillover {
+            return false;
+        }
+        let Some((started, length)) = *self.window.lock().unwrap() else {
+            return false;
+        };
+        let elapsed = started.elapsed();
+        // Too early in the window to tell a pace from a spike.
+        if elapsed < length / 10 {
+            return false;
+        }
+        let server = self.urls[i].lock().unwrap();
+        let left = server.current_limit + self.budgets[i].available();
+        let used = server.window_limit.saturating_sub(left);
+        let projected = used as f64 * length.as_secs_f64() / elapsed.as_secs_f64();
+        projected > server.window_limit as f64
+    }
+
+    /// Whether the server at `i` is in the local region.
+    fn is_local(&self, i: usize) -> bool {
+        let (Some(local), Some(region)) = (&self.local_region, &self.regions[i]) else {
+            return false;
+        };
+        region.eq_ignore_ascii_case(local)
+    }
+
+    /// Takes the next eligible server in rotation on the shared index, then
+    /// burst allowance once none has a steady token left.
+    pub fn next_among(&self, eligible: impl Fn(usize) -> bool) -> Option<String> {
         let len = self.urls.len();
         for _ in 0..len {
             let i = self.index.load(Ordering::Relaxed) % self.urls.len();
-            {
-                let mut server = self.urls[i].lock().unwrap();
-                if server.current_limit > 0 {
-                    server.current_limit -= 1;
-                    return Some(server.url.clone());
+            if eligible(i) {
+                if let Some(url) = self.take_steady(i) {
+                    if !self.drain_first {
+                        self.index.store((i + 1) % len, Ordering::Relaxed);
+                    }
+                    return Some(url);
                 }
             }
             self.index.store((i + 1) % len, Ordering::Relaxed);
         }
 
+        // Once every steady limit is used up, absorb the spike with burst allowance
+        for i in 0..self.urls.len() {
+            if !eligible(i) || self.is_sidelined(i) {
+                continue;
+            }
+            if self.limiters[i].consume_burst() {
+                return Some(self.endpoints[i].clone());
+            }
+        }
+
         // If no servers have available limits, return None
         None
     }
 
+    /// Draws among the eligible servers by weight until one has a steady
+    /// token left. Servers weighted `0`, and burst allowance, are only used
+    /// once every weighted server ran out, as by [`RoundRobin::next_among`].
+    pub fn weighted_among(&self, eligible: impl Fn(usize) -> bool) -> Option<String> {
+        self.draw_among(eligible, |i| self.weights[i] as u64)
+    }
+
+    /// Draws among the eligible servers with equal chances, weights aside.
+    pub fn random_among(&self, eligible: impl Fn(usize) -> bool) -> Option<String> {
+        self.draw_among(eligible, |_| 1)
+    }
+
+    fn draw_among(
+        &self,
+        eligible: impl Fn(usize) -> bool,
+        weight_of: impl Fn(usize) -> u64,
+    ) -> Option<String> {
+        let mut candidates: Vec<usize> = (0..self.urls.len())
+            .filter(|&i| eligible(i) && weight_of(i) > 0)
+            .collect();
+        while !candidates.is_empty() {
+            let total: u64 = candidates.iter().map(|&i| weight_of(i)).sum();
+            let mut draw = rand::random_range(0..total);
+            let position = candidates
+                .iter()
+                .position(|&i| {
+                    let weight = weight_of(i);
+                    if draw < weight {
+                        return true;
+                    }
+                    draw -= weight;
+                    false
+                })
+                .unwrap_or(0);
+            if let Some(url) = self.take_steady(candidates[position]) {
+                return Some(url);
+            }
+            candidates.swap_remove(position);
+        }
+        self.next_among(eligible)
+    }
+
+    /// Takes the eligible server with the lowest latency moving average.
+    pub fn fastest_among(&self, eligible: impl Fn(usize) -> bool) -> Option<String> {
+        let mut best: Option<(usize, f64)> = None;
+        for i in 0..self.urls.len() {
+            if !eligible(i) || self.is_sidelined(i) {
+                continue;
+            }
+            if !self.limiters[i].has_capacity() {
+                continue;
+            }
+            let latency = self.stats[i].lock().unwrap().latency_ms.unwrap_or(0.0);
+            if best.is_none_or(|(_, best_latency)| latency < best_latency) {
+                best = Some((i, latency));
+            }
+        }
+
+        let (i, _) = best?;
+        self.try_take(i)
+    }
+
+    /// Takes the eligible server with the fewest requests in flight, ties
+    /// going to a random one of them so idle pools still spread requests.
+    pub fn least_loaded_among(&self, eligible: impl Fn(usize) -> bool) -> Option<String> {
+        let len = self.urls.len();
+        let start = rand::random_range(0..len.max(1));
+        let (i, _) = (0..len)
+            .map(|offset| (start + offset) % len)
+            .filter(|&i| eligible(i) && !self.is_sidelined(i) && self.limiters[i].has_capacity())
+            .map(|i| (i, self.in_flight[i].load(Ordering::Relaxed)))
+            .min_by_key(|(_, in_flight)| *in_flight)?;
+        self.try_take(i)
+    }
+
+    pub fn healthiest_among(&self, eligible: impl Fn(usize) -> bool) -> Option<String> {
+        let candidates: Vec<usize> = (0..self.urls.len())
+            .filter(|&i| eligible(i) && !self.is_sidelined(i) && self.limiters[i].has_capacity())
+            .collect();
+        let fastest = candidates
+            .iter()
+            .filter_map(|&i| self.stats[i].lock().unwrap().latency_ms)
+            .fold(f64::INFINITY, f64::min);
+        let top = self.top_head();
+        let (i, _) = candidates
+            .into_iter()
+            .map(|i| (i, self.health_score(i, fastest, top)))
+            .fold(None, |best: Option<(usize, f64)>, (i, score)| {
+                match best.is_none_or(|(_, best_score)| score > best_score) {
+                    true => Some((i, score)),
+                    false => best,
+                }
+            })?;
+        self.try_take(i)
+    }
+
+    /// Scores the server at `i` from 0 to 1 by the weighted mean of the share
+    /// of its window's steady limit left, `fastest` latency over its own, the
+    /// share of its recent attempts that succeeded and `1 / (1 + lag)` behind
+    /// the `top` head. Servers not measured yet score 1 on latency and lag.
+    fn health_score(&self, i: usize, fastest: f64, top: Option<u64>) -> f64 {
+        let weights = &self.health_weights;
+        let limit = {
+            let server = self.urls[i].lock().unwrap();
+            let left = server.current_limit + self.budgets[i].available();
+            (left as f64 / server.window_limit.max(1) as f64).min(1.0)
+        };
+        let stats = self.stats[i].lock().unwrap();
+        let latency = match stats.latency_ms {
+            Some(latency) if latency > 0.0 => (fastest / latency).min(1.0),
+            _ => 1.0,
+        };
+        let lag = match (top, stats.head) {
+            (Some(top), Some(head)) => 1.0 / (1.0 + top.saturating_sub(head) as f64),
+            _ => 1.0,
+        };
+        let total = weights.limit + weights.latency + weights.errors + weights.lag;
+        if total <= 0.0 {
+            return 0.0;
+        }
+        (weights.limit * limit
+            + weights.latency * latency
+            + weights.errors * (1.0 - stats.error_rate)
+            + weights.lag * lag)
+            / total
+    }
+
+    /// The highest block any server reported.
+    fn top_head(&self) -> Option<u64> {
+        self.stats
+            .iter()
+            .filter_map(|stats| stats.lock().unwrap().head)
+            .max()
+    }
+
+    /// Takes one steady token of the server at `i`, returning its url.
+    pub fn take_steady(&self, i: usize) -> Option<String> {
+        if self.is_sidelined(i) || !self.limiters[i].consume() {
+            return None;
+        }
+        self.charge_errors(i);
+        Some(self.endpoints[i].clone())
+    }
+
+    /// Tokens a request to the server at `i` costs: 1, growing with its error
+    /// rate to `error_cost` for a server failing every attempt. Its share of
+    /// the traffic shrinks and recovers smoothly with the rate, rather than
+    /// flapping in and out of rotation.
+    pub fn token_cost(&self, i: usize) -> f64 {
+        let error_rate = self.stats[i].lock().unwrap().error_rate;
+        1.0 + (self.error_cost - 1.0).max(0.0) * error_rate
+    }
+
+    /// Takes the tokens of a request beyond the first, a fraction of one by
+    /// chance, as far as the server has them left.
+    fn charge_errors(&self, i: usize) {
+        if self.error_cost <= 1.0 {
+            return;
+        }
+        let extra = self.token_cost(i) - 1.0;
+        let tokens = extra.floor() as u32 + u32::from(rand::random::<f64>() < extra.fract());
+        for _ in 0..tokens {
+            if !self.limiters[i].consume() {
+                break;
+            }
+        }
+    }
+
+    /// Takes one token of the server at `i`, drawing from the burst allowance
+    /// once the steady limit is used up.
+    pub fn try_take(&self, i: usize) -> Option<String> {
+        self.take_steady(i).or_else(|| {
+            if self.is_sidelined(i) || !self.limiters[i].consume_burst() {
+                return None;
+            }
+            Some(self.endpoints[i].clone())
+        })
+    }
+
+    /// Gives back the token of a request to `url` which never reached it.
+    pub fn refund(&self, url: &str) {
+        for i in (0..self.endpoints.len()).filter(|&i| self.endpoints[i] == url) {
+            self.limiters[i].refund();
+        }
+    }
+
+    /// Sends `request` to the server at `url`, whose token was taken by the
+    /// caller, through the server's transport. The token is refunded unless
+    /// the request was written to the backend, including when the caller gives
+    /// up on it first, e.g. because its own client disconnected.
+    pub async fn send(
+        &self,
+        url: &str,
+        request: &UpstreamRequest,
+        timeout: Option<Duration>,
+    ) -> Result<reqwest::Response, TransportError> {
+        let sent = Sent::default();
+        let _refund = RefundUnsent {
+            round_robin: self,
+            url,
+            sent: &sent,
+        };
+        let _in_flight = InFlight::start(self, url);
+        let transport = self
+            .transport_for(url)
+            .ok_or_else(|| TransportError::connect("unknown backend"))?;
+        // Failures before the request was written never reached the backend,
+        // however late they happened.
+        transport
+            .send(url, request, timeout, &sent)
+            .await
+            .map_err(|e| TransportError {
+                connect: e.connect || !sent.is_marked(),
+                ..e
+            })
+    }
+
+    /// Folds a latency sample into the moving average and the histogram of
+    /// the server at `url`.
+    pub fn record_latency(&self, url: &str, latency: Duration) {
+        let sample = latency.as_secs_f64() * 1000.0;
+        for (i, server) in self.urls.iter().enumerate() {
+            if server.lock().unwrap().url != url {
+                continue;
+            }
+            let mut stats = self.stats[i].lock().unwrap();
+            stats.latency_ms = Some(match stats.latency_ms {
+                Some(average) => average + self.latency_smoothing * (sample - average),
+                None => sample,
+            });
+
+            let now = Instant::now();
+            stats.histogram.record(latency, now);
+            stats.last_response = Some(now);
+        }
+    }
+
+    /// Folds whether an attempt on the server at `url` succeeded into its
+    /// error rate.
+    pub fn record_outcome(&self, url: &str, succeeded: bool) {
+        let sample = if succeeded { 0.0 } else { 1.0 };
+        for i in (0..self.endpoints.len()).filter(|&i| self.endpoints[i] == url) {
+            let mut stats = self.stats[i].lock().unwrap();
+            stats.error_rate += ERROR_SMOOTHING * (sample - stats.error_rate);
+            drop(stats);
+            for balancer in self.balancers.values() {
+                balancer.report_result(i, succeeded);
+            }
+        }
+    }
+
+    /// Records the latest block the server at `url` reported.
+    pub fn record_head(&self, url: &str, block: u64) {
+        for i in (0..self.endpoints.len()).filter(|&i| self.endpoints[i] == url) {
+            self.stats[i].lock().unwrap().head = Some(block);
+        }
+    }
+
+    /// Whether the p95 of the last minute of the server at `i` exceeds the latency
+    /// budget. Servers without samples yet are within it.
+    fn is_slow(&self, i: usize) -> bool {
+        let Some(budget) = self.latency_budget_ms else {
+            return false;
+        };
+        self.stats[i]
+            .lock()
+            .unwrap()
+            .histogram
+            .percentile(0.95, Instant::now())
+            .is_some_and(|p95| p95 > budget as f64)
+    }
+
+    /// Whether every server is over the latency budget, so even the best
+    /// choice is slow.
+    pub fn latency_degraded(&self) -> bool {
+        self.latency_budget_ms.is_some() && (0..self.urls.len()).all(|i| self.is_slow(i))
+    }
+
+    /// Whether any server could take a request right now, and why not.
+    pub fn pool_status(&self) -> PoolStatus {
+        if self.endpoints.is_empty() {
+            return PoolStatus::Empty;
+        }
+        let usable: Vec<usize> = (0..self.endpoints.len())
+            .filter(|&i| !self.is_sidelined(i))
+            .collect();
+        if usable.is_empty() {
+            return PoolStatus::Unhealthy;
+        }
+        if usable.iter().any(|&i| self.limiters[i].has_capacity()) {
+            return PoolStatus::Available;
+        }
+        PoolStatus::RateLimited {
+            until: *self.next_refill.lock().unwrap(),
+        }
+    }
+
+    /// Whether the server at `i` is left out of selection, because its key is
+    /// quarantined, it fails its health check or it was disabled.
+    pub fn is_sidelined(&self, i: usize) -> bool {
+        let stats = self.stats[i].lock().unwrap();
+        stats.key.is_quarantined(Instant::now()) || stats.unhealthy || stats.disabled
+    }
+
+    /// The position of the server referred to by its `name` or by its
+    /// position in `rpc_urls`.
+    pub fn position_of(&self, backend: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .position(|label| label == backend)
+            .or_else(|| backend.parse().ok().filter(|&i| i < self.labels.len()))
+    }
+
+    /// Takes the server at `i` out of selection, or puts it back, whatever
+    /// its health. Requests already sent to it finish.
+    pub fn set_enabled(&self, i: usize, enabled: bool) {
+        let mut stats = self.stats[i].lock().unwrap();
+        if stats.disabled == enabled {
+            println!(
+                "Backend {} {}",
+                self.labels[i],
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+        stats.disabled = !enabled;
+    }
+
+    /// Tracks a health check result of the server at `url`. It is sidelined
+    /// after `failure_threshold` failures in a row and back after one pass.
+    pub fn record_health(&self, url: &str, passed: bool, failure_threshold: u32) {
+        for i in (0..self.endpoints.len()).filter(|&i| self.endpoints[i] == url) {
+            let mut stats = self.stats[i].lock().unwrap();
+            let was_unhealthy = stats.unhealthy;
+            stats.failed_checks = if passed { 0 } else { stats.failed_checks + 1 };
+            stats.unhealthy = stats.failed_checks >= failure_threshold.max(1);
+            match (was_unhealthy, stats.unhealthy) {
+                (false, true) => println!(
+                    "Backend {} failed {} health checks, taking it out of rotation",
+                    self.labels[i], stats.failed_checks
+                ),
+                (true, false) => {
+                    println!("Backend {} passed its health check again", self.labels[i])
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Tracks the key of the server at `url` by the status it answered with.
+    pub fn record_status(&self, url: &str, status: StatusCode, retry_after: Option<Duration>) {
+        for i in (0..self.endpoints.len()).filter(|&i| self.endpoints[i] == url) {
+            let mut stats = self.stats[i].lock().unwrap();
+            let was_quarantined = stats.key.is_quarantined(Instant::now());
+            stats.key.record(status, retry_after, Instant::now());
+            if !was_quarantined && stats.key.is_quarantined(Instant::now()) {
+                println!(
+                    "Quarantining key of backend {} after a {} response",
+                    self.labels[i], status
+                );
+            }
+        }
+    }
+
+    /// The key status of every server, in configuration order.
+    pub fn key_reports(&self) -> Vec<KeyReport> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .zip(self.stats.iter())
+            .enumerate()
+            .map(|(i, (url, stats))| KeyReport {
+                name: self.urls[i].lock().unwrap().name.clone(),
+                ..stats.lock().unwrap().key.report(i, url, now)
+            })
+            .collect()
+    }
+
+    /// The latency percentiles and health of every server, in configuration
+    /// order.
+    pub fn status(&self) -> Vec<BackendStatus> {
+        let now = Instant::now();
+        let fastest = self
+            .stats
+            .iter()
+            .filter_map(|stats| stats.lock().unwrap().latency_ms)
+            .fold(f64::INFINITY, f64::min);
+        let top = self.top_head();
+        self.stats
+            .iter()
+            .enumerate()
+            .map(|(i, stats)| {
+                let score = self.health_score(i, fastest, top);
+                let token_cost = self.token_cost(i);
+                let stats = stats.lock().unwrap();
+                BackendStatus {
+                    index: i,
+                    backend: self.labels[i].clone(),
+                    healthy: !stats.unhealthy,
+                    enabled: !stats.disabled,
+                    error_rate: stats.error_rate,
+                    head: stats.head,
+                    latency: stats.histogram.percentiles(now),
+                    score,
+                    token_cost,
+                    in_flight: self.in_flight[i].load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+
     pub async fn refill_limits(&self, interval: Duration) {
+        // A window restored from a snapshot, or left by the loop this one
+        // takes over from, runs to its end first.
+        let pending = self
+            .next_refill
+            .lock()
+            .unwrap()
+            .and_then(|at| (at - Utc::now()).to_std().ok());
+        if let Some(pending) = pending {
+            time::sleep(pending).await;
+        }
         loop {
-            for server in self.urls.iter() {
-                {
-                    let mut server = server.lock().unwrap();
-                    server.current_limit = server.request_limit;
-                }
+            let now = schedule::local_now(self.timezone);
+            for limiter in self.limiters.iter() {
+                limiter.refill(now);
             }
+            *self.window.lock().unwrap() = Some((Instant::now(), interval));
+            *self.next_refill.lock().unwrap() = chrono::Duration::from_std(interval)
+                .ok()
+                .map(|interval| Utc::now() + interval);
             time::sleep(interval).await;
         }
     }
 
+    /// Steady tokens spent in the current refill window and the window's
+    /// limit, per server.
+    pub fn window_usage(&self) -> Vec<(String, u32, u32)> {
+        self.urls
+            .iter()
+            .zip(self.budgets.iter())
+            .map(|(server, budget)| {
+                let server = server.lock().unwrap();
+                let left = server.current_limit + budget.available();
+                (
+                    server.url.clone(),
+                    server.window_limit.saturating_sub(left),
+                    server.window_limit,
+                )
+            })
+            .collect()
+    }
+
+    /// The state of every server which outlives a restart, in configuration
+    /// order.
+    pub fn snapshot(&self) -> Vec<BackendSnapshot> {
+        let (now, unix_now) = (Instant::now(), warm_state::unix_now());
+        let window_ends = self.next_refill.lock().unwrap().map(|at| at.timestamp());
+        self.stats
+            .iter()
+            .zip(self.window_usage())
+            .map(|(stats, (url, used, _))| {
+                let stats = stats.lock().unwrap();
+                BackendSnapshot {
+                    url,
+                    quarantine: stats
+                        .key
+                        .quarantine(now)
+                        .map(|(state, left)| (state, unix_now + left.as_secs())),
+                    failed_checks: stats.failed_checks,
+                    unhealthy: stats.unhealthy,
+                    used,
+                    window_ends,
+                }
+            })
+            .collect()
+    }
+
+    /// Carries `snapshots` over to the servers with the same url: quarantines
+    /// not over yet, health check failures when `health_checked`, and the
+    /// tokens spent in a limit window still running, whose end the first
+    /// refill then waits for. Returns how many servers were restored.
+    pub fn restore(&self, snapshots: &[BackendSnapshot], health_checked: bool) -> usize {
+        let (now, unix_now) = (Instant::now(), warm_state::unix_now());
+        let window_ends = snapshots
+            .iter()
+            .filter_map(|snapshot| DateTime::from_timestamp(snapshot.window_ends?, 0))
+            .find(|at| *at > Utc::now());
+        if let Some(window_ends) = window_ends {
+            let now = schedule::local_now(self.timezone);
+            for limiter in self.limiters.iter() {
+                limiter.refill(now);
+            }
+            *self.next_refill.lock().unwrap() = Some(window_ends);
+        }
+
+        let mut restored = 0;
+        for snapshot in snapshots {
+            let Some(i) = self.endpoints.iter().position(|url| *url == snapshot.url) else {
+                continue;
+            };
+            restored += 1;
+            if window_ends.is_some() {
+                for _ in 0..snapshot.used {
+                    if !self.limiters[i].consume() {
+                        break;
+                    }
+                }
+            }
+            let mut stats = self.stats[i].lock().unwrap();
+            if let Some((state, until)) = snapshot.quarantine.filter(|(_, until)| *until > unix_now)
+            {
+                let left = Duration::from_secs(until - unix_now);
+                stats.key.quarantine_for(state, left, now);
+            }
+            if health_checked {
+                stats.failed_checks = snapshot.failed_checks;
+                stats.unhealthy = snapshot.unhealthy;
+            }
+        }
+        restored
+    }
+
+    pub fn server_urls(&self) -> Vec<String> {
+        self.endpoints.to_vec()
+    }
+
+    /// Takes one request token from up to `max` servers that still have
+    /// limit left and returns their urls, starting at the current index.
+    ///
+    /// Used by endpoints that fan a query out to several backends at once.
+    pub fn take_many(&self, max: usize) -> Vec<String> {
+        let len = self.urls.len();
+        let start = self.index.load(Ordering::Relaxed);
+        let mut taken = Vec::new();
+
+        for offset in 0..len {
+            if taken.len() >= max {
+                break;
+            }
+            if let Some(url) = self.try_take((start + offset) % len) {
+                taken.push(url);
+            }
+        }
+
+        taken
+    }
+
+    /// Takes one request token from the first server other than `exclude`
+    /// that still has limit left.
+    pub fn take_other(&self, exclude: &str) -> Option<String> {
+        (0..self.urls.len())
+            .filter(|&i| self.endpoints[i] != exclude)
+            .find_map(|i| self.try_take(i))
+    }
+
+    pub fn timeout_for(&self, url: &str) -> Option<Duration> {
+        self.urls.iter().find_map(|server| {
+            let server = server.lock().unwrap();
+            if server.url == url {
+                server.timeout_ms.map(Duration::from_millis)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The transport of the server at `url`.
+    pub fn transport_for(&self, url: &str) -> Option<Arc<dyn UpstreamTransport>> {
+        let i = self.endpoints.iter().position(|endpoint| endpoint == url)?;
+        Some(self.transports[i].clone())
+    }
+
+    /// Moves past the server a failed attempt went to. Picks already rotate
+    /// unless the chain drains servers first.
     pub fn retry_connection(&self) {
+        if !self.drain_first {
+            return;
+        }
         let len = self.urls.len();
         let i = self.index.load(Ordering::Relaxed);
         self.index.store((i + 1) % len, Ordering::Relaxed);
@@ -63,29 +1250,534 @@ impl RoundRobin {
 
 #[derive(Debug, Clone)]
 pub struct LoadBalancer {
-    pub load_balancers: Arc<HashMap<String, Arc<Mutex<RoundRobin>>>>,
+    pub load_balancers: Arc<HashMap<String, Arc<RoundRobin>>>,
+    pub chains: Arc<HashMap<String, Chains>>,
+    pub tx_tracker: Arc<TxTracker>,
+    pub gas_oracle: Arc<GasOracle>,
+    pub metrics: Arc<Metrics>,
+    pub cache: Arc<ResponseCache>,
+    pub consumers: Arc<Consumers>,
+    pub regions: Arc<ClientRegions>,
+    /// The resolved config the balancer runs, which proposed ones are
+    /// compared against.
+    pub applied_config: Arc<toml::Table>,
+    pub sla_classes: Arc<HashMap<String, SlaClass>>,
+    pub chaos: Arc<Chaos>,
+    pub tx_journal: Arc<TxJournal>,
+    pub tx_dedup: Arc<TxDedup>,
+    pub kill_switch: Arc<KillSwitch>,
+    pub request_log: Arc<RequestLog>,
+    /// Kept across configs, so filters outlive reloads.
+    pub filters: Arc<FilterPins>,
+    pub replays: Arc<ReplayGuard>,
+}
+
+impl LoadBalancer {
+    pub fn new(load_balancers: Arc<HashMap<String, Arc<RoundRobin>>>) -> Self {
+        Self {
+            load_balancers,
+            chains: Arc::new(HashMap::new()),
+            tx_tracker: Arc::new(TxTracker::default()),
+            gas_oracle: Arc::new(GasOracle::default()),
+            metrics: Arc::new(Metrics::default()),
+            cache: Arc::new(ResponseCache::default()),
+            consumers: Arc::new(Consumers::default()),
+            regions: Arc::new(ClientRegions::default()),
+            applied_config: Arc::new(toml::Table::new()),
+            sla_classes: Arc::default(),
+            chaos: Arc::default(),
+            tx_journal: Arc::default(),
+            tx_dedup: Arc::default(),
+            kill_switch: Arc::default(),
+            request_log: Arc::default(),
+            filters: Arc::default(),
+            replays: Arc::default(),
+        }
+    }
+
+    /// Returns the configuration of `chain`, chains registered without one
+    /// (e.g. in tests) behave as if every optional setting was left out.
+    pub fn chain_config(&self, chain: &str) -> Option<&Chains> {
+        self.chains.get(chain)
+    }
+
+    pub fn is_enabled(&self, chain: &str) -> bool {
+        self.chain_config(chain).is_none_or(Chains::is_enabled)
+    }
+
+    /// The names of the chains served, sorted.
+    pub fn chains(&self) -> Vec<String> {
+        let mut chains: Vec<String> = self.load_balancers.keys().cloned().collect();
+        chains.sort();
+        chains
+    }
+
+    /// The backends of `chain` in `rpc_urls` order, with the limits they
+    /// have left in the current window. `None` for unknown chains.
+    pub fn backends(&self, chain: &str) -> Option<Vec<RpcServer>> {
+        let round_robin = self.load_balancers.get(chain)?;
+        Some(
+            round_robin
+                .urls
+                .iter()
+                .map(|server| server.lock().unwrap().clone())
+                .collect(),
+        )
+    }
+
+    /// What `/{chain}/status` reports for every backend of `chain`: health,
+    /// latency percentiles, error rate and requests in flight.
+    pub fn stats(&self, chain: &str) -> Option<Vec<BackendStatus>> {
+        self.load_balancers
+            .get(chain)
+            .map(|round_robin| round_robin.status())
+    }
+
+    /// Switches the default strategy of `chain` to `algorithm` until the
+    /// config is reloaded, or back to its configured one with `None`.
+    pub fn set_algorithm(&self, chain: &str, algorithm: Option<Strategy>) -> Result<(), String> {
+        let round_robin = self
+            .load_balancers
+            .get(chain)
+            .ok_or_else(|| format!("Invalid chain: {}", chain))?;
+        round_robin.set_algorithm(algorithm);
+        Ok(())
+    }
+
+    /// Takes a backend of `chain`, referred to by its `name` or position in
+    /// `rpc_urls`, out of selection or puts it back. The setting lasts until
+    /// the config is reloaded, which rebuilds the chain's pool.
+    pub fn set_backend_enabled(
+        &self,
+        chain: &str,
+        backend: &str,
+        enabled: bool,
+    ) -> Result<(), String> {
+        let round_robin = self
+            .load_balancers
+            .get(chain)
+            .ok_or_else(|| format!("Invalid chain: {}", chain))?;
+        let i = round_robin
+            .position_of(backend)
+            .ok_or_else(|| format!("Unknown backend: {}", backend))?;
+        round_robin.set_enabled(i, enabled);
+        Ok(())
+    }
+}
+
+impl Chains {
+    /// Fields a successful response to `method` has to contain, if checked.
+    pub fn schema_for(&self, method: &str) -> Option<Vec<String>> {
+        if let Some(fields) = self.response_schemas.get(method) {
+            return Some(fields.clone());
+        }
+        if !self.validate_responses {
+            return None;
+        }
+        response_guard::builtin_schema(method)
+            .map(|fields| fields.iter().map(|field| field.to_string()).collect())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
+    /// Whether request bodies are parsed for the methods they call.
+    pub fn inspects_body(&self) -> bool {
+        !self.opaque && self.inspect_body.unwrap_or(true)
+    }
+
+    /// The configured settings which act on the method of requests, and so
+    /// need their body parsed.
+    pub fn method_settings(&self) -> Vec<&'static str> {
+        let routing = &self.routing;
+        [
+            ("rebroadcast", self.rebroadcast.is_some()),
+            ("tx_dedup_secs", self.tx_dedup_secs.is_some()),
+            ("blocked_methods", !self.blocked_methods.is_empty()),
+            ("block_writes", self.block_writes),
+            ("replayable_methods", !self.replayable_methods.is_empty()),
+            ("mirror_ratio", self.mirror_ratio > 0.0),
+            (
+                "routing",
+                routing.read.is_some()
+                    || routing.heavy.is_some()
+                    || routing.write.is_some()
+                    || !routing.methods.is_empty(),
+            ),
+            ("validate_responses", self.validate_responses),
+            ("response_schemas", !self.response_schemas.is_empty()),
+            ("cache", !self.cache.is_empty()),
+            ("cache_warming", self.cache_warming.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, used)| *used)
+        .map(|(setting, _)| setting)
+        .collect()
+    }
+
+    pub fn failback_after(&self) -> Option<Duration> {
+        self.failback_secs.map(Duration::from_secs)
+    }
+
+    /// The region whose backends the chain prefers given the replica's
+    /// `[server] region`, none unless its `locality` is `prefer_local`.
+    pub fn local_region(&self, replica: Option<&str>) -> Option<String> {
+        match self.locality {
+            Locality::Any => None,
+            Locality::PreferLocal => replica.map(str::to_string),
+        }
+    }
+
+    /// The configured timezone, already validated when the config was loaded.
+    pub fn timezone(&self) -> Option<Tz> {
+        self.timezone
+            .as_deref()
+            .and_then(|timezone| schedule::parse_timezone(timezone).ok())
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub chains: HashMap<String, Chains>,
+    #[serde(default)]
+    pub server: ServerConfig,
 }
 
-#[derive(Deserialize, Debug)]
+/// Settings of the listener itself, shared by every chain.
+#[derive(Clone, Default, Deserialize, Debug)]
+pub struct ServerConfig {
+    /// Stop accepting connections while this many requests are in flight.
+    pub max_in_flight: Option<usize>,
+    /// Limits of client connections.
+    #[serde(default)]
+    pub listener: ListenerConfig,
+    pub startup: Option<StartupMode>,
+    #[serde(default)]
+    pub consumers: ConsumerConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    pub quota_webhook: Option<QuotaWebhook>,
+    /// Authentication of client requests, none when unset.
+    pub auth: Option<AuthConfig>,
+    /// Locates clients without an `X-Client-Region` header.
+    pub geoip: Option<GeoIpConfig>,
+    /// Region this replica runs in, overridden by the `REGION` variable so
+    /// replicas can share a config.
+    pub region: Option<String>,
+    #[serde(default)]
+    pub config_history: HistoryConfig,
+    /// Print the probe report of every backend at startup.
+    #[serde(default)]
+    pub probe_report: bool,
+    /// Upstream settings of the principals in each class, by class name.
+    #[serde(default)]
+    pub sla_classes: HashMap<String, SlaClass>,
+    /// Consul or etcd key holding more chains, shared by a fleet.
+    pub registry: Option<RegistryConfig>,
+    /// Where `eth_sendRawTransaction` payloads are journaled before they are
+    /// forwarded, none when unset.
+    pub tx_journal: Option<JournalConfig>,
+    /// Seconds backends removed by a config change keep serving the filters
+    /// installed on them, 30 when unset.
+    pub drain_secs: Option<u64>,
+    /// File the limit and health state of every backend is written to on
+    /// shutdown and restored from at startup, none when unset.
+    pub state_file: Option<String>,
+}
+
+impl ServerConfig {
+    pub fn drain_period(&self) -> Duration {
+        Duration::from_secs(self.drain_secs.unwrap_or(DEFAULT_DRAIN_SECS))
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Debug)]
 pub struct Chains {
+    /// `false` keeps the chain configured but answers its requests with `503`.
+    pub enabled: Option<bool>,
+    /// Empty by default for chains taking their backends from `discovery`
+    /// or `urls_file`.
+    #[serde(default)]
     pub rpc_urls: Vec<RpcServer>,
+    #[serde(default)]
+    pub rebroadcast: Option<RebroadcastConfig>,
+    /// Seconds a raw transaction's response answers identical submissions,
+    /// which are then not forwarded again.
+    pub tx_dedup_secs: Option<u64>,
+    /// Methods answered with an error instead of being forwarded.
+    #[serde(default)]
+    pub blocked_methods: Vec<String>,
+    /// Answer every write method with an error instead of forwarding it.
+    #[serde(default)]
+    pub block_writes: bool,
+    /// Non-idempotent methods retried even after an attempt may have
+    /// reached a backend, e.g. `eth_sendRawTransaction` where resending a
+    /// signed transaction is harmless.
+    #[serde(default)]
+    pub replayable_methods: Vec<String>,
+    /// Share of deterministic requests cross-checked against a second backend.
+    #[serde(default)]
+    pub mirror_ratio: f64,
+    /// Strategy of the chain's requests, short for `routing.default` and
+    /// taking precedence over it.
+    pub algorithm: Option<Strategy>,
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    /// Default `request_limit` of backends which don't declare their own.
+    pub request_limit: Option<u32>,
+    /// Default `burst_limit` of backends which don't declare their own.
+    pub burst_limit: Option<u32>,
+    /// Tokens a request costs on a backend failing every attempt, down to 1
+    /// as its error rate drops. 1, no extra cost, when unset.
+    pub error_cost: Option<f64>,
+    /// IANA timezone limit schedules are evaluated in, the host's when unset.
+    pub timezone: Option<String>,
+    /// Timeout of a single upstream attempt.
+    pub timeout_ms: Option<u64>,
+    /// Upstream attempts per request, defaults to the number of backends.
+    pub max_retries: Option<u32>,
+    /// Proxy requests as-is: no JSON-RPC parsing or method based features,
+    /// the upstream content type is kept and the response is streamed back.
+    #[serde(default)]
+    pub opaque: bool,
+    /// `false` forwards JSON-RPC requests without parsing their body, which
+    /// saves its CPU on busy chains but rules out every method based
+    /// feature. Parsed when unset.
+    pub inspect_body: Option<bool>,
+    #[serde(default)]
+    pub duplicates: DuplicatePolicy,
+    /// List the upstream attempts in the error body of failed requests.
+    #[serde(default)]
+    pub report_attempts: bool,
+    /// Backends whose p95 latency of the last minute exceeds this are
+    /// deprioritized.
+    pub latency_budget_ms: Option<u64>,
+    /// Seconds spent on fallback backends before failing back to the primaries.
+    pub failback_secs: Option<u64>,
+    /// Shift requests away from backends on pace to use up their limit before
+    /// the refill window ends, instead of waiting for them to run out.
+    #[serde(default)]
+    pub predictive_spillover: bool,
+    /// Send round robin requests to one backend until its limit is used up
+    /// before moving to the next, instead of rotating on every request.
+    #[serde(default)]
+    pub drain_first: bool,
+    #[serde(default)]
+    pub locality: Locality,
+    /// Add `X-Served-By`, `X-Upstream-Attempts` and `X-Cache` response headers.
+    #[serde(default)]
+    pub debug_headers: bool,
+    /// Largest request body accepted from clients, 1 MiB when unset. Larger
+    /// ones are answered with `413`.
+    pub max_request_bytes: Option<usize>,
+    /// Largest upstream response passed on to the client, unlimited when unset.
+    pub max_response_bytes: Option<usize>,
+    /// Check the responses of well known methods for their required fields.
+    #[serde(default)]
+    pub validate_responses: bool,
+    /// Fields the `result` of a method has to contain, on top of the built-in
+    /// ones enabled by `validate_responses`.
+    #[serde(default)]
+    pub response_schemas: HashMap<String, Vec<String>>,
+    /// Cache policies keyed by method name or pattern such as `eth_get*`.
+    #[serde(default)]
+    pub cache: HashMap<String, CachePolicy>,
+    /// Refreshing of popular cached calls before they expire, off when unset.
+    pub cache_warming: Option<CacheWarming>,
+    /// `Cache-Control` and `Age` headers of the chain's responses, none are
+    /// added when unset.
+    pub cache_control: Option<CacheControl>,
+    #[serde(default)]
+    pub headers: HeaderPolicy,
+    /// Active health check of the backends, none are run when unset.
+    pub health_check: Option<HealthCheck>,
+    /// Chain ID the backends must answer `eth_chainId` with, checked by the
+    /// probe report. Without it they are checked against each other.
+    pub chain_id: Option<u64>,
+    /// Source of backends added to `rpc_urls` and kept in sync with it.
+    pub discovery: Option<BackendSource>,
+    /// File listing more backend urls, one per line, whose changes are
+    /// picked up while running.
+    pub urls_file: Option<String>,
+    /// Connections kept open to the HTTP backends while they are idle, none
+    /// when unset.
+    pub prewarm: Option<Prewarm>,
+    /// Connection settings of a client of the chain's own, the backends share
+    /// one with every other chain when unset.
+    pub http: Option<HttpClientConfig>,
+}
+
+/// What to do when a chain lists the same backend url more than once.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Fold the duplicates into one backend with the summed limits.
+    #[default]
+    Merge,
+    /// Refuse to load the config.
+    Error,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+/// Which regions a chain's requests go to, in deployments with replicas in
+/// several regions.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Locality {
+    /// Every backend is used alike.
+    #[default]
+    Any,
+    /// Backends in the replica's `[server] region` are used first, others only
+    /// once every local one is out of limit.
+    PreferLocal,
+}
+
+/// Default of `[server] drain_secs`.
+const DEFAULT_DRAIN_SECS: u64 = 30;
+/// Default of `routing.latency_smoothing`.
+const DEFAULT_LATENCY_SMOOTHING: f64 = 0.3;
+/// Weight given to the newest attempt in the error rate moving average.
+const ERROR_SMOOTHING: f64 = 0.1;
+
+/// The state of one backend, as served by `/{chain}/status`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BackendStatus {
+    pub index: usize,
+    /// Name or host of the backend.
+    pub backend: String,
+    pub healthy: bool,
+    /// False while taken out of selection with `set_backend_enabled`.
+    pub enabled: bool,
+    pub error_rate: f64,
+    pub head: Option<u64>,
+    /// `None` until the backend served a request in the last minute.
+    pub latency: Option<LatencyPercentiles>,
+    /// Score `health_weighted` routing ranks the backend by, from 0 to 1.
+    pub score: f64,
+    /// Tokens a request currently costs, above 1 with `error_cost`.
+    pub token_cost: f64,
+    /// Requests sent and not answered yet, which `least_conn` balances.
+    pub in_flight: usize,
+}
+
+/// Runtime measurements kept alongside each `RpcServer`.
+#[derive(Clone, Debug, Default)]
+pub struct BackendStats {
+    pub latency_ms: Option<f64>,
+    /// Latency samples of the last minute, for percentiles.
+    pub histogram: LatencyHistogram,
+    pub key: KeyHealth,
+    /// Health checks failed in a row.
+    pub failed_checks: u32,
+    pub unhealthy: bool,
+    /// Taken out of selection by `LoadBalancer::set_backend_enabled`.
+    pub disabled: bool,
+    /// Moving average of failed attempts, from 0 to 1.
+    pub error_rate: f64,
+    /// Latest block reported to `eth_blockNumber` health checks.
+    pub head: Option<u64>,
+    /// When the server last answered a request, as its connections are warm
+    /// until a while after.
+    pub last_response: Option<Instant>,
+}
+
+#[derive(Clone, Default, Deserialize, Debug)]
 pub struct RpcServer {
     pub url: String,
     pub current_limit: u32,
     pub request_limit: u32,
+    /// Overrides the chain's `timeout_ms` for this backend.
+    pub timeout_ms: Option<u64>,
+    /// Extra requests allowed on top of `request_limit` to absorb spikes.
+    #[serde(default)]
+    pub burst_limit: u32,
+    #[serde(skip)]
+    pub current_burst: u32,
+    /// Windows overriding the limits above at certain times of day/week.
+    #[serde(default)]
+    pub schedule: Vec<LimitWindow>,
+    /// Steady limit of the current refill window, which schedules can lower.
+    #[serde(skip)]
+    pub window_limit: u32,
+    /// Only used while no primary backend can take a request, e.g. a public
+    /// endpoint backing up keyed providers.
+    #[serde(default)]
+    pub fallback: bool,
+    /// `1` for primaries, `2` for fallbacks, as an alternative to `fallback`.
+    pub tier: Option<u8>,
+    /// `Host` header sent instead of the host of `url`.
+    pub host_header: Option<String>,
+    /// Address connected to instead of resolving the host of `url`, which is
+    /// still used for SNI and certificate checks. The port comes from `url`.
+    pub connect_to: Option<IpAddr>,
+    /// Region the backend serves, preferred for clients from the same one.
+    pub region: Option<String>,
+    /// Stable name of the backend, used instead of its host in logs, metrics,
+    /// headers and admin paths.
+    pub name: Option<String>,
+    /// Share of `weighted_random` and `weighted_round_robin` picks relative to
+    /// the chain's other backends, `1` when unset. Backends weighted `0` only
+    /// get requests once the others ran out of limit.
+    pub weight: Option<u32>,
+    /// Latency or errors injected into the backend's requests, in builds
+    /// with the `chaos` feature.
+    pub chaos: Option<Fault>,
+    /// Added by the chain's `discovery` or `urls_file` rather than configured.
+    #[serde(default)]
+    pub discovered: bool,
+    /// Labels `[[routing.headers]]` rules send requests to, e.g. `archive`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Request bodies of at least this many bytes, e.g. large batches, are
+    /// sent gzipped to this HTTP backend. Only for backends which accept
+    /// `Content-Encoding: gzip`.
+    pub compress_requests_over: Option<usize>,
+    /// Shares of the attempts on this backend which are logged.
+    pub log: Option<LogSampling>,
+}
+
+impl RpcServer {
+    /// The name of the server, or the host of its url.
+    pub fn label(&self) -> String {
+        self.name.clone().unwrap_or_else(|| host_of(&self.url))
+    }
+
+    /// Whether the server is flagged `fallback` or in `tier = 2`.
+    pub fn is_fallback(&self) -> bool {
+        self.fallback || self.tier == Some(2)
+    }
+
+    pub fn has_capacity(&self) -> bool {
+        self.current_limit > 0 || self.current_burst > 0
+    }
+
+    /// Starts a new limit window. Steady tokens left unused in the previous
+    /// window are earned back as burst allowance, so the burst bucket only
+    /// recovers while traffic stays below the steady limit.
+    ///
+    /// A schedule window active at `now` replaces the configured limits.
+    pub fn refill(&mut self, now: NaiveDateTime) {
+        let (request_limit, burst_limit) = match schedule::active_window(&self.schedule, now) {
+            Some(window) => (
+                window.request_limit,
+                window.burst_limit.unwrap_or(self.burst_limit),
+            ),
+            None => (self.request_limit, self.burst_limit),
+        };
+
+        self.current_burst = (self.current_burst + self.current_limit).min(burst_limit);
+        self.current_limit = request_limit;
+        self.window_limit = request_limit;
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::algorithms::{
+        key_health::KeyState,
+        strategy::{Fastest, Rotation},
+    };
     use std::sync::atomic::Ordering;
 
     fn create_test_servers() -> Vec<RpcServer> {
@@ -94,11 +1786,13 @@ mod tests {
                 url: "https://sepolia.drpc.org/".to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://polygon-rpc.com".to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
         ]
     }
@@ -124,7 +1818,7 @@ mod tests {
     #[test]
     fn test_get_next() {
         let servers = create_test_servers();
-        let mut round_robin = RoundRobin::new(servers);
+        let round_robin = RoundRobin::new(servers).with_drain_first(true);
 
         let url1 = round_robin.get_next();
         assert_eq!(url1, Some("https://sepolia.drpc.org/".to_string()));
@@ -142,4 +1836,650 @@ mod tests {
         assert_eq!(url4, None);
         assert_eq!(round_robin.index.load(Ordering::Relaxed), 1);
     }
+
+    #[test]
+    fn test_get_next_rotates() {
+        let servers = create_test_servers()
+            .into_iter()
+            .map(|server| RpcServer {
+                request_limit: 3,
+                current_limit: 3,
+                ..server
+            })
+            .collect();
+        let round_robin = RoundRobin::new(servers);
+
+        let picks: Vec<Option<String>> = (0..7).map(|_| round_robin.get_next()).collect();
+        let (a, b) = (
+            Some("https://sepolia.drpc.org/".to_string()),
+            Some("https://polygon-rpc.com".to_string()),
+        );
+        assert_eq!(
+            picks,
+            vec![a.clone(), b.clone(), a.clone(), b.clone(), a, b, None]
+        );
+
+        // Once one server is out of limit the other takes every request.
+        let round_robin = RoundRobin::new(
+            create_test_servers()
+                .into_iter()
+                .map(|server| RpcServer {
+                    request_limit: 3,
+                    current_limit: 3,
+                    ..server
+                })
+                .collect(),
+        );
+        round_robin.urls[0].lock().unwrap().current_limit = 0;
+        assert_eq!(
+            round_robin.get_next(),
+            Some("https://polygon-rpc.com".to_string())
+        );
+        assert_eq!(
+            round_robin.get_next(),
+            Some("https://polygon-rpc.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_get_fastest() {
+        let servers = create_test_servers();
+        let round_robin = RoundRobin::new(servers);
+
+        round_robin.record_latency("https://sepolia.drpc.org/", Duration::from_millis(200));
+        round_robin.record_latency("https://polygon-rpc.com", Duration::from_millis(50));
+
+        assert_eq!(
+            round_robin.get_fastest(),
+            Some("https://polygon-rpc.com".to_string())
+        );
+        // The fastest server is out of limit, so the slower one is used.
+        assert_eq!(
+            round_robin.get_fastest(),
+            Some("https://sepolia.drpc.org/".to_string())
+        );
+        assert_eq!(round_robin.get_fastest(), None);
+    }
+
+    #[test]
+    fn test_latency_smoothing() {
+        let (a, b) = ("https://sepolia.drpc.org/", "https://polygon-rpc.com");
+        let average = |smoothing: Option<f64>| {
+            let round_robin =
+                RoundRobin::new(create_test_servers()).with_latency_smoothing(smoothing);
+            round_robin.record_latency(a, Duration::from_millis(100));
+            round_robin.record_latency(a, Duration::from_millis(200));
+            round_robin.record_latency(b, Duration::from_millis(150));
+            let latency = round_robin.stats[0].lock().unwrap().latency_ms.unwrap();
+            (latency, round_robin.get_fastest())
+        };
+
+        // A slow response only moves the average part of the way...
+        assert_eq!(average(None), (130.0, Some(a.to_string())));
+        // ...unless the chain reacts to the latest one right away.
+        assert_eq!(average(Some(1.0)), (200.0, Some(b.to_string())));
+    }
+
+    #[test]
+    fn test_get_healthiest() {
+        let mut servers = create_test_servers();
+        for server in servers.iter_mut() {
+            server.request_limit = 10;
+            server.current_limit = 10;
+        }
+        let round_robin = RoundRobin::new(servers).with_health_weights(HealthWeights {
+            errors: 3.0,
+            ..Default::default()
+        });
+        let (flaky, lagging) = ("https://sepolia.drpc.org/", "https://polygon-rpc.com");
+
+        round_robin.record_head(flaky, 100);
+        round_robin.record_head(lagging, 90);
+        assert_eq!(round_robin.get_healthiest(), Some(flaky.to_string()));
+
+        for _ in 0..20 {
+            round_robin.record_outcome(flaky, false);
+        }
+        assert_eq!(round_robin.get_healthiest(), Some(lagging.to_string()));
+    }
+
+    #[test]
+    fn test_get_weighted() {
+        let servers = [
+            "https://a.example",
+            "https://b.example",
+            "https://c.example",
+        ]
+        .into_iter()
+        .zip([3, 1, 0])
+        .map(|(url, weight)| RpcServer {
+            url: url.to_string(),
+            request_limit: 1000,
+            current_limit: 1000,
+            weight: Some(weight),
+            ..Default::default()
+        })
+        .collect();
+        let round_robin = RoundRobin::new(servers);
+
+        let mut picks = HashMap::new();
+        for _ in 0..1000 {
+            *picks
+                .entry(round_robin.get_weighted().unwrap())
+                .or_insert(0) += 1;
+        }
+        let heavy = picks[&round_robin.endpoints[0]];
+        assert!((650..850).contains(&heavy), "{} picks", heavy);
+        assert_eq!(picks.get(&round_robin.endpoints[2]), None);
+        assert_eq!(round_robin.index.load(Ordering::Relaxed), 0);
+
+        // Unweighted servers take over once the others ran out.
+        for _ in 0..1000 {
+            round_robin.get_weighted().unwrap();
+        }
+        assert_eq!(
+            round_robin.get_weighted().as_ref(),
+            Some(&round_robin.endpoints[2])
+        );
+    }
+
+    #[test]
+    fn test_get_random() {
+        let servers = ["https://a.example", "https://b.example"]
+            .into_iter()
+            .zip([3, 0])
+            .map(|(url, weight)| RpcServer {
+                url: url.to_string(),
+                request_limit: 1000,
+                current_limit: 1000,
+                weight: Some(weight),
+                ..Default::default()
+            })
+            .collect();
+        let round_robin = RoundRobin::new(servers);
+
+        // Weights do not matter, every server is as likely.
+        let picks = (0..1000)
+            .filter(|_| round_robin.get_random().unwrap() == round_robin.endpoints[1])
+            .count();
+        assert!((400..600).contains(&picks), "{} picks", picks);
+        assert_eq!(round_robin.index.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_get_least_loaded() {
+        let servers = create_test_servers()
+            .into_iter()
+            .map(|server| RpcServer {
+                request_limit: 100,
+                current_limit: 100,
+                ..server
+            })
+            .collect();
+        let round_robin = RoundRobin::new(servers);
+        let (busy, idle) = (&round_robin.endpoints[0], &round_robin.endpoints[1]);
+
+        let _waiting = InFlight::start(&round_robin, busy);
+        for _ in 0..10 {
+            assert_eq!(round_robin.get_least_loaded().as_ref(), Some(idle));
+        }
+        let _waiting = [
+            InFlight::start(&round_robin, idle),
+            InFlight::start(&round_robin, idle),
+        ];
+        assert_eq!(round_robin.get_least_loaded().as_ref(), Some(busy));
+        assert_eq!(round_robin.index.load(Ordering::Relaxed), 0);
+        let in_flight: Vec<usize> = round_robin
+            .status()
+            .iter()
+            .map(|status| status.in_flight)
+            .collect();
+        assert_eq!(in_flight, [1, 2]);
+    }
+
+    #[test]
+    fn test_error_cost() {
+        let servers = create_test_servers()
+            .into_iter()
+            .map(|server| RpcServer {
+                request_limit: 100,
+                current_limit: 100,
+                ..server
+            })
+            .collect();
+        let round_robin = RoundRobin::new(servers).with_error_cost(Some(3.0));
+        let (failing, fine) = (&round_robin.endpoints[0], &round_robin.endpoints[1]);
+        round_robin.stats[0].lock().unwrap().error_rate = 0.5;
+        assert_eq!(round_robin.token_cost(0), 2.0);
+        assert_eq!(round_robin.token_cost(1), 1.0);
+
+        for _ in 0..20 {
+            round_robin.get_next().unwrap();
+        }
+        let usage = round_robin.window_usage();
+        assert_eq!(usage[0].0, *failing);
+        assert_eq!((usage[0].1, usage[1].1), (20, 10));
+        assert_eq!(usage[1].0, *fine);
+
+        // The cost eases off as attempts succeed again.
+        for _ in 0..20 {
+            round_robin.record_outcome(failing, true);
+        }
+        assert!(round_robin.token_cost(0) < 1.2);
+    }
+
+    #[test]
+    fn test_predictive_spillover() {
+        let servers = create_test_servers()
+            .into_iter()
+            .map(|server| RpcServer {
+                request_limit: 100,
+                current_limit: 100,
+                ..server
+            })
+            .collect();
+        let round_robin = RoundRobin::new(servers).with_predictive_spillover(true);
+        let (busy, idle) = (
+            round_robin.endpoints[0].clone(),
+            round_robin.endpoints[1].clone(),
+        );
+        // Half way through the window the first server used 60 of its 100.
+        *round_robin.window.lock().unwrap() = Some((
+            Instant::now() - Duration::from_secs(30),
+            Duration::from_secs(60),
+        ));
+        round_robin.urls[0].lock().unwrap().current_limit = 40;
+        round_robin.urls[1].lock().unwrap().current_limit = 90;
+
+        assert!(round_robin.is_pressed(0));
+        assert!(!round_robin.is_pressed(1));
+        for _ in 0..41 {
+            assert_eq!(round_robin.get_next().as_ref(), Some(&idle));
+        }
+
+        // Once both are on pace to run out, they share the requests again.
+        assert!(round_robin.is_pressed(1));
+        let picks: Vec<String> = (0..2).filter_map(|_| round_robin.get_next()).collect();
+        assert!(picks.contains(&busy) && picks.contains(&idle));
+    }
+
+    #[test]
+    fn test_get_in_region() {
+        let mut servers = create_test_servers();
+        servers[0].region = Some("eu-west".to_string());
+        servers[1].region = Some("us-east".to_string());
+        let round_robin = RoundRobin::new(servers);
+        let (eu, us) = ("https://sepolia.drpc.org/", "https://polygon-rpc.com");
+        round_robin.record_latency(eu, Duration::from_millis(200));
+        round_robin.record_latency(us, Duration::from_millis(50));
+
+        assert!(round_robin.has_regions());
+        assert_eq!(
+            round_robin.get_in_region("EU-West", &Rotation),
+            Some(eu.to_string())
+        );
+        // The local backend is out of limit, so the fastest other one is used.
+        assert_eq!(
+            round_robin.get_in_region("eu-west", &Rotation),
+            Some(us.to_string())
+        );
+        assert_eq!(round_robin.get_in_region("eu-west", &Fastest), None);
+    }
+
+    #[test]
+    fn test_get_tagged() {
+        let mut servers = create_test_servers();
+        servers[1].tags = vec!["archive".to_string()];
+        let round_robin = RoundRobin::new(servers);
+        let archive = "https://polygon-rpc.com";
+
+        assert_eq!(
+            round_robin.get_tagged("archive", &Rotation),
+            Some(archive.to_string())
+        );
+        assert_eq!(round_robin.get_tagged("trace", &Rotation), None);
+    }
+
+    #[test]
+    fn test_get_sticky() {
+        let servers = (0..4)
+            .map(|i| RpcServer {
+                url: format!("https://{}.example", i),
+                request_limit: 3,
+                current_limit: 3,
+                ..Default::default()
+            })
+            .collect();
+        let round_robin = RoundRobin::new(servers);
+
+        let first = round_robin.get_sticky(7).unwrap();
+        for _ in 0..2 {
+            assert_eq!(round_robin.get_sticky(7).as_ref(), Some(&first));
+        }
+        // Once its backend ran out, the client moves on, and only it.
+        let second = round_robin.get_sticky(7).unwrap();
+        assert_ne!(second, first);
+        let others: std::collections::HashSet<String> = (100..200)
+            .filter_map(|key| round_robin.get_sticky(key))
+            .collect();
+        assert!(others.len() > 1);
+        assert!(!others.contains(&first));
+    }
+
+    #[test]
+    fn test_prefer_local_region() {
+        let mut servers = create_test_servers();
+        servers[0].region = Some("us-east".to_string());
+        servers[1].region = Some("eu-west".to_string());
+        let round_robin = RoundRobin::new(servers).with_local_region(Some("eu-west".to_string()));
+        let (us, eu) = ("https://sepolia.drpc.org/", "https://polygon-rpc.com");
+        round_robin.record_latency(us, Duration::from_millis(20));
+        round_robin.record_latency(eu, Duration::from_millis(200));
+
+        // The local backend wins over a faster remote one until it runs out.
+        assert_eq!(round_robin.get_fastest(), Some(eu.to_string()));
+        assert_eq!(round_robin.get_fastest(), Some(us.to_string()));
+        assert_eq!(round_robin.get_next(), None);
+    }
+
+    #[test]
+    fn test_latency_budget() {
+        let mut servers = create_test_servers();
+        for server in servers.iter_mut() {
+            server.request_limit = 10;
+            server.current_limit = 10;
+        }
+        let round_robin = RoundRobin::new(servers).with_latency_budget(Some(100));
+        let (slow, fast) = ("https://sepolia.drpc.org/", "https://polygon-rpc.com");
+
+        for _ in 0..20 {
+            round_robin.record_latency(slow, Duration::from_millis(300));
+            round_robin.record_latency(fast, Duration::from_millis(20));
+        }
+        assert_eq!(round_robin.get_next(), Some(fast.to_string()));
+        assert!(!round_robin.latency_degraded());
+
+        round_robin.record_latency(fast, Duration::from_millis(500));
+        round_robin.record_latency(fast, Duration::from_millis(500));
+        assert!(round_robin.latency_degraded());
+        // Over budget backends still serve when no other one is left.
+        assert!(round_robin.get_next().is_some());
+    }
+
+    #[test]
+    fn test_status() {
+        let round_robin = RoundRobin::new(create_test_servers());
+        let url = "https://sepolia.drpc.org/";
+        for ms in [10, 20, 30, 40] {
+            round_robin.record_latency(url, Duration::from_millis(ms));
+        }
+        round_robin.record_outcome(url, false);
+
+        let status = round_robin.status();
+        assert_eq!(status.len(), 2);
+        assert_eq!(status[0].backend, "sepolia.drpc.org");
+        assert_eq!(status[0].error_rate, ERROR_SMOOTHING);
+        let latency = status[0].latency.unwrap();
+        assert_eq!(latency.samples, 4);
+        assert!((19.0..21.0).contains(&latency.p50_ms));
+        assert!((39.0..41.0).contains(&latency.p99_ms));
+        assert_eq!(status[1].latency, None);
+        // As fast as the fastest, but for the failed attempt.
+        assert!(
+            (status[0].score - 0.975).abs() < 1e-9,
+            "{}",
+            status[0].score
+        );
+        assert_eq!(status[1].score, 1.0);
+    }
+
+    #[test]
+    fn test_failing_health_checks() {
+        let round_robin = RoundRobin::new(create_test_servers());
+        let url = "https://sepolia.drpc.org/";
+
+        round_robin.record_health(url, false, 2);
+        assert!(!round_robin.is_sidelined(0));
+        round_robin.record_health(url, false, 2);
+        assert!(round_robin.is_sidelined(0));
+        assert_eq!(
+            round_robin.get_next(),
+            Some("https://polygon-rpc.com".to_string())
+        );
+
+        round_robin.record_health(url, true, 2);
+        assert!(!round_robin.is_sidelined(0));
+    }
+
+    #[test]
+    fn test_failback_to_primary() {
+        let mut servers = create_test_servers();
+        servers[1].tier = Some(2);
+        servers[1].request_limit = 10;
+        servers[1].current_limit = 10;
+        let primary = "https://sepolia.drpc.org/".to_string();
+        let fallback = "https://polygon-rpc.com".to_string();
+
+        let round_robin = RoundRobin::new(servers.clone());
+        assert_eq!(round_robin.get_next(), Some(primary.clone()));
+        assert_eq!(round_robin.get_next(), Some(fallback.clone()));
+
+        // The primary recovered, but the pool holds on to the fallback.
+        round_robin.urls[0].lock().unwrap().current_limit = 1;
+        assert_eq!(round_robin.get_next(), Some(fallback.clone()));
+
+        let round_robin = RoundRobin::new(servers).with_failback(Some(Duration::ZERO));
+        assert_eq!(round_robin.get_next(), Some(primary.clone()));
+        assert_eq!(round_robin.get_next(), Some(fallback.clone()));
+        assert_eq!(round_robin.get_next(), Some(fallback));
+        round_robin.urls[0].lock().unwrap().current_limit = 1;
+        assert_eq!(round_robin.get_next(), Some(primary));
+    }
+
+    #[test]
+    fn test_burst_allowance() {
+        let servers = vec![RpcServer {
+            url: "https://sepolia.drpc.org/".to_string(),
+            request_limit: 1,
+            current_limit: 1,
+            burst_limit: 2,
+            ..Default::default()
+        }];
+        let round_robin = RoundRobin::new(servers);
+
+        assert!(round_robin.get_next().is_some());
+        assert!(round_robin.get_next().is_some());
+        assert!(round_robin.get_next().is_some());
+        assert_eq!(round_robin.get_next(), None);
+
+        // A window without traffic earns the burst allowance back.
+        let now = schedule::local_now(None);
+        let mut server = round_robin.urls[0].lock().unwrap();
+        server.refill(now);
+        assert_eq!(server.current_burst, 0);
+        server.refill(now);
+        assert_eq!(server.current_burst, 1);
+        assert_eq!(server.current_limit, 1);
+    }
+
+    #[test]
+    fn test_scheduled_limits() {
+        let mut server: RpcServer = toml::from_str(
+            r#"
+            url = "https://sepolia.drpc.org"
+            request_limit = 20
+            current_limit = 20
+            schedule = [{ days = ["mon"], from = "09:00", to = "18:00", request_limit = 5 }]
+            "#,
+        )
+        .unwrap();
+
+        // 2024-01-01 is a Monday
+        let monday = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        server.refill(monday.and_hms_opt(10, 0, 0).unwrap());
+        assert_eq!(server.current_limit, 5);
+        server.refill(monday.and_hms_opt(20, 0, 0).unwrap());
+        assert_eq!(server.current_limit, 20);
+    }
+
+    #[test]
+    fn test_sharded_tokens_across_threads() {
+        let servers = vec![RpcServer {
+            url: "https://sepolia.drpc.org/".to_string(),
+            request_limit: 100,
+            current_limit: 100,
+            ..Default::default()
+        }];
+        let round_robin = RoundRobin::new(servers);
+
+        let taken: usize = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| std::iter::from_fn(|| round_robin.get_next()).count()))
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+        assert_eq!(taken, 100);
+    }
+
+    #[test]
+    fn test_take_many() {
+        let servers = create_test_servers();
+        let round_robin = RoundRobin::new(servers);
+
+        let urls = round_robin.take_many(5);
+        assert_eq!(
+            urls,
+            vec![
+                "https://sepolia.drpc.org/".to_string(),
+                "https://polygon-rpc.com".to_string()
+            ]
+        );
+
+        assert!(round_robin.take_many(5).is_empty());
+    }
+
+    #[test]
+    fn test_quarantined_keys_are_skipped() {
+        let servers = create_test_servers();
+        let round_robin = RoundRobin::new(servers);
+
+        round_robin.record_status("https://sepolia.drpc.org/", StatusCode::UNAUTHORIZED, None);
+        assert_eq!(
+            round_robin.take_many(5),
+            vec!["https://polygon-rpc.com".to_string()]
+        );
+
+        let reports = round_robin.key_reports();
+        assert_eq!(reports[0].state, KeyState::Revoked);
+        assert_eq!(reports[0].unauthorized, 1);
+        assert_eq!(reports[1].state, KeyState::Healthy);
+    }
+
+    /// Marks requests sent, when told to, and never answers them.
+    #[derive(Debug)]
+    struct Hanging {
+        writes: bool,
+    }
+
+    impl UpstreamTransport for Hanging {
+        fn send<'a>(
+            &'a self,
+            _url: &'a str,
+            _request: &'a UpstreamRequest,
+            _timeout: Option<Duration>,
+            sent: &'a Sent,
+        ) -> transport::TransportFuture<'a> {
+            if self.writes {
+                sent.mark();
+            }
+            Box::pin(std::future::pending())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsent_requests_are_refunded() {
+        let url = "https://sepolia.drpc.org/";
+        for writes in [false, true] {
+            let mut servers = create_test_servers();
+            servers.truncate(1);
+            let round_robin =
+                RoundRobin::new(servers).with_transport(url, Arc::new(Hanging { writes }));
+            assert_eq!(round_robin.get_next().as_deref(), Some(url));
+
+            // The client disconnects while the request is still in flight.
+            let request = UpstreamRequest::json("{}");
+            let send = round_robin.send(url, &request, None);
+            assert!(time::timeout(Duration::from_millis(10), send)
+                .await
+                .is_err());
+
+            // Only the request which was never written gets its token back.
+            assert_eq!(round_robin.get_next().is_some(), !writes);
+        }
+    }
+
+    #[test]
+    fn test_pool_status() {
+        let round_robin = RoundRobin::new(create_test_servers());
+        assert_eq!(round_robin.pool_status(), PoolStatus::Available);
+
+        *round_robin.next_refill.lock().unwrap() = DateTime::from_timestamp(1_700_000_000, 0);
+        while round_robin.get_next().is_some() {}
+        assert_eq!(
+            round_robin.pool_status(),
+            PoolStatus::RateLimited {
+                until: DateTime::from_timestamp(1_700_000_000, 0)
+            }
+        );
+
+        for url in round_robin.server_urls() {
+            round_robin.record_health(&url, false, 1);
+        }
+        assert_eq!(round_robin.pool_status(), PoolStatus::Unhealthy);
+        assert_eq!(RoundRobin::new(vec![]).pool_status(), PoolStatus::Empty);
+    }
+
+    #[test]
+    fn test_backend_introspection() {
+        let mut servers = create_test_servers();
+        servers[0].name = Some("drpc".to_string());
+        let chains = HashMap::from([
+            ("sepolia".to_string(), Arc::new(RoundRobin::new(servers))),
+            (
+                "base".to_string(),
+                Arc::new(RoundRobin::new(create_test_servers())),
+            ),
+        ]);
+        let lb = LoadBalancer::new(Arc::new(chains));
+        assert_eq!(lb.chains(), ["base", "sepolia"]);
+        let backends = lb.backends("sepolia").unwrap();
+        assert_eq!(backends[1].url, "https://polygon-rpc.com");
+        assert!(lb.backends("mainnet").is_none());
+
+        lb.set_backend_enabled("sepolia", "drpc", false).unwrap();
+        let stats = lb.stats("sepolia").unwrap();
+        assert_eq!((stats[0].enabled, stats[1].enabled), (false, true));
+        let round_robin = &lb.load_balancers["sepolia"];
+        assert_eq!(
+            round_robin.get_next().as_deref(),
+            Some("https://polygon-rpc.com")
+        );
+        assert_eq!(round_robin.get_next(), None);
+
+        lb.set_backend_enabled("sepolia", "0", true).unwrap();
+        assert_eq!(
+            round_robin.get_next().as_deref(),
+            Some("https://sepolia.drpc.org/")
+        );
+        assert!(lb.set_backend_enabled("sepolia", "2", false).is_err());
+        assert!(lb.set_backend_enabled("mainnet", "0", false).is_err());
+
+        lb.set_algorithm("sepolia", Some(Strategy::Latency))
+            .unwrap();
+        assert_eq!(round_robin.algorithm(), Some(Strategy::Latency));
+        assert_eq!(lb.load_balancers["base"].algorithm(), None);
+        lb.set_algorithm("sepolia", None).unwrap();
+        assert_eq!(round_robin.algorithm(), None);
+        assert!(lb.set_algorithm("mainnet", None).is_err());
+    }
 }
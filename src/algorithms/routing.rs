@@ -0,0 +1,337 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    net::IpAddr,
+};
+
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+
+/// How a backend is chosen for a single request.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    #[default]
+    RoundRobin,
+    /// Prefer the backend with the lowest observed latency.
+    Latency,
+    /// Send to every backend with limit left and return the first success.
+    Broadcast,
+    /// Pick a backend uniformly at random.
+    Random,
+    /// Pick a backend at random, by the `weight` of each.
+    WeightedRandom,
+    /// Rotate through the backends, each taking a share of the requests of
+    /// every round as large as its `weight`, interleaved.
+    #[serde(alias = "weighted")]
+    WeightedRoundRobin,
+    /// Prefer the backend with the fewest requests waiting on it.
+    LeastConn,
+    /// Prefer the less loaded of two backends drawn at random.
+    #[serde(alias = "p2c")]
+    PowerOfTwo,
+    /// Prefer the backend scoring best on limit left, latency, error rate
+    /// and block lag, weighted by the chain's `health_weights`.
+    HealthWeighted,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 9] = [
+        Strategy::RoundRobin,
+        Strategy::Latency,
+        Strategy::Broadcast,
+        Strategy::Random,
+        Strategy::WeightedRandom,
+        Strategy::WeightedRoundRobin,
+        Strategy::LeastConn,
+        Strategy::PowerOfTwo,
+        Strategy::HealthWeighted,
+    ];
+
+    /// The name configs use for the strategy.
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::RoundRobin => "round_robin",
+            Strategy::Latency => "latency",
+            Strategy::Broadcast => "broadcast",
+            Strategy::Random => "random",
+            Strategy::WeightedRandom => "weighted_random",
+            Strategy::WeightedRoundRobin => "weighted_round_robin",
+            Strategy::LeastConn => "least_conn",
+            Strategy::PowerOfTwo => "power_of_two",
+            Strategy::HealthWeighted => "health_weighted",
+        }
+    }
+}
+
+/// Coarse request classes which usually deserve different strategies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MethodCategory {
+    Read,
+    Heavy,
+    Write,
+}
+
+const WRITE_METHODS: [&str; 3] = [
+    "eth_sendRawTransaction",
+    "eth_sendTransaction",
+    "sendrawtransaction",
+];
+
+const HEAVY_METHODS: [&str; 5] = [
+    "eth_getLogs",
+    "eth_getBlockReceipts",
+    "eth_estimateGas",
+    "eth_call",
+    "eth_feeHistory",
+];
+
+const HEAVY_PREFIXES: [&str; 2] = ["debug_", "trace_"];
+
+impl MethodCategory {
+    pub fn of(method: &str) -> Self {
+        if WRITE_METHODS.contains(&method) {
+            MethodCategory::Write
+        } else if HEAVY_METHODS.contains(&method)
+            || HEAVY_PREFIXES
+                .iter()
+                .any(|prefix| method.starts_with(prefix))
+        {
+            MethodCategory::Heavy
+        } else {
+            MethodCategory::Read
+        }
+    }
+}
+
+/// Per-chain `[routing]` table mapping request classes to strategies.
+///
+/// Exact method entries win over categories, categories over `default`.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct RoutingConfig {
+    #[serde(default)]
+    pub default: Strategy,
+    pub read: Option<Strategy>,
+    pub heavy: Option<Strategy>,
+    pub write: Option<Strategy>,
+    #[serde(default)]
+    pub methods: HashMap<String, Strategy>,
+    /// Rules letting callers state their intent in a header, the first
+    /// matching one applies.
+    #[serde(default)]
+    pub headers: Vec<HeaderRule>,
+    #[serde(default)]
+    pub health_weights: HealthWeights,
+    /// Weight of the newest sample in each backend's latency moving average,
+    /// from above 0 to 1: higher reacts faster to changes, lower rides out
+    /// outliers. 0.3 when unset.
+    pub latency_smoothing: Option<f64>,
+    /// Keep each client on one backend rather than following the strategy.
+    pub sticky: Option<Sticky>,
+}
+
+/// Sends the requests of a client to the same backend while it can take
+/// them, by hashing the client onto the backends: a backend joining or
+/// leaving only moves the clients hashed onto it.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct Sticky {
+    /// Header identifying the client, e.g. an API key. Its address when
+    /// unset, or when a request lacks the header.
+    pub header: Option<String>,
+}
+
+impl Sticky {
+    /// The hash of the client sending a request with `headers` from `client`.
+    pub fn key_of(&self, headers: &HeaderMap, client: Option<IpAddr>) -> Option<u64> {
+        let mut hasher = DefaultHasher::new();
+        let value = self
+            .header
+            .as_deref()
+            .and_then(|header| headers.get(header))
+            .map(|value| value.as_bytes());
+        match (value, client) {
+            (Some(value), _) => value.hash(&mut hasher),
+            (None, Some(client)) => client.hash(&mut hasher),
+            (None, None) => return None,
+        }
+        Some(hasher.finish())
+    }
+}
+
+/// How much each measurement counts in the score of the `health_weighted`
+/// strategy. Each is scored from 0 to 1 and the weighted mean picks the
+/// backend, e.g. `errors = 3` to avoid flaky providers above all.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct HealthWeights {
+    /// Share of the window's steady limit left.
+    pub limit: f64,
+    /// Latency of the fastest backend over the backend's own.
+    pub latency: f64,
+    /// Share of recent attempts which succeeded.
+    pub errors: f64,
+    /// Closeness to the highest head of the chain, from health checks.
+    pub lag: f64,
+}
+
+impl Default for HealthWeights {
+    fn default() -> Self {
+        Self {
+            limit: 1.0,
+            latency: 1.0,
+            errors: 1.0,
+            lag: 1.0,
+        }
+    }
+}
+
+/// Sends requests whose `header` is `value` to the backends tagged `tag`,
+/// and with the settings of the `[server.sla_classes]` entry `sla_class`
+/// unless their principal has one, e.g. `X-Routing-Hint: archive` to
+/// archive nodes or `X-Priority: low` with few retries.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct HeaderRule {
+    pub header: String,
+    /// Compared ignoring case.
+    pub value: String,
+    pub tag: Option<String>,
+    pub sla_class: Option<String>,
+}
+
+impl RoutingConfig {
+    pub fn strategy_for(&self, method: Option<&str>) -> Strategy {
+        self.strategy_with(method, self.default)
+    }
+
+    /// The strategy for `method`, with `default` standing in for the
+    /// configured one, e.g. after it was switched at runtime.
+    pub fn strategy_with(&self, method: Option<&str>, default: Strategy) -> Strategy {
+        let Some(method) = method else {
+            return default;
+        };
+
+        if let Some(strategy) = self.methods.get(method) {
+            return *strategy;
+        }
+
+        let category = match MethodCategory::of(method) {
+            MethodCategory::Read => self.read,
+            MethodCategory::Heavy => self.heavy,
+            MethodCategory::Write => self.write,
+        };
+        category.unwrap_or(default)
+    }
+
+    /// The first header rule the request `headers` match.
+    pub fn header_rule(&self, headers: &HeaderMap) -> Option<&HeaderRule> {
+        self.headers.iter().find(|rule| {
+            headers
+                .get_all(rule.header.as_str())
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .any(|value| value.trim().eq_ignore_ascii_case(&rule.value))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_method_categories() {
+        assert_eq!(MethodCategory::of("eth_blockNumber"), MethodCategory::Read);
+        assert_eq!(MethodCategory::of("eth_getLogs"), MethodCategory::Heavy);
+        assert_eq!(
+            MethodCategory::of("debug_traceTransaction"),
+            MethodCategory::Heavy
+        );
+        assert_eq!(
+            MethodCategory::of("eth_sendRawTransaction"),
+            MethodCategory::Write
+        );
+    }
+
+    #[test]
+    fn test_sticky_key() {
+        let sticky = Sticky {
+            header: Some("x-api-key".to_string()),
+        };
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut headers = HeaderMap::new();
+        let by_address = sticky.key_of(&headers, Some(client));
+        assert!(by_address.is_some());
+        assert_eq!(sticky.key_of(&headers, None), None);
+        headers.insert("x-api-key", "alice".parse().unwrap());
+        let by_key = sticky.key_of(&headers, Some(client));
+        assert_ne!(by_key, by_address);
+        assert_eq!(sticky.key_of(&headers, None), by_key);
+    }
+
+    #[test]
+    fn test_header_rule() {
+        let routing: RoutingConfig = toml::from_str(
+            r#"
+            headers = [
+                { header = "X-Routing-Hint", value = "archive", tag = "archive" },
+                { header = "X-Priority", value = "low", sla_class = "batch" },
+            ]
+            "#,
+        )
+        .unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(routing.header_rule(&headers), None);
+        headers.insert("x-priority", "LOW".parse().unwrap());
+        let rule = routing.header_rule(&headers).unwrap();
+        assert_eq!(rule.sla_class.as_deref(), Some("batch"));
+        headers.insert("x-routing-hint", "archive".parse().unwrap());
+        let rule = routing.header_rule(&headers).unwrap();
+        assert_eq!(rule.tag.as_deref(), Some("archive"));
+    }
+
+    #[test]
+    fn test_strategy_precedence() {
+        let routing: RoutingConfig = toml::from_str(
+            r#"
+            default = "round_robin"
+            read = "latency"
+            write = "broadcast"
+            methods = { eth_chainId = "round_robin" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            routing.strategy_for(Some("eth_blockNumber")),
+            Strategy::Latency
+        );
+        assert_eq!(
+            routing.strategy_for(Some("eth_chainId")),
+            Strategy::RoundRobin
+        );
+        assert_eq!(
+            routing.strategy_for(Some("eth_sendRawTransaction")),
+            Strategy::Broadcast
+        );
+        assert_eq!(
+            routing.strategy_for(Some("eth_getLogs")),
+            Strategy::RoundRobin
+        );
+        assert_eq!(routing.strategy_for(None), Strategy::RoundRobin);
+
+        // A default switched at runtime leaves method and category rules be.
+        assert_eq!(
+            routing.strategy_with(None, Strategy::LeastConn),
+            Strategy::LeastConn
+        );
+        assert_eq!(
+            routing.strategy_with(Some("eth_getLogs"), Strategy::LeastConn),
+            Strategy::LeastConn
+        );
+        assert_eq!(
+            routing.strategy_with(Some("eth_chainId"), Strategy::LeastConn),
+            Strategy::RoundRobin
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// A recurring window during which a backend runs with different limits,
+/// e.g. capping a shared key during business hours.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(try_from = "RawLimitWindow")]
+pub struct LimitWindow {
+    /// Days the window applies to, every day when empty.
+    pub days: Vec<Weekday>,
+    pub from: NaiveTime,
+    pub to: NaiveTime,
+    pub request_limit: u32,
+    pub burst_limit: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RawLimitWindow {
+    #[serde(default)]
+    days: Vec<String>,
+    from: String,
+    to: String,
+    request_limit: u32,
+    burst_limit: Option<u32>,
+}
+
+impl TryFrom<RawLimitWindow> for LimitWindow {
+    type Error = String;
+
+    fn try_from(raw: RawLimitWindow) -> Result<Self, Self::Error> {
+        let days = raw
+            .days
+            .iter()
+            .map(|day| {
+                day.parse::<Weekday>()
+                    .map_err(|_| format!("Invalid day in schedule: {}", day))
+            })
+            .collect::<Result<_, _>>()?;
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| format!("Invalid time in schedule, expected HH:MM: {}", time))
+        };
+
+        Ok(LimitWindow {
+            days,
+            from: parse_time(&raw.from)?,
+            to: parse_time(&raw.to)?,
+            request_limit: raw.request_limit,
+            burst_limit: raw.burst_limit,
+        })
+    }
+}
+
+impl LimitWindow {
+    /// Windows whose `to` is before `from` wrap around midnight, the day
+    /// filter applies to the day the window starts on.
+    pub fn contains(&self, now: NaiveDateTime) -> bool {
+        let time = now.time();
+        let (in_window, day) = if self.from <= self.to {
+            (self.from <= time && time < self.to, now.weekday())
+        } else if time >= self.from {
+            (true, now.weekday())
+        } else {
+            (time < self.to, now.weekday().pred())
+        };
+
+        in_window && (self.days.is_empty() || self.days.contains(&day))
+    }
+}
+
+/// Returns the first window of `schedule` active at `now`.
+pub fn active_window(schedule: &[LimitWindow], now: NaiveDateTime) -> Option<&LimitWindow> {
+    schedule.iter().find(|window| window.contains(now))
+}
+
+/// Current wall-clock time in `timezone`, or in the host's local timezone.
+pub fn local_now(timezone: Option<Tz>) -> NaiveDateTime {
+    match timezone {
+        Some(timezone) => Utc::now().with_timezone(&timezone).naive_local(),
+        None => Local::now().naive_local(),
+    }
+}
+
+pub fn parse_timezone(timezone: &str) -> Result<Tz, String> {
+    timezone
+        .parse()
+        .map_err(|_| format!("Invalid timezone: {}", timezone))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        // 2024-01-01 is a Monday
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_business_hours_window() {
+        let window: LimitWindow = toml::from_str(
+            r#"
+            days = ["mon", "tue", "wed", "thu", "fri"]
+            from = "09:00"
+            to = "18:00"
+            request_limit = 5
+            "#,
+        )
+        .unwrap();
+
+        assert!(window.contains(at(1, 10)));
+        assert!(!window.contains(at(1, 18)));
+        assert!(!window.contains(at(6, 10)));
+    }
+
+    #[test]
+    fn test_window_wrapping_midnight() {
+        let window: LimitWindow = toml::from_str(
+            r#"
+            days = ["fri"]
+            from = "22:00"
+            to = "06:00"
+            request_limit = 100
+            "#,
+        )
+        .unwrap();
+
+        assert!(window.contains(at(5, 23)));
+        assert!(window.contains(at(6, 2)));
+        assert!(!window.contains(at(6, 23)));
+        assert!(!window.contains(at(5, 12)));
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        let error = toml::from_str::<LimitWindow>(
+            r#"
+            from = "9am"
+            to = "18:00"
+            request_limit = 5
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("expected HH:MM"));
+        assert!(parse_timezone("Europe/Berlin").is_ok());
+        assert!(parse_timezone("Mars/Olympus").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+    thread,
+};
+
+/// Hands out shard indexes to worker threads on first use.
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Index of the shard owned by the calling thread. Tokio workers live for the
+/// whole process, so each of them keeps hitting the same shard.
+fn shard_index() -> usize {
+    SHARD.with(|shard| {
+        shard.get().unwrap_or_else(|| {
+            let index = NEXT_SHARD.fetch_add(1, Ordering::Relaxed);
+            shard.set(Some(index));
+            index
+        })
+    })
+}
+
+/// Number of shards used per backend, one per available core.
+pub fn shard_count() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// How many leases a full limit window is split into per shard. Smaller
+/// leases strand fewer tokens on idle workers, larger ones lock less often.
+const LEASES_PER_SHARD: u32 = 4;
+
+/// Kept on its own cache line so workers decrementing neighbouring shards do
+/// not invalidate each other's caches.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Shard(AtomicU32);
+
+/// Request tokens of one backend leased out to worker threads.
+///
+/// Workers lease a batch of tokens from the backend's limit and then spend
+/// them with a single atomic operation on their own shard. Tokens only move
+/// back to the backend when its limit is refilled, see [`ShardedBudget::drain`].
+#[derive(Debug)]
+pub struct ShardedBudget {
+    shards: Box<[Shard]>,
+}
+
+impl ShardedBudget {
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Shard::default()).collect(),
+        }
+    }
+
+    /// Number of tokens a worker leases at once from a backend allowing
+    /// `request_limit` requests per window.
+    pub fn lease_size(&self, request_limit: u32) -> u32 {
+        let leases = (self.shards.len() as u32).saturating_mul(LEASES_PER_SHARD);
+        (request_limit / leases).max(1)
+    }
+
+    fn local(&self) -> &AtomicU32 {
+        &self.shards[shard_index() % self.shards.len()].0
+    }
+
+    /// Takes one token leased to the calling thread.
+    pub fn take_local(&self) -> bool {
+        take_one(self.local())
+    }
+
+    /// Leases `tokens` to the calling thread.
+    pub fn deposit(&self, tokens: u32) {
+        self.local().fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    /// Takes one token leased to any thread. Only used once the backend's
+    /// own limit is exhausted, so no token is stranded on an idle worker.
+    pub fn steal(&self) -> bool {
+        self.shards.iter().any(|shard| take_one(&shard.0))
+    }
+
+    pub fn has_tokens(&self) -> bool {
+        self.shards
+            .iter()
+            .any(|shard| shard.0.load(Ordering::Relaxed) > 0)
+    }
+
+    /// Tokens leased out and not spent yet.
+    pub fn available(&self) -> u32 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Takes back every leased token and returns how many there were.
+    pub fn drain(&self) -> u32 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.swap(0, Ordering::Relaxed))
+            .sum()
+    }
+}
+
+fn take_one(tokens: &AtomicU32) -> bool {
+    tokens
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |tokens| {
+            tokens.checked_sub(1)
+        })
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leased_tokens_are_spent_and_drained() {
+        let budget = ShardedBudget::new(4);
+        assert!(!budget.take_local());
+        assert_eq!(budget.lease_size(100), 6);
+        assert_eq!(budget.lease_size(1), 1);
+
+        budget.deposit(3);
+        assert!(budget.take_local());
+        assert!(budget.has_tokens());
+        assert_eq!(budget.drain(), 2);
+        assert!(!budget.has_tokens());
+    }
+
+    #[test]
+    fn test_steal_from_other_threads() {
+        let budget = ShardedBudget::new(64);
+        thread::scope(|scope| {
+            scope.spawn(|| budget.deposit(1));
+        });
+
+        assert!(budget.steal());
+        assert!(!budget.steal());
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    fmt::Debug,
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use super::{round_robin::RoundRobin, routing::Strategy};
+
+/// Picks the backend of a request among the servers of a pool.
+///
+/// Every [`Strategy`] a chain can configure is backed by one, the built-in
+/// implementation below unless another was plugged in with
+/// `RoundRobin::with_balancer`. The pool keeps one instance of each, so
+/// implementations may keep state of their own across requests, and decides
+/// which servers are `eligible`: tiers, regions, tags, latency budgets and
+/// predictive spillover are applied alike whatever the strategy.
+pub trait BalancingStrategy: Send + Sync + Debug {
+    /// Takes a token of one of the `eligible` servers of `pool`, by index,
+    /// and returns its url. `None` when none of them can take the request.
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String>;
+
+    /// Told the outcome of every attempt on the server at `index`, for
+    /// strategies learning from them beyond the pool's own stats.
+    fn report_result(&self, _index: usize, _succeeded: bool) {}
+}
+
+/// The built-in implementation of `strategy`.
+pub fn built_in(strategy: Strategy) -> Arc<dyn BalancingStrategy> {
+    match strategy {
+        // Broadcasts fan out in the handler, single picks rotate.
+        Strategy::RoundRobin | Strategy::Broadcast => Arc::new(Rotation),
+        Strategy::Latency => Arc::new(Fastest),
+        Strategy::Random => Arc::new(Uniform),
+        Strategy::WeightedRandom => Arc::new(Weighted),
+        Strategy::WeightedRoundRobin => Arc::new(WeightedRotation::default()),
+        Strategy::LeastConn => Arc::new(LeastLoaded),
+        Strategy::PowerOfTwo => Arc::new(TwoChoices),
+        Strategy::HealthWeighted => Arc::new(Healthiest),
+    }
+}
+
+/// Rotates through the servers on the pool's shared index.
+#[derive(Debug)]
+pub struct Rotation;
+
+impl BalancingStrategy for Rotation {
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+        pool.next_among(eligible)
+    }
+}
+
+/// Prefers the server with the lowest observed latency.
+#[derive(Debug)]
+pub struct Fastest;
+
+impl BalancingStrategy for Fastest {
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+        pool.fastest_among(eligible)
+    }
+}
+
+/// Draws a server with equal chances.
+#[derive(Debug)]
+pub struct Uniform;
+
+impl BalancingStrategy for Uniform {
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+        pool.random_among(eligible)
+    }
+}
+
+/// Draws a server with chances proportional to its `weight`.
+#[derive(Debug)]
+pub struct Weighted;
+
+impl BalancingStrategy for Weighted {
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+        pool.weighted_among(eligible)
+    }
+}
+
+/// Rotates through the servers by `weight` the way nginx does, spreading
+/// each server's requests over the rotation instead of sending them in a row:
+/// weights 2 and 1 go A, B, A rather than A, A, B, which keeps heavy servers
+/// clear of burst limits. Servers weighted `0`, and burst allowance, are only
+/// used once every weighted server ran out, as by [`RoundRobin::next_among`].
+#[derive(Debug, Default)]
+pub struct WeightedRotation {
+    /// How far each server is owed a request, raised by its weight on every
+    /// pick and lowered by the total weight when it is picked.
+    current: Mutex<Vec<i64>>,
+}
+
+impl BalancingStrategy for WeightedRotation {
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+        let candidates: Vec<usize> = (0..pool.urls.len())
+            .filter(|&i| pool.weights[i] > 0 && eligible(i) && !pool.is_sidelined(i))
+            .collect();
+        {
+            let mut current = self.current.lock().unwrap();
+            current.resize(pool.urls.len(), 0);
+            let mut candidates = candidates;
+            // Servers out of tokens sit the pick out, the others share it.
+            while !candidates.is_empty() {
+                let total: i64 = candidates.iter().map(|&i| pool.weights[i] as i64).sum();
+                for &i in &candidates {
+                    current[i] += pool.weights[i] as i64;
+                }
+                let best = *candidates
+                    .iter()
+                    .rev()
+                    .max_by_key(|&&i| current[i])
+                    .unwrap();
+                if let Some(url) = pool.take_steady(best) {
+                    current[best] -= total;
+                    return Some(url);
+                }
+                // Undo the round for a server which ran out meanwhile.
+                for &i in &candidates {
+                    current[i] -= pool.weights[i] as i64;
+                }
+                candidates.retain(|&i| i != best);
+            }
+        }
+        pool.next_among(eligible)
+    }
+}
+
+/// Prefers the server with the fewest requests in flight.
+#[derive(Debug)]
+pub struct LeastLoaded;
+
+impl BalancingStrategy for LeastLoaded {
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+        pool.least_loaded_among(eligible)
+    }
+}
+
+/// Draws two servers at random and takes the one with fewer requests in
+/// flight, the lower latency on a tie. Nearly as good as comparing every
+/// server, while looking at two and sharing no index between requests.
+#[derive(Debug)]
+pub struct TwoChoices;
+
+impl TwoChoices {
+    fn load_of(pool: &RoundRobin, i: usize) -> (usize, f64) {
+        let in_flight = pool.in_flight[i].load(Ordering::Relaxed);
+        let latency = pool.stats[i].lock().unwrap().latency_ms.unwrap_or(0.0);
+        (in_flight, latency)
+    }
+}
+
+impl BalancingStrategy for TwoChoices {
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+        let candidates: Vec<usize> = (0..pool.urls.len())
+            .filter(|&i| eligible(i) && !pool.is_sidelined(i) && pool.limiters[i].has_capacity())
+            .collect();
+        let chosen = match candidates.len() {
+            0 => None,
+            1 => Some(candidates[0]),
+            len => {
+                let first = rand::random_range(0..len);
+                let second = (first + rand::random_range(1..len)) % len;
+                let (a, b) = (candidates[first], candidates[second]);
+                let (load_a, load_b) = (Self::load_of(pool, a), Self::load_of(pool, b));
+                Some(if load_b < load_a { b } else { a })
+            }
+        };
+        chosen
+            .and_then(|i| pool.try_take(i))
+            .or_else(|| pool.next_among(eligible))
+    }
+}
+
+/// Prefers the server with the best health score.
+#[derive(Debug)]
+pub struct Healthiest;
+
+impl BalancingStrategy for Healthiest {
+    fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+        pool.healthiest_among(eligible)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::RpcServer;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    /// Always takes the last eligible server, counting failures.
+    #[derive(Debug, Default)]
+    struct Last {
+        failures: AtomicUsize,
+    }
+
+    impl BalancingStrategy for Last {
+        fn get_next(&self, pool: &RoundRobin, eligible: &dyn Fn(usize) -> bool) -> Option<String> {
+            let i = (0..pool.urls.len()).rev().find(|&i| eligible(i))?;
+            pool.try_take(i)
+        }
+
+        fn report_result(&self, _index: usize, succeeded: bool) {
+            if !succeeded {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn pool(weights: &[u32], limit: u32) -> RoundRobin {
+        let servers = weights
+            .iter()
+            .enumerate()
+            .map(|(i, weight)| RpcServer {
+                url: format!("https://{}.example", i),
+                request_limit: limit,
+                current_limit: limit,
+                weight: Some(*weight),
+                ..Default::default()
+            })
+            .collect();
+        RoundRobin::new(servers)
+    }
+
+    #[test]
+    fn test_weighted_rotation() {
+        let round_robin = pool(&[3, 1, 0], 4);
+        let balancer = round_robin.balancer(Strategy::WeightedRoundRobin);
+        let picks: Vec<String> = (0..8)
+            .map(|_| round_robin.get_with(&*balancer).unwrap())
+            .collect();
+        let expected = [0, 0, 1, 0, 0, 1, 1, 1].map(|i| round_robin.endpoints[i].clone());
+        assert_eq!(picks, expected);
+
+        // The unweighted server takes over once the others ran out.
+        assert_eq!(
+            round_robin.get_with(&*balancer).as_ref(),
+            Some(&round_robin.endpoints[2])
+        );
+
+        // The heavier server's picks are spread over the rotation.
+        let round_robin = pool(&[2, 1], 100);
+        let balancer = round_robin.balancer(Strategy::WeightedRoundRobin);
+        let picks: Vec<String> = (0..6)
+            .map(|_| round_robin.get_with(&*balancer).unwrap())
+            .collect();
+        let expected = [0, 1, 0, 0, 1, 0].map(|i| round_robin.endpoints[i].clone());
+        assert_eq!(picks, expected);
+    }
+
+    #[test]
+    fn test_two_choices() {
+        let round_robin = pool(&[1, 1], 100);
+        let balancer = round_robin.balancer(Strategy::PowerOfTwo);
+        // With two servers both are always drawn, so the idle one wins.
+        round_robin.in_flight[0].store(3, Ordering::Relaxed);
+        for _ in 0..10 {
+            assert_eq!(
+                round_robin.get_with(&*balancer).as_ref(),
+                Some(&round_robin.endpoints[1])
+            );
+        }
+        round_robin.in_flight[1].store(3, Ordering::Relaxed);
+        round_robin.record_latency(&round_robin.endpoints[1], Duration::from_millis(50));
+        round_robin.record_latency(&round_robin.endpoints[0], Duration::from_millis(10));
+        assert_eq!(
+            round_robin.get_with(&*balancer).as_ref(),
+            Some(&round_robin.endpoints[0])
+        );
+    }
+
+    #[test]
+    fn test_custom_balancer() {
+        let servers = ["https://a.example", "https://b.example"]
+            .iter()
+            .map(|url| RpcServer {
+                url: url.to_string(),
+                request_limit: 2,
+                current_limit: 2,
+                ..Default::default()
+            })
+            .collect();
+        let last = Arc::new(Last::default());
+        let round_robin =
+            RoundRobin::new(servers).with_balancer(Strategy::RoundRobin, last.clone());
+
+        assert_eq!(round_robin.get_next().as_deref(), Some("https://b.example"));
+        assert_eq!(round_robin.get_next().as_deref(), Some("https://b.example"));
+        assert_eq!(round_robin.get_next(), None);
+        // Other strategies keep their built-in implementation.
+        assert_eq!(
+            round_robin.get_fastest().as_deref(),
+            Some("https://a.example")
+        );
+
+        round_robin.record_outcome("https://b.example", false);
+        assert_eq!(last.failures.load(Ordering::Relaxed), 1);
+    }
+}
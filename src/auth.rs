@@ -0,0 +1,456 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::future::BoxFuture;
+use reqwest::{
+    header::{HeaderMap, RETRY_AFTER},
+    Method, StatusCode,
+};
+use serde::Deserialize;
+
+pub mod jwt;
+
+use jwt::{JwtAuth, JwtConfig};
+
+/// Paths served without authentication, so liveness probes and metric
+/// scrapers need no credentials.
+const PUBLIC_PATHS: [&str; 2] = ["/", "/metrics"];
+
+/// First path segments which are not chains, e.g. `/admin/top-consumers`.
+const NON_CHAIN_SEGMENTS: [&str; 2] = ["admin", "metrics"];
+
+/// Who sent a request and what it may access.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    /// Name requests are accounted to, e.g. in `/admin/top-consumers`.
+    pub id: String,
+    /// Chains the principal may use, every chain when unset.
+    pub chains: Option<Vec<String>>,
+    /// Requests the principal may send per second, unlimited when unset.
+    pub requests_per_second: Option<u32>,
+    /// Name of the `[server.sla_classes]` entry its requests are sent with,
+    /// the chain's settings when unset.
+    pub sla_class: Option<String>,
+    /// May call the `/admin` routes.
+    pub admin: bool,
+}
+
+impl Principal {
+    pub fn may_use(&self, chain: &str) -> bool {
+        self.chains
+            .as_ref()
+            .is_none_or(|chains| chains.iter().any(|allowed| allowed == chain))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthError {
+    /// The request carries no credentials.
+    Missing,
+    /// The credentials were rejected, for the reason given.
+    Invalid(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Missing => f.write_str("missing credentials"),
+            AuthError::Invalid(reason) => write!(f, "invalid credentials: {}", reason),
+        }
+    }
+}
+
+pub type AuthFuture<'a> = BoxFuture<'a, Result<Principal, AuthError>>;
+
+/// Validates the credentials of a request and tells who sent it.
+///
+/// The server runs the one built from `[server.auth]`, the built-in
+/// [`ApiKeyAuth`] or [`JwtAuth`]. Embedders can wrap their own in a [`Gate`]
+/// for [`require`], e.g. one checking tokens of an internal SSO, which may
+/// call out to another service as authentication is async.
+pub trait Authenticator: Send + Sync + fmt::Debug {
+    fn authenticate<'a>(&'a self, headers: &'a HeaderMap) -> AuthFuture<'a>;
+}
+
+/// The `[server.auth]` section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthConfig {
+    ApiKey {
+        #[serde(default = "default_header")]
+        header: String,
+        /// Accepted keys and what they grant.
+        keys: HashMap<String, KeyGrant>,
+    },
+    Jwt(JwtConfig),
+}
+
+fn default_header() -> String {
+    "x-api-key".to_string()
+}
+
+/// What an API key grants.
+#[derive(Clone, Debug, Deserialize)]
+pub struct KeyGrant {
+    pub name: String,
+    pub chains: Option<Vec<String>>,
+    pub requests_per_second: Option<u32>,
+    pub sla_class: Option<String>,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// A `[server.sla_classes]` entry: how hard the requests of principals in
+/// the class are pushed upstream, e.g. short timeouts and hedging for
+/// interactive clients, long timeouts and few retries for batch jobs.
+/// Unset settings keep the chain's.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct SlaClass {
+    /// Timeout of a single upstream attempt.
+    pub timeout_ms: Option<u64>,
+    /// Upstream attempts per request.
+    pub max_retries: Option<u32>,
+    /// Send a second attempt to another backend when the first has not
+    /// answered after this long, and use whichever answers first.
+    pub hedge_after_ms: Option<u64>,
+}
+
+impl SlaClass {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_ms.map(Duration::from_millis)
+    }
+
+    pub fn hedge_after(&self) -> Option<Duration> {
+        self.hedge_after_ms.map(Duration::from_millis)
+    }
+}
+
+impl AuthConfig {
+    /// Names of the SLA classes the keys or policies refer to.
+    pub fn sla_classes(&self) -> Vec<&str> {
+        let classes: Vec<&Option<String>> = match self {
+            AuthConfig::ApiKey { keys, .. } => {
+                keys.values().map(|grant| &grant.sla_class).collect()
+            }
+            AuthConfig::Jwt(config) => config
+                .policies
+                .values()
+                .map(|policy| &policy.sla_class)
+                .collect(),
+        };
+        classes.into_iter().flatten().map(String::as_str).collect()
+    }
+
+    /// Whether any key or policy grants `admin`, without which the `/admin`
+    /// routes are not served at all.
+    pub fn has_admin(&self) -> bool {
+        match self {
+            AuthConfig::ApiKey { keys, .. } => keys.values().any(|grant| grant.admin),
+            AuthConfig::Jwt(config) => config.policies.values().any(|policy| policy.admin),
+        }
+    }
+
+    pub fn build(&self) -> Result<Arc<dyn Authenticator>, String> {
+        match self {
+            AuthConfig::ApiKey { header, keys } => Ok(Arc::new(ApiKeyAuth::new(
+                header,
+                keys.iter()
+                    .map(|(key, grant)| {
+                        let principal = Principal {
+                            id: grant.name.clone(),
+                            chains: grant.chains.clone(),
+                            requests_per_second: grant.requests_per_second,
+                            sla_class: grant.sla_class.clone(),
+                            admin: grant.admin,
+                        };
+                        (key.clone(), principal)
+                    })
+                    .collect(),
+            ))),
+            AuthConfig::Jwt(config) => Ok(Arc::new(JwtAuth::new(config)?)),
+        }
+    }
+}
+
+/// Accepts requests carrying one of the configured keys in `header`.
+#[derive(Debug)]
+pub struct ApiKeyAuth {
+    header: String,
+    keys: HashMap<String, Principal>,
+}
+
+impl ApiKeyAuth {
+    pub fn new(header: &str, keys: HashMap<String, Principal>) -> Self {
+        Self {
+            header: header.to_string(),
+            keys,
+        }
+    }
+}
+
+impl Authenticator for ApiKeyAuth {
+    fn authenticate<'a>(&'a self, headers: &'a HeaderMap) -> AuthFuture<'a> {
+        let result = match headers
+            .get(self.header.as_str())
+            .and_then(|value| value.to_str().ok())
+        {
+            None => Err(AuthError::Missing),
+            Some(key) => self
+                .keys
+                .get(key)
+                .cloned()
+                .ok_or_else(|| AuthError::Invalid("unknown API key".to_string())),
+        };
+        Box::pin(async move { result })
+    }
+}
+
+/// Principals whose request counts are kept before stale ones are dropped.
+const MAX_TRACKED: usize = 10_000;
+
+/// An authenticator together with the request rates of the principals it
+/// admitted, the state of [`require`].
+#[derive(Debug)]
+pub struct Gate {
+    authenticator: Arc<dyn Authenticator>,
+    /// Second and requests in it of each rate limited principal.
+    usage: Mutex<HashMap<String, (u64, u32)>>,
+}
+
+impl Gate {
+    pub fn new(authenticator: Arc<dyn Authenticator>) -> Self {
+        Self {
+            authenticator,
+            usage: Mutex::default(),
+        }
+    }
+
+    /// Counts a request of `principal` in the second `now`, returns `false`
+    /// once it is over its `requests_per_second`.
+    fn admit(&self, principal: &Principal, now: u64) -> bool {
+        let Some(limit) = principal.requests_per_second else {
+            return true;
+        };
+        let mut usage = self.usage.lock().unwrap();
+        if usage.len() >= MAX_TRACKED {
+            usage.retain(|_, (second, _)| *second == now);
+        }
+        let (second, count) = usage.entry(principal.id.clone()).or_insert((now, 0));
+        if *second != now {
+            *second = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count <= limit
+    }
+}
+
+/// Middleware authenticating every request but the public paths. The
+/// [`Principal`] is added to the request's extensions, requests to chains it
+/// may not use are refused with `403` and ones over its rate with `429`.
+pub async fn require(State(gate): State<Arc<Gate>>, mut request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    // Browsers send CORS preflights without credentials.
+    if PUBLIC_PATHS.contains(&path) || request.method() == Method::OPTIONS {
+        return next.run(request).await;
+    }
+
+    let principal = match gate.authenticator.authenticate(request.headers()).await {
+        Ok(principal) => principal,
+        Err(e) => return refuse(StatusCode::UNAUTHORIZED, e.to_string()),
+    };
+    let segment = path.trim_start_matches('/').split('/').next().unwrap_or("");
+    if !segment.is_empty() && !NON_CHAIN_SEGMENTS.contains(&segment) && !principal.may_use(segment)
+    {
+        return refuse(
+            StatusCode::FORBIDDEN,
+            format!("{} may not use chain {}", principal.id, segment),
+        );
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if !gate.admit(&principal, now) {
+        let mut response = refuse(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("{} is over its request rate", principal.id),
+        );
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, reqwest::header::HeaderValue::from_static("1"));
+        return response;
+    }
+
+    request.extensions_mut().insert(principal);
+    next.run(request).await
+}
+
+/// Middleware of the `/admin` routes, layered inside [`require`] whose
+/// principal has to be granted `admin`. Other principals get `403`.
+pub async fn require_admin(request: Request, next: Next) -> Response {
+    match request.extensions().get::<Principal>() {
+        Some(principal) if principal.admin => next.run(request).await,
+        Some(principal) => refuse(
+            StatusCode::FORBIDDEN,
+            format!("{} may not use the admin routes", principal.id),
+        ),
+        None => refuse(StatusCode::UNAUTHORIZED, AuthError::Missing.to_string()),
+    }
+}
+
+fn refuse(status: StatusCode, message: String) -> Response {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(message))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_keys() {
+        let config: AuthConfig = toml::from_str(
+            r#"
+            type = "api_key"
+            keys = { "k-1" = { name = "indexer", chains = ["sepolia"] } }
+            "#,
+        )
+        .unwrap();
+        let auth = config.build().unwrap();
+        let authenticate = |headers: &HeaderMap| {
+            futures_util::FutureExt::now_or_never(auth.authenticate(headers)).unwrap()
+        };
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(authenticate(&headers), Err(AuthError::Missing));
+        headers.insert("x-api-key", "k-2".parse().unwrap());
+        assert!(matches!(authenticate(&headers), Err(AuthError::Invalid(_))));
+        headers.insert("x-api-key", "k-1".parse().unwrap());
+        assert_eq!(authenticate(&headers).unwrap().id, "indexer");
+        assert!(!config.has_admin());
+    }
+
+    #[test]
+    fn test_request_rates() {
+        let gate = Gate::new(Arc::new(ApiKeyAuth::new("x-api-key", HashMap::new())));
+        let principal = Principal {
+            id: "trial".to_string(),
+            chains: None,
+            requests_per_second: Some(2),
+            sla_class: None,
+            admin: false,
+        };
+
+        assert!(gate.admit(&principal, 10));
+        assert!(gate.admit(&principal, 10));
+        assert!(!gate.admit(&principal, 10));
+        assert!(gate.admit(&principal, 11));
+        let unlimited = Principal {
+            requests_per_second: None,
+            ..principal
+        };
+        assert!(gate.admit(&unlimited, 11));
+    }
+
+    #[tokio::test]
+    async fn test_require() {
+        let auth: Arc<dyn Authenticator> = Arc::new(ApiKeyAuth::new(
+            "x-api-key",
+            HashMap::from([
+                (
+                    "k-1".to_string(),
+                    Principal {
+                        id: "indexer".to_string(),
+                        chains: Some(vec!["sepolia".to_string()]),
+                        requests_per_second: None,
+                        sla_class: None,
+                        admin: false,
+                    },
+                ),
+                (
+                    "k-ops".to_string(),
+                    Principal {
+                        id: "ops".to_string(),
+                        chains: None,
+                        requests_per_second: None,
+                        sla_class: None,
+                        admin: true,
+                    },
+                ),
+            ]),
+        ));
+        let app = axum::Router::new()
+            .route("/", axum::routing::get(|| async { "home" }))
+            .route(
+                "/admin/chaos",
+                axum::routing::post(|| async { "chaos" })
+                    .route_layer(axum::middleware::from_fn(require_admin)),
+            )
+            .route(
+                "/{*path}",
+                axum::routing::post(|request: Request| async move {
+                    request.extensions().get::<Principal>().unwrap().id.clone()
+                }),
+            )
+            .layer(axum::middleware::from_fn_with_state(
+                Arc::new(Gate::new(auth)),
+                require,
+            ));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let send = |path: &str, key: Option<&str>| {
+            let mut request = client.post(format!("{}{}", url, path));
+            if let Some(key) = key {
+                request = request.header("x-api-key", key);
+            }
+            request.send()
+        };
+
+        assert_eq!(
+            client.get(&url).send().await.unwrap().status(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send("/sepolia", None).await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send("/mainnet", Some("k-1")).await.unwrap().status(),
+            StatusCode::FORBIDDEN
+        );
+        let response = send("/sepolia", Some("k-1")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "indexer");
+
+        // Client keys may not use the admin routes, however many chains
+        // they may use.
+        assert_eq!(
+            send("/admin/chaos", Some("k-1")).await.unwrap().status(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            send("/admin/chaos", None).await.unwrap().status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let response = send("/admin/chaos", Some("k-ops")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use openssl::{
+    bn::BigNum,
+    hash::MessageDigest,
+    memcmp,
+    pkey::{PKey, Public},
+    rsa::Rsa,
+    sign::{Signer, Verifier},
+};
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{AuthError, AuthFuture, Authenticator, Principal};
+use crate::transport::http::shared_client;
+
+/// How long fetched signing keys are trusted before the key set is reloaded.
+const JWKS_TTL: Duration = Duration::from_secs(600);
+/// Least time between two fetches, so tokens naming unknown keys can not
+/// make the balancer hammer the key server.
+const JWKS_MIN_REFETCH: Duration = Duration::from_secs(30);
+const JWKS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `type = "jwt"`: bearer tokens signed with `secret` (HS256), the private
+/// key of `public_key` (RS256, PEM encoded) or one of the keys published at
+/// `jwks_url` (RS256).
+#[derive(Clone, Debug, Deserialize)]
+pub struct JwtConfig {
+    pub secret: Option<String>,
+    pub public_key: Option<String>,
+    pub jwks_url: Option<String>,
+    /// Required `iss` claim.
+    pub issuer: Option<String>,
+    /// Required `aud` claim.
+    pub audience: Option<String>,
+    /// Claim naming the tenant requests are accounted to.
+    #[serde(default = "default_tenant_claim")]
+    pub tenant_claim: String,
+    /// Claim listing the chains the tenant may use, every chain without it.
+    #[serde(default = "default_chains_claim")]
+    pub chains_claim: String,
+    /// Claim whose value picks one of `policies`, e.g. a `tier`.
+    pub policy_claim: Option<String>,
+    #[serde(default)]
+    pub policies: HashMap<String, TenantPolicy>,
+}
+
+fn default_tenant_claim() -> String {
+    "sub".to_string()
+}
+
+fn default_chains_claim() -> String {
+    "chains".to_string()
+}
+
+/// What the tenants of a policy may do. Its `chains` replace the ones of
+/// the token's chains claim.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct TenantPolicy {
+    pub chains: Option<Vec<String>>,
+    pub requests_per_second: Option<u32>,
+    pub sla_class: Option<String>,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+enum JwtKey {
+    Hs256(Vec<u8>),
+    Rs256(PKey<Public>),
+    Jwks(Jwks),
+}
+
+/// Accepts `Authorization: Bearer` JSON web tokens, mapping their claims to
+/// a [`Principal`] as set up in [`JwtConfig`].
+pub struct JwtAuth {
+    key: JwtKey,
+    config: JwtConfig,
+}
+
+impl fmt::Debug for JwtAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = match &self.key {
+            JwtKey::Hs256(_) => "HS256",
+            JwtKey::Rs256(_) => "RS256",
+            JwtKey::Jwks(jwks) => jwks.url.as_str(),
+        };
+        f.debug_struct("JwtAuth")
+            .field("key", &key)
+            .field("issuer", &self.config.issuer)
+            .field("audience", &self.config.audience)
+            .finish()
+    }
+}
+
+impl JwtAuth {
+    pub fn new(config: &JwtConfig) -> Result<Self, String> {
+        let key = match (&config.secret, &config.public_key, &config.jwks_url) {
+            (Some(secret), None, None) => JwtKey::Hs256(secret.as_bytes().to_vec()),
+            (None, Some(pem), None) => JwtKey::Rs256(
+                PKey::public_key_from_pem(pem.as_bytes())
+                    .map_err(|e| format!("Invalid JWT public_key: {}", e))?,
+            ),
+            (None, None, Some(url)) => JwtKey::Jwks(Jwks::new(url)),
+            _ => {
+                return Err(
+                    "JWT auth needs exactly one of `secret`, `public_key` and `jwks_url`"
+                        .to_string(),
+                )
+            }
+        };
+        Ok(Self {
+            key,
+            config: config.clone(),
+        })
+    }
+
+    /// Checks `token` at `now` (unix seconds). `published` is the key of the
+    /// key set the token names, for JWKS keys.
+    fn verify(
+        &self,
+        token: &str,
+        published: Option<&PKey<Public>>,
+        now: i64,
+    ) -> Result<Principal, String> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let [header, payload, signature] = parts[..] else {
+            return Err("malformed token".to_string());
+        };
+        let header = decode_json(header)?;
+        let signature = decode(signature)?;
+        let signed = format!("{}.{}", parts[0], payload);
+
+        let valid = match (&self.key, header["alg"].as_str()) {
+            (JwtKey::Hs256(secret), Some("HS256")) => {
+                let key = PKey::hmac(secret).map_err(|e| e.to_string())?;
+                let mut signer =
+                    Signer::new(MessageDigest::sha256(), &key).map_err(|e| e.to_string())?;
+                let expected = signer
+                    .sign_oneshot_to_vec(signed.as_bytes())
+                    .map_err(|e| e.to_string())?;
+                expected.len() == signature.len() && memcmp::eq(&expected, &signature)
+            }
+            (JwtKey::Rs256(key), Some("RS256")) => verify_rs256(key, &signed, &signature)?,
+            (JwtKey::Jwks(_), Some("RS256")) => {
+                let key = published.ok_or_else(|| "unknown signing key".to_string())?;
+                verify_rs256(key, &signed, &signature)?
+            }
+            (_, alg) => return Err(format!("unexpected algorithm {}", alg.unwrap_or("none"))),
+        };
+        if !valid {
+            return Err("bad signature".to_string());
+        }
+
+        let claims = decode_json(payload)?;
+        self.check_claims(&claims, now)?;
+        self.principal(&claims)
+    }
+
+    fn check_claims(&self, claims: &Value, now: i64) -> Result<(), String> {
+        if claims["exp"].as_i64().is_some_and(|exp| exp <= now) {
+            return Err("token expired".to_string());
+        }
+        if claims["nbf"].as_i64().is_some_and(|nbf| nbf > now) {
+            return Err("token not valid yet".to_string());
+        }
+        if let Some(issuer) = &self.config.issuer {
+            if claims["iss"].as_str() != Some(issuer.as_str()) {
+                return Err("wrong issuer".to_string());
+            }
+        }
+        if let Some(audience) = &self.config.audience {
+            let matches = match &claims["aud"] {
+                Value::String(aud) => aud == audience,
+                Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err("wrong audience".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps the claims to the tenant and its policy.
+    fn principal(&self, claims: &Value) -> Result<Principal, String> {
+        let id = claims[&self.config.tenant_claim]
+            .as_str()
+            .ok_or_else(|| format!("token has no {} claim", self.config.tenant_claim))?;
+        let mut principal = Principal {
+            id: id.to_string(),
+            chains: claims[&self.config.chains_claim].as_array().map(|chains| {
+                chains
+                    .iter()
+                    .filter_map(|chain| chain.as_str().map(str::to_string))
+                    .collect()
+            }),
+            requests_per_second: None,
+            sla_class: None,
+            admin: false,
+        };
+
+        if let Some(policy_claim) = &self.config.policy_claim {
+            let name = claims[policy_claim]
+                .as_str()
+                .ok_or_else(|| format!("token has no {} claim", policy_claim))?;
+            let policy = self
+                .config
+                .policies
+                .get(name)
+                .ok_or_else(|| format!("unknown policy {}", name))?;
+            if policy.chains.is_some() {
+                principal.chains = policy.chains.clone();
+            }
+            principal.requests_per_second = policy.requests_per_second;
+            principal.sla_class = policy.sla_class.clone();
+            principal.admin = policy.admin;
+        }
+        Ok(principal)
+    }
+}
+
+impl Authenticator for JwtAuth {
+    fn authenticate<'a>(&'a self, headers: &'a HeaderMap) -> AuthFuture<'a> {
+        Box::pin(async move {
+            let token = headers
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(str::trim)
+                .ok_or(AuthError::Missing)?;
+
+            let published = match &self.key {
+                JwtKey::Jwks(jwks) => {
+                    let header = token.split('.').next().unwrap_or_default();
+                    let kid = decode_json(header)
+                        .ok()
+                        .and_then(|header| header["kid"].as_str().map(str::to_string));
+                    jwks.key(kid.as_deref()).await
+                }
+                _ => None,
+            };
+            self.verify(token, published.as_ref(), chrono::Utc::now().timestamp())
+                .map_err(AuthError::Invalid)
+        })
+    }
+}
+
+fn decode(part: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .map_err(|_| "malformed token".to_string())
+}
+
+fn decode_json(part: &str) -> Result<Value, String> {
+    serde_json::from_slice(&decode(part)?).map_err(|_| "malformed token".to_string())
+}
+
+fn verify_rs256(key: &PKey<Public>, signed: &str, signature: &[u8]) -> Result<bool, String> {
+    let mut verifier = Verifier::new(MessageDigest::sha256(), key).map_err(|e| e.to_string())?;
+    Ok(verifier
+        .verify_oneshot(signature, signed.as_bytes())
+        .unwrap_or(false))
+}
+
+/// The RSA signing keys published at a JWKS url, by key id.
+struct Jwks {
+    url: String,
+    keys: RwLock<HashMap<String, PKey<Public>>>,
+    fetched_at: Mutex<Option<Instant>>,
+}
+
+impl Jwks {
+    fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            keys: RwLock::default(),
+            fetched_at: Mutex::new(None),
+        }
+    }
+
+    /// The key `kid` names, or the only key of a set without ids. The set is
+    /// reloaded once stale, or when it lacks the key and was not just fetched.
+    async fn key(&self, kid: Option<&str>) -> Option<PKey<Public>> {
+        let lookup = |keys: &HashMap<String, PKey<Public>>| match kid {
+            Some(kid) => keys.get(kid).cloned(),
+            None if keys.len() == 1 => keys.values().next().cloned(),
+            None => None,
+        };
+
+        let age = self.fetched_at.lock().unwrap().map(|at| at.elapsed());
+        let key = lookup(&self.keys.read().unwrap());
+        let refetch = match (age, &key) {
+            (None, _) => true,
+            (Some(age), Some(_)) => age >= JWKS_TTL,
+            (Some(age), None) => age >= JWKS_MIN_REFETCH,
+        };
+        if !refetch {
+            return key;
+        }
+
+        *self.fetched_at.lock().unwrap() = Some(Instant::now());
+        match self.fetch().await {
+            Ok(keys) => {
+                let key = lookup(&keys);
+                *self.keys.write().unwrap() = keys;
+                key
+            }
+            Err(e) => {
+                println!("Failed to fetch JWKS from {}: {}", self.url, e);
+                key
+            }
+        }
+    }
+
+    async fn fetch(&self) -> Result<HashMap<String, PKey<Public>>, String> {
+        let body = shared_client()
+            .get(&self.url)
+            .timeout(JWKS_TIMEOUT)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| e.to_string())?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?;
+        parse_jwks(&body)
+    }
+}
+
+/// Reads the RSA keys of a JWKS document, other key types are skipped.
+fn parse_jwks(body: &[u8]) -> Result<HashMap<String, PKey<Public>>, String> {
+    let document: Value = serde_json::from_slice(body).map_err(|_| "JWKS is not valid JSON")?;
+    let keys = document["keys"]
+        .as_array()
+        .ok_or_else(|| "JWKS has no keys".to_string())?;
+
+    let mut parsed = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        if key["kty"] != "RSA" {
+            continue;
+        }
+        let component = |name: &str| {
+            key[name]
+                .as_str()
+                .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+                .and_then(|bytes| BigNum::from_slice(&bytes).ok())
+                .ok_or_else(|| format!("JWKS key {} has no valid {}", i, name))
+        };
+        let rsa = Rsa::from_public_components(component("n")?, component("e")?)
+            .map_err(|e| e.to_string())?;
+        let kid = key["kid"]
+            .as_str()
+            .map_or_else(|| i.to_string(), str::to_string);
+        parsed.insert(kid, PKey::from_rsa(rsa).map_err(|e| e.to_string())?);
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::pkey::Private;
+    use serde_json::json;
+
+    fn sign(header: Value, claims: Value, sign: impl Fn(&[u8]) -> Vec<u8>) -> String {
+        let signed = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = sign(signed.as_bytes());
+        format!("{}.{}", signed, URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    fn hs256(secret: &str, claims: Value) -> String {
+        let key = PKey::hmac(secret.as_bytes()).unwrap();
+        sign(json!({"alg": "HS256", "typ": "JWT"}), claims, |data| {
+            Signer::new(MessageDigest::sha256(), &key)
+                .unwrap()
+                .sign_oneshot_to_vec(data)
+                .unwrap()
+        })
+    }
+
+    fn rs256(key: &PKey<Private>, header: Value, claims: Value) -> String {
+        sign(header, claims, |data| {
+            Signer::new(MessageDigest::sha256(), key)
+                .unwrap()
+                .sign_oneshot_to_vec(data)
+                .unwrap()
+        })
+    }
+
+    fn config(toml: &str) -> JwtConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_hs256_tokens() {
+        let auth = JwtAuth::new(&config(
+            r#"
+            secret = "secret"
+            issuer = "sso"
+            audience = "rpc-lb"
+            "#,
+        ))
+        .unwrap();
+        let claims = json!({
+            "sub": "indexer", "iss": "sso", "aud": ["rpc-lb"], "exp": 2_000, "chains": ["sepolia"]
+        });
+
+        let principal = auth
+            .verify(&hs256("secret", claims.clone()), None, 1_000)
+            .unwrap();
+        assert_eq!(principal.id, "indexer");
+        assert!(principal.may_use("sepolia"));
+        assert!(!principal.may_use("mainnet"));
+
+        assert_eq!(
+            auth.verify(&hs256("secret", claims.clone()), None, 2_000),
+            Err("token expired".to_string())
+        );
+        assert_eq!(
+            auth.verify(&hs256("other", claims.clone()), None, 1_000),
+            Err("bad signature".to_string())
+        );
+        let mut wrong_issuer = claims;
+        wrong_issuer["iss"] = json!("elsewhere");
+        assert_eq!(
+            auth.verify(&hs256("secret", wrong_issuer), None, 1_000),
+            Err("wrong issuer".to_string())
+        );
+
+        // Unsigned tokens are never accepted.
+        let unsigned = sign(json!({"alg": "none"}), json!({"sub": "x"}), |_| Vec::new());
+        assert!(auth.verify(&unsigned, None, 1_000).is_err());
+    }
+
+    #[test]
+    fn test_claims_map_to_policies() {
+        let auth = JwtAuth::new(&config(
+            r#"
+            secret = "secret"
+            tenant_claim = "tenant"
+            policy_claim = "tier"
+            policies = { gold = { requests_per_second = 100 }, trial = { chains = ["sepolia"], requests_per_second = 5 } }
+            "#,
+        ))
+        .unwrap();
+
+        let principal = auth
+            .verify(
+                &hs256(
+                    "secret",
+                    json!({"tenant": "acme", "tier": "trial", "chains": ["mainnet"]}),
+                ),
+                None,
+                0,
+            )
+            .unwrap();
+        assert_eq!(principal.id, "acme");
+        assert_eq!(principal.chains, Some(vec!["sepolia".to_string()]));
+        assert_eq!(principal.requests_per_second, Some(5));
+
+        let principal = auth
+            .verify(
+                &hs256("secret", json!({"tenant": "acme", "tier": "gold"})),
+                None,
+                0,
+            )
+            .unwrap();
+        assert_eq!(principal.chains, None);
+        assert_eq!(principal.requests_per_second, Some(100));
+
+        assert_eq!(
+            auth.verify(
+                &hs256("secret", json!({"tenant": "acme", "tier": "platinum"})),
+                None,
+                0
+            ),
+            Err("unknown policy platinum".to_string())
+        );
+    }
+
+    #[test]
+    fn test_rs256_tokens() {
+        let private = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let pem = String::from_utf8(private.public_key_to_pem().unwrap()).unwrap();
+        let auth = JwtAuth::new(&config(&format!("public_key = '''\n{}'''", pem))).unwrap();
+
+        let token = rs256(&private, json!({"alg": "RS256"}), json!({"sub": "wallet"}));
+        let principal = futures_util::FutureExt::now_or_never(auth.authenticate(&bearer(&token)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(principal.id, "wallet");
+        assert!(principal.may_use("mainnet"));
+    }
+
+    #[tokio::test]
+    async fn test_jwks_keys() {
+        let rsa = Rsa::generate(2048).unwrap();
+        let jwks = json!({"keys": [{
+            "kty": "RSA",
+            "kid": "k1",
+            "n": URL_SAFE_NO_PAD.encode(rsa.n().to_vec()),
+            "e": URL_SAFE_NO_PAD.encode(rsa.e().to_vec()),
+        }]});
+        let private = PKey::from_rsa(rsa).unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/jwks.json", listener.local_addr().unwrap());
+        let app = axum::Router::new().route(
+            "/jwks.json",
+            axum::routing::get(move || async move { jwks.to_string() }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let auth = JwtAuth::new(&config(&format!("jwks_url = \"{}\"", url))).unwrap();
+        let token = rs256(
+            &private,
+            json!({"alg": "RS256", "kid": "k1"}),
+            json!({"sub": "wallet"}),
+        );
+        assert_eq!(
+            auth.authenticate(&bearer(&token)).await.unwrap().id,
+            "wallet"
+        );
+
+        let token = rs256(
+            &private,
+            json!({"alg": "RS256", "kid": "rotated"}),
+            json!({"sub": "wallet"}),
+        );
+        assert_eq!(
+            auth.authenticate(&bearer(&token)).await,
+            Err(AuthError::Invalid("unknown signing key".to_string()))
+        );
+    }
+}
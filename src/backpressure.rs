@@ -0,0 +1,163 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+    serve::Listener,
+};
+use tokio::sync::Notify;
+
+use crate::metrics::Metrics;
+
+/// Counts the requests being handled and holds back new connections once
+/// there are too many of them.
+///
+/// Accepting pauses when `high_water` requests are in flight and resumes once
+/// the count dropped to three quarters of it, so the listener does not flap
+/// around the limit.
+#[derive(Debug)]
+pub struct InFlight {
+    count: AtomicUsize,
+    high_water: usize,
+    low_water: usize,
+    released: Notify,
+    metrics: Arc<Metrics>,
+}
+
+impl InFlight {
+    pub fn new(high_water: usize, metrics: Arc<Metrics>) -> Self {
+        let high_water = high_water.max(1);
+        Self {
+            count: AtomicUsize::new(0),
+            high_water,
+            low_water: high_water * 3 / 4,
+            released: Notify::new(),
+            metrics,
+        }
+    }
+
+    pub fn current(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Marks a request as in flight until the returned guard is dropped.
+    pub fn enter(self: &Arc<Self>) -> InFlightGuard {
+        self.count.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard(self.clone())
+    }
+
+    /// Returns right away below the high-water mark, otherwise waits until
+    /// enough requests completed to get back to the low-water mark.
+    async fn wait_for_capacity(&self) {
+        if self.current() < self.high_water {
+            return;
+        }
+
+        let started = Instant::now();
+        println!(
+            "{} requests in flight, pausing new connections",
+            self.current()
+        );
+        loop {
+            let released = self.released.notified();
+            if self.current() <= self.low_water {
+                break;
+            }
+            released.await;
+        }
+
+        self.metrics.inc("rpc_lb_backpressure_pauses_total", &[]);
+        self.metrics.add(
+            "rpc_lb_backpressure_milliseconds_total",
+            &[],
+            started.elapsed().as_millis() as u64,
+        );
+    }
+}
+
+pub struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let previous = self.0.count.fetch_sub(1, Ordering::Relaxed);
+        if previous - 1 <= self.0.low_water {
+            self.0.released.notify_waiters();
+        }
+    }
+}
+
+/// Middleware keeping the in-flight count of every request handled.
+pub async fn track(
+    State(in_flight): State<Arc<InFlight>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let _guard = in_flight.enter();
+    next.run(request).await
+}
+
+/// Wraps a listener so connections are only accepted while the in-flight
+/// count allows it. Pending connections wait in the kernel's backlog instead
+/// of piling up requests in memory.
+pub struct BackpressureListener<L> {
+    inner: L,
+    in_flight: Arc<InFlight>,
+}
+
+impl<L> BackpressureListener<L> {
+    pub fn new(inner: L, in_flight: Arc<InFlight>) -> Self {
+        Self { inner, in_flight }
+    }
+}
+
+impl<L: Listener> Listener for BackpressureListener<L> {
+    type Io = L::Io;
+    type Addr = L::Addr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        self.in_flight.wait_for_capacity().await;
+        self.inner.accept().await
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.inner.local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_waits_until_low_water() {
+        let metrics = Arc::new(Metrics::default());
+        let in_flight = Arc::new(InFlight::new(4, metrics.clone()));
+
+        let mut guards: Vec<_> = (0..4).map(|_| in_flight.enter()).collect();
+        let waiter = {
+            let in_flight = in_flight.clone();
+            tokio::spawn(async move { in_flight.wait_for_capacity().await })
+        };
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiter.is_finished());
+
+        // Three in flight is the low-water mark for a high-water mark of four.
+        guards.pop();
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(in_flight.current(), 3);
+        assert_eq!(metrics.counter("rpc_lb_backpressure_pauses_total", &[]), 1);
+    }
+}
@@ -0,0 +1,874 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use toml::{Table, Value};
+
+use crate::{
+    algorithms::{
+        round_robin::{Chains, Config, DuplicatePolicy, Locality, RpcServer},
+        schedule::parse_timezone,
+    },
+    services::head::host_of,
+    transport,
+};
+
+pub mod diff;
+pub mod history;
+pub mod summary;
+
+/// Reads and parses the config file at `path`, together with the files it
+/// pulls in through `include`.
+pub fn load(path: &str) -> Result<Config, String> {
+    let table = load_table(path)?;
+    build(table).map_err(|e| format!("Failed to parse {}: {}", path, e))
+}
+
+/// Reads the config file at `path` with its includes, defaults and short
+/// backend forms resolved, the form configs are compared in by [`diff`].
+pub fn load_table(path: &str) -> Result<Table, String> {
+    load_table_with(path, None)
+}
+
+/// Like [`load_table`], with the `[chains]` of `registry`, a document read
+/// from the `[server.registry]`, added as if it were an included file.
+pub fn load_table_with(path: &str, registry: Option<Table>) -> Result<Table, String> {
+    let mut table = read_table(Path::new(path))?;
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    resolve_includes(&mut table, base_dir)?;
+    if let Some(registry) = registry {
+        merge_chains(&mut table, registry, "the registry")?;
+    }
+    expand(table).map_err(|e| format!("Failed to parse {}: {}", path, e))
+}
+
+/// Like [`load_table`] for a config sent as text, e.g. to
+/// `/admin/config/validate`. Includes are relative to the working directory.
+pub fn parse_table(content: &str) -> Result<Table, String> {
+    let mut table: Table = toml::from_str(content).map_err(|e| e.to_string())?;
+    resolve_includes(&mut table, Path::new("."))?;
+    expand(table)
+}
+
+fn read_table(path: &Path) -> Result<Table, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+}
+
+/// Merges the `[chains]` of every file matched by the `include` glob patterns
+/// into `table`. Patterns are relative to the directory of the main config,
+/// and a chain may only be declared once across all files.
+fn resolve_includes(table: &mut Table, base_dir: &Path) -> Result<(), String> {
+    let Some(include) = table.remove("include") else {
+        return Ok(());
+    };
+    let Value::Array(patterns) = include else {
+        return Err("`include` must be an array of glob patterns".to_string());
+    };
+
+    for pattern in patterns {
+        let Value::String(pattern) = pattern else {
+            return Err("`include` must be an array of glob patterns".to_string());
+        };
+        let pattern = base_dir.join(&pattern);
+        let mut paths: Vec<_> = glob::glob(&pattern.to_string_lossy())
+            .map_err(|e| format!("Invalid include pattern {}: {}", pattern.display(), e))?
+            .filter_map(Result::ok)
+            .collect();
+        paths.sort();
+
+        if paths.is_empty() {
+            eprintln!("Include pattern {} matched no files", pattern.display());
+        }
+
+        for path in paths {
+            let included = read_table(&path)?;
+            merge_chains(
+                table,
+                included,
+                &format!("included file {}", path.display()),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds the `[chains]` of `document`, which may declare nothing else, to
+/// those of `table`. A chain may only be declared once.
+fn merge_chains(table: &mut Table, mut document: Table, origin: &str) -> Result<(), String> {
+    let chains = match document.remove("chains") {
+        Some(Value::Table(chains)) => chains,
+        Some(_) => return Err(format!("`chains` of {} must be a table", origin)),
+        None => Table::new(),
+    };
+    if let Some(key) = document.keys().next() {
+        return Err(format!(
+            "Only [chains] may be declared in {}, found `{}`",
+            origin, key
+        ));
+    }
+
+    let Value::Table(target) = table
+        .entry("chains")
+        .or_insert_with(|| Value::Table(Table::new()))
+    else {
+        return Err("`chains` must be a table".to_string());
+    };
+    for (name, chain) in chains {
+        if target.contains_key(&name) {
+            return Err(format!("Chain {} in {} is already declared", name, origin));
+        }
+        target.insert(name, chain);
+    }
+    Ok(())
+}
+
+/// Applies the `[defaults]` table to every chain and expands their backends.
+///
+/// Chains only need to declare the settings where they deviate from the
+/// defaults, nested tables such as `routing` are merged key by key.
+fn expand(mut table: Table) -> Result<Table, String> {
+    let defaults = match table.remove("defaults") {
+        Some(Value::Table(defaults)) => defaults,
+        Some(_) => return Err("`defaults` must be a table".to_string()),
+        None => Table::new(),
+    };
+
+    if let Some(Value::Table(chains)) = table.get_mut("chains") {
+        for (name, chain) in chains.iter_mut() {
+            if let Value::Table(chain) = chain {
+                merge_defaults(chain, &defaults);
+                expand_backends(name, chain)?;
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// Deserializes and validates a table resolved by [`load_table`] or
+/// [`parse_table`].
+pub fn build(table: Table) -> Result<Config, String> {
+    let mut config: Config = Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| e.to_string())?;
+
+    for (name, chain) in config.chains.iter_mut() {
+        dedupe_backends(name, chain)?;
+        if let Some(algorithm) = chain.algorithm {
+            chain.routing.default = algorithm;
+        }
+        if let Some(timezone) = &chain.timezone {
+            parse_timezone(timezone).map_err(|e| format!("Chain {}: {}", name, e))?;
+        }
+        chain
+            .headers
+            .validate()
+            .map_err(|e| format!("Chain {}: {}", name, e))?;
+        if chain.locality == Locality::PreferLocal
+            && chain.rpc_urls.iter().all(|server| server.region.is_none())
+        {
+            return Err(format!(
+                "Chain {} prefers local backends but none has a region",
+                name
+            ));
+        }
+        if let Some(discovery) = &chain.discovery {
+            discovery
+                .validate()
+                .map_err(|e| format!("Chain {}: {}", name, e))?;
+        }
+        if let Some(prewarm) = &chain.prewarm {
+            let pool_idle_timeout_secs = chain
+                .http
+                .as_ref()
+                .and_then(|http| http.pool_idle_timeout_secs);
+            prewarm
+                .validate(pool_idle_timeout_secs)
+                .map_err(|e| format!("Chain {}: {}", name, e))?;
+        }
+        if let Some(cache_control) = &chain.cache_control {
+            cache_control
+                .validate()
+                .map_err(|e| format!("Chain {}: {}", name, e))?;
+        }
+        for pattern in chain.cache.keys() {
+            glob::Pattern::new(pattern)
+                .map_err(|e| format!("Chain {}: invalid cache pattern {}: {}", name, pattern, e))?;
+        }
+        let mut names = HashSet::new();
+        for backend in chain
+            .rpc_urls
+            .iter()
+            .filter_map(|server| server.name.as_ref())
+        {
+            let valid = !backend.is_empty()
+                && backend
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+            if !valid {
+                return Err(format!(
+                    "Chain {}: backend name {} may only use letters, digits, '-', '_' and '.'",
+                    name, backend
+                ));
+            }
+            if !names.insert(backend) {
+                return Err(format!(
+                    "Chain {}: backend name {} is used more than once",
+                    name, backend
+                ));
+            }
+        }
+        for server in &chain.rpc_urls {
+            let scheme = server.url.split("://").next().unwrap_or_default();
+            if !server.url.contains("://") || !transport::SCHEMES.contains(&scheme) {
+                return Err(format!(
+                    "Chain {}: unsupported url scheme of {}, expected one of {}",
+                    name,
+                    host_of(&server.url),
+                    transport::SCHEMES.join(", ")
+                ));
+            }
+            if let Some(fault) = &server.chaos {
+                fault
+                    .validate()
+                    .map_err(|e| format!("Chain {}: {}", name, e))?;
+            }
+            if let Some(log) = &server.log {
+                log.validate()
+                    .map_err(|e| format!("Chain {}: {}", name, e))?;
+            }
+            match server.tier {
+                None | Some(2) => {}
+                Some(1) if !server.fallback => {}
+                Some(1) => {
+                    return Err(format!(
+                        "Chain {}: {} is both in tier 1 and a fallback",
+                        name,
+                        host_of(&server.url)
+                    ));
+                }
+                Some(tier) => {
+                    return Err(format!(
+                        "Chain {}: tier {} of {} should be 1 (primary) or 2 (fallback)",
+                        name,
+                        tier,
+                        host_of(&server.url)
+                    ));
+                }
+            }
+            if let Some(host) = &server.host_header {
+                reqwest::header::HeaderValue::from_str(host).map_err(|_| {
+                    format!(
+                        "Chain {}: invalid host_header {} for {}",
+                        name, host, server.url
+                    )
+                })?;
+            }
+        }
+    }
+
+    for (name, chain) in &config.chains {
+        let settings = chain.method_settings();
+        if chain.inspect_body == Some(false) && !settings.is_empty() {
+            return Err(format!(
+                "Chain {}: {} need the method of requests, which inspect_body = false leaves unparsed",
+                name,
+                settings.join(", ")
+            ));
+        }
+        // Writes can not be told apart in unparsed bodies, so a retry could
+        // send one again after it reached a backend.
+        if chain.inspect_body == Some(false) && chain.max_retries != Some(1) {
+            return Err(format!(
+                "Chain {}: inspect_body = false needs max_retries = 1, failed writes would be retried otherwise",
+                name
+            ));
+        }
+        if let Some(error_cost) = chain.error_cost {
+            if error_cost.is_nan() || error_cost < 1.0 {
+                return Err(format!(
+                    "Chain {}: error_cost {} is below 1",
+                    name, error_cost
+                ));
+            }
+        }
+        if let Some(smoothing) = chain.routing.latency_smoothing {
+            if !(smoothing > 0.0 && smoothing <= 1.0) {
+                return Err(format!(
+                    "Chain {}: latency_smoothing {} is not above 0 and at most 1",
+                    name, smoothing
+                ));
+            }
+        }
+        if let Some(header) = chain
+            .routing
+            .sticky
+            .as_ref()
+            .and_then(|sticky| sticky.header.as_ref())
+        {
+            reqwest::header::HeaderName::from_bytes(header.as_bytes())
+                .map_err(|_| format!("Chain {}: invalid sticky header {}", name, header))?;
+        }
+        for rule in &chain.routing.headers {
+            reqwest::header::HeaderName::from_bytes(rule.header.as_bytes())
+                .map_err(|_| format!("Chain {}: invalid routing header {}", name, rule.header))?;
+            if let Some(class) = rule.sla_class.as_ref() {
+                if !config.server.sla_classes.contains_key(class) {
+                    return Err(format!("Chain {}: unknown SLA class {}", name, class));
+                }
+            }
+            if let Some(tag) = rule.tag.as_ref() {
+                if !chain
+                    .rpc_urls
+                    .iter()
+                    .any(|server| server.tags.contains(tag))
+                {
+                    return Err(format!("Chain {}: no backend is tagged {}", name, tag));
+                }
+            }
+        }
+    }
+
+    let tls = config.server.listener.tls.as_ref();
+    if let Some(acme) = tls.and_then(|tls| tls.acme.as_ref()) {
+        acme.validate()?;
+    }
+
+    if let Some(auth) = &config.server.auth {
+        for class in auth.sla_classes() {
+            if !config.server.sla_classes.contains_key(class) {
+                return Err(format!("Unknown SLA class {} in [server.auth]", class));
+            }
+        }
+    }
+
+    Ok(config)
+}
+
+/// Expands the short backend forms of a chain into full tables: a bare url
+/// string takes the chain's `request_limit` and `burst_limit`, and
+/// `current_limit` starts at `request_limit` when left out. Placeholders in
+/// the url are filled from the backend's `vars`.
+fn expand_backends(name: &str, chain: &mut Table) -> Result<(), String> {
+    let chain_limit = chain.get("request_limit").cloned();
+    let chain_burst = chain.get("burst_limit").cloned();
+    let Some(Value::Array(backends)) = chain.get_mut("rpc_urls") else {
+        return Ok(());
+    };
+
+    for backend in backends.iter_mut() {
+        if let Value::String(url) = backend {
+            let mut table = Table::new();
+            table.insert("url".to_string(), Value::String(url.clone()));
+            *backend = Value::Table(table);
+        }
+
+        let Value::Table(backend) = backend else {
+            return Err(format!(
+                "Backends of chain {} must be url strings or tables",
+                name
+            ));
+        };
+
+        let vars = match backend.remove("vars") {
+            Some(Value::Table(vars)) => vars,
+            Some(_) => return Err(format!("`vars` of chain {} must be a table", name)),
+            None => Table::new(),
+        };
+        if let Some(Value::String(url)) = backend.get_mut("url") {
+            *url = fill_template(url, &vars).map_err(|e| format!("Chain {}: {}", name, e))?;
+        }
+
+        if !backend.contains_key("request_limit") {
+            let Some(limit) = chain_limit.clone() else {
+                return Err(format!(
+                    "Backend {} of chain {} has no request_limit and the chain declares none",
+                    backend
+                        .get("url")
+                        .and_then(Value::as_str)
+                        .map(host_of)
+                        .unwrap_or_default(),
+                    name
+                ));
+            };
+            backend.insert("request_limit".to_string(), limit);
+        }
+        if let (false, Some(burst)) = (backend.contains_key("burst_limit"), &chain_burst) {
+            backend.insert("burst_limit".to_string(), burst.clone());
+        }
+        if !backend.contains_key("current_limit") {
+            let limit = backend["request_limit"].clone();
+            backend.insert("current_limit".to_string(), limit);
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the discovered backends of `chain` in the resolved config
+/// `table` with backends at `urls`, which take the chain's limits.
+pub fn with_discovered<'a>(
+    mut table: Table,
+    chain: &str,
+    urls: impl IntoIterator<Item = &'a String>,
+) -> Result<Table, String> {
+    let Some(Value::Table(settings)) = table
+        .get_mut("chains")
+        .and_then(|chains| chains.get_mut(chain))
+    else {
+        return Err(format!("Unknown chain {}", chain));
+    };
+    let Value::Array(backends) = settings
+        .entry("rpc_urls")
+        .or_insert_with(|| Value::Array(Vec::new()))
+    else {
+        return Err(format!("`rpc_urls` of chain {} must be an array", chain));
+    };
+    backends.retain(|backend| backend.get("discovered") != Some(&Value::Boolean(true)));
+    for url in urls {
+        let mut backend = Table::new();
+        backend.insert("url".to_string(), Value::String(url.clone()));
+        backend.insert("discovered".to_string(), Value::Boolean(true));
+        backends.push(Value::Table(backend));
+    }
+    expand_backends(chain, settings)?;
+    Ok(table)
+}
+
+/// Replaces every `{name}` in a backend url with the value of `name` in
+/// `vars`. Strings are used as they are, other values in their TOML form.
+fn fill_template(url: &str, vars: &Table) -> Result<String, String> {
+    let mut filled = String::with_capacity(url.len());
+    let mut rest = url;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!("Unclosed placeholder in url of {}", host_of(url)));
+        };
+        let var = &rest[start + 1..start + end];
+        let value = match vars.get(var) {
+            Some(Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+            None => {
+                return Err(format!(
+                    "Backend {} uses {{{}}} but declares no such var",
+                    host_of(url),
+                    var
+                ))
+            }
+        };
+        filled.push_str(&rest[..start]);
+        filled.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+/// Normalizes a backend url so that spellings of the same endpoint compare
+/// equal: the host is lowercased and trailing slashes are dropped.
+pub fn normalize_url(url: &str) -> Result<String, String> {
+    let mut parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid url {}: {}", url, e))?;
+    let path = parsed.path().trim_end_matches('/').to_string();
+    parsed.set_path(&path);
+    Ok(parsed.to_string())
+}
+
+/// Normalizes the urls of `chain` and folds duplicates together, since the
+/// round robin would otherwise count them as separate capacity.
+fn dedupe_backends(name: &str, chain: &mut Chains) -> Result<(), String> {
+    let mut backends: Vec<RpcServer> = Vec::with_capacity(chain.rpc_urls.len());
+
+    for mut server in chain.rpc_urls.drain(..) {
+        server.url = normalize_url(&server.url)?;
+
+        match backends
+            .iter_mut()
+            .find(|existing| existing.url == server.url)
+        {
+            Some(existing) => match chain.duplicates {
+                DuplicatePolicy::Error => {
+                    return Err(format!(
+                        "Chain {} declares backend {} more than once",
+                        name,
+                        host_of(&server.url)
+                    ));
+                }
+                DuplicatePolicy::Merge => {
+                    eprintln!(
+                        "Chain {} declares backend {} more than once, merging their limits",
+                        name,
+                        host_of(&server.url)
+                    );
+                    existing.request_limit += server.request_limit;
+                    existing.current_limit += server.current_limit;
+                }
+            },
+            None => backends.push(server),
+        }
+    }
+
+    chain.rpc_urls = backends;
+    Ok(())
+}
+
+fn merge_defaults(target: &mut Table, defaults: &Table) {
+    for (key, default) in defaults {
+        match (target.get_mut(key), default) {
+            (Some(Value::Table(target)), Value::Table(default)) => merge_defaults(target, default),
+            (Some(_), _) => {}
+            (None, _) => {
+                target.insert(key.clone(), default.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::routing::Strategy;
+
+    fn parse(content: &str) -> Result<Config, String> {
+        let table: Table = toml::from_str(content).map_err(|e| e.to_string())?;
+        build(expand(table)?)
+    }
+
+    #[test]
+    fn test_defaults_apply_to_chains() {
+        let config = parse(
+            r#"
+            [defaults]
+            timeout_ms = 5000
+            max_retries = 2
+            routing = { default = "latency", write = "broadcast" }
+
+            [chains.sepolia]
+            rpc_urls = [{ url = "https://sepolia.drpc.org", request_limit = 1, current_limit = 1 }]
+            timeout_ms = 1000
+            routing = { default = "round_robin" }
+
+            [chains.bitcoin]
+            rpc_urls = []
+            "#,
+        )
+        .unwrap();
+
+        let sepolia = &config.chains["sepolia"];
+        assert_eq!(sepolia.timeout_ms, Some(1000));
+        assert_eq!(sepolia.max_retries, Some(2));
+        assert_eq!(sepolia.routing.default, Strategy::RoundRobin);
+        assert_eq!(sepolia.routing.write, Some(Strategy::Broadcast));
+
+        let bitcoin = &config.chains["bitcoin"];
+        assert_eq!(bitcoin.timeout_ms, Some(5000));
+        assert_eq!(bitcoin.routing.default, Strategy::Latency);
+    }
+
+    #[test]
+    fn test_chain_algorithm() {
+        let config = parse(
+            r#"
+            [defaults]
+            routing = { default = "latency", write = "broadcast" }
+
+            [chains.bitcoin]
+            rpc_urls = []
+            algorithm = "round_robin"
+
+            [chains.ethereum_sepolia]
+            rpc_urls = []
+            algorithm = "weighted"
+
+            [chains.base]
+            rpc_urls = []
+            algorithm = "least_conn"
+            "#,
+        )
+        .unwrap();
+
+        let bitcoin = &config.chains["bitcoin"].routing;
+        assert_eq!(bitcoin.default, Strategy::RoundRobin);
+        assert_eq!(bitcoin.write, Some(Strategy::Broadcast));
+        let sepolia = &config.chains["ethereum_sepolia"].routing;
+        assert_eq!(sepolia.default, Strategy::WeightedRoundRobin);
+        assert_eq!(config.chains["base"].routing.default, Strategy::LeastConn);
+        assert!(parse("[chains.a]\nrpc_urls = []\nalgorithm = \"fastest\"").is_err());
+    }
+
+    #[test]
+    fn test_inspect_body() {
+        let config =
+            parse("[chains.base]\nrpc_urls = []\ninspect_body = false\nmax_retries = 1").unwrap();
+        assert!(!config.chains["base"].inspects_body());
+        let error = parse("[chains.base]\nrpc_urls = []\ninspect_body = false").unwrap_err();
+        assert!(error.contains("max_retries = 1"), "{}", error);
+        let error = parse(
+            r#"
+            [chains.base]
+            rpc_urls = []
+            inspect_body = false
+            blocked_methods = ["eth_sendRawTransaction"]
+            cache = { eth_chainId = { ttl = 60 } }
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.contains("blocked_methods, cache"), "{}", error);
+    }
+
+    #[test]
+    fn test_cache_policies_merge_per_method() {
+        let config = parse(
+            r#"
+            [defaults.cache]
+            "eth_get*" = { ttl = 2 }
+            eth_chainId = { ttl = 3600, scope = "global" }
+
+            [chains.sepolia]
+            rpc_urls = []
+            cache = { "eth_get*" = { enabled = false } }
+            "#,
+        )
+        .unwrap();
+
+        let cache = &config.chains["sepolia"].cache;
+        assert_eq!(cache["eth_get*"].ttl, 2);
+        assert!(!cache["eth_get*"].is_active());
+        assert!(cache["eth_chainId"].is_active());
+
+        let invalid = parse(
+            r#"
+            [chains.sepolia]
+            rpc_urls = []
+            cache = { "eth_[get" = { ttl = 2 } }
+            "#,
+        );
+        assert!(invalid.unwrap_err().contains("invalid cache pattern"));
+    }
+
+    #[test]
+    fn test_include_files() {
+        let dir = std::env::temp_dir().join(format!("rpc_lb_include_{}", std::process::id()));
+        fs::create_dir_all(dir.join("chains")).unwrap();
+        fs::write(
+            dir.join("Config.toml"),
+            r#"
+            include = ["chains/*.toml"]
+
+            [defaults]
+            timeout_ms = 5000
+
+            [chains.bitcoin]
+            rpc_urls = []
+            "#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("chains/sepolia.toml"),
+            r#"
+            [chains.sepolia]
+            rpc_urls = []
+            max_retries = 1
+            "#,
+        )
+        .unwrap();
+
+        let config = load(&dir.join("Config.toml").to_string_lossy()).unwrap();
+        assert_eq!(config.chains.len(), 2);
+        assert_eq!(config.chains["sepolia"].max_retries, Some(1));
+        assert_eq!(config.chains["sepolia"].timeout_ms, Some(5000));
+
+        fs::write(
+            dir.join("chains/duplicate.toml"),
+            r#"
+            [chains.bitcoin]
+            rpc_urls = []
+            "#,
+        )
+        .unwrap();
+        let error = load(&dir.join("Config.toml").to_string_lossy()).unwrap_err();
+        assert!(error.contains("Chain bitcoin"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url("https://Sepolia.DRPC.org/").unwrap(),
+            "https://sepolia.drpc.org/"
+        );
+        assert_eq!(
+            normalize_url("https://eth-sepolia.g.alchemy.com/v2/AbC/").unwrap(),
+            "https://eth-sepolia.g.alchemy.com/v2/AbC"
+        );
+        assert!(normalize_url("not a url").is_err());
+    }
+
+    #[test]
+    fn test_duplicate_backends() {
+        let config = r#"
+            [chains.base]
+            rpc_urls = [
+                { url = "https://1rpc.io/base", request_limit = 20, current_limit = 20 },
+                { url = "https://1RPC.io/base/", request_limit = 10, current_limit = 10 },
+                { url = "https://mainnet.base.org", request_limit = 5, current_limit = 5 },
+            ]
+            "#;
+
+        let merged = parse(config).unwrap();
+        let backends = &merged.chains["base"].rpc_urls;
+        assert_eq!(backends.len(), 2);
+        assert_eq!(backends[0].url, "https://1rpc.io/base");
+        assert_eq!(backends[0].request_limit, 30);
+        assert_eq!(backends[0].current_limit, 30);
+
+        let strict = config.replace("[chains.base]", "[chains.base]\nduplicates = \"error\"");
+        assert!(parse(&strict).unwrap_err().contains("more than once"));
+    }
+
+    #[test]
+    fn test_backend_names() {
+        let config = |names: [&str; 2]| {
+            format!(
+                r#"
+                [chains.sepolia]
+                request_limit = 10
+                rpc_urls = [
+                    {{ url = "https://1rpc.io/sepolia", name = "{}" }},
+                    {{ url = "https://sepolia.drpc.org", name = "{}" }},
+                ]
+                "#,
+                names[0], names[1]
+            )
+        };
+
+        let parsed = parse(&config(["onerpc", "drpc-1"])).unwrap();
+        assert_eq!(parsed.chains["sepolia"].rpc_urls[1].label(), "drpc-1");
+        assert!(parse(&config(["drpc", "drpc"]))
+            .unwrap_err()
+            .contains("used more than once"));
+        assert!(parse(&config(["onerpc", "d/rpc"]))
+            .unwrap_err()
+            .contains("may only use"));
+    }
+
+    #[test]
+    fn test_backend_tiers() {
+        let config = |tiers: [&str; 2]| {
+            format!(
+                r#"
+                [chains.sepolia]
+                request_limit = 10
+                rpc_urls = [
+                    {{ url = "https://1rpc.io/sepolia", {} }},
+                    {{ url = "https://sepolia.drpc.org", {} }},
+                ]
+                "#,
+                tiers[0], tiers[1]
+            )
+        };
+
+        let parsed = parse(&config(["tier = 1", "tier = 2"])).unwrap();
+        let servers = &parsed.chains["sepolia"].rpc_urls;
+        assert!(!servers[0].is_fallback());
+        assert!(servers[1].is_fallback());
+        assert!(parse(&config(["tier = 1", "tier = 3"]))
+            .unwrap_err()
+            .contains("should be 1 (primary) or 2 (fallback)"));
+        assert!(parse(&config(["tier = 1, fallback = true", "tier = 2"]))
+            .unwrap_err()
+            .contains("both in tier 1 and a fallback"));
+    }
+
+    #[test]
+    fn test_short_backend_entries() {
+        let config = parse(
+            r#"
+            [defaults]
+            request_limit = 20
+
+            [chains.sepolia]
+            request_limit = 10
+            burst_limit = 40
+            timeout_ms = 3000
+            rpc_urls = [
+                "https://1rpc.io/sepolia",
+                { url = "https://sepolia.drpc.org", request_limit = 5, timeout_ms = 500 },
+            ]
+
+            [chains.bitcoin]
+            rpc_urls = ["https://rpc.ankr.com/btc_signet"]
+            "#,
+        )
+        .unwrap();
+
+        let sepolia = &config.chains["sepolia"].rpc_urls;
+        assert_eq!(sepolia[0].request_limit, 10);
+        assert_eq!(sepolia[0].current_limit, 10);
+        assert_eq!(sepolia[0].timeout_ms, None);
+        assert_eq!(sepolia[0].burst_limit, 40);
+        assert_eq!(sepolia[1].request_limit, 5);
+        assert_eq!(sepolia[1].current_limit, 5);
+        assert_eq!(sepolia[1].timeout_ms, Some(500));
+        assert_eq!(config.chains["bitcoin"].rpc_urls[0].request_limit, 20);
+
+        let error = parse(
+            r#"
+            [chains.sepolia]
+            rpc_urls = ["https://1rpc.io/sepolia"]
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.contains("no request_limit"));
+    }
+
+    #[test]
+    fn test_repository_config_loads() {
+        let config = load(concat!(env!("CARGO_MANIFEST_DIR"), "/Config.toml")).unwrap();
+        let sepolia = &config.chains["ethereum_sepolia"].rpc_urls;
+        assert!(sepolia.iter().all(|server| server.request_limit == 20));
+        assert!(sepolia.iter().all(|server| server.current_limit == 20));
+    }
+
+    #[test]
+    fn test_url_templates() {
+        let config = parse(
+            r#"
+            [chains.sepolia]
+            request_limit = 10
+            rpc_urls = [
+                { url = "https://api.example.com/{api_key}/rpc/{chain_id}", vars = { api_key = "abc", chain_id = 11155111 } },
+            ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.chains["sepolia"].rpc_urls[0].url,
+            "https://api.example.com/abc/rpc/11155111"
+        );
+
+        let error = parse(
+            r#"
+            [chains.sepolia]
+            request_limit = 10
+            rpc_urls = ["https://api.example.com/{api_key}"]
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.contains("{api_key}"));
+    }
+
+    #[test]
+    fn test_config_without_defaults() {
+        let config = parse(
+            r#"
+            [chains.sepolia]
+            rpc_urls = []
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.chains["sepolia"].timeout_ms, None);
+        assert_eq!(config.chains["sepolia"].mirror_ratio, 0.0);
+    }
+}
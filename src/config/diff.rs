@@ -0,0 +1,304 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use serde::Serialize;
+use toml::{Table, Value};
+
+use super::normalize_url;
+use crate::{algorithms::key_health::key_hint, services::head::host_of};
+
+/// Setting names whose values are never reported, only that they changed.
+const SECRET_WORDS: [&str; 5] = ["auth", "secret", "token", "password", "authorization"];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// One difference between the running and a proposed config.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// Chain the change is in, none for `[server]`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain: Option<String>,
+    /// Host and key hint of the backend the change is in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Dotted path of the setting, none when a whole chain or backend is
+    /// added or removed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub setting: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Value>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.chain.as_deref().unwrap_or("server"))?;
+        if let Some(backend) = &self.backend {
+            write!(f, ": backend {}", backend)?;
+        }
+        if let Some(setting) = &self.setting {
+            write!(f, ": {}", setting)?;
+        }
+        match (self.kind, &self.from, &self.to) {
+            (ChangeKind::Changed, Some(from), Some(to)) => write!(f, " {} -> {}", from, to),
+            (ChangeKind::Added, _, Some(to)) => write!(f, " added: {}", to),
+            (ChangeKind::Removed, Some(from), _) => write!(f, " removed, was {}", from),
+            (ChangeKind::Added, _, _) => f.write_str(" added"),
+            (ChangeKind::Removed, _, _) => f.write_str(" removed"),
+            (ChangeKind::Changed, _, _) => f.write_str(" changed"),
+        }
+    }
+}
+
+/// Lists what applying `proposed` instead of `running` would change, both
+/// resolved by [`super::load_table`] or [`super::parse_table`]. Backends are
+/// matched by their normalized url.
+pub fn diff(running: &Table, proposed: &Table) -> Vec<Change> {
+    let mut changes = Vec::new();
+    diff_settings(
+        &mut changes,
+        None,
+        None,
+        &flatten(running.get("server")),
+        &flatten(proposed.get("server")),
+    );
+
+    let (running_chains, proposed_chains) = (chains(running), chains(proposed));
+    let names: BTreeSet<&String> = running_chains
+        .keys()
+        .chain(proposed_chains.keys())
+        .collect();
+    for name in names {
+        let kind = match (running_chains.get(name), proposed_chains.get(name)) {
+            (Some(before), Some(after)) => {
+                diff_chain(&mut changes, name, before, after);
+                continue;
+            }
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+        };
+        changes.push(Change {
+            kind,
+            chain: Some(name.clone()),
+            backend: None,
+            setting: None,
+            from: None,
+            to: None,
+        });
+    }
+    changes
+}
+
+fn chains(config: &Table) -> BTreeMap<String, Table> {
+    match config.get("chains") {
+        Some(Value::Table(chains)) => chains
+            .iter()
+            .filter_map(|(name, chain)| Some((name.clone(), chain.as_table()?.clone())))
+            .collect(),
+        _ => BTreeMap::new(),
+    }
+}
+
+fn diff_chain(changes: &mut Vec<Change>, name: &str, before: &Table, after: &Table) {
+    let settings = |chain: &Table| {
+        let mut chain = chain.clone();
+        chain.remove("rpc_urls");
+        flatten(Some(&Value::Table(chain)))
+    };
+    diff_settings(
+        changes,
+        Some(name),
+        None,
+        &settings(before),
+        &settings(after),
+    );
+
+    let (before, after) = (backends(before), backends(after));
+    let urls: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    for url in urls {
+        let backend = backend_label(url);
+        let kind = match (before.get(url), after.get(url)) {
+            (Some(before), Some(after)) => {
+                diff_settings(
+                    changes,
+                    Some(name),
+                    Some(&backend),
+                    &flatten(Some(before)),
+                    &flatten(Some(after)),
+                );
+                continue;
+            }
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+        };
+        changes.push(Change {
+            kind,
+            chain: Some(name.to_string()),
+            backend: Some(backend),
+            setting: None,
+            from: None,
+            to: None,
+        });
+    }
+}
+
+/// The backends of a chain by normalized url, without their url.
+fn backends(chain: &Table) -> BTreeMap<String, Value> {
+    let Some(Value::Array(backends)) = chain.get("rpc_urls") else {
+        return BTreeMap::new();
+    };
+    backends
+        .iter()
+        .filter_map(|backend| {
+            let mut backend = backend.as_table()?.clone();
+            let url = backend.remove("url")?;
+            let url = url.as_str()?;
+            let url = normalize_url(url).unwrap_or_else(|_| url.to_string());
+            Some((url, Value::Table(backend)))
+        })
+        .collect()
+}
+
+/// Host and key hint of a backend, which tell backends apart without
+/// exposing their keys.
+pub(super) fn backend_label(url: &str) -> String {
+    match key_hint(url).as_str() {
+        "..." => host_of(url),
+        hint => format!("{} {}", host_of(url), hint),
+    }
+}
+
+/// The leaves of `value` by dotted path. Arrays are leaves.
+fn flatten(value: Option<&Value>) -> BTreeMap<String, Value> {
+    fn walk(prefix: &str, value: &Value, leaves: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Table(table) => {
+                for (key, value) in table {
+                    let path = match prefix {
+                        "" => key.clone(),
+                        _ => format!("{}.{}", prefix, key),
+                    };
+                    walk(&path, value, leaves);
+                }
+            }
+            _ => {
+                leaves.insert(prefix.to_string(), value.clone());
+            }
+        }
+    }
+
+    let mut leaves = BTreeMap::new();
+    if let Some(value) = value {
+        walk("", value, &mut leaves);
+    }
+    leaves
+}
+
+fn diff_settings(
+    changes: &mut Vec<Change>,
+    chain: Option<&str>,
+    backend: Option<&str>,
+    before: &BTreeMap<String, Value>,
+    after: &BTreeMap<String, Value>,
+) {
+    let paths: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    for path in paths {
+        let (from, to) = (before.get(path), after.get(path));
+        let kind = match (from, to) {
+            (Some(from), Some(to)) if from == to => continue,
+            (Some(_), Some(_)) => ChangeKind::Changed,
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Removed,
+        };
+        let shown = |value: Option<&Value>| match is_secret(path) {
+            true => value.map(|_| Value::String("(redacted)".to_string())),
+            false => value.cloned(),
+        };
+        changes.push(Change {
+            kind,
+            chain: chain.map(str::to_string),
+            backend: backend.map(str::to_string),
+            setting: Some(path.clone()),
+            from: shown(from),
+            to: shown(to),
+        });
+    }
+}
+
+pub(super) fn is_secret(path: &str) -> bool {
+    let path = path.to_ascii_lowercase();
+    path.split('.')
+        .any(|segment| SECRET_WORDS.iter().any(|word| segment.contains(word)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_table;
+
+    #[test]
+    fn test_diff() {
+        let running = parse_table(
+            r#"
+            [server]
+            max_in_flight = 100
+            auth = { type = "jwt", secret = "old" }
+
+            [chains.sepolia]
+            request_limit = 10
+            rpc_urls = ["https://1rpc.io/sepolia", "https://rpc.sepolia.org"]
+            routing = { read = "latency" }
+
+            [chains.base]
+            rpc_urls = [{ url = "https://mainnet.base.org", request_limit = 5 }]
+            "#,
+        )
+        .unwrap();
+        let proposed = parse_table(
+            r#"
+            [server]
+            max_in_flight = 100
+            auth = { type = "jwt", secret = "new" }
+
+            [chains.sepolia]
+            request_limit = 20
+            rpc_urls = ["https://1RPC.io/sepolia/", "https://sepolia.drpc.org"]
+            routing = { read = "round_robin" }
+
+            [chains.polygon]
+            rpc_urls = [{ url = "https://polygon-rpc.com", request_limit = 5 }]
+            "#,
+        )
+        .unwrap();
+
+        let changes: Vec<String> = diff(&running, &proposed)
+            .iter()
+            .map(Change::to_string)
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                r#"server: auth.secret "(redacted)" -> "(redacted)""#,
+                "base removed",
+                "polygon added",
+                "sepolia: request_limit 10 -> 20",
+                r#"sepolia: routing.read "latency" -> "round_robin""#,
+                "sepolia: backend 1rpc.io ...olia: current_limit 10 -> 20",
+                "sepolia: backend 1rpc.io ...olia: request_limit 10 -> 20",
+                "sepolia: backend rpc.sepolia.org removed",
+                "sepolia: backend sepolia.drpc.org added",
+            ]
+        );
+        assert!(diff(&running, &running).is_empty());
+    }
+}
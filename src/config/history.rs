@@ -0,0 +1,192 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use toml::Table;
+
+/// The `[server.config_history]` section.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HistoryConfig {
+    /// Applied configs kept for rollbacks.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    /// Directory the kept configs are written to, so they survive restarts.
+    pub dir: Option<String>,
+}
+
+fn default_keep() -> usize {
+    10
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            keep: default_keep(),
+            dir: None,
+        }
+    }
+}
+
+/// A config as it was applied.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Version {
+    pub id: u64,
+    /// RFC 3339 time the version was applied at.
+    pub applied_at: String,
+    /// What applied it, e.g. `startup`, `reload` or `rollback to 3`.
+    pub origin: String,
+    /// The resolved config, see [`super::load_table`].
+    pub config: Table,
+}
+
+/// The configs applied last, newest at the back.
+#[derive(Debug)]
+pub struct ConfigHistory {
+    keep: usize,
+    dir: Option<PathBuf>,
+    versions: Mutex<VecDeque<Version>>,
+}
+
+impl ConfigHistory {
+    /// Starts the history from the versions kept in `dir`, if any.
+    pub fn new(config: &HistoryConfig) -> Result<Self, String> {
+        let dir = config.dir.as_ref().map(PathBuf::from);
+        let mut versions = Vec::new();
+        if let Some(dir) = &dir {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            versions = read_versions(dir)?;
+        }
+        versions.sort_by_key(|version| version.id);
+        let skip = versions.len().saturating_sub(config.keep);
+        Ok(Self {
+            keep: config.keep.max(1),
+            dir,
+            versions: Mutex::new(versions.into_iter().skip(skip).collect()),
+        })
+    }
+
+    /// Records `config` as the current version and returns it, dropping the
+    /// oldest one beyond `keep`.
+    pub fn record(&self, config: Table, origin: &str) -> Result<Version, String> {
+        let mut versions = self.versions.lock().unwrap();
+        let version = Version {
+            id: versions.back().map_or(1, |last| last.id + 1),
+            applied_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            origin: origin.to_string(),
+            config,
+        };
+        if let Some(dir) = &self.dir {
+            let content = toml::to_string(&version).map_err(|e| e.to_string())?;
+            let path = version_path(dir, version.id);
+            fs::write(&path, content)
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        }
+        versions.push_back(version.clone());
+        while versions.len() > self.keep {
+            let Some(dropped) = versions.pop_front() else {
+                break;
+            };
+            if let Some(dir) = &self.dir {
+                let _ = fs::remove_file(version_path(dir, dropped.id));
+            }
+        }
+        Ok(version)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Version> {
+        let versions = self.versions.lock().unwrap();
+        versions.iter().find(|version| version.id == id).cloned()
+    }
+
+    /// The version applied before the current one.
+    pub fn previous(&self) -> Option<Version> {
+        let versions = self.versions.lock().unwrap();
+        versions.iter().rev().nth(1).cloned()
+    }
+
+    pub fn current(&self) -> Option<Version> {
+        self.versions.lock().unwrap().back().cloned()
+    }
+
+    /// Ids, times and origins of the kept versions, oldest first.
+    pub fn list(&self) -> Vec<(u64, String, String)> {
+        let versions = self.versions.lock().unwrap();
+        versions
+            .iter()
+            .map(|version| {
+                (
+                    version.id,
+                    version.applied_at.clone(),
+                    version.origin.clone(),
+                )
+            })
+            .collect()
+    }
+}
+
+fn version_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("config-{}.toml", id))
+}
+
+fn read_versions(dir: &Path) -> Result<Vec<Version>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut versions = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let is_version = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("config-") && name.ends_with(".toml"));
+        if !is_version {
+            continue;
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let version = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        versions.push(version);
+    }
+    Ok(versions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(limit: i64) -> Table {
+        toml::from_str(&format!("[chains.sepolia]\nrequest_limit = {}", limit)).unwrap()
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = std::env::temp_dir().join(format!("rpc-lb-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let settings = HistoryConfig {
+            keep: 2,
+            dir: Some(dir.to_string_lossy().into_owned()),
+        };
+
+        let history = ConfigHistory::new(&settings).unwrap();
+        assert_eq!(history.previous(), None);
+        for limit in [10, 20, 30] {
+            history.record(config(limit), "reload").unwrap();
+        }
+        assert_eq!(history.get(1), None);
+        assert_eq!(history.previous().unwrap().config, config(20));
+        assert_eq!(history.current().unwrap().id, 3);
+
+        // The kept versions are restored after a restart.
+        let restored = ConfigHistory::new(&settings).unwrap();
+        let ids: Vec<u64> = restored.list().iter().map(|(id, _, _)| *id).collect();
+        assert_eq!(ids, vec![2, 3]);
+        assert_eq!(restored.record(config(40), "startup").unwrap().id, 4);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
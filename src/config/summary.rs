@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, HashMap};
+
+use toml::{Table, Value};
+
+use super::diff::{backend_label, is_secret};
+use crate::{
+    algorithms::round_robin::{Chains, ServerConfig},
+    auth::AuthConfig,
+};
+
+/// What the server runs with, logged at startup as `key=value` lines: one
+/// for the listener at `address`, then one per chain in name order.
+pub fn render(chains: &HashMap<String, Chains>, server: &ServerConfig, address: &str) -> String {
+    let show = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let listener = &server.listener;
+    let auth = match &server.auth {
+        Some(AuthConfig::ApiKey { .. }) => "api_key",
+        Some(AuthConfig::Jwt(_)) => "jwt",
+        None => "none",
+    };
+    let mut lines = vec![format!(
+        "listener address={} max_in_flight={} max_connections={} max_requests_per_connection={} idle_timeout_secs={} header_read_timeout_secs={} auth={}",
+        address,
+        show(server.max_in_flight.map(|value| value.to_string())),
+        show(listener.max_connections.map(|value| value.to_string())),
+        show(listener.max_requests_per_connection.map(|value| value.to_string())),
+        show(listener.idle_timeout_secs.map(|value| value.to_string())),
+        show(listener.header_read_timeout_secs.map(|value| value.to_string())),
+        auth,
+    )];
+
+    let chains: BTreeMap<_, _> = chains.iter().collect();
+    for (name, chain) in chains {
+        let routing = &chain.routing;
+        let strategy = |strategy: Option<_>| strategy.unwrap_or(routing.default).name();
+        let request_limit: u64 = chain
+            .rpc_urls
+            .iter()
+            .map(|server| server.request_limit as u64)
+            .sum();
+        lines.push(format!(
+            "chain name={} enabled={} backends={} strategy={} read={} heavy={} write={} request_limit={} timeout_ms={}",
+            name,
+            chain.is_enabled(),
+            chain.rpc_urls.len(),
+            routing.default.name(),
+            strategy(routing.read),
+            strategy(routing.heavy),
+            strategy(routing.write),
+            request_limit,
+            show(chain.timeout_ms.map(|value| value.to_string())),
+        ));
+    }
+    lines.join("\n")
+}
+
+/// `config`, resolved by [`super::load_table`], as it can be shown: secret
+/// settings and the headers added to upstream requests are redacted, urls
+/// reduced to their host and key hint.
+pub fn redacted(config: &Table) -> Table {
+    fn walk(path: &str, key: &str, value: &Value) -> Value {
+        let hidden = || Value::String("(redacted)".to_string());
+        match value {
+            _ if is_secret(path) => hidden(),
+            Value::String(url) if key == "url" => Value::String(backend_label(url)),
+            Value::Table(table) => {
+                // Headers added to upstream requests often carry keys.
+                let headers = path.ends_with("headers.request");
+                let table = table.iter().map(|(key, value)| {
+                    let value = match (headers, path) {
+                        (true, _) => hidden(),
+                        (false, "") => walk(key, key, value),
+                        (false, _) => walk(&format!("{}.{}", path, key), key, value),
+                    };
+                    (key.clone(), value)
+                });
+                Value::Table(table.collect())
+            }
+            Value::Array(values) => {
+                Value::Array(values.iter().map(|value| walk(path, key, value)).collect())
+            }
+            _ => value.clone(),
+        }
+    }
+
+    match walk("", "", &Value::Table(config.clone())) {
+        Value::Table(table) => table,
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{build, parse_table};
+
+    const CONFIG: &str = r#"
+        [server]
+        max_in_flight = 100
+        auth = { type = "jwt", secret = "hunter2" }
+        listener = { idle_timeout_secs = 60 }
+
+        [chains.sepolia]
+        request_limit = 10
+        rpc_urls = [
+            "https://eth-sepolia.g.alchemy.com/v2/abcdef123456",
+            "https://rpc.sepolia.org",
+        ]
+        routing = { read = "latency" }
+        headers = { request = { "x-api-key" = "k-1" } }
+
+        [chains.base]
+        enabled = false
+        timeout_ms = 2000
+        rpc_urls = [{ url = "https://mainnet.base.org", request_limit = 5 }]
+    "#;
+
+    #[test]
+    fn test_render() {
+        let config = build(parse_table(CONFIG).unwrap()).unwrap();
+        let summary = render(&config.chains, &config.server, "0.0.0.0:8080");
+        assert_eq!(
+            summary.lines().collect::<Vec<_>>(),
+            vec![
+                "listener address=0.0.0.0:8080 max_in_flight=100 max_connections=- max_requests_per_connection=- idle_timeout_secs=60 header_read_timeout_secs=- auth=jwt",
+                "chain name=base enabled=false backends=1 strategy=round_robin read=round_robin heavy=round_robin write=round_robin request_limit=5 timeout_ms=2000",
+                "chain name=sepolia enabled=true backends=2 strategy=round_robin read=latency heavy=round_robin write=round_robin request_limit=20 timeout_ms=-",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redacted() {
+        let config = redacted(&parse_table(CONFIG).unwrap());
+        let shown = toml::to_string(&config).unwrap();
+        for secret in ["hunter2", "abcdef123456", "k-1"] {
+            assert!(!shown.contains(secret), "{} shown", secret);
+        }
+        assert_eq!(config["server"]["auth"].as_str(), Some("(redacted)"));
+        assert_eq!(config["server"]["max_in_flight"].as_integer(), Some(100));
+        let sepolia = &config["chains"]["sepolia"];
+        assert_eq!(
+            sepolia["rpc_urls"][0]["url"].as_str(),
+            Some("eth-sepolia.g.alchemy.com ...3456")
+        );
+        assert_eq!(
+            sepolia["rpc_urls"][1]["request_limit"].as_integer(),
+            Some(10)
+        );
+        assert_eq!(
+            sepolia["headers"]["request"]["x-api-key"].as_str(),
+            Some("(redacted)")
+        );
+    }
+}
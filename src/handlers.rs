@@ -1 +1,9 @@
+pub mod admin;
+pub mod gas;
+pub mod head;
+pub mod keys;
 pub mod load_balancer;
+pub mod metrics;
+pub mod status;
+pub mod tx_lookup;
+pub mod tx_status;
@@ -0,0 +1,333 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    response::Response,
+};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    algorithms::{round_robin::LoadBalancer, routing::Strategy},
+    config,
+    services::{cache::PurgeFilter, chaos::Fault, request_log::LogSampling},
+};
+
+#[derive(Deserialize)]
+pub struct TopConsumersQuery {
+    limit: Option<usize>,
+}
+
+/// Lists the consumers sending the most requests, with their error rate and
+/// method mix.
+pub async fn top_consumers(
+    State(state): State<Arc<LoadBalancer>>,
+    Query(query): Query<TopConsumersQuery>,
+) -> Response<Body> {
+    let top = state.consumers.top(query.limit.unwrap_or(10));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&top).unwrap()))
+        .unwrap()
+}
+
+/// Checks a proposed config sent as TOML and lists what applying it would
+/// change, without applying anything.
+pub async fn validate_config(
+    State(state): State<Arc<LoadBalancer>>,
+    body: String,
+) -> Response<Body> {
+    let validated =
+        config::parse_table(&body).and_then(|table| config::build(table.clone()).map(|_| table));
+    let (status, report) = match validated {
+        Ok(table) => (
+            StatusCode::OK,
+            json!({
+                "valid": true,
+                "changes": config::diff::diff(&state.applied_config, &table),
+            }),
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            json!({ "valid": false, "error": e }),
+        ),
+    };
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(report.to_string()))
+        .unwrap()
+}
+
+/// Drops the cached responses matching the `chain`, `method` and `key` query
+/// filters, e.g. after a backend served bad data that got cached.
+pub async fn purge_cache(
+    State(state): State<Arc<LoadBalancer>>,
+    Query(filter): Query<PurgeFilter>,
+) -> Response<Body> {
+    let (status, report) = match state.cache.purge(&filter) {
+        Ok(purged) => {
+            println!("Purged {} cached responses ({:?})", purged, filter);
+            (StatusCode::OK, json!({ "purged": purged }))
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, json!({ "error": e })),
+    };
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(report.to_string()))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+pub struct CacheOnlyQuery {
+    enabled: bool,
+    /// Every chain when left out.
+    chain: Option<String>,
+}
+
+/// Turns cache only mode on or off for a chain, or all of them: while on,
+/// backends are not called and cacheable reads are answered from the cache,
+/// expired entries included.
+pub async fn cache_only(
+    State(state): State<Arc<LoadBalancer>>,
+    Query(query): Query<CacheOnlyQuery>,
+) -> Response<Body> {
+    let chain = query.chain.as_deref();
+    if let Some(chain) = chain.filter(|chain| !state.load_balancers.contains_key(*chain)) {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({ "error": format!("Unknown chain {}", chain) }).to_string(),
+            ))
+            .unwrap();
+    }
+    state.cache.set_cache_only(chain, query.enabled);
+    println!(
+        "Cache only mode {} on {}",
+        if query.enabled { "enabled" } else { "disabled" },
+        chain.unwrap_or("every chain")
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            json!({ "chain": chain, "cache_only": query.enabled }).to_string(),
+        ))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+pub struct ChaosQuery {
+    chain: String,
+    backend: String,
+    latency_ms: Option<u64>,
+    error_rate: Option<f64>,
+    error_status: Option<u16>,
+}
+
+/// Injects latency or errors into a backend of `chain`, referred to by its
+/// `name` or by its position in `rpc_urls`, in builds with the `chaos`
+/// feature. A query without `latency_ms` and `error_rate` stops injecting.
+pub async fn chaos(
+    State(state): State<Arc<LoadBalancer>>,
+    Query(query): Query<ChaosQuery>,
+) -> Response<Body> {
+    let url = state
+        .load_balancers
+        .get(&query.chain)
+        .and_then(|round_robin| {
+            round_robin
+                .labels
+                .iter()
+                .position(|label| label == &query.backend)
+                .or_else(|| query.backend.parse().ok())
+                .and_then(|i| round_robin.endpoints.get(i).cloned())
+        });
+    let Some(url) = url else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .body(Body::from(
+                json!({ "error": format!("Unknown backend {} of chain {}", query.backend, query.chain) })
+                    .to_string(),
+            ))
+            .unwrap();
+    };
+    let fault = Fault {
+        latency_ms: query.latency_ms.unwrap_or_default(),
+        error_rate: query.error_rate.unwrap_or_default(),
+        error_status: query.error_status.unwrap_or(Fault::default().error_status),
+    };
+
+    let (status, report) = match state.chaos.set(&query.chain, &url, fault.clone()) {
+        Ok(()) => {
+            println!(
+                "Chaos on backend {} of chain {}: {:?}",
+                query.backend, query.chain, fault
+            );
+            (
+                StatusCode::OK,
+                json!({ "chain": query.chain, "backend": query.backend, "fault": fault }),
+            )
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, json!({ "error": e })),
+    };
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(report.to_string()))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+pub struct KillSwitchQuery {
+    chain: String,
+    method: Option<String>,
+    #[serde(default)]
+    writes: bool,
+    enabled: bool,
+}
+
+/// Stops forwarding `method`, or every write with `writes=true`, on `chain`,
+/// or lifts the block with `enabled=false`. Lasts until the config is
+/// reloaded, which restores the chain's `blocked_methods` and `block_writes`.
+pub async fn kill_switch(
+    State(state): State<Arc<LoadBalancer>>,
+    Query(query): Query<KillSwitchQuery>,
+) -> Response<Body> {
+    let (status, report) = if !state.load_balancers.contains_key(&query.chain) {
+        (
+            StatusCode::NOT_FOUND,
+            json!({ "error": format!("Unknown chain {}", query.chain) }),
+        )
+    } else if query.method.is_none() && !query.writes {
+        (
+            StatusCode::BAD_REQUEST,
+            json!({ "error": "Expected a method or writes=true" }),
+        )
+    } else {
+        let blocked = state.kill_switch.set(
+            &query.chain,
+            query.method.as_deref(),
+            query.writes,
+            query.enabled,
+        );
+        println!("Kill switch of chain {}: {:?}", query.chain, blocked);
+        (
+            StatusCode::OK,
+            json!({ "chain": query.chain, "blocked": blocked }),
+        )
+    };
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(report.to_string()))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+pub struct RequestLogQuery {
+    chain: String,
+    backend: String,
+    success: Option<f64>,
+    failure: Option<f64>,
+}
+
+/// Sets the shares of the successful and failed attempts on a backend of
+/// `chain`, referred to by its `name` or host, which are logged. Rates left
+/// out log everything. Lasts until the config is reloaded, which restores the
+/// backends' `log`.
+pub async fn request_log(
+    State(state): State<Arc<LoadBalancer>>,
+    Query(query): Query<RequestLogQuery>,
+) -> Response<Body> {
+    let known = state
+        .load_balancers
+        .get(&query.chain)
+        .is_some_and(|round_robin| round_robin.labels.contains(&query.backend));
+    let sampling = LogSampling {
+        success: query.success.unwrap_or(1.0),
+        failure: query.failure.unwrap_or(1.0),
+    };
+    let (status, report) = if !known {
+        (
+            StatusCode::NOT_FOUND,
+            json!({ "error": format!("Unknown backend {} of chain {}", query.backend, query.chain) }),
+        )
+    } else {
+        match state
+            .request_log
+            .set(&query.chain, &query.backend, sampling)
+        {
+            Ok(()) => {
+                println!(
+                    "Request log of backend {} of chain {}: {:?}",
+                    query.backend, query.chain, sampling
+                );
+                (
+                    StatusCode::OK,
+                    json!({ "chain": query.chain, "backend": query.backend, "log": sampling }),
+                )
+            }
+            Err(e) => (StatusCode::BAD_REQUEST, json!({ "error": e })),
+        }
+    };
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(report.to_string()))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+pub struct AlgorithmBody {
+    algorithm: Option<Strategy>,
+}
+
+/// Switches the strategy of `chain` to the `algorithm` of the JSON body, or
+/// back to its configured one with `null`, without a restart. Methods and
+/// categories with a strategy of their own keep it. Lasts until the config
+/// is reloaded.
+pub async fn set_algorithm(
+    Path(chain): Path<String>,
+    State(state): State<Arc<LoadBalancer>>,
+    body: String,
+) -> Response<Body> {
+    let (status, report) = match serde_json::from_str::<AlgorithmBody>(&body) {
+        Err(e) => (StatusCode::BAD_REQUEST, json!({ "error": e.to_string() })),
+        Ok(body) => match state.set_algorithm(&chain, body.algorithm) {
+            Err(e) => (StatusCode::NOT_FOUND, json!({ "error": e })),
+            Ok(()) => {
+                let algorithm = body.algorithm.unwrap_or_else(|| {
+                    state
+                        .chain_config(&chain)
+                        .map(|config| config.routing.default)
+                        .unwrap_or_default()
+                });
+                println!("Algorithm of chain {}: {}", chain, algorithm.name());
+                (
+                    StatusCode::OK,
+                    json!({ "chain": chain, "algorithm": algorithm.name() }),
+                )
+            }
+        },
+    };
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(report.to_string()))
+        .unwrap()
+}
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::Response,
+};
+use reqwest::StatusCode;
+
+use crate::algorithms::round_robin::LoadBalancer;
+
+pub async fn gas(
+    Path(chain): Path<String>,
+    State(state): State<Arc<LoadBalancer>>,
+) -> Response<Body> {
+    let Some(round_robin) = state.load_balancers.get(&chain) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Invalid chain: {}", chain)))
+            .unwrap();
+    };
+
+    match state.gas_oracle.suggest(&chain, round_robin).await {
+        Some(suggestion) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&suggestion).unwrap()))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("Content-Type", "application/json")
+            .body(Body::from("No RPC endpoint returned a gas price"))
+            .unwrap(),
+    }
+}
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::Response,
+};
+use reqwest::StatusCode;
+
+use crate::{algorithms::round_robin::LoadBalancer, services::head};
+
+pub async fn head(
+    Path(chain): Path<String>,
+    State(state): State<Arc<LoadBalancer>>,
+) -> Response<Body> {
+    let Some(round_robin) = state.load_balancers.get(&chain) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Invalid chain: {}", chain)))
+            .unwrap();
+    };
+
+    let urls = round_robin.server_urls();
+
+    let heads = head::fetch_heads(round_robin, &urls).await;
+    let summary = head::summarize(&urls, heads);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&summary).unwrap()))
+        .unwrap()
+}
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::Response,
+};
+use reqwest::StatusCode;
+
+use crate::algorithms::round_robin::LoadBalancer;
+
+/// Reports the health of the provider key of every backend of `chain`, so
+/// revoked or exhausted keys stand out.
+pub async fn keys(
+    Path(chain): Path<String>,
+    State(state): State<Arc<LoadBalancer>>,
+) -> Response<Body> {
+    let Some(round_robin) = state.load_balancers.get(&chain) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Invalid chain: {}", chain)))
+            .unwrap();
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&round_robin.key_reports()).unwrap(),
+        ))
+        .unwrap()
+}
+
+/// Reports the key health of one backend of `chain`, referred to by its
+/// `name` or by its position in `rpc_urls`.
+pub async fn backend_key(
+    Path((chain, backend)): Path<(String, String)>,
+    State(state): State<Arc<LoadBalancer>>,
+) -> Response<Body> {
+    let Some(round_robin) = state.load_balancers.get(&chain) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Invalid chain: {}", chain)))
+            .unwrap();
+    };
+    let report = round_robin.key_reports().into_iter().find(|report| {
+        report.name.as_deref() == Some(backend.as_str()) || backend.parse() == Ok(report.index)
+    });
+    let Some(report) = report else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Unknown backend: {}", backend)))
+            .unwrap();
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&report).unwrap()))
+        .unwrap()
+}
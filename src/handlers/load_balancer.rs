@@ -1,16 +1,52 @@
 use std::{
     convert::Infallible,
-    sync::{Arc, Mutex},
-    time::Duration,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
-use crate::algorithms::round_robin::{LoadBalancer, RoundRobin};
+use crate::{
+    algorithms::{
+        key_health,
+        round_robin::{LoadBalancer, PoolStatus, RoundRobin},
+        routing::Strategy,
+    },
+    auth::{Principal, SlaClass},
+    services::{
+        cache::{self, CacheControl, CacheKey, CachedResponse},
+        filters,
+        geo::ClientAddr,
+        headers::HeaderPolicy,
+        mirror,
+        replay::{self, Guarded},
+        response_guard::{self, ResponseChecks},
+        tx_dedup::Submission,
+        tx_rebroadcast,
+    },
+    transport::UpstreamRequest,
+};
 use axum::{
-    body::{self, Body, Bytes},
-    extract::{Path, State},
+    body::{self, Body},
+    extract::{ConnectInfo, Path, State},
+    http::{response::Builder, Method},
     response::Response,
 };
-use reqwest::{Method, RequestBuilder, Response as ReqwestResponse, StatusCode};
+use chrono::{SecondsFormat, Utc};
+use http_body_util::LengthLimitError;
+use reqwest::{
+    header::{
+        HeaderValue, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+        ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, AGE, ALLOW, CACHE_CONTROL,
+        CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER,
+    },
+    Response as ReqwestResponse, StatusCode,
+};
+use serde::{de::IgnoredAny, Serialize};
+use serde_json::{json, Value};
+use tokio::{sync::mpsc, task::JoinSet, time};
+
+/// Largest request body read from clients, unless the chain sets
+/// `max_request_bytes`.
+const DEFAULT_MAX_REQUEST_BYTES: usize = 1024 * 1024;
 
 #[derive(Debug, PartialEq)]
 enum RpcErrorStatus {
@@ -48,10 +84,63 @@ pub async fn load_balancer(
     Path(chain): Path<String>,
     State(state): State<Arc<LoadBalancer>>,
     request: axum::http::Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    // Authenticated requests are accounted to their principal.
+    let consumer = match request.extensions().get::<Principal>() {
+        Some(principal) => principal.id.clone(),
+        None => state.consumers.identify(request.headers()),
+    };
+    let mut rpc_method = None;
+    let Ok(mut response) = forward(chain.clone(), state.clone(), request, &mut rpc_method).await;
+    if state.load_balancers.contains_key(&chain) {
+        state.consumers.record(
+            &state.metrics,
+            &consumer,
+            &chain,
+            rpc_method.as_deref(),
+            response.status().as_u16() >= 400,
+        );
+    }
+    if let Some(config) = state.chain_config(&chain) {
+        let uncached = config
+            .cache_control
+            .as_ref()
+            .and_then(CacheControl::uncached);
+        if let Some(uncached) = uncached {
+            let headers = response.headers_mut();
+            if !headers.contains_key(CACHE_CONTROL) {
+                headers.insert(CACHE_CONTROL, uncached);
+            }
+        }
+        config.headers.apply_to_response(response.headers_mut());
+    }
+    // Every backend is over the chain's latency budget, let callers know.
+    if state
+        .load_balancers
+        .get(&chain)
+        .is_some_and(|round_robin| round_robin.latency_degraded())
+    {
+        state
+            .metrics
+            .inc("rpc_lb_latency_degraded_total", &[("chain", &chain)]);
+        response
+            .headers_mut()
+            .insert("X-Latency-Degraded", HeaderValue::from_static("true"));
+    }
+    Ok(response)
+}
+
+/// Forwards `request`, setting `rpc_method` to the method it calls once the
+/// body was parsed.
+async fn forward(
+    chain: String,
+    state: Arc<LoadBalancer>,
+    request: axum::http::Request<Body>,
+    rpc_method: &mut Option<String>,
 ) -> Result<Response<Body>, Infallible> {
     let round_robin = {
         let rr = state.load_balancers.get(&chain);
-        if let None = rr {
+        if rr.is_none() {
             return Ok(Response::builder()
                 .status(StatusCode::BAD_REQUEST)
                 .header("Content-Type", "application/json")
@@ -60,77 +149,952 @@ pub async fn load_balancer(
         }
         rr.unwrap().clone()
     };
+    // Opaque chains are proxied as-is, whatever the method. JSON-RPC only
+    // travels in POST bodies, so the balancer answers anything else itself.
+    let opaque = state
+        .chain_config(&chain)
+        .is_some_and(|config| config.opaque);
+    let inspect_body = state
+        .chain_config(&chain)
+        .is_none_or(|config| config.inspects_body());
+    if !opaque && request.method() == Method::OPTIONS {
+        return Ok(preflight());
+    }
+    if !state.is_enabled(&chain) {
+        return Ok(Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Chain {} is disabled", chain)))
+            .unwrap());
+    }
+    if !round_robin.is_ready() {
+        return Ok(Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Chain {} is not ready", chain)))
+            .unwrap());
+    }
+    // HEAD checks the chain is up, which it is past the checks above.
+    if !opaque && request.method() == Method::HEAD {
+        return Ok(Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::empty())
+            .unwrap());
+    }
+    if !opaque && request.method() != Method::POST {
+        let method = state.metrics.method_label(request.method().as_str());
+        state.metrics.inc(
+            "rpc_lb_method_rejected_total",
+            &[("chain", &chain), ("method", &method)],
+        );
+        return Ok(method_not_allowed(request.method()));
+    }
 
-    let max_size = 1024 * 1024;
+    let max_request_bytes = state
+        .chain_config(&chain)
+        .and_then(|config| config.max_request_bytes)
+        .unwrap_or(DEFAULT_MAX_REQUEST_BYTES);
 
-    let method = Arc::new(request.method().clone());
+    let report_attempts = state
+        .chain_config(&chain)
+        .is_some_and(|config| config.report_attempts);
+    let debug_headers = state
+        .chain_config(&chain)
+        .is_some_and(|config| config.debug_headers);
+    let max_response_bytes = state
+        .chain_config(&chain)
+        .and_then(|config| config.max_response_bytes);
+    let cache_control = state
+        .chain_config(&chain)
+        .and_then(|config| config.cache_control.clone());
 
-    let body_bytes = {
-        let body = request.into_body();
-        let body_bytes = body::to_bytes(body, max_size).await;
-        if let Err(_) = body_bytes {
+    let header_rule = state
+        .chain_config(&chain)
+        .and_then(|config| config.routing.header_rule(request.headers()))
+        .cloned();
+    // The class of the principal wins over the one a header asks for.
+    let sla = request
+        .extensions()
+        .get::<Principal>()
+        .and_then(|principal| principal.sla_class.as_ref())
+        .or(header_rule
+            .as_ref()
+            .and_then(|rule| rule.sla_class.as_ref()))
+        .and_then(|class| state.sla_classes.get(class))
+        .cloned();
+    let method = request.method().clone();
+    let if_none_match = request
+        .headers()
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let client_content_type = request.headers().get(CONTENT_TYPE).cloned();
+    let client = request
+        .extensions()
+        .get::<ConnectInfo<ClientAddr>>()
+        .map(|ConnectInfo(ClientAddr(addr))| addr.ip());
+    let region = state
+        .regions
+        .region_of(request.headers(), client)
+        .filter(|_| round_robin.has_regions());
+    let affinity = state
+        .chain_config(&chain)
+        .and_then(|config| config.routing.sticky.as_ref())
+        .and_then(|sticky| sticky.key_of(request.headers(), client));
+    let headers = state
+        .chain_config(&chain)
+        .map(|config| config.headers.upstream_headers(request.headers(), client))
+        .unwrap_or_default();
+
+    let body_bytes = match body::to_bytes(request.into_body(), max_request_bytes).await {
+        Ok(body_bytes) => body_bytes,
+        Err(e) if is_length_limit(&e) => {
+            state
+                .metrics
+                .inc("rpc_lb_request_too_large_total", &[("chain", &chain)]);
+            return Ok(too_large(max_request_bytes));
+        }
+        Err(_) => {
             return Ok(Response::builder()
                 .status(StatusCode::BAD_REQUEST)
                 .header("Content-Type", "application/json")
                 .body(Body::from("Failed to read request body"))
                 .unwrap());
         }
-        Arc::new(body_bytes.unwrap_or_default())
     };
 
-    let forwarded_request = retry_with_backoff(method, body_bytes, round_robin).await;
+    state.metrics.observe_size(
+        "rpc_lb_request_bytes",
+        &[("chain", &chain)],
+        body_bytes.len(),
+    );
 
-    match forwarded_request {
-        Some(response) => {
-            let status = response.status();
-            let body_bytes = response.bytes().await.unwrap_or_default();
-            let forwarded_response = Response::builder()
-                .status(status)
-                .header("Content-Type", "application/json")
-                .body(Body::from(body_bytes))
-                .unwrap();
-            return Ok(forwarded_response);
+    // Opaque chains are proxied as-is, and chains not inspecting bodies
+    // forwarded unparsed, without any of the method based features.
+    let request_json = inspect_body
+        .then(|| serde_json::from_slice::<Value>(&body_bytes).ok())
+        .flatten();
+    *rpc_method = request_json.as_ref().and_then(|request| match request {
+        Value::Array(_) => Some("batch".to_string()),
+        _ => request["method"].as_str().map(str::to_string),
+    });
+
+    // A batch is refused as a whole when any of its calls is blocked.
+    let blocked = request_json.as_ref().and_then(|request| {
+        let calls = match request {
+            Value::Array(calls) => calls.iter().collect(),
+            call => vec![call],
+        };
+        calls
+            .into_iter()
+            .filter_map(|call| call["method"].as_str())
+            .find(|method| state.kill_switch.blocks(&chain, method))
+    });
+    if let Some(method) = blocked {
+        state.metrics.inc(
+            "rpc_lb_blocked_requests_total",
+            &[("chain", &chain), ("method", method)],
+        );
+        return Ok(method_blocked(&chain, method));
+    }
+
+    // Anything but JSON-RPC, e.g. protobuf or form-encoded bodies, is passed
+    // through with its content type kept both ways. Unparsed bodies of
+    // chains not inspecting them are taken to be JSON-RPC.
+    let passthrough = opaque || (inspect_body && request_json.as_ref().is_none());
+    let content_type = client_content_type
+        .filter(|_| passthrough)
+        .unwrap_or(HeaderValue::from_static("application/json"));
+
+    let submitted_raw = request_json
+        .as_ref()
+        .and_then(tx_rebroadcast::raw_transaction);
+    let dedup_window = state
+        .chain_config(&chain)
+        .and_then(|config| config.tx_dedup_secs)
+        .map(Duration::from_secs);
+    // Raw transactions whose response is looked at, for tracking or dedup.
+    let raw_transaction = submitted_raw
+        .clone()
+        .filter(|_| state.tx_tracker.is_enabled(&chain) || dedup_window.is_some());
+    // Identical submissions wait for this one's response until it is dropped,
+    // after the response was kept below.
+    let _dedup_pending = match (&raw_transaction, request_json.as_ref(), dedup_window) {
+        (Some(raw), Some(request), Some(_)) => {
+            match state.tx_dedup.submit(&chain, raw, &request["id"]).await {
+                Submission::Duplicate(body) => {
+                    state
+                        .metrics
+                        .inc("rpc_lb_duplicate_tx_total", &[("chain", &chain)]);
+                    return Ok(Response::builder()
+                        .status(StatusCode::OK)
+                        .header("Content-Type", "application/json")
+                        .body(Body::from(body))
+                        .unwrap());
+                }
+                Submission::First(pending) => Some(pending),
+            }
+        }
+        _ => None,
+    };
+    let mirror_ratio = state
+        .chain_config(&chain)
+        .map(|config| config.mirror_ratio)
+        .unwrap_or_default();
+    let mirror_method = match request_json.as_ref() {
+        Some(request)
+            if mirror::is_deterministic(request) && mirror::should_sample(mirror_ratio) =>
+        {
+            request["method"].as_str().map(str::to_string)
         }
-        None => {
+        _ => None,
+    };
+
+    let cache_key = request_json.as_ref().and_then(|request| {
+        let config = state.chain_config(&chain)?;
+        let policy = cache::policy_for(&config.cache, request["method"].as_str()?)
+            .filter(|policy| policy.is_active())?;
+        let key = CacheKey::new(&chain, policy, request)?;
+        Some((key, Duration::from_secs(policy.ttl)))
+    });
+    // In cache only mode no backend is called, reads get whatever is cached.
+    if state.cache.is_cache_only(&chain) {
+        let cached = cache_key
+            .as_ref()
+            .zip(request_json.as_ref())
+            .and_then(|((key, _), request)| state.cache.get_stale(key, &request["id"]));
+        let Some(cached) = cached else {
             return Ok(Response::builder()
                 .status(StatusCode::SERVICE_UNAVAILABLE)
                 .header("Content-Type", "application/json")
-                .body(Body::from("Service temporarily unavailable. This may be due to no available RPC endpoints, invalid request format, or missing method specification."))
+                .body(Body::from(format!(
+                    "Chain {} only serves cached reads right now",
+                    chain
+                )))
                 .unwrap());
+        };
+        state
+            .metrics
+            .inc("rpc_lb_cache_hits_total", &[("chain", &chain)]);
+        return Ok(cached_response(
+            cached,
+            cache_control.as_ref(),
+            debug_headers,
+            if_none_match.as_deref(),
+        ));
+    }
+    if let (Some((key, _)), Some(request)) = (&cache_key, request_json.as_ref()) {
+        if let Some(cached) = state.cache.get(key, &request["id"]) {
+            state
+                .metrics
+                .inc("rpc_lb_cache_hits_total", &[("chain", &chain)]);
+            return Ok(cached_response(
+                cached,
+                cache_control.as_ref(),
+                debug_headers,
+                if_none_match.as_deref(),
+            ));
+        }
+        state
+            .metrics
+            .inc("rpc_lb_cache_misses_total", &[("chain", &chain)]);
+    }
+
+    // Journaled only once a backend is going to be asked, answers from the
+    // cache never reach one to acknowledge the entry.
+    let journal_entry = match submitted_raw {
+        Some(raw) => match state.tx_journal.record(&chain, &raw) {
+            Ok(entry) => entry,
+            Err(e) => {
+                println!("{}", e);
+                return Ok(Response::builder()
+                    .status(StatusCode::SERVICE_UNAVAILABLE)
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(e))
+                    .unwrap());
+            }
+        },
+        None => None,
+    };
+
+    let rpc_method = request_json.as_ref().and_then(|r| r["method"].as_str());
+    let algorithm = round_robin.algorithm();
+    let policy = state
+        .chain_config(&chain)
+        .map(|config| UpstreamPolicy {
+            strategy: config
+                .routing
+                .strategy_with(rpc_method, algorithm.unwrap_or(config.routing.default)),
+            timeout: config.timeout_ms.map(Duration::from_millis),
+            max_retries: config.max_retries,
+            region: None,
+            tag: None,
+            sla: None,
+            backend: None,
+            affinity: None,
+            replay: None,
+        })
+        .unwrap_or_else(|| UpstreamPolicy {
+            strategy: algorithm.unwrap_or_default(),
+            ..Default::default()
+        });
+    // A filter only exists on the backend which installed it, so its polls
+    // go there, even while that backend drains after a config change.
+    let filter = request_json.as_ref().and_then(filters::filter_of);
+    let pinned = filter.and_then(|filter| state.filters.route(&chain, filter, &round_robin));
+    let creates_filter = request_json.as_ref().is_some_and(filters::creates_filter);
+    let upstream = pinned
+        .as_ref()
+        .map_or_else(|| round_robin.clone(), |(pool, _)| pool.clone());
+    // JSON-RPC bodies left unparsed may be writes, which SLA classes must not
+    // retry or hedge either.
+    let replayable = (inspect_body || opaque)
+        && rpc_method.is_none_or(|method| {
+            replay::is_idempotent(method)
+                || state
+                    .chain_config(&chain)
+                    .is_some_and(|config| config.replayable_methods.iter().any(|m| m == method))
+        });
+    let policy = UpstreamPolicy {
+        region,
+        tag: header_rule.and_then(|rule| rule.tag),
+        sla,
+        backend: pinned.map(|(_, url)| url),
+        affinity,
+        replay: (!replayable).then(|| state.replays.guard(&chain, &body_bytes)),
+        ..policy
+    };
+    let checks = Arc::new(ResponseChecks {
+        json_rpc: !passthrough,
+        required_fields: rpc_method
+            .and_then(|method| state.chain_config(&chain)?.schema_for(method)),
+    });
+
+    let upstream_request = Arc::new(UpstreamRequest {
+        method,
+        headers,
+        content_type,
+        body: body_bytes,
+    });
+    let header_policy = state.chain_config(&chain).map(|config| &config.headers);
+    let request_id_headers: Arc<[String]> = header_policy
+        .map(|policy| policy.provider_request_id.as_slice())
+        .unwrap_or_default()
+        .into();
+    let outcome = retry_with_backoff(
+        upstream_request.clone(),
+        upstream,
+        policy,
+        checks,
+        request_id_headers,
+    )
+    .await;
+
+    let attempt_count = outcome.attempts.len();
+    for attempt in &outcome.attempts {
+        if let Some(backend) = &attempt.backend {
+            let status = attempt
+                .status
+                .map_or("error".to_string(), |status| status.to_string());
+            state.metrics.inc(
+                "rpc_lb_backend_attempts_total",
+                &[("chain", &chain), ("backend", backend), ("status", &status)],
+            );
+            let succeeded =
+                attempt.error.is_none() && attempt.status.is_some_and(|s| (200..300).contains(&s));
+            if state.request_log.should_log(&chain, backend, succeeded) {
+                match &attempt.error {
+                    Some(e) => println!(
+                        "Chain {} backend {} failed in {}ms: {}",
+                        chain, backend, attempt.latency_ms, e
+                    ),
+                    None => println!(
+                        "Chain {} backend {} answered {} in {}ms",
+                        chain, backend, status, attempt.latency_ms
+                    ),
+                }
+            }
+        }
+    }
+    let echoed_request_id = header_policy
+        .filter(|policy| policy.echo_provider_request_id)
+        .and_then(|_| outcome.provider_request_id())
+        .and_then(|id| HeaderValue::from_str(id).ok());
+    let served_builder = |served_by: &str, status: StatusCode| {
+        let mut builder = Response::builder().status(status);
+        if let Some(id) = &echoed_request_id {
+            builder = builder.header(PROVIDER_REQUEST_ID, id);
+        }
+        if debug_headers {
+            with_debug_headers(builder, &round_robin.label_of(served_by), attempt_count)
+        } else {
+            builder
+        }
+    };
+
+    // Left in the journal for a replay when no backend answered.
+    if let (Some(entry), Some(_)) = (&journal_entry, &outcome.served) {
+        state.tx_journal.acknowledge(entry);
+    }
+
+    let Some((served_by, response)) = outcome.served else {
+        // Only when no backend was tried is the pool to blame rather than
+        // the upstream responses.
+        let pool_status = if outcome.attempts.iter().all(|a| a.backend.is_none()) {
+            round_robin.pool_status()
+        } else {
+            PoolStatus::Available
+        };
+        state.metrics.inc(
+            "rpc_lb_unavailable_total",
+            &[("chain", &chain), ("reason", pool_status.reason())],
+        );
+        let mut response = unavailable(
+            &chain,
+            &pool_status,
+            report_attempts.then_some(&outcome.attempts),
+        );
+        if let Some(id) = echoed_request_id {
+            response.headers_mut().insert(PROVIDER_REQUEST_ID, id);
+        }
+        return Ok(response);
+    };
+
+    if let Err(e) = response_guard::check_declared_size(&response, max_response_bytes) {
+        return Ok(bad_gateway(e));
+    }
+    // Streamed responses are only counted when their size is declared.
+    let observe_response = |bytes: usize| {
+        state
+            .metrics
+            .observe_size("rpc_lb_response_bytes", &[("chain", &chain)], bytes)
+    };
+    let declared_size = response.content_length().map(|bytes| bytes as usize);
+
+    let status = response.status();
+    let mut builder = served_builder(&served_by, status);
+    if let Some(headers) = builder.headers_mut() {
+        headers.extend(response_guard::end_to_end_headers(response.headers()));
+    }
+
+    if passthrough {
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .cloned()
+            .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+        if let Some(bytes) = declared_size {
+            observe_response(bytes);
+        }
+        return Ok(builder
+            .header(CONTENT_TYPE, content_type)
+            .body(response_guard::limited_body(response, max_response_bytes))
+            .unwrap());
+    }
+
+    if let Some(filter) = filter.filter(|_| rpc_method == Some("eth_uninstallFilter")) {
+        state.filters.unpin(&chain, filter);
+    }
+
+    // Nothing needs to look at the response, so it is streamed back as it arrives.
+    if raw_transaction.is_none()
+        && mirror_method.is_none()
+        && cache_key.is_none()
+        && !creates_filter
+    {
+        if let Some(bytes) = declared_size {
+            observe_response(bytes);
+        }
+        return Ok(builder
+            .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+            .body(response_guard::limited_body(response, max_response_bytes))
+            .unwrap());
+    }
+
+    let claims_json = response_guard::claims_json(response.headers());
+    let body_bytes = match response_guard::read_limited(response, max_response_bytes).await {
+        Ok(body_bytes) => body_bytes,
+        Err(e) => return Ok(bad_gateway(e)),
+    };
+    observe_response(body_bytes.len());
+    if claims_json && serde_json::from_slice::<IgnoredAny>(&body_bytes).is_err() {
+        return Ok(bad_gateway(
+            "Upstream returned a malformed JSON response".to_string(),
+        ));
+    }
+
+    if let Some((key, ttl)) = cache_key.filter(|_| status == StatusCode::OK) {
+        if let Some(etag) = state.cache.insert(key, ttl, &body_bytes) {
+            builder = builder.header(ETAG, etag);
+            if let Some(cache_control) = &cache_control {
+                builder = builder.header(CACHE_CONTROL, cache_control.cacheable(ttl));
+            }
+        }
+    }
+
+    if creates_filter && status == StatusCode::OK {
+        if let Some(id) = filters::created_filter(&body_bytes) {
+            state.filters.pin(&chain, &id, &served_by);
+        }
+    }
+
+    if let Some(raw) = raw_transaction {
+        if let Some(hash) = tx_rebroadcast::submitted_tx_hash(&body_bytes) {
+            state.tx_tracker.track(&chain, &hash, &raw);
+        }
+        if let Some(window) = dedup_window.filter(|_| status == StatusCode::OK) {
+            state.tx_dedup.insert(&chain, &raw, window, &body_bytes);
+        }
+    }
+
+    if let Some(mirror_method) = mirror_method {
+        let served_result = serde_json::from_slice::<Value>(&body_bytes)
+            .ok()
+            .and_then(|response| response.get("result").cloned());
+        // The mirror's token is only taken once there is a result to compare.
+        let mirror_url = served_result
+            .is_some()
+            .then(|| round_robin.take_other(&served_by))
+            .flatten();
+        if let (Some(served_result), Some(mirror_url)) = (served_result, mirror_url) {
+            tokio::spawn(mirror::cross_check(
+                round_robin.clone(),
+                state.metrics.clone(),
+                chain.clone(),
+                mirror_method,
+                mirror_url,
+                upstream_request.body.clone(),
+                served_result,
+            ));
+        }
+    }
+
+    let forwarded_response = builder
+        .header("Content-Type", "application/json")
+        .body(Body::from(body_bytes))
+        .unwrap();
+    Ok(forwarded_response)
+}
+
+/// Carries the request id of the provider's response, see
+/// [`HeaderPolicy::provider_request_id`].
+const PROVIDER_REQUEST_ID: &str = "x-provider-request-id";
+
+/// Whether reading a request body failed on its size limit rather than on
+/// the connection.
+fn is_length_limit(error: &axum::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(error) = source {
+        if error.is::<LengthLimitError>() {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// Methods JSON-RPC chains accept, as listed in `Allow`.
+const ALLOWED_METHODS: &str = "POST, OPTIONS";
+
+/// Answers a CORS preflight, letting browsers on any origin call the chain.
+/// The chain's `[headers.response]` can narrow `Access-Control-Allow-Origin`.
+fn preflight() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(ALLOW, ALLOWED_METHODS)
+        .header(ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(ACCESS_CONTROL_ALLOW_METHODS, ALLOWED_METHODS)
+        .header(
+            ACCESS_CONTROL_ALLOW_HEADERS,
+            "Content-Type, Authorization, X-Api-Key",
+        )
+        .header(ACCESS_CONTROL_MAX_AGE, "86400")
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn method_not_allowed(method: &Method) -> Response<Body> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": {
+            "code": -32600,
+            "message": format!("{} is not supported, JSON-RPC requests are sent with POST", method),
+        },
+    });
+    Response::builder()
+        .status(StatusCode::METHOD_NOT_ALLOWED)
+        .header(ALLOW, ALLOWED_METHODS)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// `503` with a JSON-RPC error for a request the kill switch stopped.
+fn method_blocked(chain: &str, method: &str) -> Response<Body> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": {
+            "code": -32000,
+            "message": format!("{} is temporarily disabled on chain {}", method, chain),
+        },
+    });
+    Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// `413` with a JSON-RPC error naming the limit the request body went over.
+fn too_large(max_request_bytes: usize) -> Response<Body> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": {
+            "code": -32600,
+            "message": format!("Request body exceeds the maximum of {} bytes", max_request_bytes),
+        },
+    });
+    Response::builder()
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// The `503` of a request no backend served, telling why.
+fn unavailable(
+    chain: &str,
+    pool_status: &PoolStatus,
+    attempts: Option<&Vec<Attempt>>,
+) -> Response<Body> {
+    let message = match pool_status {
+        PoolStatus::Empty => format!("Chain {} has no backends configured", chain),
+        PoolStatus::Unhealthy => format!(
+            "Every backend of chain {} is unhealthy or has its key rejected",
+            chain
+        ),
+        PoolStatus::RateLimited { until: Some(until) } => format!(
+            "Every backend of chain {} is rate limited until {}",
+            chain,
+            until.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ),
+        PoolStatus::RateLimited { until: None } => {
+            format!("Every backend of chain {} is rate limited", chain)
+        }
+        PoolStatus::Available => {
+            "Service temporarily unavailable, every upstream attempt failed.".to_string()
+        }
+    };
+
+    let mut builder = Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .header("Content-Type", "application/json");
+    if let PoolStatus::RateLimited { until: Some(until) } = pool_status {
+        let seconds = (*until - Utc::now()).num_seconds().max(1);
+        builder = builder.header(RETRY_AFTER, seconds);
+    }
+    let body = match attempts {
+        Some(attempts) => json!({
+            "error": message,
+            "reason": pool_status.reason(),
+            "attempts": attempts,
+        })
+        .to_string(),
+        None => message,
+    };
+    builder.body(Body::from(body)).unwrap()
+}
+
+fn bad_gateway(message: String) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::BAD_GATEWAY)
+        .header("Content-Type", "application/json")
+        .body(Body::from(message))
+        .unwrap()
+}
+
+/// Tells which backend served the response and after how many tries, or
+/// that it came from the response cache.
+fn with_debug_headers(builder: Builder, served_by: &str, attempts: usize) -> Builder {
+    builder
+        .header("X-Served-By", served_by)
+        .header("X-Upstream-Attempts", attempts)
+        .header("X-Cache", "MISS")
+}
+
+/// Answers with a response of the cache, fresh or, in cache only mode,
+/// expired. Polling clients already holding it get an empty 304.
+fn cached_response(
+    cached: CachedResponse,
+    cache_control: Option<&CacheControl>,
+    debug_headers: bool,
+    if_none_match: Option<&str>,
+) -> Response<Body> {
+    let mut builder = Response::builder().header(ETAG, &cached.etag);
+    if let Some(cache_control) = cache_control {
+        builder = builder
+            .header(CACHE_CONTROL, cache_control.cacheable(cached.fresh_for))
+            .header(AGE, cached.age.as_secs());
+    }
+    if debug_headers {
+        builder = builder
+            .header("X-Served-By", "cache")
+            .header("X-Upstream-Attempts", 0)
+            .header("X-Cache", if cached.stale { "STALE" } else { "HIT" });
+    }
+    if if_none_match.is_some_and(|tags| cache::etag_matches(tags, &cached.etag)) {
+        return builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+    }
+    builder
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(cached.body))
+        .unwrap()
+}
+
+/// What happened on a single upstream try, reported to the client when a
+/// request fails on a chain with `report_attempts` enabled.
+#[derive(Serialize, Debug)]
+struct Attempt {
+    /// Name or host of the backend tried, urls are left out as they may
+    /// carry API keys.
+    backend: Option<String>,
+    status: Option<u16>,
+    error: Option<String>,
+    latency_ms: u64,
+    /// Request id the provider returned, to quote in support tickets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    provider_request_id: Option<String>,
+    /// The attempt failed after the request was written, so the backend
+    /// may have acted on it.
+    #[serde(skip)]
+    maybe_delivered: bool,
+}
+
+impl Attempt {
+    fn new(
+        backend: String,
+        started: Instant,
+        status: Option<StatusCode>,
+        error: Option<String>,
+    ) -> Self {
+        Self {
+            backend: Some(backend),
+            status: status.map(|status| status.as_u16()),
+            error,
+            latency_ms: started.elapsed().as_millis() as u64,
+            provider_request_id: None,
+            maybe_delivered: false,
+        }
+    }
+
+    fn no_backend() -> Self {
+        Self {
+            backend: None,
+            status: None,
+            error: Some("no backend with request limit left".to_string()),
+            latency_ms: 0,
+            provider_request_id: None,
+            maybe_delivered: false,
+        }
+    }
+}
+
+/// The response that was served, if any, and every try made to get it.
+struct UpstreamOutcome {
+    served: Option<(String, ReqwestResponse)>,
+    attempts: Vec<Attempt>,
+}
+
+impl UpstreamOutcome {
+    /// Request id of the provider's last response, if it sent one.
+    fn provider_request_id(&self) -> Option<&str> {
+        self.attempts
+            .iter()
+            .rev()
+            .find_map(|attempt| attempt.provider_request_id.as_deref())
+    }
+}
+
+/// Per-chain settings applied to the upstream attempts of a request.
+#[derive(Clone, Debug, Default)]
+struct UpstreamPolicy {
+    strategy: Strategy,
+    timeout: Option<Duration>,
+    max_retries: Option<u32>,
+    /// Region of the client, whose backends are preferred.
+    region: Option<String>,
+    /// Tag of the only backends the request may go to.
+    tag: Option<String>,
+    /// SLA class of the client, overriding the chain's settings.
+    sla: Option<SlaClass>,
+    /// The only backend the request may go to, e.g. the one a polled filter
+    /// was installed on.
+    backend: Option<String>,
+    /// Hash of the client for sticky routing, cleared after the first
+    /// attempt so retries go elsewhere.
+    affinity: Option<u64>,
+    /// Set for non-idempotent requests, which are neither hedged nor retried
+    /// once an attempt may have reached a backend.
+    replay: Option<Guarded>,
+}
+
+impl UpstreamPolicy {
+    /// The timeout of an attempt on `uri`: the client's SLA class wins over
+    /// the backend's own timeout, which wins over the chain's.
+    fn timeout_for(&self, state: &RoundRobin, uri: &str) -> Option<Duration> {
+        self.sla
+            .as_ref()
+            .and_then(SlaClass::timeout)
+            .or_else(|| state.timeout_for(uri))
+            .or(self.timeout)
+    }
+
+    fn max_retries(&self) -> Option<u32> {
+        self.sla
+            .as_ref()
+            .and_then(|sla| sla.max_retries)
+            .or(self.max_retries)
+    }
+}
+
+/// Sends one attempt to `uri`, refunding its token if it is never sent. The
+/// response is only accepted when its status is not an error and it passes `checks`.
+async fn try_backend(
+    state: &RoundRobin,
+    uri: &str,
+    request: &UpstreamRequest,
+    timeout: Option<Duration>,
+    checks: &ResponseChecks,
+    request_id_headers: &[String],
+) -> (Attempt, Option<ReqwestResponse>) {
+    let started = Instant::now();
+    let backend = state.label_of(uri);
+    let res = match state.send(uri, request, timeout).await {
+        Ok(res) => res,
+        Err(e) => {
+            state.record_outcome(uri, false);
+            let attempt = Attempt {
+                maybe_delivered: !e.connect,
+                ..Attempt::new(backend, started, None, Some(e.message))
+            };
+            return (attempt, None);
+        }
+    };
+    state.record_latency(uri, started.elapsed());
+
+    let status = res.status();
+    state.record_status(uri, status, key_health::retry_after(res.headers()));
+    let provider_request_id = HeaderPolicy::provider_request_id(res.headers(), request_id_headers);
+    if let Some(id) = &provider_request_id {
+        println!(
+            "Backend {} answered {} with provider request id {}",
+            backend, status, id
+        );
+    }
+    let attempt = |error| Attempt {
+        provider_request_id,
+        ..Attempt::new(backend.clone(), started, Some(status), error)
+    };
+
+    if RpcErrorStatus::contains(status) {
+        state.record_outcome(uri, false);
+        return (attempt(None), None);
+    }
+    let inspected = checks.inspect(res).await;
+    state.record_outcome(uri, inspected.is_ok());
+    match inspected {
+        Ok(res) => (attempt(None), Some(res)),
+        Err(e) => {
+            println!("Rejected response of {}: {}", backend, e);
+            (attempt(Some(e)), None)
         }
     }
 }
 
 async fn retry_with_backoff(
-    method: Arc<Method>,
-    body_bytes: Arc<Bytes>,
-    state: Arc<Mutex<RoundRobin>>,
-) -> Option<ReqwestResponse> {
+    request: Arc<UpstreamRequest>,
+    state: Arc<RoundRobin>,
+    mut policy: UpstreamPolicy,
+    checks: Arc<ResponseChecks>,
+    request_id_headers: Arc<[String]>,
+) -> UpstreamOutcome {
+    if policy.strategy == Strategy::Broadcast && policy.backend.is_none() {
+        return broadcast(request, state, policy, checks, request_id_headers).await;
+    }
+
     let mut retries: u32 = 0;
     let base_delay = Duration::from_millis(100);
 
-    let max_retries;
-
-    {
-        let rr = state.lock().unwrap();
-        max_retries = rr.urls.len() as u32;
-    }
+    let max_retries = policy.max_retries().unwrap_or(state.urls.len() as u32);
+    let hedge_after = policy
+        .sla
+        .as_ref()
+        .and_then(SlaClass::hedge_after)
+        .filter(|_| policy.replay.is_none());
+    let mut attempts = Vec::new();
 
     while retries < max_retries {
-        let result = get_forward_request(state.clone(), method.clone(), body_bytes.clone()).await;
+        if let Some(guarded) = policy.replay.as_ref().filter(|_| retries > 0) {
+            if !guarded.may_replay() {
+                println!("Not retrying a request which may have reached a backend already");
+                break;
+            }
+        }
+        let result = select_backend(&state, &policy);
 
-        if let Some(request) = result {
-            if let Ok(res) = request.send().await {
-                if !RpcErrorStatus::contains(res.status()) {
-                    return Some(res);
+        if let Some((uri, timeout)) = result {
+            let (tries, served) = match hedge_after.filter(|_| retries == 0) {
+                Some(delay) => {
+                    hedged(
+                        (uri, timeout),
+                        delay,
+                        &request,
+                        &state,
+                        &policy,
+                        &checks,
+                        &request_id_headers,
+                    )
+                    .await
+                }
+                None => {
+                    let (attempt, res) = try_backend(
+                        &state,
+                        &uri,
+                        &request,
+                        timeout,
+                        &checks,
+                        &request_id_headers,
+                    )
+                    .await;
+                    (vec![attempt], res.map(|res| (uri, res)))
+                }
+            };
+            if let Some(guarded) = &policy.replay {
+                if tries.iter().any(|attempt| attempt.maybe_delivered) {
+                    guarded.record();
                 }
             }
+            attempts.extend(tries);
+            if let Some(served) = served {
+                return UpstreamOutcome {
+                    served: Some(served),
+                    attempts,
+                };
+            }
+        } else {
+            attempts.push(Attempt::no_backend());
         }
 
-        {
-            let round_robin = state.lock().unwrap();
-            round_robin.retry_connection();
-        }
+        state.retry_connection();
+        policy.affinity = None;
 
         retries += 1;
         if retries < max_retries {
@@ -140,34 +1104,181 @@ async fn retry_with_backoff(
         }
     }
 
-    None
+    UpstreamOutcome {
+        served: None,
+        attempts,
+    }
 }
 
-async fn get_forward_request(
-    state: Arc<Mutex<RoundRobin>>,
-    method: Arc<Method>,
-    body_bytes: Arc<Bytes>,
-) -> Option<RequestBuilder> {
-    let uri;
+/// Sends the first attempt to `first` and, when it has not answered after
+/// `delay`, a second one to another backend, returning the first success.
+/// The slower attempt is dropped.
+async fn hedged(
+    first: (String, Option<Duration>),
+    delay: Duration,
+    request: &Arc<UpstreamRequest>,
+    state: &Arc<RoundRobin>,
+    policy: &UpstreamPolicy,
+    checks: &Arc<ResponseChecks>,
+    request_id_headers: &Arc<[String]>,
+) -> (Vec<Attempt>, Option<(String, ReqwestResponse)>) {
+    let mut tries = JoinSet::new();
+    let spawn = |tries: &mut JoinSet<_>, uri: String, timeout: Option<Duration>| {
+        let unstarted = Unstarted(Some((state.clone(), uri.clone())));
+        let (state, request) = (state.clone(), request.clone());
+        let (checks, request_id_headers) = (checks.clone(), request_id_headers.clone());
+        tries.spawn(async move {
+            unstarted.start();
+            let (attempt, res) = try_backend(
+                &state,
+                &uri,
+                &request,
+                timeout,
+                &checks,
+                &request_id_headers,
+            )
+            .await;
+            (uri, attempt, res)
+        });
+    };
+    let first_uri = first.0.clone();
+    spawn(&mut tries, first.0, first.1);
 
-    {
-        let mut round_robin = state.lock().unwrap();
-        uri = round_robin.get_next();
+    let mut attempts = Vec::new();
+    let hedge = time::sleep(delay);
+    tokio::pin!(hedge);
+    let mut hedge_sent = false;
+    loop {
+        tokio::select! {
+            joined = tries.join_next() => {
+                let Some(joined) = joined else {
+                    break;
+                };
+                let Ok((uri, attempt, res)) = joined else {
+                    continue;
+                };
+                attempts.push(attempt);
+                if let Some(res) = res {
+                    return (attempts, Some((uri, res)));
+                }
+                // A failed attempt is retried as usual rather than hedged.
+                if tries.is_empty() {
+                    break;
+                }
+            }
+            _ = &mut hedge, if !hedge_sent => {
+                hedge_sent = true;
+                if let Some(uri) = state.take_other(&first_uri) {
+                    println!("Hedging request to : {}", state.label_of(&uri));
+                    let timeout = policy.timeout_for(state, &uri);
+                    spawn(&mut tries, uri, timeout);
+                }
+            }
+        }
+    }
+    (attempts, None)
+}
+
+/// The token of an attempt spawned into a `JoinSet`, refunded when the task is
+/// aborted before it ever ran, e.g. as another attempt answered first. Once
+/// started, [`RoundRobin::send`] refunds it unless the request was written.
+struct Unstarted(Option<(Arc<RoundRobin>, String)>);
+
+impl Unstarted {
+    fn start(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for Unstarted {
+    fn drop(&mut self) {
+        if let Some((state, uri)) = self.0.take() {
+            state.refund(&uri);
+        }
     }
+}
+
+/// Sends the request to every server with limit left and returns the first
+/// successful response. The remaining sends keep running in the background so
+/// every backend still receives the request.
+async fn broadcast(
+    request: Arc<UpstreamRequest>,
+    state: Arc<RoundRobin>,
+    policy: UpstreamPolicy,
+    checks: Arc<ResponseChecks>,
+    request_id_headers: Arc<[String]>,
+) -> UpstreamOutcome {
+    let urls: Vec<(String, Option<Duration>)> = state
+        .take_many(usize::MAX)
+        .into_iter()
+        .map(|uri| {
+            let timeout = policy.timeout_for(&state, &uri);
+            (uri, timeout)
+        })
+        .collect();
+    println!("Broadcasting request to {} RPC Urls", urls.len());
 
-    if let Some(uri) = uri {
-        println!("Forwarding request to : {}", &uri);
+    let (sender, mut receiver) = mpsc::channel(urls.len().max(1));
+    for (uri, timeout) in urls {
+        let sender = sender.clone();
+        let state = state.clone();
+        let request = request.clone();
+        let checks = checks.clone();
+        let request_id_headers = request_id_headers.clone();
+        tokio::spawn(async move {
+            let (attempt, res) = try_backend(
+                &state,
+                &uri,
+                &request,
+                timeout,
+                &checks,
+                &request_id_headers,
+            )
+            .await;
+            let _ = sender.send((uri, attempt, res)).await;
+        });
+    }
+    drop(sender);
 
-        let client = reqwest::Client::new();
+    let mut attempts = Vec::new();
+    while let Some((uri, attempt, res)) = receiver.recv().await {
+        attempts.push(attempt);
+        if let Some(res) = res {
+            return UpstreamOutcome {
+                served: Some((uri, res)),
+                attempts,
+            };
+        }
+    }
+    if attempts.is_empty() {
+        attempts.push(Attempt::no_backend());
+    }
 
-        let mut forwarded_request = client.request((*method).clone(), &uri);
+    UpstreamOutcome {
+        served: None,
+        attempts,
+    }
+}
 
-        forwarded_request = forwarded_request.header("Content-Type", "application/json");
-        forwarded_request = forwarded_request.body((*body_bytes).clone());
-        return Some(forwarded_request);
-    } else {
-        None
+/// Picks the backend of the next attempt and the timeout to send it with.
+fn select_backend(
+    state: &RoundRobin,
+    policy: &UpstreamPolicy,
+) -> Option<(String, Option<Duration>)> {
+    if let Some(url) = &policy.backend {
+        let uri = state.get_pinned(url)?;
+        let timeout = policy.timeout_for(state, &uri);
+        return Some((uri, timeout));
     }
+    let balancer = state.balancer(policy.strategy);
+    let uri = match (&policy.tag, policy.affinity, &policy.region) {
+        (Some(tag), _, _) => state.get_tagged(tag, &*balancer),
+        (None, Some(key), _) => state.get_sticky(key),
+        (None, None, Some(region)) => state.get_in_region(region, &*balancer),
+        (None, None, None) => state.get_with(&*balancer),
+    }?;
+    let timeout = policy.timeout_for(state, &uri);
+    Some((uri, timeout))
 }
 
 // load balancer tests
@@ -176,8 +1287,16 @@ mod tests {
     use std::collections::HashMap;
 
     use super::*;
-    use crate::algorithms::round_robin::{RoundRobin, RpcServer};
-    use axum::http::Request;
+    use crate::services::{
+        kill_switch::KillSwitch,
+        tx_journal::{JournalConfig, TxJournal},
+    };
+    use crate::{
+        algorithms::round_robin::{Chains, RoundRobin, RpcServer},
+        services::cache::CachePolicy,
+    };
+    use axum::{body::Bytes, http::Request, routing::post, Router};
+    use reqwest::header::HOST;
 
     use tokio::test;
     fn create_test_servers() -> Vec<RpcServer> {
@@ -186,11 +1305,13 @@ mod tests {
                 url: "https://sepolia.drpc.org/".to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://polygon-rpc.com".to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
         ]
     }
@@ -207,16 +1328,1205 @@ mod tests {
             .unwrap()
     }
 
+    // Helper function to serve `app` on a local port, acting as an upstream RPC node
+    async fn spawn_upstream(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    // Helper function to create a balancer with a single configured chain
+    fn create_balancer(chain: &str, urls: Vec<String>, config: Chains) -> Arc<LoadBalancer> {
+        let servers = urls
+            .into_iter()
+            .map(|url| RpcServer {
+                url,
+                request_limit: 10,
+                current_limit: 10,
+                ..Default::default()
+            })
+            .collect();
+        let round_robin = Arc::new(RoundRobin::new(servers));
+        let mut lb = LoadBalancer::new(Arc::new(HashMap::from([(chain.to_string(), round_robin)])));
+        lb.chains = Arc::new(HashMap::from([(chain.to_string(), config)]));
+        Arc::new(lb)
+    }
+
+    #[test]
+    async fn test_disabled_chain() {
+        let lb = create_balancer(
+            "sepolia",
+            vec!["http://127.0.0.1:1".to_string()],
+            Chains {
+                enabled: Some(false),
+                ..Default::default()
+            },
+        );
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb),
+            create_test_request(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "Chain sepolia is disabled");
+    }
+
+    #[test]
+    async fn test_method_policy() {
+        let mut headers = HeaderPolicy::default();
+        headers.response.insert(
+            "access-control-allow-origin".to_string(),
+            "https://app.example.com".to_string(),
+        );
+        let lb = create_balancer(
+            "sepolia",
+            vec!["http://127.0.0.1:1".to_string()],
+            Chains {
+                headers,
+                ..Default::default()
+            },
+        );
+        let send = |method: &str| {
+            let request = Request::builder()
+                .method(method)
+                .body(Body::empty())
+                .unwrap();
+            load_balancer(Path("sepolia".to_string()), State(lb.clone()), request)
+        };
+
+        let response = send("OPTIONS").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_METHODS],
+            "POST, OPTIONS"
+        );
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(send("HEAD").await.unwrap().status(), StatusCode::OK);
+
+        let response = send("GET").await.unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "POST, OPTIONS");
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let error: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(error["error"]["code"], -32600);
+        assert_eq!(
+            lb.metrics.counter(
+                "rpc_lb_method_rejected_total",
+                &[("chain", "sepolia"), ("method", "GET")]
+            ),
+            1
+        );
+    }
+
+    #[test]
+    async fn test_unavailable_reasons() {
+        let url = "http://127.0.0.1:1".to_string();
+        let empty = create_balancer("sepolia", vec![], Chains::default());
+        let unhealthy = create_balancer("sepolia", vec![url.clone()], Chains::default());
+        unhealthy.load_balancers["sepolia"].record_health(&url, false, 1);
+        let exhausted = create_balancer("sepolia", vec![url], Chains::default());
+        while exhausted.load_balancers["sepolia"].get_next().is_some() {}
+
+        for (lb, reason, message) in [
+            (
+                empty,
+                "no_backends",
+                "Chain sepolia has no backends configured",
+            ),
+            (
+                unhealthy,
+                "unhealthy",
+                "Every backend of chain sepolia is unhealthy or has its key rejected",
+            ),
+            (
+                exhausted,
+                "rate_limited",
+                "Every backend of chain sepolia is rate limited",
+            ),
+        ] {
+            let response = load_balancer(
+                Path("sepolia".to_string()),
+                State(lb.clone()),
+                create_test_request(),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+            let body = body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, message);
+            assert_eq!(
+                lb.metrics.counter(
+                    "rpc_lb_unavailable_total",
+                    &[("chain", "sepolia"), ("reason", reason)]
+                ),
+                1
+            );
+        }
+    }
+
+    #[test]
+    async fn test_provider_request_id_echo() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    [("x-alchemy-request-id", "alc-123")],
+                )
+            }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![upstream],
+            Chains {
+                max_retries: Some(1),
+                report_attempts: true,
+                headers: toml::from_str(
+                    r#"
+                    provider_request_id = ["x-alchemy-request-id"]
+                    echo_provider_request_id = true
+                    "#,
+                )
+                .unwrap(),
+                ..Default::default()
+            },
+        );
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb),
+            create_test_request(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[PROVIDER_REQUEST_ID], "alc-123");
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["attempts"][0]["provider_request_id"], "alc-123");
+    }
+
+    #[test]
+    async fn test_opaque_chain_passthrough() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|body: String| async move {
+                ([(CONTENT_TYPE, "text/plain")], format!("echo:{}", body))
+            }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "bitcoin",
+            vec![upstream],
+            Chains {
+                opaque: true,
+                ..Default::default()
+            },
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .header(CONTENT_TYPE, "text/plain")
+            .body(Body::from("ping"))
+            .unwrap();
+        let response = load_balancer(Path("bitcoin".to_string()), State(lb), request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/plain");
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "echo:ping");
+    }
+
+    #[test]
+    async fn test_uninspected_body() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|headers: axum::http::HeaderMap| async move {
+                let content_type = headers[CONTENT_TYPE].to_str().unwrap().to_string();
+                json!({"jsonrpc": "2.0", "result": content_type, "id": 1}).to_string()
+            }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "base",
+            vec![upstream],
+            Chains {
+                inspect_body: Some(false),
+                max_retries: Some(1),
+                ..Default::default()
+            },
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .body(Body::from(
+                r#"{"jsonrpc":"2.0","id":1,"method":"eth_blockNumber"}"#,
+            ))
+            .unwrap();
+        let mut rpc_method = None;
+        let response = forward("base".to_string(), lb, request, &mut rpc_method)
+            .await
+            .unwrap();
+
+        // Forwarded as JSON-RPC without the body ever being parsed.
+        assert_eq!(rpc_method, None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"], "application/json");
+    }
+
+    #[test]
+    async fn test_binary_body_passthrough() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|headers: axum::http::HeaderMap, body: Bytes| async move {
+                let content_type = headers[CONTENT_TYPE].clone();
+                ([(CONTENT_TYPE, content_type)], body)
+            }),
+        ))
+        .await;
+        let lb = create_balancer("cosmos", vec![upstream], Chains::default());
+
+        let payload = vec![0x0a, 0x03, 0xff, 0x00, 0x7b];
+        let request = Request::builder()
+            .method("POST")
+            .header(CONTENT_TYPE, "application/x-protobuf")
+            .body(Body::from(payload.clone()))
+            .unwrap();
+        let response = load_balancer(Path("cosmos".to_string()), State(lb), request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/x-protobuf");
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, payload);
+    }
+
+    #[test]
+    async fn test_host_header_and_connect_to() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|headers: axum::http::HeaderMap| async move {
+                let host = headers[HOST].to_str().unwrap().to_string();
+                json!({"jsonrpc": "2.0", "result": host, "id": 1}).to_string()
+            }),
+        ))
+        .await;
+        let port = upstream.rsplit(':').next().unwrap();
+
+        // The backend's host does not resolve, it is only reachable through
+        // `connect_to`.
+        let server = RpcServer {
+            url: format!("http://rpc.internal.test:{}", port),
+            request_limit: 10,
+            current_limit: 10,
+            host_header: Some("gateway.example".to_string()),
+            connect_to: Some("127.0.0.1".parse().unwrap()),
+            ..Default::default()
+        };
+        let round_robin = Arc::new(RoundRobin::new(vec![server]));
+        let mut lb = LoadBalancer::new(Arc::new(HashMap::from([(
+            "sepolia".to_string(),
+            round_robin,
+        )])));
+        lb.chains = Arc::new(HashMap::from([("sepolia".to_string(), Chains::default())]));
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(Arc::new(lb)),
+            create_test_request(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"], "gateway.example");
+    }
+
+    #[test]
+    async fn test_header_routing() {
+        let mut servers = Vec::new();
+        for (result, tags) in [("full", vec![]), ("archive", vec!["archive".to_string()])] {
+            let upstream = spawn_upstream(Router::new().route(
+                "/",
+                post(move || async move {
+                    json!({"jsonrpc": "2.0", "result": result, "id": 1}).to_string()
+                }),
+            ))
+            .await;
+            servers.push(RpcServer {
+                url: upstream,
+                request_limit: 10,
+                current_limit: 10,
+                tags,
+                ..Default::default()
+            });
+        }
+        let mut lb = LoadBalancer::new(Arc::new(HashMap::from([(
+            "sepolia".to_string(),
+            Arc::new(RoundRobin::new(servers)),
+        )])));
+        let routing = toml::from_str(
+            r#"headers = [{ header = "X-Routing-Hint", value = "archive", tag = "archive" }]"#,
+        )
+        .unwrap();
+        lb.chains = Arc::new(HashMap::from([(
+            "sepolia".to_string(),
+            Chains {
+                routing,
+                ..Default::default()
+            },
+        )]));
+        let lb = Arc::new(lb);
+
+        for _ in 0..3 {
+            let mut request = create_test_request();
+            request
+                .headers_mut()
+                .insert("X-Routing-Hint", HeaderValue::from_static("archive"));
+            let response = load_balancer(Path("sepolia".to_string()), State(lb.clone()), request)
+                .await
+                .unwrap();
+            let body = body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["result"], "archive");
+        }
+    }
+
+    #[test]
+    async fn test_debug_headers() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":"0x1","id":1}"# }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![upstream],
+            Chains {
+                debug_headers: true,
+                ..Default::default()
+            },
+        );
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb),
+            create_test_request(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["X-Served-By"], "127.0.0.1");
+        assert_eq!(response.headers()["X-Upstream-Attempts"], "1");
+        assert_eq!(response.headers()["X-Cache"], "MISS");
+    }
+
+    #[test]
+    async fn test_response_guarding() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":"0x1","id":1}"# }),
+        ))
+        .await;
+        let truncated = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { ([(CONTENT_TYPE, "application/json")], r#"{"jsonrpc":"#) }),
+        ))
+        .await;
+        let chain_id_request = || {
+            Request::builder()
+                .method("POST")
+                .body(Body::from(
+                    r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#,
+                ))
+                .unwrap()
+        };
+
+        let lb = create_balancer(
+            "sepolia",
+            vec![upstream.clone()],
+            Chains {
+                max_response_bytes: Some(4),
+                ..Default::default()
+            },
+        );
+        let response = load_balancer(Path("sepolia".to_string()), State(lb), chain_id_request())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        // A body ending before the JSON does fails its attempt, the next
+        // backend serves the request.
+        let lb = create_balancer("sepolia", vec![truncated, upstream], Chains::default());
+        let response = load_balancer(Path("sepolia".to_string()), State(lb), chain_id_request())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, r#"{"jsonrpc":"2.0","result":"0x1","id":1}"#);
+    }
+
+    #[test]
+    async fn test_tx_journal() {
+        let dir = std::env::temp_dir().join(format!("rpc_lb_journal_lb_{}", std::process::id()));
+        let journal = Arc::new(
+            TxJournal::new(Some(&JournalConfig {
+                dir: dir.display().to_string(),
+                retry_secs: 60,
+                max_age_secs: 3600,
+            }))
+            .unwrap(),
+        );
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":"0xabc","id":1}"# }),
+        ))
+        .await;
+        let send_raw = || {
+            Request::builder()
+                .method("POST")
+                .body(Body::from(
+                    r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x02f8"],"id":1}"#,
+                ))
+                .unwrap()
+        };
+        let journaled = || std::fs::read_dir(&dir).unwrap().count();
+
+        for (url, status, left) in [
+            (
+                "http://127.0.0.1:1".to_string(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                1,
+            ),
+            (upstream, StatusCode::OK, 1),
+        ] {
+            let mut lb =
+                LoadBalancer::clone(&create_balancer("sepolia", vec![url], Chains::default()));
+            lb.tx_journal = journal.clone();
+            let response =
+                load_balancer(Path("sepolia".to_string()), State(Arc::new(lb)), send_raw())
+                    .await
+                    .unwrap();
+            assert_eq!(response.status(), status);
+            assert_eq!(journaled(), left);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    async fn test_cache_only_skips_tx_journal() {
+        let dir =
+            std::env::temp_dir().join(format!("rpc_lb_journal_cache_only_{}", std::process::id()));
+        let mut lb = LoadBalancer::clone(&create_balancer(
+            "sepolia",
+            vec!["http://127.0.0.1:1".to_string()],
+            Chains::default(),
+        ));
+        lb.tx_journal = Arc::new(
+            TxJournal::new(Some(&JournalConfig {
+                dir: dir.display().to_string(),
+                retry_secs: 60,
+                max_age_secs: 3600,
+            }))
+            .unwrap(),
+        );
+        lb.cache.set_cache_only(Some("sepolia"), true);
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(Arc::new(lb)),
+            Request::builder()
+                .method("POST")
+                .body(Body::from(
+                    r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x02f8"],"id":1}"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    async fn test_duplicate_tx_suppression() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let submissions = Arc::new(AtomicUsize::new(0));
+        let counter = submissions.clone();
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(move || {
+                counter.fetch_add(1, Ordering::Relaxed);
+                async { r#"{"jsonrpc":"2.0","result":"0xabc","id":1}"# }
+            }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![upstream],
+            Chains {
+                tx_dedup_secs: Some(60),
+                ..Default::default()
+            },
+        );
+
+        for id in [1, 2] {
+            let request = Request::builder()
+                .method("POST")
+                .body(Body::from(format!(
+                    r#"{{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x02f8"],"id":{}}}"#,
+                    id
+                )))
+                .unwrap();
+            let response = load_balancer(Path("sepolia".to_string()), State(lb.clone()), request)
+                .await
+                .unwrap();
+            let body = body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["result"], "0xabc");
+            assert_eq!(body["id"], id);
+        }
+        assert_eq!(submissions.load(Ordering::Relaxed), 1);
+        assert_eq!(
+            lb.metrics
+                .counter("rpc_lb_duplicate_tx_total", &[("chain", "sepolia")]),
+            1
+        );
+    }
+
+    #[test]
+    async fn test_blocked_methods() {
+        let mut lb = LoadBalancer::clone(&create_balancer(
+            "sepolia",
+            vec!["http://127.0.0.1:1".to_string()],
+            Chains {
+                block_writes: true,
+                ..Default::default()
+            },
+        ));
+        lb.kill_switch = Arc::new(KillSwitch::new(&lb.chains));
+        let lb = Arc::new(lb);
+        let request = Request::builder()
+            .method("POST")
+            .body(Body::from(
+                r#"[{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1},
+                    {"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x02f8"],"id":2}]"#,
+            ))
+            .unwrap();
+        let response = load_balancer(Path("sepolia".to_string()), State(lb.clone()), request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let error: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            error["error"]["message"],
+            "eth_sendRawTransaction is temporarily disabled on chain sepolia"
+        );
+        assert_eq!(
+            lb.metrics.counter(
+                "rpc_lb_blocked_requests_total",
+                &[("chain", "sepolia"), ("method", "eth_sendRawTransaction")]
+            ),
+            1
+        );
+    }
+
+    #[test]
+    async fn test_request_too_large() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":"0x1","id":1}"# }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![upstream],
+            Chains {
+                max_request_bytes: Some(32),
+                ..Default::default()
+            },
+        );
+        let request = Request::builder()
+            .method("POST")
+            .body(Body::from(format!(
+                r#"{{"jsonrpc":"2.0","method":"eth_call","params":["{}"],"id":1}}"#,
+                "0".repeat(64)
+            )))
+            .unwrap();
+        let response = load_balancer(Path("sepolia".to_string()), State(lb.clone()), request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body["error"]["message"],
+            "Request body exceeds the maximum of 32 bytes"
+        );
+        assert!(lb
+            .metrics
+            .render()
+            .contains(r#"rpc_lb_request_too_large_total{chain="sepolia"} 1"#));
+    }
+
+    #[test]
+    async fn test_schema_violation_retries_next_backend() {
+        let broken = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":{"error":"upstream"},"id":1}"# }),
+        ))
+        .await;
+        let healthy = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async {
+                r#"{"jsonrpc":"2.0","result":{"hash":"0xab","number":"0x1","parentHash":"0xaa"},"id":1}"#
+            }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![broken, healthy],
+            Chains {
+                validate_responses: true,
+                ..Default::default()
+            },
+        );
+
+        let request = Request::builder()
+            .method("POST")
+            .body(Body::from(
+                r#"{"jsonrpc":"2.0","method":"eth_getBlockByNumber","params":["0x1",false],"id":1}"#,
+            ))
+            .unwrap();
+        let response = load_balancer(Path("sepolia".to_string()), State(lb), request)
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"]["hash"], "0xab");
+    }
+
+    #[test]
+    async fn test_html_error_page_retries_next_backend() {
+        let cloudflare = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { "<html><body>502 Bad Gateway</body></html>" }),
+        ))
+        .await;
+        let empty = spawn_upstream(Router::new().route("/", post(|| async { "" }))).await;
+        let healthy = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":"0x10","id":1}"# }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![cloudflare, empty, healthy],
+            Chains {
+                report_attempts: true,
+                ..Default::default()
+            },
+        );
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb),
+            create_test_request(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, r#"{"jsonrpc":"2.0","result":"0x10","id":1}"#);
+    }
+
+    #[test]
+    async fn test_filter_polls_follow_their_backend() {
+        let mut urls = Vec::new();
+        for name in ["0xa", "0xb"] {
+            let body = format!(r#"{{"jsonrpc":"2.0","result":"{}","id":1}}"#, name);
+            urls.push(spawn_upstream(Router::new().route("/", post(|| async { body }))).await);
+        }
+        let lb = create_balancer("sepolia", urls, Chains::default());
+        let call = |method: &str, params: &str| {
+            Request::builder()
+                .method("POST")
+                .body(Body::from(format!(
+                    r#"{{"jsonrpc":"2.0","method":"{}","params":{},"id":1}}"#,
+                    method, params
+                )))
+                .unwrap()
+        };
+        let result = |response: Response<Body>| async {
+            let body = body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<Value>(&body).unwrap()["result"].clone()
+        };
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb.clone()),
+            call("eth_newBlockFilter", "[]"),
+        )
+        .await
+        .unwrap();
+        let filter = result(response).await;
+        for _ in 0..3 {
+            let response = load_balancer(
+                Path("sepolia".to_string()),
+                State(lb.clone()),
+                call("eth_getFilterChanges", &format!("[{}]", filter)),
+            )
+            .await
+            .unwrap();
+            assert_eq!(result(response).await, filter);
+        }
+    }
+
+    #[test]
+    async fn test_cached_response() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":"0xaa36a7","id":1}"# }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![upstream],
+            Chains {
+                debug_headers: true,
+                cache: HashMap::from([(
+                    "eth_*".to_string(),
+                    CachePolicy {
+                        ttl: 60,
+                        scope: Default::default(),
+                        enabled: true,
+                    },
+                )]),
+                ..Default::default()
+            },
+        );
+        let chain_id_request = |id: u32| {
+            Request::builder()
+                .method("POST")
+                .body(Body::from(format!(
+                    r#"{{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":{}}}"#,
+                    id
+                )))
+                .unwrap()
+        };
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb.clone()),
+            chain_id_request(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.headers()["X-Cache"], "MISS");
+        let etag = response.headers()[ETAG].clone();
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb.clone()),
+            chain_id_request(2),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.headers()["X-Cache"], "HIT");
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"], "0xaa36a7");
+        assert_eq!(body["id"], 2);
+        assert_eq!(
+            lb.metrics
+                .counter("rpc_lb_cache_hits_total", &[("chain", "sepolia")]),
+            1
+        );
+
+        let mut request = chain_id_request(3);
+        request.headers_mut().insert(IF_NONE_MATCH, etag);
+        let response = load_balancer(Path("sepolia".to_string()), State(lb), request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    async fn test_cache_control() {
+        let upstream = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":"0xaa36a7","id":1}"# }),
+        ))
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![upstream],
+            Chains {
+                cache: HashMap::from([(
+                    "eth_chainId".to_string(),
+                    CachePolicy {
+                        ttl: 60,
+                        scope: Default::default(),
+                        enabled: true,
+                    },
+                )]),
+                cache_control: Some(CacheControl {
+                    directives: None,
+                    uncached: Some("no-store".to_string()),
+                }),
+                ..Default::default()
+            },
+        );
+        let request = |method: &str| {
+            Request::builder()
+                .method("POST")
+                .body(Body::from(format!(
+                    r#"{{"jsonrpc":"2.0","method":"{}","params":[],"id":1}}"#,
+                    method
+                )))
+                .unwrap()
+        };
+        let send = |method: &str| {
+            load_balancer(
+                Path("sepolia".to_string()),
+                State(lb.clone()),
+                request(method),
+            )
+        };
+
+        let response = send("eth_chainId").await.unwrap();
+        assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=60");
+        let response = send("eth_chainId").await.unwrap();
+        let max_age = response.headers()[CACHE_CONTROL].to_str().unwrap();
+        assert!(max_age.starts_with("public, max-age=5"), "{}", max_age);
+        assert_eq!(response.headers()[AGE], "0");
+
+        let response = send("eth_blockNumber").await.unwrap();
+        assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+    }
+
+    #[test]
+    async fn test_cache_only_mode() {
+        let policy = CachePolicy {
+            ttl: 60,
+            scope: Default::default(),
+            enabled: true,
+        };
+        let lb = create_balancer(
+            "sepolia",
+            vec!["http://127.0.0.1:1".to_string()],
+            Chains {
+                cache: HashMap::from([("eth_chainId".to_string(), policy.clone())]),
+                cache_control: Some(CacheControl::default()),
+                debug_headers: true,
+                ..Default::default()
+            },
+        );
+        let request = |method: &str| {
+            Request::builder()
+                .method("POST")
+                .body(Body::from(format!(
+                    r#"{{"jsonrpc":"2.0","method":"{}","params":[],"id":1}}"#,
+                    method
+                )))
+                .unwrap()
+        };
+        // Cached with no time to live, so already expired.
+        let key = CacheKey::new(
+            "sepolia",
+            &policy,
+            &serde_json::json!({"method": "eth_chainId", "params": []}),
+        )
+        .unwrap();
+        lb.cache.insert(
+            key,
+            Duration::ZERO,
+            br#"{"jsonrpc":"2.0","result":"0xaa36a7","id":1}"#,
+        );
+
+        lb.cache.set_cache_only(Some("sepolia"), true);
+        // Expired entries are still served, marked as stale.
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb.clone()),
+            request("eth_chainId"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["X-Cache"], "STALE");
+        assert_eq!(response.headers()[AGE], "0");
+        assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=0");
+
+        // Polling clients get the same 304 as from fresh entries.
+        let mut conditional = request("eth_chainId");
+        conditional
+            .headers_mut()
+            .insert(IF_NONE_MATCH, response.headers()[ETAG].clone());
+        let response = load_balancer(Path("sepolia".to_string()), State(lb.clone()), conditional)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb.clone()),
+            request("eth_blockNumber"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    async fn test_sla_class_hedges() {
+        let slow = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async {
+                tokio::time::sleep(Duration::from_millis(2000)).await;
+                r#"{"jsonrpc":"2.0","result":"0x1","id":1}"#
+            }),
+        ))
+        .await;
+        let fast = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { r#"{"jsonrpc":"2.0","result":"0x2","id":1}"# }),
+        ))
+        .await;
+        let mut lb = create_balancer(
+            "sepolia",
+            vec![slow, fast],
+            Chains {
+                debug_headers: true,
+                ..Default::default()
+            },
+        );
+        Arc::get_mut(&mut lb).unwrap().sla_classes = Arc::new(HashMap::from([(
+            "interactive".to_string(),
+            SlaClass {
+                hedge_after_ms: Some(50),
+                ..Default::default()
+            },
+        )]));
+        let mut request = Request::builder()
+            .method("POST")
+            .body(Body::from(
+                r#"{"jsonrpc":"2.0","method":"eth_blockNumber","params":[],"id":1}"#,
+            ))
+            .unwrap();
+        request.extensions_mut().insert(Principal {
+            id: "wallet".to_string(),
+            chains: None,
+            requests_per_second: None,
+            sla_class: Some("interactive".to_string()),
+            admin: false,
+        });
+
+        let started = Instant::now();
+        let response = load_balancer(Path("sepolia".to_string()), State(lb), request)
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_millis(1000));
+        assert_eq!(response.headers()["X-Upstream-Attempts"], "1");
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["result"], "0x2");
+    }
+
+    #[test]
+    async fn test_aborted_hedge_refunds_its_token() {
+        let round_robin = Arc::new(RoundRobin::new(vec![RpcServer {
+            url: "http://127.0.0.1:1".to_string(),
+            request_limit: 1,
+            current_limit: 1,
+            ..Default::default()
+        }]));
+        let policy = UpstreamPolicy::default();
+        let first = select_backend(&round_robin, &policy).unwrap();
+        assert!(select_backend(&round_robin, &policy).is_none());
+
+        // Given up on before the spawned attempt first ran, so it never sent.
+        let request = Arc::new(UpstreamRequest::json("{}"));
+        let (checks, request_id_headers) = (Arc::default(), Arc::from([]));
+        let hedging = hedged(
+            first,
+            Duration::from_secs(1),
+            &request,
+            &round_robin,
+            &policy,
+            &checks,
+            &request_id_headers,
+        );
+        tokio::select! {
+            biased;
+            _ = hedging => panic!("the attempt never ran"),
+            _ = std::future::ready(()) => {}
+        }
+        // Aborted tasks are dropped once the runtime gets to them.
+        tokio::task::yield_now().await;
+        assert!(select_backend(&round_robin, &policy).is_some());
+    }
+
+    #[test]
+    async fn test_failed_attempts_reported() {
+        let upstream = spawn_upstream(
+            Router::new().route("/", post(|| async { StatusCode::INTERNAL_SERVER_ERROR })),
+        )
+        .await;
+        let lb = create_balancer(
+            "sepolia",
+            vec![upstream],
+            Chains {
+                max_retries: Some(2),
+                report_attempts: true,
+                ..Default::default()
+            },
+        );
+
+        let response = load_balancer(
+            Path("sepolia".to_string()),
+            State(lb),
+            create_test_request(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        let attempts = body["attempts"].as_array().unwrap();
+        assert_eq!(attempts.len(), 2);
+        assert_eq!(attempts[0]["backend"], "127.0.0.1");
+        assert_eq!(attempts[0]["status"], 500);
+    }
+
+    #[test]
+    async fn test_ambiguous_writes_not_retried() {
+        let slow = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                ""
+            }),
+        ))
+        .await;
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counted = hits.clone();
+        let fast = spawn_upstream(Router::new().route(
+            "/",
+            post(move || async move {
+                counted.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                r#"{"jsonrpc":"2.0","result":"0xab","id":1}"#
+            }),
+        ))
+        .await;
+        let send = |lb: Arc<LoadBalancer>| {
+            let request = Request::builder()
+                .method("POST")
+                .body(Body::from(
+                    r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x02"],"id":1}"#,
+                ))
+                .unwrap();
+            load_balancer(Path("sepolia".to_string()), State(lb), request)
+        };
+        let config = Chains {
+            timeout_ms: Some(200),
+            ..Default::default()
+        };
+
+        // The slow backend timed out after getting the transaction, which
+        // is not sent to the other one.
+        let lb = create_balancer("sepolia", vec![slow.clone(), fast.clone()], config.clone());
+        let response = send(lb).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::Relaxed), 0);
+
+        let lb = create_balancer(
+            "sepolia",
+            vec![slow, fast],
+            Chains {
+                replayable_methods: vec!["eth_sendRawTransaction".to_string()],
+                ..config
+            },
+        );
+        let response = send(lb).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
     #[test]
     async fn test_successful_request_forwarding() {
         let servers = create_test_servers();
-        let mock_round_robin = Arc::new(Mutex::new(RoundRobin::new(servers)));
-        let mut chains: HashMap<String, Arc<Mutex<RoundRobin>>> = HashMap::new();
+        let mock_round_robin = Arc::new(RoundRobin::new(servers));
+        let mut chains: HashMap<String, Arc<RoundRobin>> = HashMap::new();
         chains.insert("sepolia".to_string(), mock_round_robin);
         let fin_chains = Arc::new(chains);
-        let lbs = LoadBalancer {
-            load_balancers: fin_chains,
-        };
+        let lbs = LoadBalancer::new(fin_chains);
 
         let request = create_test_request();
 
@@ -230,13 +2540,11 @@ mod tests {
     #[test]
     async fn test_request_headers_forwarded() {
         let servers = create_test_servers();
-        let mock_round_robin = Arc::new(Mutex::new(RoundRobin::new(servers)));
-        let mut chains: HashMap<String, Arc<Mutex<RoundRobin>>> = HashMap::new();
+        let mock_round_robin = Arc::new(RoundRobin::new(servers));
+        let mut chains: HashMap<String, Arc<RoundRobin>> = HashMap::new();
         chains.insert("sepolia".to_string(), mock_round_robin);
         let fin_chains = Arc::new(chains);
-        let lbs = LoadBalancer {
-            load_balancers: fin_chains,
-        };
+        let lbs = LoadBalancer::new(fin_chains);
 
         let request = Request::builder()
             .method("POST")
@@ -267,31 +2575,33 @@ mod tests {
                 url: "https://sepolia.d.org".to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://endpoints.omniatech.io/v1/eth/sepolia/public".to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://sepolia.drpc.org".to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://endpoints.omniatech.io/v1/eth/sepolia/public".to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
         ];
 
-        let mock_round_robin = Arc::new(Mutex::new(RoundRobin::new(servers)));
-        let mut chains: HashMap<String, Arc<Mutex<RoundRobin>>> = HashMap::new();
+        let mock_round_robin = Arc::new(RoundRobin::new(servers));
+        let mut chains: HashMap<String, Arc<RoundRobin>> = HashMap::new();
         chains.insert("ethereum_sepolia".to_string(), mock_round_robin);
         let fin_chains = Arc::new(chains);
-        let lbs = LoadBalancer {
-            load_balancers: fin_chains,
-        };
+        let lbs = LoadBalancer::new(fin_chains);
         let path: Path<String> = Path("ethereum_sepolia".to_string());
         println!("before resp");
         let response = load_balancer(path, State(Arc::new(lbs)), request)
@@ -310,12 +2620,14 @@ mod tests {
                     .to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://eth-sepolia.g.alchemy.com/v2/fjZ8CPTHtjIN989lInvYqljpGNqJTspg"
                     .to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
         ];
 
@@ -325,12 +2637,14 @@ mod tests {
                     .to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://arb-sepolia.g.alchemy.com/v2/Vt-glQ2N0u8FIs-f0try1ghd7DAdYobc"
                     .to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
         ];
 
@@ -340,12 +2654,14 @@ mod tests {
                     .to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://base-sepolia.g.alchemy.com/v2/Vt-glQ2N0u8FIs-f0try1ghd7DAdYobc"
                     .to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
         ];
 
@@ -355,12 +2671,14 @@ mod tests {
                     .to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
             RpcServer {
                 url: "https://berachain-bartio.g.alchemy.com/v2/mRRENj5uQ1jqgfIIrtFZFzqUWQtU1lvH"
                     .to_string(),
                 request_limit: 1,
                 current_limit: 1,
+                ..Default::default()
             },
         ];
 
@@ -368,39 +2686,36 @@ mod tests {
             RpcServer{
                 url : "https://rpc.ankr.com/btc_signet/2a8161e0d7bc03b1d7198e539c94b34481ad94443090a041314aedc2b29ea17b".to_string(),
                 request_limit : 5,
-                current_limit : 5
+                current_limit : 5,
+                ..Default::default()
             },
             RpcServer{
                 url : "https://rpc.ankr.com/btc_signet/bc0fb296415993c1eccfc983e9b8f4881272efa66f8f92fa916ea053b2bb768c".to_string(),
                 request_limit : 5,
-                current_limit : 5 },
+                current_limit : 5,
+                ..Default::default()
+            },
         ];
 
-        let sepolia_servers = Arc::new(Mutex::new(RoundRobin::new(servers)));
-        let arb_servers = Arc::new(Mutex::new(RoundRobin::new(arb)));
-        let base_servers = Arc::new(Mutex::new(RoundRobin::new(base)));
-        let berachain_servers = Arc::new(Mutex::new(RoundRobin::new(berachain)));
-        let bitcoin_servers = Arc::new(Mutex::new(RoundRobin::new(bitcoin)));
-        let mut chains: HashMap<String, Arc<Mutex<RoundRobin>>> = HashMap::new();
+        let sepolia_servers = Arc::new(RoundRobin::new(servers));
+        let arb_servers = Arc::new(RoundRobin::new(arb));
+        let base_servers = Arc::new(RoundRobin::new(base));
+        let berachain_servers = Arc::new(RoundRobin::new(berachain));
+        let bitcoin_servers = Arc::new(RoundRobin::new(bitcoin));
+        let mut chains: HashMap<String, Arc<RoundRobin>> = HashMap::new();
         chains.insert("ethereum_sepolia".to_string(), sepolia_servers);
         chains.insert("arbitrum_sepolia".to_string(), arb_servers);
         chains.insert("base_sepolia".to_string(), base_servers);
         chains.insert("berachain".to_string(), berachain_servers);
         chains.insert("bitcoin".to_string(), bitcoin_servers);
         let fin_chains = Arc::new(chains);
-        let lbs = LoadBalancer {
-            load_balancers: fin_chains,
-        };
+        let lbs = LoadBalancer::new(fin_chains);
 
         {
             let round_robin_lb = &lbs.load_balancers;
 
             for round_robin in round_robin_lb.values() {
-                let rr_clone;
-                {
-                    let rr = round_robin.lock().unwrap();
-                    rr_clone = rr.clone();
-                }
+                let rr_clone = round_robin.clone();
 
                 tokio::spawn(async move {
                     rr_clone.refill_limits(Duration::from_secs(5)).await;
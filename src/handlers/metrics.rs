@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use axum::{body::Body, extract::State, response::Response};
+use reqwest::StatusCode;
+
+use crate::algorithms::round_robin::LoadBalancer;
+
+pub async fn metrics(State(state): State<Arc<LoadBalancer>>) -> Response<Body> {
+    // Percentiles change as samples leave the window, so they are taken at
+    // scrape time.
+    for (chain, round_robin) in state.load_balancers.iter() {
+        for status in round_robin.status() {
+            state.metrics.set_gauge(
+                "rpc_lb_backend_in_flight",
+                &[("chain", chain.as_str()), ("backend", &status.backend)],
+                status.in_flight as f64,
+            );
+            let Some(latency) = status.latency else {
+                continue;
+            };
+            for (quantile, value) in [
+                ("0.5", latency.p50_ms),
+                ("0.9", latency.p90_ms),
+                ("0.99", latency.p99_ms),
+            ] {
+                let labels = [
+                    ("chain", chain.as_str()),
+                    ("backend", &status.backend),
+                    ("quantile", quantile),
+                ];
+                state
+                    .metrics
+                    .set_gauge("rpc_lb_backend_latency_ms", &labels, value);
+            }
+        }
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(Body::from(state.metrics.render()))
+        .unwrap()
+}
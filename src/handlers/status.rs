@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::Response,
+};
+use reqwest::StatusCode;
+
+use crate::algorithms::round_robin::LoadBalancer;
+
+/// Reports the latency percentiles of the last minute and the health of
+/// every backend of `chain`.
+pub async fn status(
+    Path(chain): Path<String>,
+    State(state): State<Arc<LoadBalancer>>,
+) -> Response<Body> {
+    let Some(round_robin) = state.load_balancers.get(&chain) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Invalid chain: {}", chain)))
+            .unwrap();
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            serde_json::to_string(&round_robin.status()).unwrap(),
+        ))
+        .unwrap()
+}
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::Response,
+};
+use reqwest::StatusCode;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::task::JoinSet;
+
+use crate::{
+    algorithms::round_robin::{LoadBalancer, RoundRobin},
+    services::rpc_client,
+};
+
+/// Number of backends a transaction lookup is fanned out to.
+const LOOKUP_FANOUT: usize = 3;
+
+/// How far a transaction has progressed according to a single backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LookupState {
+    Unknown,
+    Pending,
+    Mined,
+    Confirmed,
+}
+
+#[derive(Debug, Clone)]
+struct BackendView {
+    transaction: Value,
+    receipt: Value,
+}
+
+impl BackendView {
+    fn state(&self) -> LookupState {
+        if !self.receipt.is_null() {
+            LookupState::Confirmed
+        } else if self.transaction.is_null() {
+            LookupState::Unknown
+        } else if self.transaction["blockNumber"].is_null() {
+            LookupState::Pending
+        } else {
+            LookupState::Mined
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct LookupResponse {
+    hash: String,
+    state: LookupState,
+    transaction: Value,
+    receipt: Value,
+    backends_queried: usize,
+    backends_responded: usize,
+}
+
+pub async fn tx_lookup(
+    Path((chain, hash)): Path<(String, String)>,
+    State(state): State<Arc<LoadBalancer>>,
+) -> Response<Body> {
+    let Some(round_robin) = state.load_balancers.get(&chain) else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Invalid chain: {}", chain)))
+            .unwrap();
+    };
+
+    let urls = round_robin.take_many(LOOKUP_FANOUT);
+
+    if urls.is_empty() {
+        return Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .header("Content-Type", "application/json")
+            .body(Body::from("No RPC endpoints available for lookup"))
+            .unwrap();
+    }
+
+    let mut lookups = JoinSet::new();
+    for url in &urls {
+        let url = url.clone();
+        let round_robin = round_robin.clone();
+        let hash = hash.clone();
+        lookups.spawn(async move { query_backend(&round_robin, &url, &hash).await });
+    }
+
+    let mut views = Vec::new();
+    while let Some(result) = lookups.join_next().await {
+        if let Ok(Some(view)) = result {
+            views.push(view);
+        }
+    }
+
+    let best = most_advanced(&views);
+    let response = LookupResponse {
+        hash,
+        state: best.map(BackendView::state).unwrap_or(LookupState::Unknown),
+        transaction: best.map(|v| v.transaction.clone()).unwrap_or(Value::Null),
+        receipt: best.map(|v| v.receipt.clone()).unwrap_or(Value::Null),
+        backends_queried: urls.len(),
+        backends_responded: views.len(),
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(Body::from(serde_json::to_string(&response).unwrap()))
+        .unwrap()
+}
+
+async fn query_backend(round_robin: &RoundRobin, url: &str, hash: &str) -> Option<BackendView> {
+    let transaction =
+        rpc_client::call_server(round_robin, url, "eth_getTransactionByHash", json!([hash]))
+            .await
+            .ok()?;
+    let receipt =
+        rpc_client::call_server(round_robin, url, "eth_getTransactionReceipt", json!([hash]))
+            .await
+            .unwrap_or(Value::Null);
+
+    Some(BackendView {
+        transaction,
+        receipt,
+    })
+}
+
+fn most_advanced(views: &[BackendView]) -> Option<&BackendView> {
+    views.iter().max_by_key(|view| view.state())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_most_advanced_state_wins() {
+        let views = vec![
+            BackendView {
+                transaction: Value::Null,
+                receipt: Value::Null,
+            },
+            BackendView {
+                transaction: json!({"hash": "0xabc", "blockNumber": null}),
+                receipt: Value::Null,
+            },
+            BackendView {
+                transaction: json!({"hash": "0xabc", "blockNumber": "0x10"}),
+                receipt: json!({"status": "0x1"}),
+            },
+        ];
+
+        let best = most_advanced(&views).unwrap();
+        assert_eq!(best.state(), LookupState::Confirmed);
+        assert_eq!(views[1].state(), LookupState::Pending);
+        assert_eq!(views[0].state(), LookupState::Unknown);
+    }
+}
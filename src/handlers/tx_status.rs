@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    response::Response,
+};
+use reqwest::StatusCode;
+
+use crate::algorithms::round_robin::LoadBalancer;
+
+pub async fn tx_status(
+    Path((chain, hash)): Path<(String, String)>,
+    State(state): State<Arc<LoadBalancer>>,
+) -> Response<Body> {
+    if !state.tx_tracker.is_enabled(&chain) {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!(
+                "Transaction rebroadcast is not enabled for chain: {}",
+                chain
+            )))
+            .unwrap();
+    }
+
+    match state.tx_tracker.status(&chain, &hash) {
+        Some(status) => Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(Body::from(serde_json::to_string(&status).unwrap()))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header("Content-Type", "application/json")
+            .body(Body::from(format!("Transaction not tracked: {}", hash)))
+            .unwrap(),
+    }
+}
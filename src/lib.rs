@@ -0,0 +1,9 @@
+pub mod algorithms;
+pub mod auth;
+pub mod backpressure;
+pub mod config;
+pub mod handlers;
+pub mod listener;
+pub mod metrics;
+pub mod services;
+pub mod transport;
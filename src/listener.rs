@@ -0,0 +1,336 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{body::Body, extract::ConnectInfo, serve::Listener, Router};
+use hyper::{
+    body::Incoming,
+    header::{HeaderValue, CONNECTION},
+    service::service_fn,
+    Request, Version,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto::Builder,
+};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    sync::{Notify, Semaphore},
+};
+use tower::ServiceExt;
+
+use crate::{metrics::Metrics, services::geo::ClientAddr};
+
+pub mod acme;
+pub mod tls;
+
+use tls::{TlsCertificate, TlsConfig};
+
+/// Time clients have to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `[server.listener]` section: limits of client connections, so slow or
+/// idle clients cannot hold on to them when the balancer is exposed
+/// publicly, e.g. slowloris attacks. Nothing is limited by default.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct ListenerConfig {
+    /// Connections served at once, further ones wait in the kernel's backlog.
+    pub max_connections: Option<usize>,
+    /// Requests served on a connection before it is closed.
+    pub max_requests_per_connection: Option<usize>,
+    /// Seconds a connection may go without a request before it is closed.
+    pub idle_timeout_secs: Option<u64>,
+    /// Seconds clients have to send the headers of a request, counted from
+    /// the moment the connection awaits it.
+    pub header_read_timeout_secs: Option<u64>,
+    /// Serve clients over TLS, plain HTTP when unset.
+    pub tls: Option<TlsConfig>,
+}
+
+/// A client connection, plain or over TLS.
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+/// What a connection is doing, for its limits.
+#[derive(Debug)]
+struct Activity {
+    requests: AtomicUsize,
+    in_flight: AtomicUsize,
+    idle_since: Mutex<Instant>,
+    changed: Notify,
+}
+
+impl Activity {
+    fn new() -> Self {
+        Self {
+            requests: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            idle_since: Mutex::new(Instant::now()),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Marks a request of the connection as in flight until the returned
+    /// guard is dropped, returning how many requests the connection got.
+    fn enter(self: &Arc<Self>) -> (RequestGuard, usize) {
+        let requests = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.changed.notify_waiters();
+        (RequestGuard(self.clone()), requests)
+    }
+
+    /// Returns once the connection is to be closed, with the reason: when
+    /// its last allowed request started, or after it went idle for too long.
+    async fn exhausted(&self, config: &ListenerConfig) -> &'static str {
+        loop {
+            let changed = self.changed.notified();
+            let requests = self.requests.load(Ordering::Relaxed);
+            if config
+                .max_requests_per_connection
+                .is_some_and(|max| requests >= max)
+            {
+                return "max_requests";
+            }
+            let idle = self.in_flight.load(Ordering::Relaxed) == 0;
+            let deadline = config
+                .idle_timeout_secs
+                .filter(|_| idle)
+                .map(|secs| *self.idle_since.lock().unwrap() + Duration::from_secs(secs));
+            match deadline {
+                Some(deadline) if deadline <= Instant::now() => return "idle",
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = changed => {}
+                        _ = tokio::time::sleep_until(deadline.into()) => {}
+                    }
+                }
+                None => changed.await,
+            }
+        }
+    }
+}
+
+struct RequestGuard(Arc<Activity>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        *self.0.idle_since.lock().unwrap() = Instant::now();
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.0.changed.notify_waiters();
+    }
+}
+
+/// Serves `app` on the connections of `listener` within the limits of
+/// `config`, over TLS with the current `tls` certificate if given.
+/// Connections closed by a limit are closed gracefully, the request in
+/// flight is still answered.
+pub async fn serve<L>(
+    mut listener: L,
+    app: Router,
+    config: ListenerConfig,
+    tls: Option<Arc<TlsCertificate>>,
+    metrics: Arc<Metrics>,
+) where
+    L: Listener<Addr = SocketAddr>,
+{
+    let mut builder = Builder::new(TokioExecutor::new());
+    if let Some(secs) = config.header_read_timeout_secs {
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(Duration::from_secs(secs));
+    }
+    let builder = Arc::new(builder);
+    let connections = config
+        .max_connections
+        .map(|max| Arc::new(Semaphore::new(max)));
+    let config = Arc::new(config);
+
+    loop {
+        let permit = match &connections {
+            Some(connections) => Some(connections.clone().acquire_owned().await.unwrap()),
+            None => None,
+        };
+        let (io, addr) = listener.accept().await;
+
+        let activity = Arc::new(Activity::new());
+        let service = {
+            let (app, activity, config, metrics) = (
+                app.clone(),
+                activity.clone(),
+                config.clone(),
+                metrics.clone(),
+            );
+            service_fn(move |request: Request<Incoming>| {
+                let (app, activity, config, metrics) = (
+                    app.clone(),
+                    activity.clone(),
+                    config.clone(),
+                    metrics.clone(),
+                );
+                async move {
+                    let (_request, requests) = activity.enter();
+                    let last = config.max_requests_per_connection == Some(requests);
+                    // HTTP/1 clients may have pipelined more requests already,
+                    // the header closes the connection after this response.
+                    let close = last && request.version() < Version::HTTP_2;
+                    let mut request = request.map(Body::new);
+                    request
+                        .extensions_mut()
+                        .insert(ConnectInfo(ClientAddr(addr)));
+                    let mut response = app.oneshot(request).await?;
+                    if last {
+                        metrics.inc(
+                            "rpc_lb_connections_closed_total",
+                            &[("reason", "max_requests")],
+                        );
+                    }
+                    if close {
+                        response
+                            .headers_mut()
+                            .insert(CONNECTION, HeaderValue::from_static("close"));
+                    }
+                    Ok::<_, Infallible>(response)
+                }
+            })
+        };
+
+        let (builder, config, metrics) = (builder.clone(), config.clone(), metrics.clone());
+        // Taken now, connections accepted before a reload keep the previous one.
+        let acceptor = tls.as_ref().map(|tls| tls.acceptor());
+        tokio::spawn(async move {
+            let _permit = permit;
+            let io: Box<dyn Connection> = match acceptor {
+                Some(acceptor) => {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(io)).await {
+                        Ok(Ok(stream)) => Box::new(stream),
+                        _ => {
+                            metrics.inc(
+                                "rpc_lb_connections_closed_total",
+                                &[("reason", "tls_handshake")],
+                            );
+                            return;
+                        }
+                    }
+                }
+                None => Box::new(io),
+            };
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+            tokio::pin!(connection);
+            tokio::select! {
+                _ = connection.as_mut() => return,
+                reason = activity.exhausted(&config) => {
+                    if reason == "idle" {
+                        metrics.inc("rpc_lb_connections_closed_total", &[("reason", reason)]);
+                    }
+                    connection.as_mut().graceful_shutdown();
+                }
+            }
+            let _ = connection.await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        time::timeout,
+    };
+
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    async fn spawn_server(config: ListenerConfig) -> (SocketAddr, Arc<Metrics>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(Metrics::default());
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        tokio::spawn(serve(listener, app, config, None, metrics.clone()));
+        (addr, metrics)
+    }
+
+    /// Everything the server sent until it closed the connection.
+    async fn read_until_closed(stream: &mut TcpStream) -> String {
+        let mut response = Vec::new();
+        timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+            .await
+            .expect("connection left open")
+            .unwrap();
+        String::from_utf8(response).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_connection_limits() {
+        let (addr, metrics) = spawn_server(ListenerConfig {
+            max_requests_per_connection: Some(2),
+            idle_timeout_secs: Some(1),
+            ..Default::default()
+        })
+        .await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&REQUEST.repeat(3)).await.unwrap();
+        let response = read_until_closed(&mut stream).await;
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 2);
+        let labels = [("reason", "max_requests")];
+        assert_eq!(
+            metrics.counter("rpc_lb_connections_closed_total", &labels),
+            1
+        );
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(REQUEST).await.unwrap();
+        let response = read_until_closed(&mut stream).await;
+        assert_eq!(response.matches("HTTP/1.1 200 OK").count(), 1);
+        let labels = [("reason", "idle")];
+        assert_eq!(
+            metrics.counter("rpc_lb_connections_closed_total", &labels),
+            1
+        );
+
+        // Headers which never end.
+        let (addr, _) = spawn_server(ListenerConfig {
+            header_read_timeout_secs: Some(1),
+            ..Default::default()
+        })
+        .await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n").await.unwrap();
+        let response = read_until_closed(&mut stream).await;
+        assert!(!response.contains("200 OK"));
+    }
+
+    #[tokio::test]
+    async fn test_max_connections() {
+        let (addr, _) = spawn_server(ListenerConfig {
+            max_connections: Some(1),
+            ..Default::default()
+        })
+        .await;
+
+        let first = TcpStream::connect(addr).await.unwrap();
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        second.write_all(REQUEST).await.unwrap();
+        let mut buffer = [0; 64];
+        let waiting = timeout(Duration::from_millis(200), second.read(&mut buffer)).await;
+        assert!(waiting.is_err());
+
+        drop(first);
+        let read = timeout(Duration::from_secs(5), second.read(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(buffer[..read].starts_with(b"HTTP/1.1 200 OK"));
+    }
+}
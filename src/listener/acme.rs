@@ -0,0 +1,661 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    net::SocketAddr,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::StatusCode,
+    routing::get,
+    Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, BigNumContext},
+    ec::{EcGroup, EcKey},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    stack::Stack,
+    x509::{extension::SubjectAlternativeName, X509NameBuilder, X509ReqBuilder, X509},
+};
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::tls::{TlsCertificate, TlsConfig};
+use crate::transport::http::USER_AGENT;
+
+const LETS_ENCRYPT: &str = "https://acme-v02.api.letsencrypt.org/directory";
+/// How often the certificate's expiry is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+/// Pause between polls of an authorization or order still pending.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: u32 = 30;
+/// Mode of the written key files, readable by the balancer's user only.
+const KEY_MODE: u32 = 0o600;
+
+/// The `[server.listener.tls.acme]` section: obtain and renew the TLS
+/// certificate from an ACME CA, Let's Encrypt by default, answering its
+/// HTTP-01 challenges on `http_address`. The certificate and its key are
+/// written to the `cert_file` and `key_file` of the TLS section.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct AcmeConfig {
+    /// Hostnames the certificate is for, all pointing at the balancer.
+    pub domains: Vec<String>,
+    /// Contact urls of the account, e.g. `mailto:ops@example.com`.
+    #[serde(default)]
+    pub contact: Vec<String>,
+    /// Directory url of the CA.
+    #[serde(default = "default_directory")]
+    pub directory: String,
+    /// PEM file of the account key, created when missing.
+    /// `acme-account.pem` next to the `key_file` when unset.
+    pub account_key_file: Option<String>,
+    /// Address the challenges are answered on, `0.0.0.0:80` when unset as
+    /// CAs connect to port 80. Another one suits a balancer behind a proxy
+    /// forwarding that port to it.
+    pub http_address: Option<String>,
+    /// Agrees to the terms of service of the CA, which it requires before
+    /// opening an account. Loading refuses the section without it.
+    #[serde(default)]
+    pub agree_tos: bool,
+    /// Days before expiry the certificate is renewed, 30 when unset.
+    pub renew_before_days: Option<u32>,
+}
+
+fn default_directory() -> String {
+    LETS_ENCRYPT.to_string()
+}
+
+impl AcmeConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.domains.is_empty() {
+            return Err("[server.listener.tls.acme] needs at least one domain".to_string());
+        }
+        if !self.agree_tos {
+            return Err(format!(
+                "[server.listener.tls.acme] needs agree_tos = true to accept the terms of service of {}",
+                self.directory
+            ));
+        }
+        self.challenge_address()?;
+        Ok(())
+    }
+
+    fn challenge_address(&self) -> Result<SocketAddr, String> {
+        let address = self.http_address.as_deref().unwrap_or("0.0.0.0:80");
+        address
+            .parse()
+            .map_err(|_| format!("Invalid ACME http_address {}", address))
+    }
+}
+
+/// Key authorizations of the pending HTTP-01 challenges, by token.
+type Challenges = Mutex<HashMap<String, String>>;
+
+/// Keeps the certificate of a TLS section with `acme` valid.
+pub struct AcmeClient {
+    tls: TlsConfig,
+    config: AcmeConfig,
+    challenges: Arc<Challenges>,
+    http: reqwest::Client,
+}
+
+impl AcmeClient {
+    /// Starts answering challenges and obtains a certificate if there is
+    /// none yet or it is due for renewal, so the listener can load it.
+    pub async fn start(tls: TlsConfig) -> Result<Arc<Self>, String> {
+        let config = tls.acme.clone().ok_or("TLS without acme")?;
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let client = Arc::new(Self {
+            tls,
+            config,
+            challenges: Arc::default(),
+            http,
+        });
+
+        let address = client.config.challenge_address()?;
+        let listener = tokio::net::TcpListener::bind(&address)
+            .await
+            .map_err(|e| format!("Failed to bind ACME challenges to {}: {}", address, e))?;
+        let app = challenge_routes(client.challenges.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        if client.due() {
+            client.obtain().await?;
+        }
+        Ok(client)
+    }
+
+    /// Renews the certificate once it is due, loading it into `certificate`.
+    pub async fn renew(self: Arc<Self>, certificate: Arc<TlsCertificate>) {
+        loop {
+            tokio::time::sleep(CHECK_INTERVAL).await;
+            if !self.due() {
+                continue;
+            }
+            let renewed = match self.obtain().await {
+                Ok(()) => certificate.reload(),
+                Err(e) => Err(e),
+            };
+            match renewed {
+                Ok(()) => println!("Renewed TLS certificate for {:?}", self.config.domains),
+                Err(e) => println!("Failed to renew TLS certificate, retrying later: {}", e),
+            }
+        }
+    }
+
+    /// Whether the certificate is missing or expires within
+    /// `renew_before_days`.
+    fn due(&self) -> bool {
+        let days = self.config.renew_before_days.unwrap_or(30);
+        needs_renewal(&self.tls.cert_file, days)
+    }
+
+    fn account_key(&self) -> Result<EcKey<Private>, String> {
+        let path = match &self.config.account_key_file {
+            Some(path) => path.clone(),
+            None => Path::new(&self.tls.key_file)
+                .with_file_name("acme-account.pem")
+                .to_string_lossy()
+                .to_string(),
+        };
+        if let Ok(pem) = fs::read(&path) {
+            return EcKey::private_key_from_pem(&pem)
+                .map_err(|e| format!("Invalid ACME account key in {}: {}", path, e));
+        }
+        let key = new_key()?;
+        let pem = key.private_key_to_pem().map_err(|e| e.to_string())?;
+        write_atomically(&path, &pem, KEY_MODE)?;
+        Ok(key)
+    }
+
+    /// Orders a certificate for the domains, answers its challenges and
+    /// writes it out once issued.
+    async fn obtain(&self) -> Result<(), String> {
+        println!("Requesting a TLS certificate for {:?}", self.config.domains);
+        let key = self.account_key()?;
+        let thumbprint = thumbprint(&key)?;
+        let mut account = Account::open(&self.http, &self.config, key).await?;
+
+        let identifiers: Vec<_> = self
+            .config
+            .domains
+            .iter()
+            .map(|domain| json!({ "type": "dns", "value": domain }))
+            .collect();
+        let new_order = account.directory.new_order.clone();
+        let response = account
+            .post(&new_order, Some(json!({ "identifiers": identifiers })))
+            .await?;
+        let order_url = location(&response)?;
+        let order = json_of(response).await?;
+
+        for authorization in order["authorizations"].as_array().into_iter().flatten() {
+            let url = authorization.as_str().ok_or("Invalid authorization url")?;
+            let response = account.post(url, None).await?;
+            let authorization = json_of(response).await?;
+            if authorization["status"] == "valid" {
+                continue;
+            }
+            let challenge = authorization["challenges"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|challenge| challenge["type"] == "http-01")
+                .ok_or("The CA offered no http-01 challenge")?;
+            let (Some(token), Some(challenge_url)) =
+                (challenge["token"].as_str(), challenge["url"].as_str())
+            else {
+                return Err("Invalid http-01 challenge".to_string());
+            };
+            let key_authorization = format!("{}.{}", token, thumbprint);
+            let challenges = &self.challenges;
+            challenges
+                .lock()
+                .unwrap()
+                .insert(token.to_string(), key_authorization);
+            let answered = match account.post(challenge_url, Some(json!({}))).await {
+                Ok(_) => account.poll(url, "valid").await,
+                Err(e) => Err(e),
+            };
+            challenges.lock().unwrap().remove(token);
+            answered?;
+        }
+
+        let domain_key = PKey::from_ec_key(new_key()?).map_err(|e| e.to_string())?;
+        let csr = csr(&domain_key, &self.config.domains)?;
+        let finalize = order["finalize"]
+            .as_str()
+            .ok_or("Order without finalize url")?;
+        account
+            .post(
+                finalize,
+                Some(json!({ "csr": URL_SAFE_NO_PAD.encode(csr) })),
+            )
+            .await?;
+        let order = account.poll(&order_url, "valid").await?;
+        let certificate_url = order["certificate"]
+            .as_str()
+            .ok_or("Order without certificate url")?;
+        let chain = account
+            .post(certificate_url, None)
+            .await?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let key_pem = domain_key
+            .private_key_to_pem_pkcs8()
+            .map_err(|e| e.to_string())?;
+        // The key first, so a reload between the two never pairs the new
+        // certificate with the old key; a mismatch fails to load instead.
+        write_atomically(&self.tls.key_file, &key_pem, KEY_MODE)?;
+        write_atomically(&self.tls.cert_file, chain.as_bytes(), 0o644)
+    }
+}
+
+/// An ACME account session signing requests with the account key.
+struct Account<'a> {
+    http: &'a reqwest::Client,
+    key: EcKey<Private>,
+    directory: Directory,
+    nonce: Option<String>,
+    /// Url of the account, once registered.
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+impl<'a> Account<'a> {
+    /// Registers the account key, or finds the account it already has.
+    async fn open(
+        http: &'a reqwest::Client,
+        config: &AcmeConfig,
+        key: EcKey<Private>,
+    ) -> Result<Self, String> {
+        let response = http
+            .get(&config.directory)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to read ACME directory: {}", e))?;
+        let directory = serde_json::from_value(json_of(response).await?)
+            .map_err(|e| format!("Invalid ACME directory: {}", e))?;
+        let mut account = Self {
+            http,
+            key,
+            directory,
+            nonce: None,
+            kid: None,
+        };
+        let payload =
+            json!({ "termsOfServiceAgreed": config.agree_tos, "contact": config.contact });
+        let new_account = account.directory.new_account.clone();
+        let response = account.post(&new_account, Some(payload)).await?;
+        account.kid = Some(location(&response)?);
+        Ok(account)
+    }
+
+    async fn nonce(&mut self) -> Result<String, String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let response = self
+            .http
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to get an ACME nonce: {}", e))?;
+        replay_nonce(&response).ok_or_else(|| "ACME server sent no nonce".to_string())
+    }
+
+    /// POSTs `payload` to `url`, or a POST-as-GET without one. A rejected
+    /// nonce is retried once with the fresh one the CA sent.
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<Value>,
+    ) -> Result<reqwest::Response, String> {
+        let payload = match &payload {
+            Some(payload) => URL_SAFE_NO_PAD.encode(payload.to_string()),
+            None => String::new(),
+        };
+        for attempt in 0..2 {
+            let mut protected = json!({ "alg": "ES256", "nonce": self.nonce().await?, "url": url });
+            match &self.kid {
+                Some(kid) => protected["kid"] = json!(kid),
+                None => protected["jwk"] = jwk(&self.key)?,
+            }
+            let body = jws(&self.key, &protected, &payload)?;
+            let response = self
+                .http
+                .post(url)
+                .header(CONTENT_TYPE, "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await
+                .map_err(|e| format!("ACME request to {} failed: {}", url, e))?;
+            self.nonce = replay_nonce(&response);
+            if response.status().is_success() {
+                return Ok(response);
+            }
+            let problem = json_of(response).await.unwrap_or_default();
+            if attempt == 0 && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                continue;
+            }
+            return Err(format!("ACME request to {} failed: {}", url, problem));
+        }
+        unreachable!()
+    }
+
+    /// Polls the authorization or order at `url` until it is `status`.
+    async fn poll(&mut self, url: &str, status: &str) -> Result<Value, String> {
+        for _ in 0..POLL_ATTEMPTS {
+            let response = self.post(url, None).await?;
+            let resource = json_of(response).await?;
+            match resource["status"].as_str() {
+                Some(current) if current == status => return Ok(resource),
+                Some("invalid") => return Err(format!("ACME validation failed: {}", resource)),
+                _ => tokio::time::sleep(POLL_INTERVAL).await,
+            }
+        }
+        Err(format!("Gave up waiting for {} to be {}", url, status))
+    }
+}
+
+fn challenge_routes(challenges: Arc<Challenges>) -> Router {
+    Router::new()
+        .route(
+            "/.well-known/acme-challenge/{token}",
+            get(
+                |UrlPath(token): UrlPath<String>, State(challenges): State<Arc<Challenges>>| async move {
+                    match challenges.lock().unwrap().get(&token) {
+                        Some(key_authorization) => (StatusCode::OK, key_authorization.clone()),
+                        None => (StatusCode::NOT_FOUND, String::new()),
+                    }
+                },
+            ),
+        )
+        .with_state(challenges)
+}
+
+/// Whether the certificate in `path` is missing, unreadable or expires
+/// within `days`.
+fn needs_renewal(path: &str, days: u32) -> bool {
+    let Some(certificate) = fs::read(path)
+        .ok()
+        .and_then(|pem| X509::from_pem(&pem).ok())
+    else {
+        return true;
+    };
+    Asn1Time::days_from_now(days).is_ok_and(|renew_at| certificate.not_after() <= renew_at)
+}
+
+fn new_key() -> Result<EcKey<Private>, String> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).map_err(|e| e.to_string())?;
+    EcKey::generate(&group).map_err(|e| e.to_string())
+}
+
+/// The public half of `key` as a JWK, its members in the order RFC 7638
+/// hashes them.
+fn jwk(key: &EcKey<Private>) -> Result<Value, String> {
+    let mut context = BigNumContext::new().map_err(|e| e.to_string())?;
+    let (mut x, mut y) = (
+        BigNum::new().map_err(|e| e.to_string())?,
+        BigNum::new().map_err(|e| e.to_string())?,
+    );
+    key.public_key()
+        .affine_coordinates(key.group(), &mut x, &mut y, &mut context)
+        .map_err(|e| e.to_string())?;
+    let coordinate = |n: &BigNum| {
+        n.to_vec_padded(32)
+            .map(|bytes| URL_SAFE_NO_PAD.encode(bytes))
+            .map_err(|e| e.to_string())
+    };
+    Ok(json!({ "crv": "P-256", "kty": "EC", "x": coordinate(&x)?, "y": coordinate(&y)? }))
+}
+
+/// The RFC 7638 thumbprint of the account key, part of every key
+/// authorization.
+fn thumbprint(key: &EcKey<Private>) -> Result<String, String> {
+    let jwk = jwk(key)?;
+    let canonical = format!(
+        r#"{{"crv":"P-256","kty":"EC","x":{},"y":{}}}"#,
+        jwk["x"], jwk["y"]
+    );
+    Ok(URL_SAFE_NO_PAD.encode(openssl::sha::sha256(canonical.as_bytes())))
+}
+
+/// A flattened JWS of `payload`, already base64url encoded, signed with ES256.
+fn jws(key: &EcKey<Private>, protected: &Value, payload: &str) -> Result<Value, String> {
+    let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+    let digest = openssl::sha::sha256(format!("{}.{}", protected, payload).as_bytes());
+    let signature = EcdsaSig::sign(&digest, key).map_err(|e| e.to_string())?;
+    let mut raw = signature.r().to_vec_padded(32).map_err(|e| e.to_string())?;
+    raw.extend(signature.s().to_vec_padded(32).map_err(|e| e.to_string())?);
+    Ok(json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": URL_SAFE_NO_PAD.encode(raw),
+    }))
+}
+
+/// A DER certificate request for `domains`, the first one as common name.
+fn csr(key: &PKey<Private>, domains: &[String]) -> Result<Vec<u8>, String> {
+    let build = || -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let mut request = X509ReqBuilder::new()?;
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", &domains[0])?;
+        request.set_subject_name(&name.build())?;
+        request.set_pubkey(key)?;
+        let mut names = SubjectAlternativeName::new();
+        for domain in domains {
+            names.dns(domain);
+        }
+        let mut extensions = Stack::new()?;
+        extensions.push(names.build(&request.x509v3_context(None))?)?;
+        request.add_extensions(&extensions)?;
+        request.sign(key, MessageDigest::sha256())?;
+        request.build().to_der()
+    };
+    build().map_err(|e| format!("Failed to build the certificate request: {}", e))
+}
+
+fn location(response: &reqwest::Response) -> Result<String, String> {
+    response
+        .headers()
+        .get(LOCATION)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| format!("ACME response from {} has no Location", response.url()))
+}
+
+async fn json_of(response: reqwest::Response) -> Result<Value, String> {
+    let url = response.url().clone();
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    serde_json::from_slice(&body).map_err(|e| format!("Invalid ACME response from {}: {}", url, e))
+}
+
+fn replay_nonce(response: &reqwest::Response) -> Option<String> {
+    response
+        .headers()
+        .get("replay-nonce")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+/// Writes `contents` to `path` through a temporary file created with
+/// `mode`, so readers never see it half written.
+fn write_atomically(path: &str, contents: &[u8], mode: u32) -> Result<(), String> {
+    let temporary = format!("{}.tmp", path);
+    let write = || -> std::io::Result<()> {
+        // A leftover of an interrupted write would keep its own mode.
+        match fs::remove_file(&temporary) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&temporary, path)
+    };
+    write().map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{self, Body};
+    use openssl::{bn::BigNum, ecdsa::EcdsaSig};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_jws() {
+        let key = new_key().unwrap();
+        let protected = json!({ "alg": "ES256", "nonce": "n", "url": "https://ca/new-order" });
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"identifiers":[]}"#);
+        let jws = jws(&key, &protected, &payload).unwrap();
+
+        let signed = format!("{}.{}", jws["protected"].as_str().unwrap(), payload);
+        let raw = URL_SAFE_NO_PAD
+            .decode(jws["signature"].as_str().unwrap())
+            .unwrap();
+        assert_eq!(raw.len(), 64);
+        let signature = EcdsaSig::from_private_components(
+            BigNum::from_slice(&raw[..32]).unwrap(),
+            BigNum::from_slice(&raw[32..]).unwrap(),
+        )
+        .unwrap();
+        let digest = openssl::sha::sha256(signed.as_bytes());
+        assert!(signature.verify(&digest, &key).unwrap());
+
+        // SHA-256 in base64url without padding.
+        assert_eq!(thumbprint(&key).unwrap().len(), 43);
+    }
+
+    #[test]
+    fn test_config() {
+        let config: AcmeConfig = toml::from_str(
+            r#"
+            domains = ["rpc.example.com"]
+            http_address = "127.0.0.1:8080"
+            agree_tos = true
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.challenge_address().unwrap(),
+            "127.0.0.1:8080".parse().unwrap()
+        );
+
+        let error = AcmeConfig {
+            agree_tos: false,
+            ..config.clone()
+        }
+        .validate()
+        .unwrap_err();
+        assert!(error.contains("agree_tos"), "{}", error);
+        assert!(AcmeConfig {
+            http_address: Some("localhost".to_string()),
+            ..config.clone()
+        }
+        .validate()
+        .is_err());
+        let config = AcmeConfig {
+            http_address: None,
+            ..config
+        };
+        assert_eq!(config.challenge_address().unwrap().port(), 80);
+    }
+
+    #[tokio::test]
+    async fn test_challenges_and_renewal() {
+        let challenges: Arc<Challenges> = Arc::default();
+        challenges
+            .lock()
+            .unwrap()
+            .insert("token".to_string(), "token.thumbprint".to_string());
+        let app = challenge_routes(challenges);
+        let request = |path: &str| {
+            axum::http::Request::builder()
+                .uri(path)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let response = app
+            .clone()
+            .oneshot(request("/.well-known/acme-challenge/token"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"token.thumbprint");
+        let response = app
+            .oneshot(request("/.well-known/acme-challenge/other"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // A certificate valid for 60 more days.
+        let path = std::env::temp_dir().join(format!("rpc_lb_acme_{}.pem", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        assert!(needs_renewal(&path, 30));
+        let key = PKey::from_ec_key(new_key().unwrap()).unwrap();
+        let mut certificate = X509::builder().unwrap();
+        certificate.set_pubkey(&key).unwrap();
+        certificate
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        certificate
+            .set_not_after(&Asn1Time::days_from_now(60).unwrap())
+            .unwrap();
+        certificate.sign(&key, MessageDigest::sha256()).unwrap();
+        write_atomically(&path, &certificate.build().to_pem().unwrap(), 0o644).unwrap();
+        assert!(!needs_renewal(&path, 30));
+        assert!(needs_renewal(&path, 90));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_keys_are_written_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir()
+            .join(format!("rpc_lb_acme_key_{}.pem", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        // Left over by an interrupted write, with a wider mode.
+        fs::write(format!("{}.tmp", path), "stale").unwrap();
+        write_atomically(&path, b"key", KEY_MODE).unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read(&path).unwrap(), b"key");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    fs,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use openssl::pkey::PKey;
+use serde::Deserialize;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_native_tls::{native_tls, TlsAcceptor};
+
+use super::acme::AcmeConfig;
+
+/// Default of `reload_secs`.
+const DEFAULT_RELOAD_SECS: u64 = 60;
+
+/// The `[server.listener.tls]` section: the certificate clients are served
+/// over TLS with, reloaded when its files change or on `SIGHUP`, as for
+/// certificates an ACME client rotates.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TlsConfig {
+    /// PEM file of the certificate followed by its chain.
+    pub cert_file: String,
+    /// PEM file of the certificate's private key, PKCS#8 or the RSA and EC
+    /// formats.
+    pub key_file: String,
+    /// Seconds between checks of the files for changes, 60 when unset.
+    pub reload_secs: Option<u64>,
+    /// Obtain and renew the certificate from an ACME CA into the files
+    /// above, rather than leaving that to an external client.
+    pub acme: Option<AcmeConfig>,
+}
+
+/// The acceptor of new TLS connections, swapped whole on reloads.
+/// Connections accepted before keep their session, so none is dropped.
+pub struct TlsCertificate {
+    config: TlsConfig,
+    acceptor: RwLock<Arc<TlsAcceptor>>,
+    /// Modification times of the files the acceptor was built from.
+    loaded: Mutex<Option<(SystemTime, SystemTime)>>,
+}
+
+impl TlsCertificate {
+    pub fn new(config: TlsConfig) -> Result<Self, String> {
+        let loaded = modified(&config);
+        let acceptor = load(&config)?;
+        Ok(Self {
+            config,
+            acceptor: RwLock::new(Arc::new(acceptor)),
+            loaded: Mutex::new(loaded),
+        })
+    }
+
+    pub fn acceptor(&self) -> Arc<TlsAcceptor> {
+        self.acceptor.read().unwrap().clone()
+    }
+
+    /// Reads the files again. The current certificate stays when they can
+    /// not be loaded, e.g. while they are half written.
+    pub fn reload(&self) -> Result<(), String> {
+        let loaded = modified(&self.config);
+        let acceptor = load(&self.config)?;
+        *self.acceptor.write().unwrap() = Arc::new(acceptor);
+        *self.loaded.lock().unwrap() = loaded;
+        Ok(())
+    }
+
+    /// Whether the files were modified since they were last loaded.
+    fn changed(&self) -> bool {
+        modified(&self.config) != *self.loaded.lock().unwrap()
+    }
+
+    /// Reloads the certificate whenever its files change or the process
+    /// gets `SIGHUP`.
+    pub async fn watch(self: Arc<Self>) {
+        let interval = Duration::from_secs(self.config.reload_secs.unwrap_or(DEFAULT_RELOAD_SECS));
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => Some(hangups),
+            Err(e) => {
+                println!(
+                    "Failed to listen for SIGHUP, TLS certificates reload on change only: {}",
+                    e
+                );
+                None
+            }
+        };
+        loop {
+            let hangup = async {
+                match hangups.as_mut() {
+                    Some(hangups) => hangups.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = hangup => {}
+                _ = tokio::time::sleep(interval) => {
+                    if !self.changed() {
+                        continue;
+                    }
+                }
+            }
+            match self.reload() {
+                Ok(()) => println!("Reloaded TLS certificate {}", self.config.cert_file),
+                Err(e) => println!(
+                    "Failed to reload TLS certificate, keeping the current one: {}",
+                    e
+                ),
+            }
+        }
+    }
+}
+
+fn modified(config: &TlsConfig) -> Option<(SystemTime, SystemTime)> {
+    let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    Some((modified(&config.cert_file)?, modified(&config.key_file)?))
+}
+
+fn load(config: &TlsConfig) -> Result<TlsAcceptor, String> {
+    let read =
+        |path: &String| fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e));
+    let cert = read(&config.cert_file)?;
+    // native-tls only takes PKCS#8 keys, ACME clients often write others.
+    let key = PKey::private_key_from_pem(&read(&config.key_file)?)
+        .and_then(|key| key.private_key_to_pem_pkcs8())
+        .map_err(|e| format!("Invalid private key in {}: {}", config.key_file, e))?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key)
+        .map_err(|e| format!("Invalid certificate in {}: {}", config.cert_file, e))?;
+    let acceptor = native_tls::TlsAcceptor::new(identity).map_err(|e| e.to_string())?;
+    Ok(TlsAcceptor::from(acceptor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        rsa::Rsa,
+        x509::{X509NameBuilder, X509},
+    };
+    use tokio_native_tls::TlsConnector;
+
+    /// Writes a self-signed certificate for `name` and its RSA key.
+    fn write_certificate(config: &TlsConfig, name: &str) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let subject = subject.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_subject_name(&subject).unwrap();
+        cert.set_issuer_name(&subject).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(30).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        fs::write(&config.cert_file, cert.build().to_pem().unwrap()).unwrap();
+        // The traditional RSA format, as some ACME clients write it.
+        let key = key.rsa().unwrap().private_key_to_pem().unwrap();
+        fs::write(&config.key_file, key).unwrap();
+    }
+
+    /// The common name of the certificate `certificate` serves.
+    async fn served_name(certificate: &TlsCertificate) -> String {
+        let (client, server) = tokio::io::duplex(16 * 1024);
+        let acceptor = certificate.acceptor();
+        tokio::spawn(async move { acceptor.accept(server).await });
+        let connector = native_tls::TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        let stream = TlsConnector::from(connector)
+            .connect("localhost", client)
+            .await
+            .unwrap();
+        let der = stream.get_ref().peer_certificate().unwrap().unwrap();
+        let cert = X509::from_der(&der.to_der().unwrap()).unwrap();
+        let name = cert.subject_name().entries().next().unwrap();
+        name.data().as_utf8().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("rpc_lb_tls_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |file: &str| dir.join(file).to_string_lossy().to_string();
+        let config = TlsConfig {
+            cert_file: path("cert.pem"),
+            key_file: path("key.pem"),
+            reload_secs: None,
+            acme: None,
+        };
+        write_certificate(&config, "first");
+        let certificate = TlsCertificate::new(config.clone()).unwrap();
+        assert_eq!(served_name(&certificate).await, "first");
+
+        write_certificate(&config, "second");
+        certificate.reload().unwrap();
+        assert_eq!(served_name(&certificate).await, "second");
+        assert!(!certificate.changed());
+
+        // A half written certificate leaves the current one in place.
+        fs::write(&config.cert_file, "-----BEGIN CERTIFICATE-----").unwrap();
+        assert!(certificate.reload().is_err());
+        assert_eq!(served_name(&certificate).await, "second");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,74 +1,784 @@
-mod algorithms;
-mod handlers;
-
 use std::{
     collections::HashMap,
-    env, fs,
-    sync::{Arc, Mutex},
+    env,
+    sync::{Arc, RwLock},
     time::Duration,
 };
 
-use algorithms::round_robin::{Config, LoadBalancer, RoundRobin};
 use axum::{
-    response::IntoResponse,
-    routing::{any, get},
+    body::Body,
+    extract::{Query, Request, State},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{any, get, post, put},
     Router,
 };
 use dotenv::dotenv;
-use handlers::load_balancer::load_balancer;
+use reqwest::StatusCode;
+use rpc_lb::{
+    algorithms::round_robin::{Config, LoadBalancer, RoundRobin, ServerConfig},
+    auth,
+    backpressure::{self, BackpressureListener, InFlight},
+    config::{self, history::ConfigHistory},
+    handlers::{
+        admin::{
+            cache_only, chaos, kill_switch, purge_cache, request_log, set_algorithm, top_consumers,
+            validate_config,
+        },
+        gas::gas,
+        head::head,
+        keys::{backend_key, keys},
+        load_balancer::load_balancer,
+        metrics::metrics,
+        status::status,
+        tx_lookup::tx_lookup,
+        tx_status::tx_status,
+    },
+    listener::{self, acme::AcmeClient, tls::TlsCertificate},
+    metrics::Metrics,
+    services::{
+        cache::ResponseCache,
+        cache_warming,
+        chaos::Chaos,
+        consumers::Consumers,
+        discovery,
+        filters::FilterPins,
+        gas_oracle::GasOracle,
+        geo::ClientRegions,
+        health,
+        kill_switch::KillSwitch,
+        prewarm, probe_report,
+        quota::{self, QuotaNotifier},
+        registry::RegistryConfig,
+        request_log::RequestLog,
+        simulation::{self, Scenario},
+        startup::{self, StartupMode},
+        tx_journal::{self, TxJournal},
+        tx_rebroadcast::TxTracker,
+        warm_state,
+    },
+};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::task::AbortHandle;
+use tower::ServiceExt;
+
+/// How often backend request limits are refilled.
+const REFILL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the `[server.registry]` is checked for changed chains.
+const REGISTRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often chains with a `discovery` source or `urls_file` are synced
+/// with them.
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(10);
 
-pub async fn initialize_load_balancer(config: Config) -> Arc<LoadBalancer> {
+pub async fn initialize_load_balancer(
+    config: Config,
+    source: toml::Table,
+    metrics: Arc<Metrics>,
+    filters: Arc<FilterPins>,
+) -> Result<Arc<LoadBalancer>, String> {
+    let region = env::var("REGION").ok().or(config.server.region.clone());
+    let chaos = Arc::new(Chaos::new(&config.chains)?);
+    let kill_switch = Arc::new(KillSwitch::new(&config.chains));
+    let request_log = Arc::new(RequestLog::new(&config.chains));
     let mut lb_map = HashMap::new();
-    for (chain_name, chain_data) in config.chains {
-        let round_robin = Arc::new(Mutex::new(RoundRobin::new(chain_data.rpc_urls)));
-        lb_map.insert(chain_name, round_robin);
+    let mut rebroadcast_chains = HashMap::new();
+    for (chain_name, chain_data) in &config.chains {
+        if !chain_data.is_enabled() {
+            println!("Chain {} is disabled", chain_name);
+        }
+        if let (true, Some(rebroadcast)) = (chain_data.is_enabled(), &chain_data.rebroadcast) {
+            rebroadcast_chains.insert(chain_name.clone(), rebroadcast.clone());
+        }
+        let round_robin = RoundRobin::new(chain_data.rpc_urls.clone())
+            .with_timezone(chain_data.timezone())
+            .with_failback(chain_data.failback_after())
+            .with_latency_budget(chain_data.latency_budget_ms)
+            .with_drain_first(chain_data.drain_first)
+            .with_predictive_spillover(chain_data.predictive_spillover)
+            .with_health_weights(chain_data.routing.health_weights)
+            .with_latency_smoothing(chain_data.routing.latency_smoothing)
+            .with_error_cost(chain_data.error_cost)
+            .with_local_region(chain_data.local_region(region.as_deref()));
+        let round_robin = match &chain_data.http {
+            Some(http) => round_robin
+                .with_http_client(http)
+                .map_err(|e| format!("Chain {}: {}", chain_name, e))?,
+            None => round_robin,
+        };
+        let round_robin = Arc::new(chaos.wrap(chain_name, round_robin));
+        lb_map.insert(chain_name.clone(), round_robin);
     }
 
-    Arc::new(LoadBalancer {
+    Ok(Arc::new(LoadBalancer {
         load_balancers: Arc::new(lb_map),
+        chains: Arc::new(config.chains),
+        tx_tracker: Arc::new(TxTracker::new(rebroadcast_chains)),
+        gas_oracle: Arc::new(GasOracle::default()),
+        metrics,
+        cache: Arc::new(ResponseCache::default()),
+        consumers: Arc::new(Consumers::new(config.server.consumers)),
+        regions: Arc::new(ClientRegions::new(config.server.geoip.as_ref())?),
+        applied_config: Arc::new(source),
+        sla_classes: Arc::new(config.server.sla_classes),
+        chaos,
+        tx_journal: Arc::new(TxJournal::new(config.server.tx_journal.as_ref())?),
+        tx_dedup: Arc::default(),
+        kill_switch,
+        request_log,
+        filters,
+        replays: Arc::default(),
+    }))
+}
+
+/// Starts the background work of `lb`: limit refills, quota alerts, health
+/// checks, connection prewarming and transaction rebroadcasts. The returned
+/// handles stop it.
+fn spawn_tasks(lb: &Arc<LoadBalancer>, server: &ServerConfig) -> Vec<AbortHandle> {
+    let mut tasks = Vec::new();
+    for round_robin in lb.load_balancers.values() {
+        let rr_clone = round_robin.clone();
+
+        tasks.push(
+            tokio::spawn(async move {
+                rr_clone.refill_limits(REFILL_INTERVAL).await;
+            })
+            .abort_handle(),
+        );
+    }
+
+    if lb.tx_journal.is_enabled() {
+        tasks.push(tokio::spawn(tx_journal::run(lb.clone())).abort_handle());
+    }
+
+    if let Some(webhook) = server.quota_webhook.clone() {
+        let notifier = Arc::new(QuotaNotifier::new(webhook));
+        for (chain, round_robin) in lb.load_balancers.iter() {
+            if lb.is_enabled(chain) {
+                tasks.push(
+                    tokio::spawn(quota::monitor(
+                        chain.clone(),
+                        round_robin.clone(),
+                        notifier.clone(),
+                        REFILL_INTERVAL,
+                    ))
+                    .abort_handle(),
+                );
+            }
+        }
+    }
+
+    for (chain, config) in lb.chains.iter() {
+        if let (true, Some(check), Some(round_robin)) = (
+            config.is_enabled(),
+            &config.health_check,
+            lb.load_balancers.get(chain),
+        ) {
+            tasks.push(
+                tokio::spawn(health::run(
+                    chain.clone(),
+                    round_robin.clone(),
+                    check.clone(),
+                ))
+                .abort_handle(),
+            );
+        }
+    }
+
+    for (chain, config) in lb.chains.iter() {
+        if let (true, Some(warming), Some(round_robin)) = (
+            config.is_enabled(),
+            &config.cache_warming,
+            lb.load_balancers.get(chain),
+        ) {
+            tasks.push(
+                tokio::spawn(cache_warming::run(
+                    chain.clone(),
+                    round_robin.clone(),
+                    lb.cache.clone(),
+                    config.cache.clone(),
+                    warming.clone(),
+                ))
+                .abort_handle(),
+            );
+        }
+    }
+
+    for (chain, config) in lb.chains.iter() {
+        if let (true, Some(prewarm), Some(round_robin)) = (
+            config.is_enabled(),
+            &config.prewarm,
+            lb.load_balancers.get(chain),
+        ) {
+            tasks.push(
+                tokio::spawn(prewarm::run(
+                    chain.clone(),
+                    round_robin.clone(),
+                    prewarm.clone(),
+                ))
+                .abort_handle(),
+            );
+        }
+    }
+
+    for chain in lb.tx_tracker.enabled_chains() {
+        if let Some(round_robin) = lb.load_balancers.get(&chain) {
+            let rr_clone = round_robin.clone();
+            let tracker = lb.tx_tracker.clone();
+
+            tasks.push(
+                tokio::spawn(async move {
+                    tracker.run(chain, rr_clone).await;
+                })
+                .abort_handle(),
+            );
+        }
+    }
+    tasks
+}
+
+/// The routes of `lb`, with the `/admin` ones when `admin` is set.
+fn routes(lb: Arc<LoadBalancer>, admin: bool) -> Router {
+    let router = Router::new();
+    let router = if admin {
+        router.merge(
+            Router::new()
+                .route("/admin/top-consumers", get(top_consumers))
+                .route("/admin/config/validate", post(validate_config))
+                .route("/admin/cache/purge", post(purge_cache))
+                .route("/admin/cache/only", post(cache_only))
+                .route("/admin/chaos", post(chaos))
+                .route("/admin/kill-switch", post(kill_switch))
+                .route("/admin/request-log", post(request_log))
+                .route("/admin/chains/{chain}/algorithm", put(set_algorithm))
+                .route_layer(middleware::from_fn(auth::require_admin)),
+        )
+    } else {
+        router
+    };
+    router
+        .route("/", get(home))
+        .route("/metrics", get(metrics))
+        .route("/{chain}/gas", get(gas))
+        .route("/{chain}/head", get(head))
+        .route("/{chain}/keys", get(keys))
+        .route("/{chain}/keys/{backend}", get(backend_key))
+        .route("/{chain}/status", get(status))
+        .route("/{chain}/tx/{hash}", get(tx_lookup))
+        .route("/{chain}/tx/{hash}/status", get(tx_status))
+        .route("/{chain}", any(load_balancer))
+        .with_state(lb)
+}
+
+/// The applied config, swapped whole by reloads and rollbacks: the routes
+/// serving it and the background tasks working for it.
+struct Runtime {
+    current: RwLock<(Arc<LoadBalancer>, Router, Vec<AbortHandle>)>,
+    history: ConfigHistory,
+    /// Kept across configs, so counters do not restart on reloads.
+    metrics: Arc<Metrics>,
+    /// Lets one reload or rollback run at a time.
+    applying: tokio::sync::Mutex<()>,
+    /// Whether the `/admin` routes are served, only with a credential
+    /// granting `admin` in `[server.auth]` as of startup.
+    admin: bool,
+}
+
+impl Runtime {
+    /// Builds the balancer of the resolved config `source` and swaps it in,
+    /// stopping the tasks of the previous one. Requests in flight finish on
+    /// the previous one, whose removed backends drain. Nothing changes on
+    /// errors.
+    async fn apply(&self, source: toml::Table, origin: &str) -> Result<u64, String> {
+        let _applying = self.applying.lock().await;
+        let config = config::build(source.clone())?;
+        let server = config.server.clone();
+        let previous = self.current.read().unwrap().0.clone();
+        let lb = initialize_load_balancer(
+            config,
+            source.clone(),
+            self.metrics.clone(),
+            previous.filters.clone(),
+        )
+        .await?;
+        let version = self.history.record(source, origin)?;
+
+        let tasks = spawn_tasks(&lb, &server);
+        let drain = server.drain_period();
+        for round_robin in lb.filters.drain(&previous, &lb, drain) {
+            tokio::spawn(async move {
+                let _ =
+                    tokio::time::timeout(drain, round_robin.refill_limits(REFILL_INTERVAL)).await;
+            });
+        }
+        let (_, _, stale) = std::mem::replace(
+            &mut *self.current.write().unwrap(),
+            (lb.clone(), routes(lb, self.admin), tasks),
+        );
+        for task in stale {
+            task.abort();
+        }
+        println!("Applied config version {} ({})", version.id, origin);
+        Ok(version.id)
+    }
+}
+
+/// Keeps the discovered backends of every chain in sync with its sources,
+/// applying the config again whenever they changed.
+async fn discover(runtime: Arc<Runtime>) {
+    loop {
+        if let Some(current) = runtime.history.current() {
+            match discovery::refresh(&current.config).await {
+                // A config applied meanwhile is left alone, the next round
+                // refreshes it instead.
+                Ok(Some(updated))
+                    if runtime.history.current().map(|version| version.id) == Some(current.id) =>
+                {
+                    if let Err(e) = runtime.apply(updated, "discovery").await {
+                        println!("Failed to apply discovered backends: {}", e);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => println!("Failed to discover backends: {}", e),
+            }
+        }
+        tokio::time::sleep(DISCOVERY_INTERVAL).await;
+    }
+}
+
+/// `Config.toml` resolved with the chains of its `[server.registry]`, if
+/// any, and the registry document they were read from.
+async fn load_source() -> Result<(toml::Table, Option<toml::Table>), String> {
+    let source = config::load_table("Config.toml")?;
+    let registry = config::build(source.clone())
+        .map_err(|e| format!("Failed to parse Config.toml: {}", e))?
+        .server
+        .registry;
+    let Some(registry) = registry else {
+        return Ok((source, None));
+    };
+    let document = registry.fetch().await?;
+    let source = config::load_table_with("Config.toml", Some(document.clone()))?;
+    Ok((source, Some(document)))
+}
+
+/// Reloads `Config.toml` whenever the registry document changes from
+/// `seen`, so every balancer reading the registry follows it.
+async fn watch_registry(
+    runtime: Arc<Runtime>,
+    registry: RegistryConfig,
+    mut seen: Option<toml::Table>,
+) {
+    loop {
+        tokio::time::sleep(REGISTRY_INTERVAL).await;
+        let document = match registry.fetch().await {
+            Ok(document) if Some(&document) != seen.as_ref() => document,
+            Ok(_) => continue,
+            Err(e) => {
+                println!("Failed to read the registry: {}", e);
+                continue;
+            }
+        };
+        // Remembered even when it fails to apply, so it is reported once.
+        seen = Some(document.clone());
+        let applied = match config::load_table_with("Config.toml", Some(document)) {
+            Ok(source) => runtime.apply(source, "registry").await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = applied {
+            println!("Failed to apply the chains of the registry: {}", e);
+        }
+    }
+}
+
+/// Hands requests to the routes of the current config.
+async fn dispatch(State(runtime): State<Arc<Runtime>>, request: Request) -> Response {
+    let router = runtime.current.read().unwrap().1.clone();
+    match router.oneshot(request).await {
+        Ok(response) => response,
+        Err(never) => match never {},
+    }
+}
+
+/// Lists the kept config versions, newest last.
+async fn config_versions(State(runtime): State<Arc<Runtime>>) -> Response {
+    let versions: Vec<_> = runtime
+        .history
+        .list()
+        .into_iter()
+        .map(|(id, applied_at, origin)| json!({ "version": id, "applied_at": applied_at, "origin": origin }))
+        .collect();
+    respond(StatusCode::OK, json!({ "versions": versions }))
+}
+
+/// Applies `Config.toml` as it is on disk now.
+async fn reload_config(State(runtime): State<Arc<Runtime>>) -> Response {
+    let applied = match load_source().await {
+        Ok((source, _)) => runtime.apply(source, "reload").await,
+        Err(e) => Err(e),
+    };
+    match applied {
+        Ok(version) => respond(StatusCode::OK, json!({ "version": version })),
+        Err(e) => respond(StatusCode::BAD_REQUEST, json!({ "error": e })),
+    }
+}
+
+#[derive(Deserialize)]
+struct RollbackQuery {
+    version: Option<u64>,
+}
+
+/// Applies a kept version again, by default the one before the current.
+async fn rollback_config(
+    State(runtime): State<Arc<Runtime>>,
+    Query(query): Query<RollbackQuery>,
+) -> Response {
+    let target = match query.version {
+        Some(id) => runtime.history.get(id),
+        None => runtime.history.previous(),
+    };
+    let Some(target) = target else {
+        return respond(
+            StatusCode::NOT_FOUND,
+            json!({ "error": "No such config version is kept" }),
+        );
+    };
+    let origin = format!("rollback to {}", target.id);
+    match runtime.apply(target.config, &origin).await {
+        Ok(version) => respond(
+            StatusCode::OK,
+            json!({ "version": version, "restored": target.id }),
+        ),
+        Err(e) => respond(StatusCode::BAD_REQUEST, json!({ "error": e })),
+    }
+}
+
+fn respond(status: StatusCode, body: serde_json::Value) -> Response {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// `--dry-run <path>`: validates the config at `path` and prints what it
+/// would change compared to `Config.toml`, returning the exit code.
+fn dry_run(path: &str) -> i32 {
+    let proposed = config::load_table(path).and_then(|table| {
+        config::build(table.clone())
+            .map(|_| table)
+            .map_err(|e| format!("Failed to parse {}: {}", path, e))
+    });
+    let proposed = match proposed {
+        Ok(proposed) => proposed,
+        Err(e) => {
+            println!("{}", e);
+            return 1;
+        }
+    };
+    let running = config::load_table("Config.toml").unwrap_or_else(|e| {
+        println!("{}, comparing against an empty config", e);
+        toml::Table::new()
+    });
+
+    let changes = config::diff::diff(&running, &proposed);
+    println!("{} is valid, {} changes:", path, changes.len());
+    for change in changes {
+        println!("  {}", change);
+    }
+    0
+}
+
+/// `--print-config`: prints `Config.toml` as it is applied, with includes,
+/// defaults and registry chains resolved and secrets redacted, as JSON.
+/// Returns the exit code.
+async fn print_config() -> i32 {
+    let config = load_source().await.and_then(|(source, _)| {
+        config::build(source.clone())
+            .map(|_| source)
+            .map_err(|e| format!("Failed to parse Config.toml: {}", e))
+    });
+    match config {
+        Ok(config) => {
+            let config = config::summary::redacted(&config);
+            println!("{}", serde_json::to_string_pretty(&config).unwrap());
+            0
+        }
+        Err(e) => {
+            println!("{}", e);
+            1
+        }
+    }
+}
+
+/// Probes every backend of the enabled chains of `lb` and prints the report,
+/// returning whether every such chain has a backend that answered.
+async fn print_probe_report(lb: &LoadBalancer) -> bool {
+    let enabled: HashMap<_, _> = lb
+        .load_balancers
+        .iter()
+        .filter(|(chain, _)| lb.is_enabled(chain))
+        .map(|(chain, round_robin)| (chain.clone(), round_robin.clone()))
+        .collect();
+    let expected_ids = lb
+        .chains
+        .iter()
+        .filter_map(|(chain, settings)| Some((chain.clone(), settings.chain_id?)))
+        .collect();
+
+    let rows = probe_report::probe_all(&enabled, &expected_ids).await;
+    println!("{}", probe_report::render(&rows));
+    enabled.keys().all(|chain| {
+        rows.iter()
+            .any(|row| &row.chain == chain && row.problem.is_none())
     })
 }
 
+/// The balancer of `Config.toml`, for commands run instead of the server.
+async fn load_configured() -> Result<Arc<LoadBalancer>, String> {
+    let (source, _) = load_source().await?;
+    let config =
+        config::build(source.clone()).map_err(|e| format!("Failed to parse Config.toml: {}", e))?;
+    initialize_load_balancer(config, source, Arc::new(Metrics::default()), Arc::default()).await
+}
+
+/// `probe`: prints the probe report of the backends in `Config.toml`,
+/// returning the exit code.
+async fn probe() -> i32 {
+    match load_configured().await {
+        Ok(lb) if print_probe_report(&lb).await => 0,
+        Ok(_) => {
+            println!("Some chains have no usable backend");
+            1
+        }
+        Err(e) => {
+            println!("{}", e);
+            1
+        }
+    }
+}
+
+/// `simulate --scenario <name> --chain <chain> [--requests <n>]`: sends
+/// requests through the routing and retries of a chain of `Config.toml`
+/// against scripted backends and prints what was decided, returning the exit
+/// code.
+async fn simulate(args: &[String]) -> i32 {
+    let usage = format!(
+        "Usage: simulate --scenario <{}> --chain <chain> [--requests <n>]",
+        Scenario::NAMES.join("|")
+    );
+    let mut options = HashMap::new();
+    for pair in args.chunks(2) {
+        match pair {
+            [flag, value] if flag.starts_with("--") => {
+                options.insert(flag.trim_start_matches("--"), value.as_str());
+            }
+            _ => {
+                println!("{}", usage);
+                return 2;
+            }
+        }
+    }
+    let (Some(scenario), Some(chain)) = (options.get("scenario"), options.get("chain")) else {
+        println!("{}", usage);
+        return 2;
+    };
+    let requests = match options.get("requests").map(|n| n.parse::<usize>()) {
+        None => 5,
+        Some(Ok(requests)) => requests,
+        Some(Err(e)) => {
+            println!("Invalid --requests: {}", e);
+            return 2;
+        }
+    };
+
+    let decisions = match scenario.parse::<Scenario>() {
+        Ok(scenario) => match load_configured().await {
+            Ok(lb) => simulation::simulate(&lb, chain, scenario, requests).await,
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e),
+    };
+    match decisions {
+        Ok(decisions) => {
+            println!("Scenario {} on chain {}:", scenario, chain);
+            println!("{}", simulation::render(&decisions));
+            0
+        }
+        Err(e) => {
+            println!("{}", e);
+            1
+        }
+    }
+}
+
 async fn home() -> impl IntoResponse {
     "Welcome to the RPC Load Balancer! Server is up and running."
 }
 
 #[tokio::main]
 async fn main() {
-    let config: Config = {
-        let config_content = fs::read_to_string("Config.toml").expect("Failed to read Config.toml");
-        toml::from_str(&config_content).expect("Failed to parse Config.toml")
-    };
+    dotenv().ok();
+    match &env::args().collect::<Vec<_>>()[..] {
+        [_, flag, path] if flag == "--dry-run" => std::process::exit(dry_run(path)),
+        [_, flag] if flag == "--print-config" => std::process::exit(print_config().await),
+        [_, command] if command == "probe" => std::process::exit(probe().await),
+        [_, command, args @ ..] if command == "simulate" => {
+            std::process::exit(simulate(args).await)
+        }
+        _ => {}
+    }
 
-    let lb = initialize_load_balancer(config).await;
+    let (source, registry_document) = load_source().await.unwrap_or_else(|e| panic!("{}", e));
+    let config: Config = config::build(source.clone())
+        .unwrap_or_else(|e| panic!("Failed to parse Config.toml: {}", e));
 
-    for round_robin in lb.load_balancers.values() {
-        let rr_clone;
+    let server = config.server.clone();
+    let history = ConfigHistory::new(&server.config_history).unwrap_or_else(|e| panic!("{}", e));
+    history
+        .record(source.clone(), "startup")
+        .unwrap_or_else(|e| panic!("{}", e));
+    let metrics = Arc::new(Metrics::new(server.metrics.clone()));
+    let lb = initialize_load_balancer(config, source, metrics.clone(), Arc::default())
+        .await
+        .unwrap_or_else(|e| panic!("{}", e));
+    if let Some(path) = &server.state_file {
+        match warm_state::restore(path, &lb) {
+            Ok(restored) => println!("Restored the state of {} backends from {}", restored, path),
+            Err(e) => println!("{}, starting from a clean state", e),
+        }
+    }
 
-        {
-            let rr = round_robin.lock().unwrap();
-            rr_clone = rr.clone();
+    if server.probe_report {
+        print_probe_report(&lb).await;
+    }
+    match server.startup {
+        Some(StartupMode::FailFast) => {
+            let enabled = lb
+                .load_balancers
+                .iter()
+                .filter(|(chain, _)| lb.is_enabled(chain))
+                .map(|(chain, round_robin)| (chain.clone(), round_robin.clone()))
+                .collect();
+            startup::check_all(&enabled)
+                .await
+                .unwrap_or_else(|e| panic!("{}", e))
         }
+        Some(StartupMode::Lazy) => {
+            for (chain, round_robin) in lb.load_balancers.iter() {
+                if !lb.is_enabled(chain) {
+                    continue;
+                }
+                round_robin.set_ready(false);
+                tokio::spawn(startup::wait_until_ready(
+                    chain.clone(),
+                    round_robin.clone(),
+                ));
+            }
+        }
+        None => {}
+    }
 
+    let tasks = spawn_tasks(&lb, &server);
+    if lb.tx_journal.is_enabled() {
+        let lb = lb.clone();
+        tokio::spawn(async move { tx_journal::replay(&lb, Duration::ZERO).await });
+    }
+    let admin = server
+        .auth
+        .as_ref()
+        .is_some_and(auth::AuthConfig::has_admin);
+    if !admin {
+        println!("Not serving the /admin routes, no [server.auth] credential grants admin");
+    }
+    let runtime = Arc::new(Runtime {
+        current: RwLock::new((lb.clone(), routes(lb.clone(), admin), tasks)),
+        history,
+        metrics,
+        applying: tokio::sync::Mutex::new(()),
+        admin,
+    });
+    tokio::spawn(discover(runtime.clone()));
+    if let Some(path) = server.state_file.clone() {
+        let runtime = runtime.clone();
         tokio::spawn(async move {
-            rr_clone.refill_limits(Duration::from_secs(5)).await;
+            warm_state::shutdown_signal().await;
+            let lb = runtime.current.read().unwrap().0.clone();
+            match warm_state::save(&path, &lb) {
+                Ok(saved) => println!("Saved the state of {} backends to {}", saved, path),
+                Err(e) => println!("{}", e),
+            }
+            std::process::exit(0);
         });
     }
+    if let Some(registry) = server.registry.clone() {
+        tokio::spawn(watch_registry(runtime.clone(), registry, registry_document));
+    }
 
-    let app = Router::new()
-        .route("/", get(home))
-        .route("/{*path}", any(load_balancer))
-        .with_state(lb);
-
-    dotenv().ok();
+    let app = Router::new();
+    let app = if admin {
+        app.route("/admin/config/versions", get(config_versions))
+            .route("/admin/config/reload", post(reload_config))
+            .route("/admin/config/rollback", post(rollback_config))
+            .route_layer(middleware::from_fn(auth::require_admin))
+    } else {
+        app
+    };
+    let app = app.fallback(dispatch).with_state(runtime);
+    let app = match &server.auth {
+        Some(auth_config) => {
+            let authenticator = auth_config.build().unwrap_or_else(|e| panic!("{}", e));
+            app.layer(middleware::from_fn_with_state(
+                Arc::new(auth::Gate::new(authenticator)),
+                auth::require,
+            ))
+        }
+        None => app,
+    };
 
-    let port = env::var("PORT").unwrap_or(format!("8080"));
+    let port = env::var("PORT").unwrap_or("8080".to_string());
 
     let binding_address = format!("0.0.0.0:{}", port);
 
-    let listener = tokio::net::TcpListener::bind(binding_address)
+    let listener = tokio::net::TcpListener::bind(&binding_address)
         .await
         .unwrap();
+    println!(
+        "{}",
+        config::summary::render(&lb.chains, &server, &binding_address)
+    );
 
-    axum::serve(listener, app).await.unwrap();
+    let metrics = lb.metrics.clone();
+    let acme = match server.listener.tls.clone().filter(|tls| tls.acme.is_some()) {
+        Some(tls) => Some(
+            AcmeClient::start(tls)
+                .await
+                .unwrap_or_else(|e| panic!("{}", e)),
+        ),
+        None => None,
+    };
+    let tls = server.listener.tls.clone().map(|tls| {
+        let certificate = TlsCertificate::new(tls).unwrap_or_else(|e| panic!("{}", e));
+        Arc::new(certificate)
+    });
+    if let Some(tls) = &tls {
+        tokio::spawn(tls.clone().watch());
+    }
+    if let (Some(acme), Some(tls)) = (acme, &tls) {
+        tokio::spawn(acme.renew(tls.clone()));
+    }
+    match server.max_in_flight {
+        Some(max_in_flight) => {
+            let in_flight = Arc::new(InFlight::new(max_in_flight, metrics.clone()));
+            let app = app.layer(middleware::from_fn_with_state(
+                in_flight.clone(),
+                backpressure::track,
+            ));
+            let listener = BackpressureListener::new(listener, in_flight);
+            listener::serve(listener, app, server.listener, tls, metrics).await
+        }
+        None => listener::serve(listener, app, server.listener, tls, metrics).await,
+    }
 }
@@ -0,0 +1,269 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    sync::Mutex,
+};
+
+use serde::Deserialize;
+
+type MetricKey = (String, Vec<(String, String)>);
+
+/// Default of `max_methods`.
+const DEFAULT_MAX_METHODS: usize = 200;
+/// Default of `size_buckets`, from 256 B to 1 MiB.
+const DEFAULT_SIZE_BUCKETS: [u64; 7] = [256, 1024, 4096, 16384, 65536, 262144, 1048576];
+/// Longest method name reported as such.
+const MAX_METHOD_LEN: usize = 64;
+
+/// The `[server.metrics]` section: bounds on the label values and series
+/// clients can make the registry grow by.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct MetricsConfig {
+    /// Distinct method names used as labels, later ones are reported as
+    /// `other`. 200 when unset.
+    pub max_methods: Option<usize>,
+    /// Upper bounds in bytes of the buckets request and response sizes are
+    /// counted in, from 256 B to 1 MiB by powers of 4 when unset.
+    pub size_buckets: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Observations up to each bound, the last one counting the rest.
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+/// Small in-process registry rendered in the Prometheus text format on `/metrics`.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    config: MetricsConfig,
+    counters: Mutex<BTreeMap<MetricKey, u64>>,
+    gauges: Mutex<BTreeMap<MetricKey, f64>>,
+    histograms: Mutex<BTreeMap<MetricKey, Histogram>>,
+    /// Method names handed out as labels so far.
+    methods: Mutex<HashSet<String>>,
+}
+
+fn key(name: &str, labels: &[(&str, &str)]) -> MetricKey {
+    (
+        name.to_string(),
+        labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    )
+}
+
+impl Metrics {
+    pub fn new(config: MetricsConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// The label `method` is reported under: itself among the first
+    /// `max_methods` seen, `other` past them and `invalid` for names no
+    /// JSON-RPC or HTTP method has, so junk sent by clients can not grow
+    /// the series without bound.
+    pub fn method_label(&self, method: &str) -> String {
+        let valid = !method.is_empty()
+            && method.len() <= MAX_METHOD_LEN
+            && method
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid {
+            return "invalid".to_string();
+        }
+        let max_methods = self.config.max_methods.unwrap_or(DEFAULT_MAX_METHODS);
+        let mut methods = self.methods.lock().unwrap();
+        if methods.contains(method) {
+            return method.to_string();
+        }
+        if methods.len() >= max_methods {
+            return "other".to_string();
+        }
+        methods.insert(method.to_string());
+        method.to_string()
+    }
+
+    /// Counts a body of `bytes` in the size buckets of `name`.
+    pub fn observe_size(&self, name: &str, labels: &[(&str, &str)], bytes: usize) {
+        let bounds = self.size_buckets();
+        let mut histograms = self.histograms.lock().unwrap();
+        let histogram = histograms.entry(key(name, labels)).or_default();
+        histogram.counts.resize(bounds.len() + 1, 0);
+        let bucket = bounds
+            .iter()
+            .position(|&bound| bytes as u64 <= bound)
+            .unwrap_or(bounds.len());
+        histogram.counts[bucket] += 1;
+        histogram.sum += bytes as f64;
+    }
+
+    fn size_buckets(&self) -> &[u64] {
+        self.config
+            .size_buckets
+            .as_deref()
+            .unwrap_or(&DEFAULT_SIZE_BUCKETS)
+    }
+
+    pub fn inc(&self, name: &str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    pub fn add(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters.entry(key(name, labels)).or_insert(0) += value;
+    }
+
+    pub fn set_gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let mut gauges = self.gauges.lock().unwrap();
+        gauges.insert(key(name, labels), value);
+    }
+
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        let counters = self.counters.lock().unwrap();
+        counters.get(&key(name, labels)).copied().unwrap_or(0)
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let counters = self.counters.lock().unwrap();
+        let gauges = self.gauges.lock().unwrap();
+
+        render_family(
+            &mut output,
+            "counter",
+            counters.iter().map(|(k, v)| (k, *v as f64)),
+        );
+        render_family(&mut output, "gauge", gauges.iter().map(|(k, v)| (k, *v)));
+        let histograms = self.histograms.lock().unwrap();
+        render_histograms(&mut output, self.size_buckets(), &histograms);
+        output
+    }
+}
+
+fn render_histograms(
+    output: &mut String,
+    bounds: &[u64],
+    histograms: &BTreeMap<MetricKey, Histogram>,
+) {
+    let mut current = None;
+    for ((name, labels), histogram) in histograms {
+        if current != Some(name) {
+            writeln!(output, "# TYPE {} histogram", name).unwrap();
+            current = Some(name);
+        }
+        let les = bounds
+            .iter()
+            .map(|bound| bound.to_string())
+            .chain(["+Inf".to_string()]);
+        let mut cumulative = 0;
+        for (le, count) in les.zip(&histogram.counts) {
+            cumulative += count;
+            let mut labels = labels.clone();
+            labels.push(("le".to_string(), le));
+            write_sample(
+                output,
+                &format!("{}_bucket", name),
+                &labels,
+                cumulative as f64,
+            );
+        }
+        write_sample(output, &format!("{}_sum", name), labels, histogram.sum);
+        write_sample(
+            output,
+            &format!("{}_count", name),
+            labels,
+            cumulative as f64,
+        );
+    }
+}
+
+fn render_family<'a>(
+    output: &mut String,
+    kind: &str,
+    samples: impl Iterator<Item = (&'a MetricKey, f64)>,
+) {
+    let mut current = None;
+    for ((name, labels), value) in samples {
+        if current != Some(name) {
+            writeln!(output, "# TYPE {} {}", name, kind).unwrap();
+            current = Some(name);
+        }
+        write_sample(output, name, labels, value);
+    }
+}
+
+fn write_sample(output: &mut String, name: &str, labels: &[(String, String)], value: f64) {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(k, v)| format!("{}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect();
+    if labels.is_empty() {
+        writeln!(output, "{} {}", name, value).unwrap();
+    } else {
+        writeln!(output, "{}{{{}}} {}", name, labels.join(","), value).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counters_and_gauges() {
+        let metrics = Metrics::default();
+        metrics.inc("rpc_lb_requests_total", &[("chain", "sepolia")]);
+        metrics.inc("rpc_lb_requests_total", &[("chain", "sepolia")]);
+        metrics.set_gauge("rpc_lb_in_flight", &[], 3.0);
+
+        assert_eq!(
+            metrics.counter("rpc_lb_requests_total", &[("chain", "sepolia")]),
+            2
+        );
+        assert_eq!(
+            metrics.render(),
+            "# TYPE rpc_lb_requests_total counter\n\
+             rpc_lb_requests_total{chain=\"sepolia\"} 2\n\
+             # TYPE rpc_lb_in_flight gauge\n\
+             rpc_lb_in_flight 3\n"
+        );
+    }
+
+    #[test]
+    fn test_method_labels() {
+        let metrics = Metrics::new(MetricsConfig {
+            max_methods: Some(2),
+            ..Default::default()
+        });
+        assert_eq!(metrics.method_label("eth_call"), "eth_call");
+        assert_eq!(metrics.method_label("eth_chainId"), "eth_chainId");
+        assert_eq!(metrics.method_label("eth_getLogs"), "other");
+        assert_eq!(metrics.method_label("eth_call"), "eth_call");
+        assert_eq!(metrics.method_label("<script>"), "invalid");
+        assert_eq!(metrics.method_label(&"a".repeat(65)), "invalid");
+    }
+
+    #[test]
+    fn test_size_histogram() {
+        let metrics = Metrics::new(MetricsConfig {
+            size_buckets: Some(vec![100, 1000]),
+            ..Default::default()
+        });
+        for bytes in [10, 500, 5000] {
+            metrics.observe_size("rpc_lb_request_bytes", &[("chain", "sepolia")], bytes);
+        }
+        assert_eq!(
+            metrics.render(),
+            "# TYPE rpc_lb_request_bytes histogram\n\
+             rpc_lb_request_bytes_bucket{chain=\"sepolia\",le=\"100\"} 1\n\
+             rpc_lb_request_bytes_bucket{chain=\"sepolia\",le=\"1000\"} 2\n\
+             rpc_lb_request_bytes_bucket{chain=\"sepolia\",le=\"+Inf\"} 3\n\
+             rpc_lb_request_bytes_sum{chain=\"sepolia\"} 5510\n\
+             rpc_lb_request_bytes_count{chain=\"sepolia\"} 3\n"
+        );
+    }
+}
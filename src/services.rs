@@ -0,0 +1,27 @@
+pub mod cache;
+pub mod cache_warming;
+pub mod chaos;
+pub mod consumers;
+pub mod discovery;
+pub mod filters;
+pub mod gas_oracle;
+pub mod geo;
+pub mod head;
+pub mod headers;
+pub mod health;
+pub mod kill_switch;
+pub mod mirror;
+pub mod prewarm;
+pub mod probe_report;
+pub mod quota;
+pub mod registry;
+pub mod replay;
+pub mod request_log;
+pub mod response_guard;
+pub mod rpc_client;
+pub mod simulation;
+pub mod startup;
+pub mod tx_dedup;
+pub mod tx_journal;
+pub mod tx_rebroadcast;
+pub mod warm_state;
@@ -0,0 +1,519 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::body::Bytes;
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Upper bound on cached responses across all chains.
+const MAX_ENTRIES: usize = 10_000;
+
+/// Whether a cached response is shared between chains.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheScope {
+    #[default]
+    Chain,
+    /// For methods whose result does not depend on the chain, e.g. `web3_sha3`.
+    Global,
+}
+
+/// How responses of a method, or of the methods matching a pattern, are cached.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CachePolicy {
+    /// Seconds a response is served from the cache.
+    #[serde(default)]
+    pub ttl: u64,
+    #[serde(default)]
+    pub scope: CacheScope,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl CachePolicy {
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.ttl > 0
+    }
+}
+
+/// Finds the policy of `method` in a chain's `cache` table. An exact entry
+/// wins over patterns such as `eth_get*`, and a longer pattern over a
+/// shorter one.
+pub fn policy_for<'a>(
+    policies: &'a HashMap<String, CachePolicy>,
+    method: &str,
+) -> Option<&'a CachePolicy> {
+    if let Some(policy) = policies.get(method) {
+        return Some(policy);
+    }
+    policies
+        .iter()
+        .filter(|(pattern, _)| {
+            glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(method))
+        })
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, policy)| policy)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// `None` for globally scoped entries.
+    chain: Option<String>,
+    method: String,
+    params: String,
+}
+
+impl CacheKey {
+    pub fn new(chain: &str, policy: &CachePolicy, request: &Value) -> Option<Self> {
+        Some(Self {
+            chain: (policy.scope == CacheScope::Chain).then(|| chain.to_string()),
+            method: request.get("method")?.as_str()?.to_string(),
+            params: request.get("params").unwrap_or(&Value::Null).to_string(),
+        })
+    }
+
+    /// The call the cached response answers, to fetch it again.
+    pub fn request(&self) -> Value {
+        let params: Value = serde_json::from_str(&self.params).unwrap_or(Value::Null);
+        json!({"jsonrpc": "2.0", "method": self.method, "params": params, "id": 1})
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    fetched: Instant,
+    expires: Instant,
+    response: Value,
+    etag: String,
+    /// Times the entry was served, halved whenever it is refreshed so that
+    /// calls which stopped being popular fade out.
+    hits: u64,
+}
+
+/// Which entries `POST /admin/cache/purge` drops, every entry when empty.
+/// `method` and `key` are patterns such as `eth_get*`, `key` is matched
+/// against the params of the call, e.g. `*0xab12*` for an address.
+#[derive(Debug, Default, Deserialize)]
+pub struct PurgeFilter {
+    /// Only entries scoped to this chain, global ones are left.
+    pub chain: Option<String>,
+    pub method: Option<String>,
+    pub key: Option<String>,
+}
+
+impl PurgeFilter {
+    fn matcher(pattern: &Option<String>) -> Result<Option<glob::Pattern>, String> {
+        pattern
+            .as_deref()
+            .map(|pattern| {
+                glob::Pattern::new(pattern)
+                    .map_err(|e| format!("Invalid pattern {}: {}", pattern, e))
+            })
+            .transpose()
+    }
+}
+
+/// A response served from the cache.
+#[derive(Debug)]
+pub struct CachedResponse {
+    pub body: Bytes,
+    pub etag: String,
+    /// Time since the response was fetched from a backend.
+    pub age: Duration,
+    /// Time left until the entry expires, zero once it did.
+    pub fresh_for: Duration,
+    /// The entry expired, which only cache only mode serves.
+    pub stale: bool,
+}
+
+/// The `Cache-Control` a chain's responses carry, so CDNs and clients in
+/// front of the balancer cache alongside it rather than on their own terms.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct CacheControl {
+    /// Directives of cacheable responses, before their `max-age`. `public`
+    /// when unset.
+    pub directives: Option<String>,
+    /// `Cache-Control` of every other response, e.g. `no-store`. None when
+    /// unset.
+    pub uncached: Option<String>,
+}
+
+impl CacheControl {
+    pub fn validate(&self) -> Result<(), String> {
+        for value in self.directives.iter().chain(&self.uncached) {
+            HeaderValue::from_str(value)
+                .map_err(|_| format!("Invalid cache_control directives {}", value))?;
+        }
+        Ok(())
+    }
+
+    /// The `Cache-Control` of a cacheable response staying fresh for
+    /// `fresh_for`.
+    pub fn cacheable(&self, fresh_for: Duration) -> HeaderValue {
+        let directives = self.directives.as_deref().unwrap_or("public");
+        let value = format!("{}, max-age={}", directives, fresh_for.as_secs());
+        HeaderValue::from_str(&value).unwrap_or(HeaderValue::from_static("no-cache"))
+    }
+
+    pub fn uncached(&self) -> Option<HeaderValue> {
+        self.uncached
+            .as_deref()
+            .and_then(|value| HeaderValue::from_str(value).ok())
+    }
+}
+
+/// Successful JSON-RPC responses kept for the `ttl` of their method's policy.
+///
+/// Entries hold the response without its `id`, the id of the request being
+/// answered is put back in when an entry is served.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+    /// Serve reads of every chain from the cache alone, expired entries
+    /// included, while the backends are down.
+    cache_only: AtomicBool,
+    /// Chains doing so on their own.
+    cache_only_chains: Mutex<HashSet<String>>,
+}
+
+impl ResponseCache {
+    /// Returns the cached response for `key`, answering the request `id`.
+    pub fn get(&self, key: &CacheKey, id: &Value) -> Option<CachedResponse> {
+        self.serve(key, id, false)
+    }
+
+    /// Returns the cached response for `key` even when it expired.
+    pub fn get_stale(&self, key: &CacheKey, id: &Value) -> Option<CachedResponse> {
+        self.serve(key, id, true)
+    }
+
+    fn serve(&self, key: &CacheKey, id: &Value, stale: bool) -> Option<CachedResponse> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(key)?;
+        let now = Instant::now();
+        if !stale && entry.expires <= now {
+            return None;
+        }
+        entry.hits += 1;
+
+        let mut response = entry.response.clone();
+        response["id"] = id.clone();
+        Some(CachedResponse {
+            body: Bytes::from(response.to_string()),
+            etag: entry.etag.clone(),
+            age: now.saturating_duration_since(entry.fetched),
+            fresh_for: entry.expires.saturating_duration_since(now),
+            stale: entry.expires <= now,
+        })
+    }
+
+    pub fn is_cache_only(&self, chain: &str) -> bool {
+        self.cache_only.load(Ordering::Relaxed)
+            || self.cache_only_chains.lock().unwrap().contains(chain)
+    }
+
+    /// Turns cache only mode on or off for `chain`, or for every chain when
+    /// `None`, which turns it off on the chains set one by one too.
+    pub fn set_cache_only(&self, chain: Option<&str>, cache_only: bool) {
+        let mut chains = self.cache_only_chains.lock().unwrap();
+        match chain {
+            Some(chain) if cache_only => {
+                chains.insert(chain.to_string());
+            }
+            Some(chain) => {
+                chains.remove(chain);
+            }
+            None => {
+                self.cache_only.store(cache_only, Ordering::Relaxed);
+                if !cache_only {
+                    chains.clear();
+                }
+            }
+        }
+    }
+
+    /// Caches `body` if it is a response carrying a non-null `result`, and
+    /// returns its ETag. Errors and empty results, such as a transaction not
+    /// yet known, are left out.
+    pub fn insert(&self, key: CacheKey, ttl: Duration, body: &[u8]) -> Option<String> {
+        let mut response = serde_json::from_slice::<Value>(body).ok()?;
+        if response.get("result").is_none_or(Value::is_null) || response.get("error").is_some() {
+            return None;
+        }
+        if let Some(response) = response.as_object_mut() {
+            response.remove("id");
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            entries.retain(|_, entry| entry.expires > now);
+            if entries.len() >= MAX_ENTRIES {
+                return None;
+            }
+        }
+        let etag = etag_of(&response);
+        let hits = entries.get(&key).map_or(0, |entry| entry.hits / 2);
+        entries.insert(
+            key,
+            CacheEntry {
+                fetched: now,
+                expires: now + ttl,
+                response,
+                etag: etag.clone(),
+                hits,
+            },
+        );
+        Some(etag)
+    }
+
+    /// Drops the entries matching `filter` and returns how many there were.
+    pub fn purge(&self, filter: &PurgeFilter) -> Result<usize, String> {
+        let method = PurgeFilter::matcher(&filter.method)?;
+        let params = PurgeFilter::matcher(&filter.key)?;
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|key, _| {
+            let matches = filter
+                .chain
+                .as_ref()
+                .is_none_or(|chain| key.chain.as_ref() == Some(chain))
+                && method
+                    .as_ref()
+                    .is_none_or(|method| method.matches(&key.method))
+                && params
+                    .as_ref()
+                    .is_none_or(|params| params.matches(&key.params));
+            !matches
+        });
+        Ok(before - entries.len())
+    }
+
+    /// The `top` most served entries of `chain` served at least `min_hits`
+    /// times, which expire within `lead` but have not yet.
+    pub fn expiring(
+        &self,
+        chain: &str,
+        lead: Duration,
+        top: usize,
+        min_hits: u64,
+    ) -> Vec<CacheKey> {
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+        let mut expiring: Vec<(&CacheKey, u64)> = entries
+            .iter()
+            .filter(|(key, entry)| {
+                key.chain.as_deref() == Some(chain)
+                    && entry.hits >= min_hits
+                    && entry.expires > now
+                    && entry.expires <= now + lead
+            })
+            .map(|(key, entry)| (key, entry.hits))
+            .collect();
+        expiring.sort_by_key(|(_, hits)| std::cmp::Reverse(*hits));
+        expiring
+            .into_iter()
+            .take(top)
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+/// A weak ETag, responses to the same call only differ by their `id`.
+fn etag_of(response: &Value) -> String {
+    let mut hasher = DefaultHasher::new();
+    response.to_string().hash(&mut hasher);
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+/// Whether an `If-None-Match` header value lists `etag`.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|candidate| opaque(candidate) == opaque(etag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn policy(ttl: u64, scope: CacheScope) -> CachePolicy {
+        CachePolicy {
+            ttl,
+            scope,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_most_specific_policy_wins() {
+        let policies = HashMap::from([
+            ("eth_*".to_string(), policy(1, CacheScope::Chain)),
+            ("eth_get*".to_string(), policy(2, CacheScope::Chain)),
+            ("eth_getCode".to_string(), policy(60, CacheScope::Chain)),
+        ]);
+
+        assert_eq!(policy_for(&policies, "eth_getCode").unwrap().ttl, 60);
+        assert_eq!(policy_for(&policies, "eth_getBalance").unwrap().ttl, 2);
+        assert_eq!(policy_for(&policies, "eth_chainId").unwrap().ttl, 1);
+        assert!(policy_for(&policies, "net_version").is_none());
+    }
+
+    #[test]
+    fn test_cache_only_per_chain() {
+        let cache = ResponseCache::default();
+        cache.set_cache_only(Some("sepolia"), true);
+        assert!(cache.is_cache_only("sepolia"));
+        assert!(!cache.is_cache_only("base"));
+
+        cache.set_cache_only(None, true);
+        assert!(cache.is_cache_only("base"));
+        cache.set_cache_only(None, false);
+        assert!(!cache.is_cache_only("sepolia"));
+        assert!(!cache.is_cache_only("base"));
+    }
+
+    #[test]
+    fn test_cached_response_answers_new_id() {
+        let cache = ResponseCache::default();
+        let request = json!({"method": "eth_chainId", "params": [], "id": 1});
+        let global = policy(60, CacheScope::Global);
+
+        let key = CacheKey::new("sepolia", &global, &request).unwrap();
+        cache.insert(
+            key,
+            Duration::from_secs(60),
+            br#"{"jsonrpc":"2.0","result":"0xaa36a7","id":1}"#,
+        );
+
+        // Globally scoped entries are shared between chains.
+        let key = CacheKey::new("holesky", &global, &request).unwrap();
+        let cached = cache.get(&key, &json!(7)).unwrap();
+        assert!(etag_matches(
+            &format!("\"x\", {}", cached.etag),
+            &cached.etag
+        ));
+        let cached: Value = serde_json::from_slice(&cached.body).unwrap();
+        assert_eq!(cached["result"], "0xaa36a7");
+        assert_eq!(cached["id"], 7);
+
+        let key = CacheKey::new("holesky", &policy(60, CacheScope::Chain), &request).unwrap();
+        assert!(cache.get(&key, &json!(7)).is_none());
+    }
+
+    #[test]
+    fn test_null_results_are_not_cached() {
+        let cache = ResponseCache::default();
+        let request = json!({"method": "eth_getTransactionByHash", "params": ["0xab"]});
+        let key = CacheKey::new("sepolia", &policy(60, CacheScope::Chain), &request).unwrap();
+
+        cache.insert(
+            key.clone(),
+            Duration::from_secs(60),
+            br#"{"jsonrpc":"2.0","result":null,"id":1}"#,
+        );
+        assert!(cache.get(&key, &json!(1)).is_none());
+    }
+
+    #[test]
+    fn test_purge() {
+        let cache = ResponseCache::default();
+        let body = br#"{"jsonrpc":"2.0","result":"0x1","id":1}"#;
+        let insert = |chain: &str, scope: CacheScope, method: &str, params: Value| {
+            let request = json!({"method": method, "params": params});
+            let key = CacheKey::new(chain, &policy(60, scope), &request).unwrap();
+            cache.insert(key, Duration::from_secs(60), body);
+        };
+        insert(
+            "sepolia",
+            CacheScope::Chain,
+            "eth_getBalance",
+            json!(["0xab12", "latest"]),
+        );
+        insert(
+            "sepolia",
+            CacheScope::Chain,
+            "eth_getBalance",
+            json!(["0xcd34", "latest"]),
+        );
+        insert(
+            "sepolia",
+            CacheScope::Chain,
+            "eth_getCode",
+            json!(["0xab12", "latest"]),
+        );
+        insert(
+            "base",
+            CacheScope::Chain,
+            "eth_getBalance",
+            json!(["0xab12", "latest"]),
+        );
+        insert(
+            "base",
+            CacheScope::Global,
+            "web3_sha3",
+            json!(["0x68656c6c6f"]),
+        );
+
+        let filter = |chain: Option<&str>, method: Option<&str>, key: Option<&str>| PurgeFilter {
+            chain: chain.map(str::to_string),
+            method: method.map(str::to_string),
+            key: key.map(str::to_string),
+        };
+        assert!(cache.purge(&filter(None, Some("eth_[get"), None)).is_err());
+        assert_eq!(
+            cache.purge(&filter(Some("sepolia"), Some("eth_get*"), Some("*0xab12*"))),
+            Ok(2)
+        );
+        assert_eq!(cache.purge(&filter(Some("base"), None, None)), Ok(1));
+        assert_eq!(cache.purge(&PurgeFilter::default()), Ok(2));
+    }
+
+    #[test]
+    fn test_expiring_entries_by_popularity() {
+        let cache = ResponseCache::default();
+        let chain = policy(1, CacheScope::Chain);
+        let balance = |account: &str| {
+            let request = json!({"method": "eth_getBalance", "params": [account, "latest"]});
+            CacheKey::new("sepolia", &chain, &request).unwrap()
+        };
+        let body = br#"{"jsonrpc":"2.0","result":"0x1","id":1}"#;
+        let (popular, rare, lasting) = (balance("0xa"), balance("0xb"), balance("0xc"));
+        cache.insert(popular.clone(), Duration::from_secs(1), body);
+        cache.insert(rare.clone(), Duration::from_secs(1), body);
+        cache.insert(lasting.clone(), Duration::from_secs(60), body);
+        for _ in 0..4 {
+            cache.get(&popular, &json!(1));
+            cache.get(&lasting, &json!(1));
+        }
+        cache.get(&rare, &json!(1));
+
+        let lead = Duration::from_secs(2);
+        assert_eq!(
+            cache.expiring("sepolia", lead, 5, 1),
+            vec![popular.clone(), rare]
+        );
+        assert_eq!(cache.expiring("sepolia", lead, 1, 1), vec![popular.clone()]);
+        assert!(cache.expiring("holesky", lead, 5, 1).is_empty());
+        assert_eq!(popular.request()["params"], json!(["0xa", "latest"]));
+
+        // Refreshing halves the popularity.
+        cache.insert(popular.clone(), Duration::from_secs(1), body);
+        assert!(cache.expiring("sepolia", lead, 5, 3).is_empty());
+    }
+}
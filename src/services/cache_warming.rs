@@ -0,0 +1,211 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use tokio::time;
+
+use super::cache::{self, CachePolicy, ResponseCache};
+use crate::{algorithms::round_robin::RoundRobin, transport::UpstreamRequest};
+
+const WARM_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The `cache_warming` of a chain: its most served cached calls are fetched
+/// again shortly before they expire, so popular entries never expire under
+/// load and their clients do not all miss at once.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CacheWarming {
+    /// Most served entries refreshed per round.
+    #[serde(default = "default_top")]
+    pub top: usize,
+    /// Seconds before expiry an entry is refreshed.
+    #[serde(default = "default_lead_secs")]
+    pub lead_secs: u64,
+    /// Times an entry has to be served to be worth refreshing.
+    #[serde(default = "default_min_hits")]
+    pub min_hits: u64,
+    /// Share of the chain's window limits above which nothing is refreshed,
+    /// leaving the remaining tokens to clients.
+    #[serde(default = "default_max_usage")]
+    pub max_usage: f64,
+}
+
+fn default_top() -> usize {
+    20
+}
+
+fn default_lead_secs() -> u64 {
+    2
+}
+
+fn default_min_hits() -> u64 {
+    3
+}
+
+fn default_max_usage() -> f64 {
+    0.5
+}
+
+/// Whether the backends of a chain used at most `max_usage` of their window
+/// limits, so refreshes do not compete with clients for tokens.
+fn is_idle(round_robin: &RoundRobin, max_usage: f64) -> bool {
+    let (used, limit) = round_robin
+        .window_usage()
+        .iter()
+        .fold((0u64, 0u64), |(used, limit), (_, u, l)| {
+            (used + *u as u64, limit + *l as u64)
+        });
+    limit > 0 && (used as f64) <= max_usage * limit as f64
+}
+
+/// Refreshes the expiring entries of `chain` once, returning how many were
+/// refreshed. Refreshes take tokens like client requests and stop as soon as
+/// the chain is busy or out of tokens.
+pub async fn warm_once(
+    chain: &str,
+    round_robin: &RoundRobin,
+    cache: &ResponseCache,
+    policies: &HashMap<String, CachePolicy>,
+    warming: &CacheWarming,
+) -> usize {
+    // Backends are left alone while the cache stands in for them.
+    if cache.is_cache_only(chain) {
+        return 0;
+    }
+    let lead = Duration::from_secs(warming.lead_secs);
+    let mut refreshed = 0;
+    for key in cache.expiring(chain, lead, warming.top, warming.min_hits) {
+        if !is_idle(round_robin, warming.max_usage) {
+            break;
+        }
+        let request = key.request();
+        let Some(policy) = request["method"]
+            .as_str()
+            .and_then(|method| cache::policy_for(policies, method))
+            .filter(|policy| policy.is_active())
+        else {
+            continue;
+        };
+        let Some(url) = round_robin.get_next() else {
+            break;
+        };
+
+        let upstream = UpstreamRequest::json(request.to_string());
+        let response = round_robin
+            .send(&url, &upstream, round_robin.timeout_for(&url))
+            .await;
+        let body = match response {
+            Ok(response) if response.status() == StatusCode::OK => response.bytes().await,
+            Ok(response) => {
+                println!(
+                    "Cache refresh on backend {} of chain {} failed: HTTP {}",
+                    round_robin.label_of(&url),
+                    chain,
+                    response.status()
+                );
+                continue;
+            }
+            Err(e) => {
+                println!(
+                    "Cache refresh on backend {} of chain {} failed: {}",
+                    round_robin.label_of(&url),
+                    chain,
+                    e.message
+                );
+                continue;
+            }
+        };
+        if let Ok(body) = body {
+            if cache
+                .insert(key, Duration::from_secs(policy.ttl), &body)
+                .is_some()
+            {
+                refreshed += 1;
+            }
+        }
+    }
+    refreshed
+}
+
+/// Keeps refreshing the expiring entries of `chain`, forever.
+pub async fn run(
+    chain: String,
+    round_robin: Arc<RoundRobin>,
+    cache: Arc<ResponseCache>,
+    policies: HashMap<String, CachePolicy>,
+    warming: CacheWarming,
+) {
+    loop {
+        warm_once(&chain, &round_robin, &cache, &policies, &warming).await;
+        time::sleep(WARM_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{algorithms::round_robin::RpcServer, services::cache::CacheKey};
+    use axum::{routing::post, Json, Router};
+    use serde_json::{json, Value};
+
+    async fn spawn_node() -> String {
+        let app = Router::new().route(
+            "/",
+            post(|Json(request): Json<Value>| async move {
+                Json(json!({"jsonrpc": "2.0", "id": request["id"], "result": "0x2"}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_warm_once() {
+        let round_robin = RoundRobin::new(vec![RpcServer {
+            url: spawn_node().await,
+            request_limit: 10,
+            current_limit: 10,
+            ..Default::default()
+        }]);
+        let policy = CachePolicy {
+            ttl: 1,
+            scope: Default::default(),
+            enabled: true,
+        };
+        let policies = HashMap::from([("eth_getBalance".to_string(), policy.clone())]);
+        let request = json!({"method": "eth_getBalance", "params": ["0xa", "latest"]});
+        let key = CacheKey::new("sepolia", &policy, &request).unwrap();
+
+        let cache = ResponseCache::default();
+        cache.insert(
+            key.clone(),
+            Duration::from_secs(1),
+            br#"{"jsonrpc":"2.0","result":"0x1","id":1}"#,
+        );
+        let warming: CacheWarming = toml::from_str("").unwrap();
+        for _ in 0..warming.min_hits {
+            cache.get(&key, &json!(1));
+        }
+
+        assert_eq!(
+            warm_once("sepolia", &round_robin, &cache, &policies, &warming).await,
+            1
+        );
+        let cached: Value =
+            serde_json::from_slice(&cache.get(&key, &json!(1)).unwrap().body).unwrap();
+        assert_eq!(cached["result"], "0x2");
+
+        // Busy chains are left alone.
+        for _ in 0..6 {
+            round_robin.get_next();
+        }
+        for _ in 0..warming.min_hits * 2 {
+            cache.get(&key, &json!(1));
+        }
+        assert_eq!(
+            warm_once("sepolia", &round_robin, &cache, &policies, &warming).await,
+            0
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::algorithms::round_robin::{Chains, RoundRobin};
+
+/// A fault injected into the requests of a backend, to rehearse failover and
+/// validate alerting in staging. Only usable in builds with the `chaos`
+/// feature, set as the `chaos` of a backend or through `/admin/chaos`.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Fault {
+    /// Delay added before each request is sent, counting towards its timeout.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Share of requests answered with `error_status` instead of being sent.
+    #[serde(default)]
+    pub error_rate: f64,
+    #[serde(default = "default_error_status")]
+    pub error_status: u16,
+}
+
+fn default_error_status() -> u16 {
+    503
+}
+
+impl Default for Fault {
+    fn default() -> Self {
+        Self {
+            latency_ms: 0,
+            error_rate: 0.0,
+            error_status: default_error_status(),
+        }
+    }
+}
+
+impl Fault {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=1.0).contains(&self.error_rate) {
+            return Err(format!(
+                "chaos error_rate {} is not between 0 and 1",
+                self.error_rate
+            ));
+        }
+        if !(400..=599).contains(&self.error_status) {
+            return Err(format!(
+                "chaos error_status {} is not an HTTP error",
+                self.error_status
+            ));
+        }
+        Ok(())
+    }
+
+    /// Whether the fault changes anything, inactive ones are dropped.
+    pub fn is_active(&self) -> bool {
+        self.latency_ms > 0 || self.error_rate > 0.0
+    }
+}
+
+/// The faults injected into each backend, by chain and backend url.
+#[derive(Debug, Default)]
+pub struct Chaos {
+    faults: Mutex<HashMap<(String, String), Fault>>,
+}
+
+impl Chaos {
+    /// Starts with the faults configured on the backends of `chains`.
+    pub fn new(chains: &HashMap<String, Chains>) -> Result<Self, String> {
+        let mut faults = HashMap::new();
+        for (chain, config) in chains {
+            for server in &config.rpc_urls {
+                if let Some(fault) = server.chaos.as_ref().filter(|fault| fault.is_active()) {
+                    faults.insert((chain.clone(), server.url.clone()), fault.clone());
+                }
+            }
+        }
+        if !faults.is_empty() && !cfg!(feature = "chaos") {
+            return Err("Backend chaos needs a build with the chaos feature".to_string());
+        }
+        Ok(Self {
+            faults: Mutex::new(faults),
+        })
+    }
+
+    /// The fault injected into the backend at `url` of `chain`, if any.
+    pub fn fault(&self, chain: &str, url: &str) -> Option<Fault> {
+        self.faults
+            .lock()
+            .unwrap()
+            .get(&(chain.to_string(), url.to_string()))
+            .cloned()
+    }
+
+    /// Injects `fault` into the backend at `url` of `chain`, or stops
+    /// injecting anything when the fault is inactive.
+    pub fn set(&self, chain: &str, url: &str, fault: Fault) -> Result<(), String> {
+        if !cfg!(feature = "chaos") {
+            return Err("Backend chaos needs a build with the chaos feature".to_string());
+        }
+        fault.validate()?;
+        let key = (chain.to_string(), url.to_string());
+        let mut faults = self.faults.lock().unwrap();
+        if fault.is_active() {
+            faults.insert(key, fault);
+        } else {
+            faults.remove(&key);
+        }
+        Ok(())
+    }
+
+    /// Sends the requests of every backend of `chain` through its faults.
+    /// Builds without the `chaos` feature keep the transports as they are.
+    pub fn wrap(self: &Arc<Self>, chain: &str, round_robin: RoundRobin) -> RoundRobin {
+        #[cfg(feature = "chaos")]
+        {
+            let mut round_robin = round_robin;
+            let transports = round_robin
+                .transports
+                .iter()
+                .map(|inner| {
+                    Arc::new(ChaosTransport {
+                        inner: inner.clone(),
+                        chaos: self.clone(),
+                        chain: chain.to_string(),
+                    }) as Arc<dyn crate::transport::UpstreamTransport>
+                })
+                .collect();
+            round_robin.transports = Arc::new(transports);
+            round_robin
+        }
+        #[cfg(not(feature = "chaos"))]
+        {
+            let _ = chain;
+            round_robin
+        }
+    }
+}
+
+/// Injects the fault of its backend, if any, before handing requests on to
+/// the backend's own transport.
+#[cfg(feature = "chaos")]
+#[derive(Debug)]
+struct ChaosTransport {
+    inner: Arc<dyn crate::transport::UpstreamTransport>,
+    chaos: Arc<Chaos>,
+    chain: String,
+}
+
+#[cfg(feature = "chaos")]
+impl crate::transport::UpstreamTransport for ChaosTransport {
+    fn send<'a>(
+        &'a self,
+        url: &'a str,
+        request: &'a crate::transport::UpstreamRequest,
+        timeout: Option<std::time::Duration>,
+        sent: &'a crate::transport::Sent,
+    ) -> crate::transport::TransportFuture<'a> {
+        use crate::transport::TransportError;
+        use std::time::Duration;
+
+        Box::pin(async move {
+            let Some(fault) = self.chaos.fault(&self.chain, url) else {
+                return self.inner.send(url, request, timeout, sent).await;
+            };
+            // Injected failures stand for a backend which got the request,
+            // so their tokens are not refunded.
+            let delay = Duration::from_millis(fault.latency_ms);
+            if let Some(timeout) = timeout.filter(|timeout| delay >= *timeout) {
+                tokio::time::sleep(timeout).await;
+                sent.mark();
+                return Err(TransportError::request("operation timed out"));
+            }
+            tokio::time::sleep(delay).await;
+            if rand::random::<f64>() < fault.error_rate {
+                sent.mark();
+                return Ok(error_response(fault.error_status));
+            }
+            let timeout = timeout.map(|timeout| timeout - delay);
+            self.inner.send(url, request, timeout, sent).await
+        })
+    }
+}
+
+/// The response of a request failed on purpose.
+#[cfg(feature = "chaos")]
+fn error_response(status: u16) -> reqwest::Response {
+    let body = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": null,
+        "error": {"code": -32603, "message": "injected by chaos mode"},
+    });
+    let status =
+        reqwest::StatusCode::from_u16(status).unwrap_or(reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    crate::transport::response_with(status, body.to_string().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fault_validation() {
+        let fault: Fault = toml::from_str("latency_ms = 200").unwrap();
+        assert!(fault.validate().is_ok());
+        assert_eq!(fault.error_status, 503);
+        assert!(Fault {
+            error_rate: 1.5,
+            ..fault.clone()
+        }
+        .validate()
+        .is_err());
+        assert!(Fault {
+            error_status: 200,
+            ..fault
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[cfg(feature = "chaos")]
+    #[tokio::test]
+    async fn test_chaos_transport() {
+        use crate::{algorithms::round_robin::RpcServer, transport::UpstreamRequest};
+        use axum::{routing::post, Router};
+        use std::time::{Duration, Instant};
+
+        let app = Router::new().route("/", post(|| async { r#"{"result":"0x1"}"# }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let chaos = Arc::new(Chaos::default());
+        let round_robin = chaos.wrap(
+            "sepolia",
+            RoundRobin::new(vec![RpcServer {
+                url: url.clone(),
+                ..Default::default()
+            }]),
+        );
+        let request = UpstreamRequest::json("{}");
+        let send = |timeout| round_robin.send(&url, &request, Some(timeout));
+
+        let response = send(Duration::from_secs(1)).await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        let slow = Fault {
+            latency_ms: 100,
+            ..Default::default()
+        };
+        chaos.set("sepolia", &url, slow.clone()).unwrap();
+        let started = Instant::now();
+        assert!(send(Duration::from_secs(1)).await.is_ok());
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(send(Duration::from_millis(50)).await.is_err());
+
+        let failing = Fault {
+            error_rate: 1.0,
+            error_status: 429,
+            ..Default::default()
+        };
+        chaos.set("sepolia", &url, failing).unwrap();
+        let response = send(Duration::from_secs(1)).await.unwrap();
+        assert_eq!(response.status(), 429);
+
+        chaos.set("sepolia", &url, Fault::default()).unwrap();
+        assert_eq!(chaos.fault("sepolia", &url), None);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+
+use crate::metrics::Metrics;
+
+/// Distinct consumers tracked, later ones are accounted to `other`.
+const MAX_CONSUMERS: usize = 1_000;
+/// Distinct methods tracked per consumer, later ones count as `other`.
+const MAX_METHODS: usize = 50;
+
+/// The `[server.consumers]` section: the header identifying the internal
+/// service sending a request, and optional names for its values.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConsumerConfig {
+    #[serde(default = "default_header")]
+    pub header: String,
+    /// Names reported instead of the key, e.g. `{ "k-19ab" = "indexer" }`.
+    #[serde(default)]
+    pub names: HashMap<String, String>,
+}
+
+fn default_header() -> String {
+    "x-api-key".to_string()
+}
+
+impl Default for ConsumerConfig {
+    fn default() -> Self {
+        Self {
+            header: default_header(),
+            names: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Usage {
+    requests: u64,
+    errors: u64,
+    methods: HashMap<String, u64>,
+}
+
+/// Requests, errors and method mix of one consumer, as served by
+/// `/admin/top-consumers`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ConsumerReport {
+    pub consumer: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub error_rate: f64,
+    pub methods: HashMap<String, u64>,
+}
+
+/// Accounts requests to the consumers sending them.
+#[derive(Debug, Default)]
+pub struct Consumers {
+    config: ConsumerConfig,
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl Consumers {
+    pub fn new(config: ConsumerConfig) -> Self {
+        Self {
+            config,
+            usage: Mutex::default(),
+        }
+    }
+
+    /// The consumer a request is accounted to: the configured name of its
+    /// key, or the key's last characters so it is not exposed.
+    pub fn identify(&self, headers: &HeaderMap) -> String {
+        let Some(key) = headers
+            .get(self.config.header.as_str())
+            .and_then(|value| value.to_str().ok())
+        else {
+            return "anonymous".to_string();
+        };
+        if let Some(name) = self.config.names.get(key) {
+            return name.clone();
+        }
+        let chars: Vec<char> = key.chars().collect();
+        let tail: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+        format!("key:...{}", tail)
+    }
+
+    pub fn record(
+        &self,
+        metrics: &Metrics,
+        consumer: &str,
+        chain: &str,
+        method: Option<&str>,
+        failed: bool,
+    ) {
+        let mut usage = self.usage.lock().unwrap();
+        let consumer = if usage.contains_key(consumer) || usage.len() < MAX_CONSUMERS {
+            consumer
+        } else {
+            "other"
+        };
+        let entry = usage.entry(consumer.to_string()).or_default();
+
+        let method = metrics.method_label(method.unwrap_or("unknown"));
+        let method = if entry.methods.contains_key(&method) || entry.methods.len() < MAX_METHODS {
+            method
+        } else {
+            "other".to_string()
+        };
+        entry.requests += 1;
+        *entry.methods.entry(method.clone()).or_insert(0) += 1;
+        if failed {
+            entry.errors += 1;
+        }
+        drop(usage);
+
+        metrics.inc(
+            "rpc_lb_consumer_requests_total",
+            &[
+                ("consumer", consumer),
+                ("chain", chain),
+                ("method", &method),
+            ],
+        );
+        if failed {
+            metrics.inc(
+                "rpc_lb_consumer_errors_total",
+                &[("consumer", consumer), ("chain", chain)],
+            );
+        }
+    }
+
+    /// The `limit` consumers with the most requests.
+    pub fn top(&self, limit: usize) -> Vec<ConsumerReport> {
+        let usage = self.usage.lock().unwrap();
+        let mut reports: Vec<ConsumerReport> = usage
+            .iter()
+            .map(|(consumer, usage)| ConsumerReport {
+                consumer: consumer.clone(),
+                requests: usage.requests,
+                errors: usage.errors,
+                error_rate: usage.errors as f64 / usage.requests.max(1) as f64,
+                methods: usage.methods.clone(),
+            })
+            .collect();
+        reports.sort_by(|a, b| {
+            b.requests
+                .cmp(&a.requests)
+                .then_with(|| a.consumer.cmp(&b.consumer))
+        });
+        reports.truncate(limit);
+        reports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_top_consumers() {
+        let consumers = Consumers::new(ConsumerConfig {
+            names: HashMap::from([("k-indexer".to_string(), "indexer".to_string())]),
+            ..Default::default()
+        });
+        let metrics = Metrics::default();
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(consumers.identify(&headers), "anonymous");
+        headers.insert("x-api-key", HeaderValue::from_static("k-indexer"));
+        assert_eq!(consumers.identify(&headers), "indexer");
+        headers.insert("x-api-key", HeaderValue::from_static("secret-wallet"));
+        assert_eq!(consumers.identify(&headers), "key:...llet");
+
+        for _ in 0..3 {
+            consumers.record(&metrics, "indexer", "sepolia", Some("eth_getLogs"), false);
+        }
+        consumers.record(&metrics, "indexer", "sepolia", Some("eth_call"), true);
+        consumers.record(&metrics, "key:...llet", "sepolia", None, false);
+
+        let top = consumers.top(1);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].consumer, "indexer");
+        assert_eq!(top[0].requests, 4);
+        assert_eq!(top[0].error_rate, 0.25);
+        assert_eq!(top[0].methods["eth_getLogs"], 3);
+        assert_eq!(
+            metrics.counter(
+                "rpc_lb_consumer_requests_total",
+                &[
+                    ("consumer", "indexer"),
+                    ("chain", "sepolia"),
+                    ("method", "eth_getLogs")
+                ]
+            ),
+            3
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use std::{collections::BTreeSet, env, fs, time::Duration};
+
+use hickory_resolver::TokioAsyncResolver;
+use serde::Deserialize;
+use serde_json::Value;
+use toml::Table;
+
+use crate::{algorithms::round_robin::Chains, config};
+
+/// Where the service account of a pod finds its token, CA and namespace.
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A source of backends added to the chain's `rpc_urls` and kept in sync
+/// with it, the `discovery` of a chain.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackendSource {
+    Kubernetes(KubernetesSource),
+    Dns(DnsSource),
+}
+
+impl BackendSource {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            BackendSource::Dns(DnsSource {
+                record: DnsRecord::A,
+                port: None,
+                ..
+            }) => Err("discovery of A records needs a port".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Urls of the backends the source currently lists.
+    pub async fn urls(&self) -> Result<BTreeSet<String>, String> {
+        match self {
+            BackendSource::Kubernetes(source) => source.urls().await,
+            BackendSource::Dns(source) => source.urls().await,
+        }
+    }
+}
+
+/// The ready endpoints of the EndpointSlices matching `label_selector`,
+/// e.g. the pods of the services of a chain's in-cluster nodes.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct KubernetesSource {
+    pub label_selector: String,
+    /// Port the nodes serve JSON-RPC on.
+    pub port: u16,
+    /// The namespace of the balancer's pod when unset.
+    pub namespace: Option<String>,
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
+    /// Appended to every backend url, e.g. `/rpc`.
+    #[serde(default)]
+    pub path: String,
+    /// The API server, the in-cluster one when unset.
+    pub api_server: Option<String>,
+}
+
+fn default_scheme() -> String {
+    "http".to_string()
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsRecord {
+    /// Targets and ports of SRV records, e.g. `_rpc._tcp.nodes.internal`.
+    #[default]
+    Srv,
+    /// Addresses of A and AAAA records, all on `port`.
+    A,
+}
+
+/// The backends a DNS name resolves to, e.g. nodes registered in a service
+/// discovery system serving DNS.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct DnsSource {
+    pub name: String,
+    #[serde(default)]
+    pub record: DnsRecord,
+    /// Port of the backends, required for A records. Overrides the ports of
+    /// SRV records.
+    pub port: Option<u16>,
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
+    /// Appended to every backend url, e.g. `/rpc`.
+    #[serde(default)]
+    pub path: String,
+}
+
+impl DnsSource {
+    /// Urls of the records the name currently resolves to, resolved with the
+    /// system's resolver config.
+    pub async fn urls(&self) -> Result<BTreeSet<String>, String> {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf().map_err(|e| e.to_string())?;
+        let targets: Vec<(String, u16)> = match self.record {
+            DnsRecord::Srv => resolver
+                .srv_lookup(self.name.as_str())
+                .await
+                .map_err(|e| format!("Resolving SRV {} failed: {}", self.name, e))?
+                .iter()
+                .map(|srv| {
+                    let target = srv.target().to_utf8();
+                    let target = target.trim_end_matches('.').to_string();
+                    (target, self.port.unwrap_or(srv.port()))
+                })
+                .collect(),
+            DnsRecord::A => resolver
+                .lookup_ip(self.name.as_str())
+                .await
+                .map_err(|e| format!("Resolving {} failed: {}", self.name, e))?
+                .iter()
+                .map(|ip| (ip.to_string(), self.port.unwrap_or_default()))
+                .collect(),
+        };
+        Ok(targets
+            .into_iter()
+            .map(|(host, port)| backend_url(&self.scheme, &host, port, &self.path))
+            .collect())
+    }
+}
+
+/// The url of a discovered backend, with IPv6 addresses in brackets.
+fn backend_url(scheme: &str, host: &str, port: u16, path: &str) -> String {
+    let host = match host.contains(':') {
+        true => format!("[{}]", host),
+        false => host.to_string(),
+    };
+    format!("{}://{}:{}{}", scheme, host, port, path)
+}
+
+impl KubernetesSource {
+    /// Urls of the ready endpoints currently matching the source.
+    pub async fn urls(&self) -> Result<BTreeSet<String>, String> {
+        let api_server = match &self.api_server {
+            Some(api_server) => api_server.clone(),
+            None => {
+                let host = env::var("KUBERNETES_SERVICE_HOST")
+                    .map_err(|_| "Not running in a Kubernetes cluster".to_string())?;
+                let port = env::var("KUBERNETES_SERVICE_PORT").unwrap_or("443".to_string());
+                format!("https://{}:{}", host, port)
+            }
+        };
+        let namespace = self.namespace.clone().unwrap_or_else(|| {
+            fs::read_to_string(format!("{}/namespace", SERVICE_ACCOUNT))
+                .map(|namespace| namespace.trim().to_string())
+                .unwrap_or("default".to_string())
+        });
+
+        let mut client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT);
+        if let Ok(ca) = fs::read(format!("{}/ca.crt", SERVICE_ACCOUNT)) {
+            let ca = reqwest::Certificate::from_pem(&ca).map_err(|e| e.to_string())?;
+            client = client.add_root_certificate(ca);
+        }
+        let client = client.build().map_err(|e| e.to_string())?;
+        let mut request = client
+            .get(format!(
+                "{}/apis/discovery.k8s.io/v1/namespaces/{}/endpointslices",
+                api_server.trim_end_matches('/'),
+                namespace
+            ))
+            .query(&[("labelSelector", &self.label_selector)]);
+        if let Ok(token) = fs::read_to_string(format!("{}/token", SERVICE_ACCOUNT)) {
+            request = request.bearer_auth(token.trim());
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("Listing EndpointSlices failed: HTTP {}", status));
+        }
+        let body = response.bytes().await.map_err(|e| e.to_string())?;
+        let slices: Value = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+        Ok(self.ready_urls(&slices))
+    }
+
+    /// Urls of the ready endpoints of an EndpointSlice list.
+    fn ready_urls(&self, slices: &Value) -> BTreeSet<String> {
+        let endpoints = slices["items"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|slice| slice["endpoints"].as_array().into_iter().flatten());
+        endpoints
+            // Endpoints without a ready condition are to be taken as ready.
+            .filter(|endpoint| endpoint["conditions"]["ready"] != Value::Bool(false))
+            .flat_map(|endpoint| endpoint["addresses"].as_array().into_iter().flatten())
+            .filter_map(Value::as_str)
+            .map(|address| backend_url(&self.scheme, address, self.port, &self.path))
+            .collect()
+    }
+}
+
+/// Backend urls listed in the file at `path`, one per line. Blank lines and
+/// lines starting with `#` are skipped.
+fn read_urls_file(path: &str) -> Result<BTreeSet<String>, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Urls of the backends the `discovery` source and `urls_file` of a chain
+/// list, in the form configured urls are normalized to.
+async fn listed_urls(settings: &Chains) -> Result<BTreeSet<String>, String> {
+    let mut urls = BTreeSet::new();
+    if let Some(source) = &settings.discovery {
+        urls.extend(source.urls().await?);
+    }
+    if let Some(path) = &settings.urls_file {
+        urls.extend(read_urls_file(path)?);
+    }
+    urls.iter().map(|url| config::normalize_url(url)).collect()
+}
+
+/// Asks the backend sources of every chain of the resolved config `table`
+/// for their backends, returning the config with them in place when any
+/// chain's discovered backends changed. Chains whose sources fail keep the
+/// backends they have.
+pub async fn refresh(table: &Table) -> Result<Option<Table>, String> {
+    let config = config::build(table.clone())?;
+    let mut updated = table.clone();
+    let mut changed = false;
+    for (chain, settings) in &config.chains {
+        if settings.discovery.is_none() && settings.urls_file.is_none() {
+            continue;
+        }
+        let urls = match listed_urls(settings).await {
+            Ok(urls) => urls,
+            Err(e) => {
+                println!("Discovering backends of chain {} failed: {}", chain, e);
+                continue;
+            }
+        };
+        let current: BTreeSet<String> = settings
+            .rpc_urls
+            .iter()
+            .filter(|server| server.discovered)
+            .map(|server| server.url.clone())
+            .collect();
+        if urls != current {
+            println!(
+                "Chain {} discovered {} backends, had {}",
+                chain,
+                urls.len(),
+                current.len()
+            );
+            updated = config::with_discovered(updated, chain, &urls)?;
+            changed = true;
+        }
+    }
+    Ok(changed.then_some(updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Json, Router};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let app = Router::new().route(
+            "/apis/discovery.k8s.io/v1/namespaces/eth/endpointslices",
+            get(|| async {
+                Json(json!({"items": [{"endpoints": [
+                    {"addresses": ["10.0.0.1"], "conditions": {"ready": true}},
+                    {"addresses": ["10.0.0.2"], "conditions": {"ready": false}},
+                    {"addresses": ["fd00::3"]},
+                ]}]}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let api_server = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let table: Table = toml::from_str(&format!(
+            r#"
+            [chains.sepolia]
+            rpc_urls = [{{ url = "https://rpc.example.com", request_limit = 5, current_limit = 5 }}]
+            request_limit = 10
+            discovery = {{ type = "kubernetes", label_selector = "app=geth", port = 8545, namespace = "eth", api_server = "{}" }}
+            "#,
+            api_server
+        ))
+        .unwrap();
+
+        let updated = refresh(&table).await.unwrap().unwrap();
+        let urls: Vec<_> = config::build(updated.clone()).unwrap().chains["sepolia"]
+            .rpc_urls
+            .iter()
+            .map(|server| (server.url.clone(), server.discovered, server.request_limit))
+            .collect();
+        assert_eq!(
+            urls,
+            vec![
+                ("https://rpc.example.com/".to_string(), false, 5),
+                ("http://10.0.0.1:8545/".to_string(), true, 10),
+                ("http://[fd00::3]:8545/".to_string(), true, 10),
+            ]
+        );
+        assert_eq!(refresh(&updated).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_urls_file() {
+        let path = std::env::temp_dir().join(format!("rpc_lb_urls_{}.txt", std::process::id()));
+        fs::write(
+            &path,
+            "# nodes\nhttps://a.example.com\n\nhttps://b.example.com/\n",
+        )
+        .unwrap();
+        let table: Table = toml::from_str(&format!(
+            "[chains.sepolia]\nrequest_limit = 10\nurls_file = {:?}",
+            path.display().to_string()
+        ))
+        .unwrap();
+
+        let updated = refresh(&table).await.unwrap().unwrap();
+        let config = config::build(updated.clone()).unwrap();
+        assert_eq!(config.chains["sepolia"].rpc_urls.len(), 2);
+        assert_eq!(refresh(&updated).await.unwrap(), None);
+
+        fs::write(&path, "https://a.example.com\n").unwrap();
+        let updated = refresh(&updated).await.unwrap().unwrap();
+        let config = config::build(updated).unwrap();
+        assert_eq!(
+            config.chains["sepolia"].rpc_urls[0].url,
+            "https://a.example.com/"
+        );
+        assert_eq!(config.chains["sepolia"].rpc_urls.len(), 1);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_dns_source() {
+        let source: BackendSource = toml::from_str(
+            r#"type = "dns"
+name = "localhost"
+record = "a""#,
+        )
+        .unwrap();
+        assert!(source.validate().is_err());
+
+        let source: BackendSource = toml::from_str(
+            r#"type = "dns"
+name = "localhost"
+record = "a"
+port = 8545
+path = "/rpc""#,
+        )
+        .unwrap();
+        assert!(source.validate().is_ok());
+        let urls = source.urls().await.unwrap();
+        assert!(urls.contains("http://127.0.0.1:8545/rpc"));
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde_json::Value;
+
+use crate::algorithms::round_robin::{LoadBalancer, RoundRobin};
+
+/// Methods whose result is the id of a filter installed on the backend.
+const CREATING_METHODS: [&str; 3] = [
+    "eth_newFilter",
+    "eth_newBlockFilter",
+    "eth_newPendingTransactionFilter",
+];
+/// Methods taking the id of a filter as their first param.
+const USING_METHODS: [&str; 3] = [
+    "eth_getFilterChanges",
+    "eth_getFilterLogs",
+    "eth_uninstallFilter",
+];
+/// Providers uninstall filters which were not polled for five minutes.
+const FILTER_TTL: Duration = Duration::from_secs(300);
+
+/// Whether `request` installs a filter, whose id is then pinned.
+pub fn creates_filter(request: &Value) -> bool {
+    request["method"]
+        .as_str()
+        .is_some_and(|method| CREATING_METHODS.contains(&method))
+}
+
+/// The id of the filter `request` polls or uninstalls, if any.
+pub fn filter_of(request: &Value) -> Option<&str> {
+    let method = request["method"].as_str()?;
+    if !USING_METHODS.contains(&method) {
+        return None;
+    }
+    request["params"][0].as_str()
+}
+
+/// The id of the filter a response to a [`creates_filter`] request carries.
+pub fn created_filter(body: &[u8]) -> Option<String> {
+    let response: Value = serde_json::from_slice(body).ok()?;
+    response["result"].as_str().map(str::to_string)
+}
+
+#[derive(Debug)]
+struct Pin {
+    url: String,
+    last_used: Instant,
+}
+
+/// A backend removed by a config change, kept for the filters pinned to it.
+#[derive(Debug)]
+struct DrainingBackend {
+    chain: String,
+    url: String,
+    round_robin: Arc<RoundRobin>,
+    until: Instant,
+}
+
+/// The backend each filter was installed on, as only that one can answer
+/// its polls. Kept across config changes: a backend removed from a chain
+/// keeps serving its filters for the `[server] drain_secs` instead of them
+/// failing at once.
+#[derive(Debug, Default)]
+pub struct FilterPins {
+    pins: Mutex<HashMap<(String, String), Pin>>,
+    draining: Mutex<Vec<DrainingBackend>>,
+}
+
+impl FilterPins {
+    pub fn pin(&self, chain: &str, filter: &str, url: &str) {
+        let pin = Pin {
+            url: url.to_string(),
+            last_used: Instant::now(),
+        };
+        let mut pins = self.pins.lock().unwrap();
+        pins.retain(|_, pin| pin.last_used + FILTER_TTL > Instant::now());
+        pins.insert((chain.to_string(), filter.to_string()), pin);
+    }
+
+    pub fn unpin(&self, chain: &str, filter: &str) {
+        let key = (chain.to_string(), filter.to_string());
+        self.pins.lock().unwrap().remove(&key);
+    }
+
+    /// The pool and url of the backend `filter` was installed on: `current`
+    /// while the backend is part of it, else the one the backend drains in.
+    /// `None` for filters not pinned or whose backend finished draining.
+    pub fn route(
+        &self,
+        chain: &str,
+        filter: &str,
+        current: &Arc<RoundRobin>,
+    ) -> Option<(Arc<RoundRobin>, String)> {
+        let now = Instant::now();
+        let url = {
+            let mut pins = self.pins.lock().unwrap();
+            let pin = pins.get_mut(&(chain.to_string(), filter.to_string()))?;
+            pin.last_used = now;
+            pin.url.clone()
+        };
+        if current.endpoints.contains(&url) {
+            return Some((current.clone(), url));
+        }
+        let mut draining = self.draining.lock().unwrap();
+        draining.retain(|backend| backend.until > now);
+        draining
+            .iter()
+            .find(|backend| backend.chain == chain && backend.url == url)
+            .map(|backend| (backend.round_robin.clone(), url))
+    }
+
+    /// Keeps the backends of `previous` which `next` no longer has for
+    /// `period`, returning the pools they drain in, whose limits still need
+    /// refills meanwhile.
+    pub fn drain(
+        &self,
+        previous: &LoadBalancer,
+        next: &LoadBalancer,
+        period: Duration,
+    ) -> Vec<Arc<RoundRobin>> {
+        let until = Instant::now() + period;
+        let mut draining = self.draining.lock().unwrap();
+        let mut pools = Vec::new();
+        for (chain, round_robin) in previous.load_balancers.iter() {
+            let kept = next.load_balancers.get(chain);
+            let removed: Vec<_> = round_robin
+                .endpoints
+                .iter()
+                .filter(|url| kept.is_none_or(|kept| !kept.endpoints.contains(url)))
+                .collect();
+            for url in &removed {
+                println!(
+                    "Draining backend {} of chain {} for {:?}",
+                    round_robin.label_of(url),
+                    chain,
+                    period
+                );
+                draining.push(DrainingBackend {
+                    chain: chain.clone(),
+                    url: url.to_string(),
+                    round_robin: round_robin.clone(),
+                    until,
+                });
+            }
+            if !removed.is_empty() {
+                pools.push(round_robin.clone());
+            }
+        }
+        pools
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::RpcServer;
+    use serde_json::json;
+
+    fn pool(urls: &[&str]) -> Arc<RoundRobin> {
+        let servers = urls
+            .iter()
+            .map(|url| RpcServer {
+                url: url.to_string(),
+                request_limit: 10,
+                current_limit: 10,
+                ..Default::default()
+            })
+            .collect();
+        Arc::new(RoundRobin::new(servers))
+    }
+
+    fn balancer(round_robin: &Arc<RoundRobin>) -> LoadBalancer {
+        let chains = HashMap::from([("sepolia".to_string(), round_robin.clone())]);
+        LoadBalancer::new(Arc::new(chains))
+    }
+
+    #[test]
+    fn test_filter_methods() {
+        let poll = json!({ "method": "eth_getFilterChanges", "params": ["0x1"] });
+        assert_eq!(filter_of(&poll), Some("0x1"));
+        assert_eq!(filter_of(&json!({ "method": "eth_blockNumber" })), None);
+        assert!(creates_filter(&json!({ "method": "eth_newBlockFilter" })));
+        let response = br#"{"jsonrpc":"2.0","id":1,"result":"0x1"}"#;
+        assert_eq!(created_filter(response), Some("0x1".to_string()));
+    }
+
+    #[test]
+    fn test_draining_backend() {
+        let (kept, removed) = ("https://polygon-rpc.com", "https://sepolia.drpc.org/");
+        let previous = pool(&[kept, removed]);
+        let next = pool(&[kept]);
+        let pins = FilterPins::default();
+        pins.pin("sepolia", "0x1", kept);
+        pins.pin("sepolia", "0x2", removed);
+
+        let pools = pins.drain(&balancer(&previous), &balancer(&next), FILTER_TTL);
+        assert_eq!(pools.len(), 1);
+        let (round_robin, url) = pins.route("sepolia", "0x1", &next).unwrap();
+        assert!(Arc::ptr_eq(&round_robin, &next));
+        assert_eq!(url, kept);
+        let (round_robin, url) = pins.route("sepolia", "0x2", &next).unwrap();
+        assert!(Arc::ptr_eq(&round_robin, &previous));
+        assert_eq!(url, removed);
+        assert!(pins.route("sepolia", "0x3", &next).is_none());
+
+        // Once drained, the filter has nowhere to go.
+        let pins = FilterPins::default();
+        pins.pin("sepolia", "0x2", removed);
+        pins.drain(&balancer(&previous), &balancer(&next), Duration::ZERO);
+        assert!(pins.route("sepolia", "0x2", &next).is_none());
+        pins.unpin("sepolia", "0x2");
+        assert!(pins.pins.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::{sync::watch, task::JoinSet};
+
+use super::rpc_client;
+use crate::algorithms::round_robin::RoundRobin;
+
+/// How long an aggregated fee suggestion is served before backends are queried again.
+const GAS_CACHE_TTL: Duration = Duration::from_secs(3);
+
+/// Number of backends queried per aggregation.
+const GAS_FANOUT: usize = 5;
+
+/// Reward percentiles requested from `eth_feeHistory`.
+const REWARD_PERCENTILES: [f64; 3] = [25.0, 50.0, 75.0];
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PriceSpread {
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PriorityFees {
+    pub low: u64,
+    pub medium: u64,
+    pub high: u64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GasSuggestion {
+    pub gas_price: PriceSpread,
+    pub base_fee: Option<u64>,
+    pub priority_fee: Option<PriorityFees>,
+    pub max_fee: Option<u64>,
+    pub backends_responded: usize,
+}
+
+/// Fee data reported by a single backend.
+#[derive(Debug, Clone, Default)]
+pub struct BackendFees {
+    pub gas_price: Option<u64>,
+    pub base_fee: Option<u64>,
+    pub rewards: Option<[u64; 3]>,
+}
+
+impl BackendFees {
+    /// Whether the backend answered any of the queries.
+    fn has_data(&self) -> bool {
+        self.gas_price.is_some() || self.base_fee.is_some() || self.rewards.is_some()
+    }
+}
+
+/// Aggregates `eth_gasPrice` and `eth_feeHistory` across backends and caches
+/// the consolidated suggestion per chain for a short time.
+#[derive(Debug, Default)]
+pub struct GasOracle {
+    cache: Mutex<HashMap<String, (Instant, GasSuggestion)>>,
+    /// Refreshes being fetched by chain, whose senders are dropped once done.
+    refreshing: Mutex<HashMap<String, watch::Sender<()>>>,
+}
+
+/// Ends the refresh of `chain`, letting the callers waiting for it read the
+/// cache.
+struct Refreshing<'a> {
+    oracle: &'a GasOracle,
+    chain: &'a str,
+}
+
+impl Drop for Refreshing<'_> {
+    fn drop(&mut self) {
+        self.oracle.refreshing.lock().unwrap().remove(self.chain);
+    }
+}
+
+impl GasOracle {
+    pub fn cached(&self, chain: &str) -> Option<GasSuggestion> {
+        let cache = self.cache.lock().unwrap();
+        cache
+            .get(chain)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < GAS_CACHE_TTL)
+            .map(|(_, suggestion)| suggestion.clone())
+    }
+
+    /// The suggestion for `chain`, refreshed from the backends of
+    /// `round_robin` once the cached one expired. Callers arriving while a
+    /// refresh is fetched wait for its result instead of querying the
+    /// backends again.
+    pub async fn suggest(
+        &self,
+        chain: &str,
+        round_robin: &Arc<RoundRobin>,
+    ) -> Option<GasSuggestion> {
+        let refreshed = match self.refreshing.lock().unwrap().entry(chain.to_string()) {
+            Entry::Occupied(refresh) => Some(refresh.get().subscribe()),
+            Entry::Vacant(refresh) => {
+                // Checked while holding the lock, a refresh ending meanwhile
+                // already cached its suggestion.
+                if let Some(suggestion) = self.cached(chain) {
+                    return Some(suggestion);
+                }
+                refresh.insert(watch::Sender::new(()));
+                None
+            }
+        };
+        match refreshed {
+            Some(mut refreshed) => {
+                // Only ends once the sender is dropped.
+                let _ = refreshed.changed().await;
+                self.cached(chain)
+            }
+            None => {
+                let _refreshing = Refreshing {
+                    oracle: self,
+                    chain,
+                };
+                self.refresh(chain, round_robin).await
+            }
+        }
+    }
+
+    async fn refresh(&self, chain: &str, round_robin: &Arc<RoundRobin>) -> Option<GasSuggestion> {
+        let mut queries = JoinSet::new();
+        for url in round_robin.take_many(GAS_FANOUT) {
+            let round_robin = round_robin.clone();
+            queries.spawn(async move { query_backend(&round_robin, &url).await });
+        }
+
+        let mut fees = Vec::new();
+        while let Some(result) = queries.join_next().await {
+            if let Ok(backend_fees) = result {
+                fees.push(backend_fees);
+            }
+        }
+
+        let suggestion = aggregate(&fees)?;
+        let mut cache = self.cache.lock().unwrap();
+        cache.insert(chain.to_string(), (Instant::now(), suggestion.clone()));
+        Some(suggestion)
+    }
+}
+
+async fn query_backend(round_robin: &RoundRobin, url: &str) -> BackendFees {
+    let gas_price = rpc_client::call_server(round_robin, url, "eth_gasPrice", json!([]))
+        .await
+        .ok()
+        .and_then(|price| rpc_client::parse_quantity(&price));
+
+    let history = rpc_client::call_server(
+        round_robin,
+        url,
+        "eth_feeHistory",
+        json!([5, "latest", REWARD_PERCENTILES]),
+    )
+    .await
+    .unwrap_or(Value::Null);
+
+    // The last base fee entry is the one for the upcoming block.
+    let base_fee = history["baseFeePerGas"]
+        .as_array()
+        .and_then(|fees| fees.last())
+        .and_then(rpc_client::parse_quantity);
+
+    let rewards = history["reward"].as_array().and_then(|blocks| {
+        let mut per_percentile: [Vec<u64>; 3] = Default::default();
+        for block in blocks {
+            for (i, reward) in block.as_array()?.iter().take(3).enumerate() {
+                per_percentile[i].push(rpc_client::parse_quantity(reward)?);
+            }
+        }
+        Some([
+            median(&mut per_percentile[0])?,
+            median(&mut per_percentile[1])?,
+            median(&mut per_percentile[2])?,
+        ])
+    });
+
+    BackendFees {
+        gas_price,
+        base_fee,
+        rewards,
+    }
+}
+
+/// Combines the per-backend fee data into one suggestion using medians, so a
+/// single provider reporting an outlier price does not skew the result.
+pub fn aggregate(fees: &[BackendFees]) -> Option<GasSuggestion> {
+    let mut gas_prices: Vec<u64> = fees.iter().filter_map(|f| f.gas_price).collect();
+    let mut base_fees: Vec<u64> = fees.iter().filter_map(|f| f.base_fee).collect();
+    let rewards: Vec<[u64; 3]> = fees.iter().filter_map(|f| f.rewards).collect();
+
+    let gas_price = PriceSpread {
+        min: *gas_prices.iter().min()?,
+        max: *gas_prices.iter().max()?,
+        median: median(&mut gas_prices)?,
+    };
+
+    let base_fee = median(&mut base_fees);
+    let priority_fee = (|| {
+        Some(PriorityFees {
+            low: median(&mut rewards.iter().map(|r| r[0]).collect::<Vec<_>>())?,
+            medium: median(&mut rewards.iter().map(|r| r[1]).collect::<Vec<_>>())?,
+            high: median(&mut rewards.iter().map(|r| r[2]).collect::<Vec<_>>())?,
+        })
+    })();
+
+    // Leave room for the base fee to double before the transaction gets priced out.
+    let max_fee = match (base_fee, &priority_fee) {
+        (Some(base_fee), Some(priority_fee)) => Some(
+            base_fee
+                .saturating_mul(2)
+                .saturating_add(priority_fee.medium),
+        ),
+        _ => None,
+    };
+
+    Some(GasSuggestion {
+        gas_price,
+        base_fee,
+        priority_fee,
+        max_fee,
+        backends_responded: fees.iter().filter(|f| f.has_data()).count(),
+    })
+}
+
+fn median(values: &mut [u64]) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some(((values[mid - 1] as u128 + values[mid] as u128) / 2) as u64)
+    } else {
+        Some(values[mid])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::RpcServer;
+    use axum::{routing::post, Json, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&mut []), None);
+        assert_eq!(median(&mut [3, 1, 2]), Some(2));
+        assert_eq!(median(&mut [4, 1, 2, 10]), Some(3));
+    }
+
+    #[test]
+    fn test_aggregate_ignores_outliers() {
+        let fees = vec![
+            BackendFees {
+                gas_price: Some(10),
+                base_fee: Some(8),
+                rewards: Some([1, 2, 3]),
+            },
+            BackendFees {
+                gas_price: Some(12),
+                base_fee: Some(8),
+                rewards: Some([1, 2, 4]),
+            },
+            BackendFees {
+                gas_price: Some(1_000),
+                base_fee: None,
+                rewards: None,
+            },
+        ];
+
+        let suggestion = aggregate(&fees).unwrap();
+        assert_eq!(
+            suggestion.gas_price,
+            PriceSpread {
+                min: 10,
+                median: 12,
+                max: 1_000
+            }
+        );
+        assert_eq!(suggestion.base_fee, Some(8));
+        assert_eq!(
+            suggestion.priority_fee,
+            Some(PriorityFees {
+                low: 1,
+                medium: 2,
+                high: 3
+            })
+        );
+        assert_eq!(suggestion.max_fee, Some(18));
+        assert_eq!(suggestion.backends_responded, 3);
+    }
+
+    #[test]
+    fn test_aggregate_counts_answering_backends() {
+        let fees = vec![
+            BackendFees {
+                gas_price: Some(10),
+                base_fee: Some(u64::MAX),
+                rewards: Some([1, 2, 3]),
+            },
+            BackendFees::default(),
+        ];
+
+        let suggestion = aggregate(&fees).unwrap();
+        assert_eq!(suggestion.max_fee, Some(u64::MAX));
+        assert_eq!(suggestion.backends_responded, 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_refreshes_share_one_fetch() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new().route(
+            "/",
+            post(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Json(json!({"jsonrpc": "2.0", "id": 1, "result": "0x10"}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        let round_robin = Arc::new(RoundRobin::new(vec![RpcServer {
+            url,
+            current_limit: 10,
+            request_limit: 10,
+            ..Default::default()
+        }]));
+
+        let oracle = GasOracle::default();
+        let (first, second) = tokio::join!(
+            oracle.suggest("sepolia", &round_robin),
+            oracle.suggest("sepolia", &round_robin)
+        );
+        // eth_gasPrice and eth_feeHistory, once.
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert_eq!(first.unwrap().gas_price.median, 16);
+        assert_eq!(second.unwrap().gas_price.median, 16);
+    }
+
+    #[test]
+    fn test_aggregate_without_gas_price() {
+        assert!(aggregate(&[BackendFees::default()]).is_none());
+    }
+}
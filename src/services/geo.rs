@@ -0,0 +1,147 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+
+/// Header clients, or the edge in front of the balancer, name their region
+/// in, e.g. `eu-west`.
+pub const CLIENT_REGION: &str = "x-client-region";
+
+/// Address of the client of a connection, handed to handlers as `ConnectInfo`
+/// by [`listener::serve`](crate::listener::serve).
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAddr(pub SocketAddr);
+
+/// The `[server.geoip]` section, only usable in builds with the `geoip`
+/// feature: a MaxMind country database and the backend region of each
+/// ISO country or continent code, e.g. `{ DE = "eu-central", EU = "eu-west" }`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct GeoIpConfig {
+    pub database: String,
+    pub regions: HashMap<String, String>,
+}
+
+/// Tells which region a request comes from: the one of its
+/// `X-Client-Region` header, else the one its address is located in.
+#[derive(Debug, Default)]
+pub struct ClientRegions {
+    #[cfg(feature = "geoip")]
+    geoip: Option<GeoIp>,
+}
+
+impl ClientRegions {
+    pub fn new(config: Option<&GeoIpConfig>) -> Result<Self, String> {
+        #[cfg(feature = "geoip")]
+        {
+            let geoip = config.map(GeoIp::open).transpose()?;
+            Ok(Self { geoip })
+        }
+        #[cfg(not(feature = "geoip"))]
+        match config {
+            Some(_) => Err("[server.geoip] needs a build with the geoip feature".to_string()),
+            None => Ok(Self {}),
+        }
+    }
+
+    pub fn region_of(&self, headers: &HeaderMap, client: Option<IpAddr>) -> Option<String> {
+        let hinted = headers
+            .get(CLIENT_REGION)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|region| !region.is_empty());
+        if let Some(region) = hinted {
+            return Some(region.to_string());
+        }
+        #[cfg(feature = "geoip")]
+        if let (Some(geoip), Some(client)) = (&self.geoip, client) {
+            return geoip.region_of(client);
+        }
+        let _ = client;
+        None
+    }
+}
+
+#[cfg(feature = "geoip")]
+struct GeoIp {
+    reader: maxminddb::Reader<Vec<u8>>,
+    regions: HashMap<String, String>,
+}
+
+#[cfg(feature = "geoip")]
+impl std::fmt::Debug for GeoIp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GeoIp")
+            .field("regions", &self.regions)
+            .finish()
+    }
+}
+
+#[cfg(feature = "geoip")]
+impl GeoIp {
+    fn open(config: &GeoIpConfig) -> Result<Self, String> {
+        let reader = maxminddb::Reader::open_readfile(&config.database)
+            .map_err(|e| format!("Failed to open GeoIP database {}: {}", config.database, e))?;
+        Ok(Self {
+            reader,
+            regions: config.regions.clone(),
+        })
+    }
+
+    fn region_of(&self, client: IpAddr) -> Option<String> {
+        let location: maxminddb::geoip2::Country = self.reader.lookup(client).ok()?;
+        let country = location.country.and_then(|country| country.iso_code);
+        let continent = location.continent.and_then(|continent| continent.code);
+        region_for(&self.regions, country, continent)
+    }
+}
+
+/// The region of a country, else of its continent.
+#[cfg_attr(not(feature = "geoip"), allow(dead_code))]
+fn region_for(
+    regions: &HashMap<String, String>,
+    country: Option<&str>,
+    continent: Option<&str>,
+) -> Option<String> {
+    [country, continent]
+        .into_iter()
+        .flatten()
+        .find_map(|code| regions.get(code))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_region() {
+        let regions = ClientRegions::default();
+        let mut headers = HeaderMap::new();
+        assert_eq!(regions.region_of(&headers, None), None);
+        headers.insert(CLIENT_REGION, " eu-west ".parse().unwrap());
+        assert_eq!(
+            regions.region_of(&headers, "10.0.0.1".parse().ok()),
+            Some("eu-west".to_string())
+        );
+    }
+
+    #[test]
+    fn test_region_for() {
+        let regions = HashMap::from([
+            ("DE".to_string(), "eu-central".to_string()),
+            ("EU".to_string(), "eu-west".to_string()),
+        ]);
+        assert_eq!(
+            region_for(&regions, Some("DE"), Some("EU")),
+            Some("eu-central".to_string())
+        );
+        assert_eq!(
+            region_for(&regions, Some("FR"), Some("EU")),
+            Some("eu-west".to_string())
+        );
+        assert_eq!(region_for(&regions, Some("US"), Some("NA")), None);
+    }
+}
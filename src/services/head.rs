@@ -0,0 +1,125 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::json;
+use tokio::task::JoinSet;
+
+use super::rpc_client;
+use crate::algorithms::round_robin::RoundRobin;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BackendHead {
+    pub index: usize,
+    pub host: String,
+    pub block_number: Option<u64>,
+    pub lag: Option<u64>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HeadSummary {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    pub median: Option<u64>,
+    pub backends: Vec<BackendHead>,
+}
+
+/// Queries `eth_blockNumber` on every url of `round_robin` concurrently,
+/// through the server's transport and within its timeout, keeping the url
+/// order.
+///
+/// This is a monitoring query and does not take tokens from the request limits.
+pub async fn fetch_heads(
+    round_robin: &Arc<RoundRobin>,
+    urls: &[String],
+) -> Vec<Result<u64, String>> {
+    let mut queries = JoinSet::new();
+    for (index, url) in urls.iter().enumerate() {
+        let round_robin = round_robin.clone();
+        let url = url.clone();
+        queries.spawn(async move {
+            let head = rpc_client::call_server(&round_robin, &url, "eth_blockNumber", json!([]))
+                .await
+                .and_then(|result| {
+                    rpc_client::parse_quantity(&result)
+                        .ok_or_else(|| format!("Invalid block number: {}", result))
+                });
+            (index, head)
+        });
+    }
+
+    let mut heads = vec![Err("Backend did not respond".to_string()); urls.len()];
+    while let Some(result) = queries.join_next().await {
+        if let Ok((index, head)) = result {
+            heads[index] = head;
+        }
+    }
+    heads
+}
+
+/// Builds the pool-wide head view, with each backend's lag measured against
+/// the highest block any backend reported.
+pub fn summarize(urls: &[String], heads: Vec<Result<u64, String>>) -> HeadSummary {
+    let mut heights: Vec<u64> = heads
+        .iter()
+        .filter_map(|h| h.as_ref().ok().copied())
+        .collect();
+    heights.sort_unstable();
+
+    let max = heights.last().copied();
+    let backends = urls
+        .iter()
+        .zip(heads)
+        .enumerate()
+        .map(|(index, (url, head))| BackendHead {
+            index,
+            host: host_of(url),
+            block_number: head.as_ref().ok().copied(),
+            lag: match (&head, max) {
+                (Ok(height), Some(max)) => Some(max - height),
+                _ => None,
+            },
+            error: head.err(),
+        })
+        .collect();
+
+    HeadSummary {
+        min: heights.first().copied(),
+        max,
+        median: heights.get(heights.len() / 2).copied(),
+        backends,
+    }
+}
+
+/// Only the host is exposed, since provider urls usually embed an api key.
+pub fn host_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_else(|| "invalid-url".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_reports_lag() {
+        let urls = vec![
+            "https://eth-sepolia.g.alchemy.com/v2/secret".to_string(),
+            "https://1rpc.io/sepolia".to_string(),
+            "https://sepolia.drpc.org".to_string(),
+        ];
+        let heads = vec![Ok(100), Ok(97), Err("timeout".to_string())];
+
+        let summary = summarize(&urls, heads);
+        assert_eq!(summary.min, Some(97));
+        assert_eq!(summary.max, Some(100));
+        assert_eq!(summary.median, Some(100));
+        assert_eq!(summary.backends[0].host, "eth-sepolia.g.alchemy.com");
+        assert_eq!(summary.backends[0].lag, Some(0));
+        assert_eq!(summary.backends[1].lag, Some(3));
+        assert_eq!(summary.backends[2].lag, None);
+        assert_eq!(summary.backends[2].error, Some("timeout".to_string()));
+    }
+}
@@ -0,0 +1,269 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT, VIA};
+use serde::Deserialize;
+
+/// Inbound headers the balancer always sets itself or which only describe
+/// the client's connection, they are never forwarded.
+const RESERVED: [&str; 10] = [
+    "host",
+    "content-length",
+    "content-type",
+    "connection",
+    "keep-alive",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// The `[headers]` section of a chain.
+///
+/// By default no inbound header is forwarded. `forward` lists the ones that
+/// are, `"*"` forwarding all of them, and `strip` removes headers again, e.g.
+/// `cookie` from a `"*"`. Static headers can be added to every upstream
+/// request with `request` and to every client response with `response`.
+///
+/// The balancer identifies itself upstream with `user_agent`, `rpc_lb/<version>`
+/// by default, appends the client's address to `X-Forwarded-For` with
+/// `forwarded_for` and its own name to `Via` with `via`.
+///
+/// Request ids providers return for their support tickets are looked up in
+/// the `provider_request_id` headers, logged, and passed to the client as
+/// `X-Provider-Request-Id` with `echo_provider_request_id`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HeaderPolicy {
+    #[serde(default)]
+    pub forward: Vec<String>,
+    #[serde(default)]
+    pub strip: Vec<String>,
+    #[serde(default)]
+    pub request: HashMap<String, String>,
+    #[serde(default)]
+    pub response: HashMap<String, String>,
+    #[serde(default = "default_provider_request_id")]
+    pub provider_request_id: Vec<String>,
+    #[serde(default)]
+    pub echo_provider_request_id: bool,
+    pub user_agent: Option<String>,
+    #[serde(default)]
+    pub forwarded_for: bool,
+    pub via: Option<String>,
+}
+
+fn default_provider_request_id() -> Vec<String> {
+    ["x-request-id", "request-id", "x-amzn-requestid", "cf-ray"]
+        .map(String::from)
+        .to_vec()
+}
+
+impl Default for HeaderPolicy {
+    fn default() -> Self {
+        Self {
+            forward: Vec::new(),
+            strip: Vec::new(),
+            request: HashMap::new(),
+            response: HashMap::new(),
+            provider_request_id: default_provider_request_id(),
+            echo_provider_request_id: false,
+            user_agent: None,
+            forwarded_for: false,
+            via: None,
+        }
+    }
+}
+
+impl HeaderPolicy {
+    /// Checks every header name and value, so applying the policy can not fail.
+    pub fn validate(&self) -> Result<(), String> {
+        for name in self.provider_request_id.iter() {
+            parse_name(name)?;
+        }
+        for name in self.forward.iter().chain(&self.strip) {
+            if name != "*" {
+                parse_name(name)?;
+            }
+        }
+        for (name, value) in self.request.iter().chain(&self.response) {
+            parse_name(name)?;
+            HeaderValue::from_str(value)
+                .map_err(|_| format!("Invalid value for header {}", name))?;
+        }
+        for (name, value) in [("user_agent", &self.user_agent), ("via", &self.via)] {
+            if let Some(value) = value {
+                HeaderValue::from_str(value).map_err(|_| format!("Invalid {}", name))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn forwards(&self, name: &HeaderName) -> bool {
+        let listed = |names: &[String]| {
+            names
+                .iter()
+                .any(|listed| listed == "*" || listed.eq_ignore_ascii_case(name.as_str()))
+        };
+        !RESERVED.contains(&name.as_str()) && listed(&self.forward) && !listed(&self.strip)
+    }
+
+    /// Headers sent upstream: the forwarded inbound ones, those identifying
+    /// the balancer and the request of `client`, then the static `request`
+    /// headers, which take precedence.
+    pub fn upstream_headers(&self, inbound: &HeaderMap, client: Option<IpAddr>) -> HeaderMap {
+        let mut headers: HeaderMap = inbound
+            .iter()
+            .filter(|(name, _)| self.forwards(name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        if let Some(user_agent) = &self.user_agent {
+            append(&mut headers, USER_AGENT, user_agent, false);
+        }
+        if let Some(client) = client.filter(|_| self.forwarded_for) {
+            append(&mut headers, X_FORWARDED_FOR, &client.to_string(), true);
+        }
+        if let Some(via) = &self.via {
+            append(&mut headers, VIA, &format!("1.1 {}", via), true);
+        }
+        insert_all(&mut headers, &self.request);
+        headers
+    }
+
+    /// The first request id found in the `names` headers of an upstream
+    /// response.
+    pub fn provider_request_id(upstream: &HeaderMap, names: &[String]) -> Option<String> {
+        names.iter().find_map(|name| {
+            upstream
+                .get(name.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        })
+    }
+
+    /// Adds the static `response` headers to a client response.
+    pub fn apply_to_response(&self, headers: &mut HeaderMap) {
+        insert_all(headers, &self.response);
+    }
+}
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// Sets `name` to `value`, after the forwarded value with `chained`, as
+/// proxies do for `X-Forwarded-For` and `Via`.
+fn append(headers: &mut HeaderMap, name: HeaderName, value: &str, chained: bool) {
+    let value = match headers.get(&name).and_then(|value| value.to_str().ok()) {
+        Some(forwarded) if chained => format!("{}, {}", forwarded, value),
+        _ => value.to_string(),
+    };
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(name, value);
+    }
+}
+
+fn parse_name(name: &str) -> Result<HeaderName, String> {
+    HeaderName::from_bytes(name.as_bytes()).map_err(|_| format!("Invalid header name {}", name))
+}
+
+fn insert_all(headers: &mut HeaderMap, extra: &HashMap<String, String>) {
+    for (name, value) in extra {
+        if let (Ok(name), Ok(value)) = (parse_name(name), HeaderValue::from_str(value)) {
+            headers.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forward_and_strip() {
+        let policy = HeaderPolicy {
+            forward: vec!["*".to_string()],
+            strip: vec!["Cookie".to_string()],
+            request: HashMap::from([("x-api-key".to_string(), "secret".to_string())]),
+            ..Default::default()
+        };
+
+        let mut inbound = HeaderMap::new();
+        inbound.insert("x-request-id", HeaderValue::from_static("42"));
+        inbound.insert("cookie", HeaderValue::from_static("session=1"));
+        inbound.insert("host", HeaderValue::from_static("balancer.local"));
+        inbound.insert("x-api-key", HeaderValue::from_static("client"));
+
+        let headers = policy.upstream_headers(&inbound, None);
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["x-request-id"], "42");
+        assert_eq!(headers["x-api-key"], "secret");
+
+        assert!(HeaderPolicy::default()
+            .upstream_headers(&inbound, None)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_identification_headers() {
+        let policy: HeaderPolicy = toml::from_str(
+            r#"
+            forward = ["x-forwarded-for"]
+            user_agent = "my-lb/1.2"
+            forwarded_for = true
+            via = "my-lb"
+            "#,
+        )
+        .unwrap();
+        assert!(policy.validate().is_ok());
+
+        let mut inbound = HeaderMap::new();
+        inbound.insert("x-forwarded-for", HeaderValue::from_static("203.0.113.7"));
+        inbound.insert("via", HeaderValue::from_static("1.1 edge"));
+        let client = "10.0.0.2".parse().ok();
+        let headers = policy.upstream_headers(&inbound, client);
+        assert_eq!(headers["user-agent"], "my-lb/1.2");
+        assert_eq!(headers["x-forwarded-for"], "203.0.113.7, 10.0.0.2");
+        assert_eq!(headers["via"], "1.1 my-lb");
+
+        let headers = HeaderPolicy::default().upstream_headers(&inbound, client);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_headers_rejected() {
+        let policy = HeaderPolicy {
+            response: HashMap::from([("bad header".to_string(), "1".to_string())]),
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn test_provider_request_id() {
+        let policy: HeaderPolicy = toml::from_str(
+            r#"
+            provider_request_id = ["x-alchemy-request-id"]
+            echo_provider_request_id = true
+            "#,
+        )
+        .unwrap();
+        assert!(policy.validate().is_ok());
+
+        let mut upstream = HeaderMap::new();
+        upstream.insert("x-request-id", HeaderValue::from_static("generic"));
+        assert_eq!(
+            HeaderPolicy::provider_request_id(&upstream, &policy.provider_request_id),
+            None
+        );
+        upstream.insert("x-alchemy-request-id", HeaderValue::from_static("a1b2"));
+        assert_eq!(
+            HeaderPolicy::provider_request_id(&upstream, &policy.provider_request_id),
+            Some("a1b2".to_string())
+        );
+        assert_eq!(
+            HeaderPolicy::provider_request_id(
+                &upstream,
+                &HeaderPolicy::default().provider_request_id
+            ),
+            Some("generic".to_string())
+        );
+    }
+}
@@ -0,0 +1,238 @@
+use std::{sync::Arc, time::Duration};
+
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::{task::JoinSet, time};
+
+use super::rpc_client::parse_quantity;
+use crate::{
+    algorithms::round_robin::RoundRobin,
+    transport::{Sent, UpstreamRequest},
+};
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `health_check` of a chain: the request sent to every backend each
+/// `interval_secs`, and what its response has to look like.
+///
+/// The request is a JSON-RPC call of `method`, or `body` sent as-is for
+/// chains not speaking JSON-RPC. A backend failing `failure_threshold`
+/// checks in a row is taken out of rotation until it passes one again.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct HealthCheck {
+    #[serde(default = "default_method")]
+    pub method: String,
+    #[serde(default = "default_params")]
+    pub params: Value,
+    pub body: Option<String>,
+    #[serde(default)]
+    pub expect: Expect,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+fn default_method() -> String {
+    "eth_blockNumber".to_string()
+}
+
+fn default_params() -> Value {
+    json!([])
+}
+
+fn default_interval_secs() -> u64 {
+    15
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+/// What a passing health check response looks like, besides a `2xx` status.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Expect {
+    /// A JSON-RPC response with a non-null `result` and no `error`.
+    #[default]
+    Result,
+    /// Nothing more, e.g. for `/status` style endpoints.
+    Status,
+    /// A `result` equal to the value, such as `"ok"` for Solana's `getHealth`.
+    ResultEquals(Value),
+    /// A body containing the text.
+    Contains(String),
+}
+
+impl Expect {
+    pub fn check(&self, body: &[u8]) -> Result<(), String> {
+        let result = || -> Result<Value, String> {
+            let response: Value = serde_json::from_slice(body)
+                .map_err(|_| "response is not valid JSON".to_string())?;
+            if let Some(error) = response.get("error") {
+                return Err(format!("error {}", error));
+            }
+            match response.get("result") {
+                Some(Value::Null) | None => Err("response has no result".to_string()),
+                Some(result) => Ok(result.clone()),
+            }
+        };
+
+        match self {
+            Expect::Result => result().map(|_| ()),
+            Expect::Status => Ok(()),
+            Expect::ResultEquals(expected) => {
+                let result = result()?;
+                if result == *expected {
+                    Ok(())
+                } else {
+                    Err(format!("result {} is not {}", result, expected))
+                }
+            }
+            Expect::Contains(text) => {
+                if String::from_utf8_lossy(body).contains(text.as_str()) {
+                    Ok(())
+                } else {
+                    Err(format!("response does not contain {}", text))
+                }
+            }
+        }
+    }
+}
+
+impl HealthCheck {
+    /// The block a passing `eth_blockNumber` check was answered with, which
+    /// tells how far the backend lags behind the others.
+    fn reported_head(&self, body: &[u8]) -> Option<u64> {
+        if self.body.is_some() || self.method != "eth_blockNumber" {
+            return None;
+        }
+        let response: Value = serde_json::from_slice(body).ok()?;
+        parse_quantity(&response["result"])
+    }
+
+    fn request(&self) -> UpstreamRequest {
+        match &self.body {
+            Some(body) => UpstreamRequest {
+                content_type: HeaderValue::from_static("text/plain"),
+                ..UpstreamRequest::json(body.clone())
+            },
+            None => UpstreamRequest::json(
+                json!({"jsonrpc": "2.0", "method": self.method, "params": self.params, "id": 1})
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+/// Runs the check against the backend at `url`, through its transport.
+/// Like other monitoring queries it does not take tokens from the limits.
+pub async fn check_backend(
+    round_robin: &RoundRobin,
+    url: &str,
+    check: &HealthCheck,
+) -> Result<(), String> {
+    let transport = round_robin
+        .transport_for(url)
+        .ok_or_else(|| "unknown backend".to_string())?;
+    let response = transport
+        .send(url, &check.request(), Some(CHECK_TIMEOUT), &Sent::default())
+        .await
+        .map_err(|e| e.message)?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+    check.expect.check(&body)?;
+    if let Some(block) = check.reported_head(&body) {
+        round_robin.record_head(url, block);
+    }
+    Ok(())
+}
+
+/// Checks every backend of `chain` each `interval_secs`, forever.
+pub async fn run(chain: String, round_robin: Arc<RoundRobin>, check: HealthCheck) {
+    let check = Arc::new(check);
+    loop {
+        let mut checks = JoinSet::new();
+        for url in round_robin.server_urls() {
+            let round_robin = round_robin.clone();
+            let check = check.clone();
+            checks.spawn(async move {
+                let result = check_backend(&round_robin, &url, &check).await;
+                (url, result)
+            });
+        }
+
+        while let Some(result) = checks.join_next().await {
+            let Ok((url, result)) = result else {
+                continue;
+            };
+            if let Err(e) = &result {
+                println!(
+                    "Health check of backend {} of chain {} failed: {}",
+                    round_robin.label_of(&url),
+                    chain,
+                    e
+                );
+            }
+            round_robin.record_health(&url, result.is_ok(), check.failure_threshold);
+        }
+
+        time::sleep(Duration::from_secs(check.interval_secs.max(1))).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expectations() {
+        let ok = br#"{"jsonrpc":"2.0","result":"ok","id":1}"#;
+        let failed = br#"{"jsonrpc":"2.0","error":{"code":-32005,"message":"behind"},"id":1}"#;
+
+        assert!(Expect::Result.check(ok).is_ok());
+        assert!(Expect::Result.check(failed).is_err());
+        assert!(Expect::Result.check(b"<html></html>").is_err());
+        assert!(Expect::Status.check(b"<html></html>").is_ok());
+        assert!(Expect::ResultEquals(json!("ok")).check(ok).is_ok());
+        assert!(Expect::ResultEquals(json!("ok"))
+            .check(br#"{"result":"behind"}"#)
+            .is_err());
+        assert!(Expect::Contains("\"catching_up\":false".to_string())
+            .check(br#"{"sync_info":{"catching_up":false}}"#)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_reported_head() {
+        let check: HealthCheck = toml::from_str("").unwrap();
+        let head = br#"{"jsonrpc":"2.0","result":"0x10","id":1}"#;
+        assert_eq!(check.reported_head(head), Some(16));
+
+        let check: HealthCheck = toml::from_str(r#"method = "eth_syncing""#).unwrap();
+        assert_eq!(check.reported_head(head), None);
+    }
+
+    #[test]
+    fn test_health_check_config() {
+        let check: HealthCheck = toml::from_str(
+            r#"
+            method = "getHealth"
+            expect = { result_equals = "ok" }
+            failure_threshold = 2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(check.expect, Expect::ResultEquals(json!("ok")));
+        assert_eq!(check.params, json!([]));
+        assert_eq!(check.interval_secs, 15);
+
+        let check: HealthCheck = toml::from_str(r#"expect = "status""#).unwrap();
+        assert_eq!(check.method, "eth_blockNumber");
+        assert_eq!(check.expect, Expect::Status);
+    }
+}
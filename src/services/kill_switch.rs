@@ -0,0 +1,110 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Mutex,
+};
+
+use serde::Serialize;
+
+use crate::algorithms::{round_robin::Chains, routing::MethodCategory};
+
+/// What a chain currently refuses to forward.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Blocked {
+    pub methods: BTreeSet<String>,
+    /// Every write method, e.g. `eth_sendRawTransaction`.
+    pub writes: bool,
+}
+
+impl Blocked {
+    fn blocks(&self, method: &str) -> bool {
+        self.methods.contains(method)
+            || (self.writes && MethodCategory::of(method) == MethodCategory::Write)
+    }
+}
+
+/// The emergency brake of every chain: methods answered with an error
+/// instead of being forwarded, e.g. while a provider mishandles
+/// transactions. Starts with the chains' `blocked_methods` and
+/// `block_writes`, `/admin/kill-switch` changes it at runtime.
+#[derive(Debug, Default)]
+pub struct KillSwitch {
+    blocked: Mutex<HashMap<String, Blocked>>,
+}
+
+impl KillSwitch {
+    pub fn new(chains: &HashMap<String, Chains>) -> Self {
+        let blocked = chains
+            .iter()
+            .map(|(chain, config)| {
+                let blocked = Blocked {
+                    methods: config.blocked_methods.iter().cloned().collect(),
+                    writes: config.block_writes,
+                };
+                (chain.clone(), blocked)
+            })
+            .filter(|(_, blocked)| *blocked != Blocked::default())
+            .collect();
+        Self {
+            blocked: Mutex::new(blocked),
+        }
+    }
+
+    /// Whether `method` may not be forwarded on `chain`.
+    pub fn blocks(&self, chain: &str, method: &str) -> bool {
+        self.blocked
+            .lock()
+            .unwrap()
+            .get(chain)
+            .is_some_and(|blocked| blocked.blocks(method))
+    }
+
+    /// Blocks `method`, or every write with `writes`, on `chain`, or lifts
+    /// the block when `enabled` is false. Returns what the chain blocks now.
+    pub fn set(&self, chain: &str, method: Option<&str>, writes: bool, enabled: bool) -> Blocked {
+        let mut blocked = self.blocked.lock().unwrap();
+        let entry = blocked.entry(chain.to_string()).or_default();
+        if let Some(method) = method {
+            match enabled {
+                true => entry.methods.insert(method.to_string()),
+                false => entry.methods.remove(method),
+            };
+        }
+        if writes {
+            entry.writes = enabled;
+        }
+        let current = entry.clone();
+        if current == Blocked::default() {
+            blocked.remove(chain);
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kill_switch() {
+        let chains = HashMap::from([(
+            "sepolia".to_string(),
+            Chains {
+                blocked_methods: vec!["debug_traceTransaction".to_string()],
+                ..Default::default()
+            },
+        )]);
+        let switch = KillSwitch::new(&chains);
+        assert!(switch.blocks("sepolia", "debug_traceTransaction"));
+        assert!(!switch.blocks("sepolia", "eth_sendRawTransaction"));
+
+        let blocked = switch.set("sepolia", None, true, true);
+        assert!(blocked.writes);
+        assert!(switch.blocks("sepolia", "eth_sendRawTransaction"));
+        assert!(!switch.blocks("base", "eth_sendRawTransaction"));
+
+        switch.set("sepolia", None, true, false);
+        let blocked = switch.set("sepolia", Some("debug_traceTransaction"), false, false);
+        assert_eq!(blocked, Blocked::default());
+        assert!(!switch.blocks("sepolia", "debug_traceTransaction"));
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use serde_json::Value;
+
+use crate::{algorithms::round_robin::RoundRobin, metrics::Metrics, transport::UpstreamRequest};
+
+/// Methods whose result only depends on their parameters.
+const DETERMINISTIC_METHODS: [&str; 6] = [
+    "eth_chainId",
+    "net_version",
+    "eth_getBlockByHash",
+    "eth_getTransactionByHash",
+    "eth_getTransactionReceipt",
+    "eth_getBlockReceipts",
+];
+
+/// Methods which are deterministic once their block parameter is pinned to a
+/// number or hash, paired with the position of that parameter.
+const BLOCK_PINNED_METHODS: [(&str, usize); 6] = [
+    ("eth_getBlockByNumber", 0),
+    ("eth_getBalance", 1),
+    ("eth_getCode", 1),
+    ("eth_getStorageAt", 2),
+    ("eth_getTransactionCount", 1),
+    ("eth_call", 1),
+];
+
+/// Returns true for single JSON-RPC requests whose response every honest
+/// backend has to agree on, which makes them safe to cross-check.
+pub fn is_deterministic(request: &Value) -> bool {
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return false;
+    };
+
+    if DETERMINISTIC_METHODS.contains(&method) {
+        return true;
+    }
+
+    BLOCK_PINNED_METHODS
+        .iter()
+        .find(|(name, _)| *name == method)
+        .and_then(|(_, position)| request.get("params")?.get(*position))
+        .map(is_pinned_block)
+        .unwrap_or(false)
+}
+
+fn is_pinned_block(block: &Value) -> bool {
+    match block {
+        Value::String(tag) => tag.starts_with("0x"),
+        Value::Object(selector) => {
+            selector.contains_key("blockHash") || selector.contains_key("blockNumber")
+        }
+        _ => false,
+    }
+}
+
+pub fn should_sample(ratio: f64) -> bool {
+    ratio > 0.0 && rand::random::<f64>() < ratio
+}
+
+/// Replays `body` against `url` and compares its `result` with the one the
+/// client received, counting checks and mismatches per chain.
+pub async fn cross_check(
+    round_robin: Arc<RoundRobin>,
+    metrics: Arc<Metrics>,
+    chain: String,
+    method: String,
+    url: String,
+    body: Bytes,
+    served_result: Value,
+) {
+    let response = round_robin
+        .send(&url, &UpstreamRequest::json(body), None)
+        .await;
+
+    let mirrored: Option<Value> = match response {
+        Ok(response) => response
+            .bytes()
+            .await
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok()),
+        Err(_) => None,
+    };
+
+    let Some(mirrored) = mirrored else {
+        metrics.inc("rpc_lb_mirror_errors_total", &[("chain", &chain)]);
+        return;
+    };
+
+    metrics.inc("rpc_lb_mirror_checks_total", &[("chain", &chain)]);
+    if mirrored.get("result") != Some(&served_result) {
+        println!("Mirror mismatch on {} for {}", chain, method);
+        metrics.inc(
+            "rpc_lb_mirror_mismatches_total",
+            &[("chain", &chain), ("method", &method)],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_is_deterministic() {
+        assert!(is_deterministic(
+            &json!({"method": "eth_getTransactionReceipt", "params": ["0xabc"]})
+        ));
+        assert!(is_deterministic(
+            &json!({"method": "eth_getBalance", "params": ["0xabc", "0x10"]})
+        ));
+        assert!(!is_deterministic(
+            &json!({"method": "eth_getBalance", "params": ["0xabc", "latest"]})
+        ));
+        assert!(!is_deterministic(
+            &json!({"method": "eth_blockNumber", "params": []})
+        ));
+        assert!(!is_deterministic(&json!([{"method": "eth_chainId"}])));
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::body::Bytes;
+use reqwest::Method;
+use serde::Deserialize;
+use tokio::{task::JoinSet, time};
+
+use crate::{
+    algorithms::round_robin::RoundRobin,
+    transport::{Sent, UpstreamRequest},
+};
+
+const WARM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `prewarm` of a chain: connections opened to each of its HTTP backends
+/// at startup, and again once a backend went `idle_secs` without answering a
+/// request, so the first requests after a lull skip the TCP and TLS
+/// handshakes.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Prewarm {
+    /// Connections opened per backend.
+    #[serde(default = "default_connections")]
+    pub connections: usize,
+    /// Seconds without traffic after which a backend's connections are
+    /// opened again. Below the idle timeout of pooled connections, so they
+    /// are refreshed before they close.
+    #[serde(default = "default_idle_secs")]
+    pub idle_secs: u64,
+}
+
+fn default_connections() -> usize {
+    2
+}
+
+fn default_idle_secs() -> u64 {
+    30
+}
+
+/// Most connections `prewarm` may open per backend.
+const MAX_CONNECTIONS: usize = 16;
+
+impl Prewarm {
+    /// `pool_idle_timeout_secs` is the one of the chain's `http` client, if set.
+    pub fn validate(&self, pool_idle_timeout_secs: Option<u64>) -> Result<(), String> {
+        if !(1..=MAX_CONNECTIONS).contains(&self.connections) {
+            return Err(format!(
+                "prewarm connections should be between 1 and {}",
+                MAX_CONNECTIONS
+            ));
+        }
+        if self.idle_secs == 0 {
+            return Err("prewarm idle_secs should be positive".to_string());
+        }
+        if pool_idle_timeout_secs.is_some_and(|timeout| self.idle_secs >= timeout) {
+            return Err(
+                "prewarm idle_secs should be below pool_idle_timeout_secs, which closes them first"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    fn is_due(&self, last_response: Option<Instant>, now: Instant) -> bool {
+        last_response.is_none_or(|at| now.duration_since(at) >= Duration::from_secs(self.idle_secs))
+    }
+}
+
+/// A request any HTTP server answers without running a JSON-RPC call, so
+/// warming takes no tokens and costs nothing on metered providers.
+fn request() -> UpstreamRequest {
+    UpstreamRequest {
+        method: Method::OPTIONS,
+        ..UpstreamRequest::json(Bytes::new())
+    }
+}
+
+/// Opens `connections` to the backend at `url` by sending that many requests
+/// at once. Connections return to the transport's pool once the responses
+/// are read, whatever their status.
+pub async fn warm_backend(
+    round_robin: &RoundRobin,
+    url: &str,
+    connections: usize,
+) -> Result<(), String> {
+    let transport = round_robin
+        .transport_for(url)
+        .ok_or_else(|| "unknown backend".to_string())?;
+    let request = Arc::new(request());
+    let mut warming = JoinSet::new();
+    for _ in 0..connections {
+        let (transport, request, url) = (transport.clone(), request.clone(), url.to_string());
+        warming.spawn(async move {
+            let response = transport
+                .send(&url, &request, Some(WARM_TIMEOUT), &Sent::default())
+                .await
+                .map_err(|e| e.message)?;
+            response.bytes().await.map_err(|e| e.to_string())?;
+            Ok::<_, String>(())
+        });
+    }
+    let mut result = Ok(());
+    while let Some(warmed) = warming.join_next().await {
+        if let Ok(Err(e)) = warmed {
+            result = Err(e);
+        }
+    }
+    result
+}
+
+/// Keeps the connections to the HTTP backends of `chain` warm, forever.
+pub async fn run(chain: String, round_robin: Arc<RoundRobin>, prewarm: Prewarm) {
+    loop {
+        let now = Instant::now();
+        let mut warming = JoinSet::new();
+        for (i, url) in round_robin.endpoints.iter().enumerate() {
+            let scheme = url.split("://").next().unwrap_or_default();
+            if !matches!(scheme, "http" | "https")
+                || !prewarm.is_due(round_robin.stats[i].lock().unwrap().last_response, now)
+            {
+                continue;
+            }
+            let (round_robin, url) = (round_robin.clone(), url.clone());
+            let connections = prewarm.connections;
+            warming.spawn(async move {
+                let result = warm_backend(&round_robin, &url, connections).await;
+                (url, result)
+            });
+        }
+        while let Some(warmed) = warming.join_next().await {
+            if let Ok((url, Err(e))) = warmed {
+                println!(
+                    "Failed to prewarm connections to backend {} of chain {}: {}",
+                    round_robin.label_of(&url),
+                    chain,
+                    e
+                );
+            }
+        }
+        time::sleep(Duration::from_secs(prewarm.idle_secs)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::RpcServer;
+    use axum::{extract::ConnectInfo, routing::any, Router};
+    use std::{collections::HashSet, net::SocketAddr, sync::Mutex};
+
+    /// A backend recording the client address of every request, one per
+    /// connection.
+    async fn spawn_node(peers: Arc<Mutex<HashSet<SocketAddr>>>) -> String {
+        let app = Router::new().route(
+            "/",
+            any(
+                move |ConnectInfo(peer): ConnectInfo<SocketAddr>| async move {
+                    peers.lock().unwrap().insert(peer);
+                    time::sleep(Duration::from_millis(50)).await;
+                    "{}"
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = app.into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_warm_backend() {
+        let peers = Arc::default();
+        let url = spawn_node(Arc::clone(&peers)).await;
+        let round_robin = RoundRobin::new(vec![RpcServer {
+            url: url.clone(),
+            request_limit: 10,
+            current_limit: 10,
+            ..Default::default()
+        }]);
+
+        warm_backend(&round_robin, &url, 3).await.unwrap();
+        assert_eq!(peers.lock().unwrap().len(), 3);
+
+        // Requests then go over the warm connections.
+        let request = UpstreamRequest::json("{}");
+        for _ in 0..3 {
+            let response = round_robin.send(&url, &request, None).await.unwrap();
+            response.bytes().await.unwrap();
+        }
+        assert_eq!(peers.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_prewarm_config() {
+        let prewarm: Prewarm = toml::from_str("").unwrap();
+        assert_eq!((prewarm.connections, prewarm.idle_secs), (2, 30));
+        assert!(prewarm.validate(None).is_ok());
+        assert!(prewarm.validate(Some(30)).is_err());
+        assert!(Prewarm {
+            connections: 0,
+            ..prewarm.clone()
+        }
+        .validate(None)
+        .is_err());
+
+        let now = Instant::now();
+        assert!(prewarm.is_due(None, now));
+        assert!(!prewarm.is_due(Some(now), now + Duration::from_secs(29)));
+        assert!(prewarm.is_due(Some(now), now + Duration::from_secs(30)));
+    }
+}
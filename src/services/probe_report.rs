@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::task::JoinSet;
+
+use super::{head::host_of, rpc_client::parse_quantity};
+use crate::{
+    algorithms::{key_health::key_hint, round_robin::RoundRobin},
+    transport::{Sent, UpstreamRequest},
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What probing one backend found, a row of the report.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct BackendProbe {
+    pub chain: String,
+    /// Host and key hint, the url usually embeds a key.
+    pub backend: String,
+    pub chain_id: Option<u64>,
+    pub head: Option<u64>,
+    /// Round trip of the `eth_chainId` call.
+    pub latency_ms: Option<u64>,
+    /// Why the backend looks misconfigured, none when it does not.
+    pub problem: Option<String>,
+}
+
+/// Sends one JSON-RPC call through the backend's transport, without taking
+/// a token, and returns its `result`.
+async fn call(round_robin: &RoundRobin, url: &str, method: &str) -> Result<Value, String> {
+    let transport = round_robin
+        .transport_for(url)
+        .ok_or_else(|| "unknown backend".to_string())?;
+    let request = UpstreamRequest::json(
+        json!({"jsonrpc": "2.0", "method": method, "params": [], "id": 1}).to_string(),
+    );
+    let response = transport
+        .send(url, &request, Some(PROBE_TIMEOUT), &Sent::default())
+        .await
+        .map_err(|e| e.message)?;
+    let status = response.status();
+    let body: Value = response
+        .bytes()
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()))
+        .map_err(|e| format!("HTTP {}, {}", status, e))?;
+    if let Some(error) = body.get("error") {
+        return Err(error.to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+    Ok(body.get("result").cloned().unwrap_or(Value::Null))
+}
+
+async fn probe_backend(chain: String, round_robin: Arc<RoundRobin>, url: String) -> BackendProbe {
+    let started = Instant::now();
+    let chain_id = call(&round_robin, &url, "eth_chainId").await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let head = call(&round_robin, &url, "eth_blockNumber").await;
+
+    let problem = match (&chain_id, &head) {
+        (Err(e), Err(_)) => Some(e.clone()),
+        _ => None,
+    };
+    BackendProbe {
+        chain,
+        backend: match key_hint(&url).as_str() {
+            "..." => host_of(&url),
+            hint => format!("{} {}", host_of(&url), hint),
+        },
+        chain_id: chain_id.ok().as_ref().and_then(parse_quantity),
+        head: head.ok().as_ref().and_then(parse_quantity),
+        latency_ms: problem.is_none().then_some(latency_ms),
+        problem,
+    }
+}
+
+/// Probes every backend of `chains` concurrently for its chain ID, head and
+/// latency. Backends answering another chain ID than `expected_ids` names,
+/// or than most backends of their chain, are flagged.
+pub async fn probe_all(
+    chains: &HashMap<String, Arc<RoundRobin>>,
+    expected_ids: &HashMap<String, u64>,
+) -> Vec<BackendProbe> {
+    let mut probes = JoinSet::new();
+    for (chain, round_robin) in chains {
+        for url in round_robin.server_urls() {
+            probes.spawn(probe_backend(chain.clone(), round_robin.clone(), url));
+        }
+    }
+    let mut rows = probes.join_all().await;
+    rows.sort_by(|a, b| (&a.chain, &a.backend).cmp(&(&b.chain, &b.backend)));
+
+    for chain in chains.keys() {
+        let expected = expected_ids
+            .get(chain)
+            .copied()
+            .or_else(|| majority_id(&rows, chain));
+        let Some(expected) = expected else {
+            continue;
+        };
+        for row in rows.iter_mut().filter(|row| &row.chain == chain) {
+            if row.chain_id.is_some_and(|id| id != expected) {
+                row.problem = Some(format!("chain ID mismatch, expected {}", expected));
+            }
+        }
+    }
+    rows
+}
+
+fn majority_id(rows: &[BackendProbe], chain: &str) -> Option<u64> {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for id in rows
+        .iter()
+        .filter(|row| row.chain == chain)
+        .filter_map(|row| row.chain_id)
+    {
+        *counts.entry(id).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(id, count)| (*count, std::cmp::Reverse(*id)))
+        .map(|(id, _)| id)
+}
+
+/// Lays the probes out as a table for the terminal.
+pub fn render(rows: &[BackendProbe]) -> String {
+    let show = |value: Option<u64>| value.map_or("-".to_string(), |value| value.to_string());
+    let mut lines = vec![[
+        "CHAIN".to_string(),
+        "BACKEND".to_string(),
+        "CHAIN ID".to_string(),
+        "HEAD".to_string(),
+        "LATENCY".to_string(),
+        "STATUS".to_string(),
+    ]];
+    for row in rows {
+        lines.push([
+            row.chain.clone(),
+            row.backend.clone(),
+            show(row.chain_id),
+            show(row.head),
+            row.latency_ms
+                .map_or("-".to_string(), |latency| format!("{}ms", latency)),
+            row.problem.clone().unwrap_or_else(|| "ok".to_string()),
+        ]);
+    }
+
+    table(&lines)
+}
+
+/// Aligns the cells of `lines` in columns, the first line being the header.
+pub fn table<const N: usize>(lines: &[[String; N]]) -> String {
+    let mut widths = [0; N];
+    for line in lines {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    lines
+        .iter()
+        .map(|line| {
+            let cells: Vec<String> = line
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::RpcServer;
+    use axum::{routing::post, Json, Router};
+
+    async fn spawn_node(chain_id: &'static str) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move |Json(request): Json<Value>| async move {
+                let result = match request["method"].as_str() {
+                    Some("eth_chainId") => chain_id,
+                    _ => "0x10",
+                };
+                Json(json!({"jsonrpc": "2.0", "id": 1, "result": result}))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_probe_all() {
+        let servers = vec![
+            spawn_node("0xaa36a7").await,
+            spawn_node("0xaa36a7").await,
+            spawn_node("0x1").await,
+            "http://127.0.0.1:9".to_string(),
+        ]
+        .into_iter()
+        .map(|url| RpcServer {
+            url,
+            ..Default::default()
+        })
+        .collect();
+        let chains = HashMap::from([("sepolia".to_string(), Arc::new(RoundRobin::new(servers)))]);
+
+        let rows = probe_all(&chains, &HashMap::new()).await;
+        let problems: Vec<_> = rows.iter().filter_map(|row| row.problem.as_ref()).collect();
+        assert_eq!(problems.len(), 2);
+        assert!(problems.contains(&&"chain ID mismatch, expected 11155111".to_string()));
+        assert_eq!(rows.iter().filter(|row| row.head == Some(16)).count(), 3);
+
+        let table = render(&rows);
+        assert!(table.starts_with("CHAIN    BACKEND"));
+        assert_eq!(table.lines().count(), 5);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+use super::head::host_of;
+use crate::algorithms::{key_health::key_hint, round_robin::RoundRobin};
+
+/// How often budgets are compared against the thresholds.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The `[server.quota_webhook]` section: where alerts about backends running
+/// through their request budget are posted.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct QuotaWebhook {
+    pub url: String,
+    /// Percentages of a budget which trigger an alert.
+    #[serde(default = "default_thresholds")]
+    pub thresholds: Vec<u32>,
+    /// Minimum time between two alerts of the same backend and threshold.
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+fn default_thresholds() -> Vec<u32> {
+    vec![80, 95, 100]
+}
+
+fn default_cooldown_secs() -> u64 {
+    300
+}
+
+/// The payload posted to the webhook.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QuotaAlert {
+    pub chain: String,
+    pub backend: String,
+    pub key: String,
+    pub threshold: u32,
+    pub used: u32,
+    pub limit: u32,
+    pub window_secs: u64,
+}
+
+#[derive(Debug)]
+pub struct QuotaNotifier {
+    webhook: QuotaWebhook,
+    client: reqwest::Client,
+    /// When each (chain, backend url, threshold) last fired.
+    fired: Mutex<HashMap<(String, String, u32), Instant>>,
+}
+
+impl QuotaNotifier {
+    pub fn new(webhook: QuotaWebhook) -> Self {
+        Self {
+            webhook,
+            client: reqwest::Client::new(),
+            fired: Mutex::default(),
+        }
+    }
+
+    /// Returns the alert for the highest threshold `used` of `limit` crossed,
+    /// unless that threshold already fired for the backend within the
+    /// cooldown.
+    pub fn crossed(
+        &self,
+        chain: &str,
+        url: &str,
+        used: u32,
+        limit: u32,
+        window: Duration,
+    ) -> Option<QuotaAlert> {
+        if limit == 0 {
+            return None;
+        }
+        let percent = (used as u64 * 100 / limit as u64) as u32;
+        let threshold = self
+            .webhook
+            .thresholds
+            .iter()
+            .copied()
+            .filter(|&threshold| percent >= threshold)
+            .max()?;
+
+        let now = Instant::now();
+        let cooldown = Duration::from_secs(self.webhook.cooldown_secs);
+        let mut fired = self.fired.lock().unwrap();
+        let key = (chain.to_string(), url.to_string(), threshold);
+        if fired
+            .get(&key)
+            .is_some_and(|last| now.duration_since(*last) < cooldown)
+        {
+            return None;
+        }
+        fired.insert(key, now);
+
+        Some(QuotaAlert {
+            chain: chain.to_string(),
+            backend: host_of(url),
+            key: key_hint(url),
+            threshold,
+            used,
+            limit,
+            window_secs: window.as_secs(),
+        })
+    }
+
+    pub async fn send(&self, alert: &QuotaAlert) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&self.webhook.url)
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(alert).unwrap())
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("HTTP {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Compares the budgets of `chain`, refilled every `window`, against the
+/// thresholds and posts an alert for each one crossed.
+pub async fn monitor(
+    chain: String,
+    round_robin: Arc<RoundRobin>,
+    notifier: Arc<QuotaNotifier>,
+    window: Duration,
+) {
+    loop {
+        for (url, used, limit) in round_robin.window_usage() {
+            let Some(mut alert) = notifier.crossed(&chain, &url, used, limit, window) else {
+                continue;
+            };
+            alert.backend = round_robin.label_of(&url);
+            println!(
+                "Backend {} of chain {} used {}% of its budget",
+                alert.backend, chain, alert.threshold
+            );
+            if let Err(e) = notifier.send(&alert).await {
+                println!("Failed to send quota alert: {}", e);
+            }
+        }
+        time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, routing::post, Json, Router};
+    use serde_json::Value;
+    use tokio::sync::mpsc;
+
+    fn notifier(url: String) -> QuotaNotifier {
+        QuotaNotifier::new(QuotaWebhook {
+            url,
+            thresholds: default_thresholds(),
+            cooldown_secs: 300,
+        })
+    }
+
+    #[test]
+    fn test_thresholds_fire_once_per_cooldown() {
+        let notifier = notifier("http://127.0.0.1:1".to_string());
+        let window = Duration::from_secs(5);
+        let url = "https://eth-sepolia.g.alchemy.com/v2/abcdef123456";
+
+        assert!(notifier.crossed("sepolia", url, 7, 10, window).is_none());
+        let alert = notifier.crossed("sepolia", url, 8, 10, window).unwrap();
+        assert_eq!(alert.threshold, 80);
+        assert_eq!(alert.key, "...3456");
+        assert!(notifier.crossed("sepolia", url, 9, 10, window).is_none());
+        assert_eq!(
+            notifier
+                .crossed("sepolia", url, 10, 10, window)
+                .unwrap()
+                .threshold,
+            100
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alert_is_posted() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let app = Router::new()
+            .route(
+                "/",
+                post(
+                    |State(sender): State<mpsc::Sender<Value>>, Json(body): Json<Value>| async move {
+                        sender.send(body).await.unwrap();
+                    },
+                ),
+            )
+            .with_state(sender);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let notifier = notifier(url);
+        let alert = notifier
+            .crossed(
+                "sepolia",
+                "https://1rpc.io/sepolia",
+                19,
+                20,
+                Duration::from_secs(5),
+            )
+            .unwrap();
+        notifier.send(&alert).await.unwrap();
+
+        let body = receiver.recv().await.unwrap();
+        assert_eq!(body["backend"], "1rpc.io");
+        assert_eq!(body["threshold"], 95);
+        assert_eq!(body["window_secs"], 5);
+    }
+}
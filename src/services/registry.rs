@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use toml::Table;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `[server.registry]` section: a key of Consul's KV store or of etcd
+/// holding a TOML document of `[chains]`, shared by a fleet of balancers.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RegistryConfig {
+    Consul {
+        /// E.g. `http://127.0.0.1:8500`.
+        address: String,
+        key: String,
+        /// ACL token sent as `X-Consul-Token`.
+        token: Option<String>,
+    },
+    Etcd {
+        /// E.g. `http://127.0.0.1:2379`, the JSON gateway of etcd v3.
+        address: String,
+        key: String,
+    },
+}
+
+impl RegistryConfig {
+    /// Reads the `[chains]` document currently stored in the registry.
+    pub async fn fetch(&self) -> Result<Table, String> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let (key, content) = match self {
+            RegistryConfig::Consul {
+                address,
+                key,
+                token,
+            } => {
+                let mut request = client
+                    .get(format!("{}/v1/kv/{}", address.trim_end_matches('/'), key))
+                    .query(&[("raw", "true")]);
+                if let Some(token) = token {
+                    request = request.header("X-Consul-Token", token);
+                }
+                let response = request.send().await.map_err(|e| e.to_string())?;
+                match response.status() {
+                    StatusCode::OK => {}
+                    StatusCode::NOT_FOUND => return Err(format!("Consul has no key {}", key)),
+                    status => return Err(format!("Reading Consul key {}: HTTP {}", key, status)),
+                }
+                let body = response.bytes().await.map_err(|e| e.to_string())?;
+                (key, body.to_vec())
+            }
+            RegistryConfig::Etcd { address, key } => {
+                let response = client
+                    .post(format!("{}/v3/kv/range", address.trim_end_matches('/')))
+                    .header("Content-Type", "application/json")
+                    .body(json!({ "key": STANDARD.encode(key) }).to_string())
+                    .send()
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let status = response.status();
+                if !status.is_success() {
+                    return Err(format!("Reading etcd key {}: HTTP {}", key, status));
+                }
+                let body = response.bytes().await.map_err(|e| e.to_string())?;
+                let range: Value = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+                let value = range["kvs"][0]["value"]
+                    .as_str()
+                    .ok_or_else(|| format!("etcd has no key {}", key))?;
+                let value = STANDARD.decode(value).map_err(|e| e.to_string())?;
+                (key, value)
+            }
+        };
+        let content = String::from_utf8(content)
+            .map_err(|_| format!("Registry key {} does not hold UTF-8 text", key))?;
+        toml::from_str(&content).map_err(|e| format!("Failed to parse registry key {}: {}", key, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        http::HeaderMap,
+        routing::{get, post},
+        Json, Router,
+    };
+
+    const CHAINS: &str = "[chains.sepolia]\nrpc_urls = [\"https://rpc.example.com\"]\n";
+
+    #[tokio::test]
+    async fn test_fetch() {
+        let app = Router::new()
+            .route(
+                "/v1/kv/rpc_lb/chains",
+                get(|headers: HeaderMap| async move {
+                    match headers.get("X-Consul-Token") {
+                        Some(token) if token == "secret" => (StatusCode::OK, CHAINS),
+                        _ => (StatusCode::FORBIDDEN, ""),
+                    }
+                }),
+            )
+            .route(
+                "/v3/kv/range",
+                post(|Json(range): Json<Value>| async move {
+                    let kvs = match range["key"].as_str() {
+                        Some(key) if key == STANDARD.encode("rpc_lb/chains") => {
+                            json!([{ "key": key, "value": STANDARD.encode(CHAINS) }])
+                        }
+                        _ => json!([]),
+                    };
+                    Json(json!({ "kvs": kvs }))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let expected: Table = toml::from_str(CHAINS).unwrap();
+        let consul = RegistryConfig::Consul {
+            address: address.clone(),
+            key: "rpc_lb/chains".to_string(),
+            token: Some("secret".to_string()),
+        };
+        assert_eq!(consul.fetch().await.unwrap(), expected);
+
+        let etcd = RegistryConfig::Etcd {
+            address: address.clone(),
+            key: "rpc_lb/chains".to_string(),
+        };
+        assert_eq!(etcd.fetch().await.unwrap(), expected);
+        let missing = RegistryConfig::Etcd {
+            address,
+            key: "rpc_lb/other".to_string(),
+        };
+        assert!(missing.fetch().await.is_err());
+    }
+}
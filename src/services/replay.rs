@@ -0,0 +1,92 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::algorithms::routing::MethodCategory;
+
+/// How long a request which may have reached a backend is not retried.
+const REPLAY_WINDOW: Duration = Duration::from_secs(120);
+
+/// Whether sending a request to `method` twice has the effect of sending it
+/// once. Writes such as `eth_sendRawTransaction` are not.
+pub fn is_idempotent(method: &str) -> bool {
+    MethodCategory::of(method) != MethodCategory::Write
+}
+
+/// Fingerprints of non-idempotent requests forwarded recently whose outcome
+/// is unknown, e.g. timed out after they were sent. Retries of them are
+/// refused for a while, instead of silently sending them again.
+#[derive(Debug, Default)]
+pub struct ReplayGuard {
+    sent: Mutex<HashMap<u64, Instant>>,
+}
+
+impl ReplayGuard {
+    /// Guards the request of `body` on `chain`.
+    pub fn guard(self: &Arc<Self>, chain: &str, body: &[u8]) -> Guarded {
+        let mut hasher = DefaultHasher::new();
+        (chain, body).hash(&mut hasher);
+        Guarded {
+            guard: self.clone(),
+            fingerprint: hasher.finish(),
+        }
+    }
+
+    fn record(&self, fingerprint: u64, now: Instant) {
+        let mut sent = self.sent.lock().unwrap();
+        sent.retain(|_, at| *at + REPLAY_WINDOW > now);
+        sent.insert(fingerprint, now);
+    }
+
+    fn may_replay(&self, fingerprint: u64, now: Instant) -> bool {
+        let sent = self.sent.lock().unwrap();
+        sent.get(&fingerprint)
+            .is_none_or(|at| *at + REPLAY_WINDOW <= now)
+    }
+}
+
+/// A request whose retries [`ReplayGuard`] decides on.
+#[derive(Clone, Debug)]
+pub struct Guarded {
+    guard: Arc<ReplayGuard>,
+    fingerprint: u64,
+}
+
+impl Guarded {
+    /// Notes that the request may have reached a backend without an answer.
+    pub fn record(&self) {
+        self.guard.record(self.fingerprint, Instant::now());
+    }
+
+    /// Whether the request may be sent again.
+    pub fn may_replay(&self) -> bool {
+        self.guard.may_replay(self.fingerprint, Instant::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_window() {
+        assert!(is_idempotent("eth_call"));
+        assert!(!is_idempotent("eth_sendRawTransaction"));
+
+        let guard = Arc::new(ReplayGuard::default());
+        let tx = guard.guard("sepolia", b"0x02f8");
+        assert!(tx.may_replay());
+        tx.record();
+        assert!(!tx.may_replay());
+        assert!(!guard.guard("sepolia", b"0x02f8").may_replay());
+        assert!(guard.guard("mainnet", b"0x02f8").may_replay());
+
+        let later = Instant::now() + REPLAY_WINDOW;
+        assert!(guard.may_replay(tx.fingerprint, later));
+        guard.record(0, later);
+        assert_eq!(guard.sent.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::algorithms::round_robin::Chains;
+
+/// The `log` of a backend: the shares of its successful and failed attempts
+/// which are logged, from 0 to 1. Everything is logged by default.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct LogSampling {
+    pub success: f64,
+    pub failure: f64,
+}
+
+impl Default for LogSampling {
+    fn default() -> Self {
+        Self {
+            success: 1.0,
+            failure: 1.0,
+        }
+    }
+}
+
+impl LogSampling {
+    pub fn validate(&self) -> Result<(), String> {
+        for rate in [self.success, self.failure] {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(format!("log sampling rate {} is not between 0 and 1", rate));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Which attempts on each backend are logged, e.g. every failure but a
+/// fraction of the successes of a busy provider, or all of them at one under
+/// investigation. Starts with the backends' `log`, `/admin/request-log`
+/// changes it at runtime.
+#[derive(Debug, Default)]
+pub struct RequestLog {
+    /// Sampling of the backends not logging everything, by chain and backend
+    /// name or host.
+    sampling: Mutex<HashMap<(String, String), LogSampling>>,
+}
+
+impl RequestLog {
+    pub fn new(chains: &HashMap<String, Chains>) -> Self {
+        let sampling = chains
+            .iter()
+            .flat_map(|(chain, config)| {
+                config.rpc_urls.iter().filter_map(move |server| {
+                    let sampling = server.log?;
+                    Some(((chain.clone(), server.label()), sampling))
+                })
+            })
+            .collect();
+        Self {
+            sampling: Mutex::new(sampling),
+        }
+    }
+
+    pub fn sampling(&self, chain: &str, backend: &str) -> LogSampling {
+        self.sampling
+            .lock()
+            .unwrap()
+            .get(&(chain.to_string(), backend.to_string()))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Whether to log an attempt on `backend` of `chain`, drawn by its rate.
+    pub fn should_log(&self, chain: &str, backend: &str, succeeded: bool) -> bool {
+        let sampling = self.sampling(chain, backend);
+        let rate = if succeeded {
+            sampling.success
+        } else {
+            sampling.failure
+        };
+        rate >= 1.0 || rand::random::<f64>() < rate
+    }
+
+    pub fn set(&self, chain: &str, backend: &str, sampling: LogSampling) -> Result<(), String> {
+        sampling.validate()?;
+        let key = (chain.to_string(), backend.to_string());
+        let mut rates = self.sampling.lock().unwrap();
+        if sampling == LogSampling::default() {
+            rates.remove(&key);
+        } else {
+            rates.insert(key, sampling);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::RpcServer;
+
+    #[test]
+    fn test_request_log() {
+        let chains = HashMap::from([(
+            "sepolia".to_string(),
+            Chains {
+                rpc_urls: vec![RpcServer {
+                    url: "https://rpc.sepolia.org".to_string(),
+                    log: Some(LogSampling {
+                        success: 0.0,
+                        failure: 1.0,
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+        )]);
+        let log = RequestLog::new(&chains);
+        assert!(!log.should_log("sepolia", "rpc.sepolia.org", true));
+        assert!(log.should_log("sepolia", "rpc.sepolia.org", false));
+        assert!(log.should_log("sepolia", "sepolia.drpc.org", true));
+
+        log.set("sepolia", "rpc.sepolia.org", LogSampling::default())
+            .unwrap();
+        assert!(log.should_log("sepolia", "rpc.sepolia.org", true));
+        let invalid = LogSampling {
+            success: 2.0,
+            failure: 1.0,
+        };
+        assert!(log.set("sepolia", "rpc.sepolia.org", invalid).is_err());
+    }
+}
@@ -0,0 +1,413 @@
+use axum::body::{Body, Bytes};
+use futures_util::StreamExt;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName},
+    Response as ReqwestResponse,
+};
+use serde_json::Value;
+
+/// Fields the `result` of well known methods always contains.
+const BUILTIN_SCHEMAS: [(&str, &[&str]); 6] = [
+    ("eth_getBlockByNumber", &["hash", "number", "parentHash"]),
+    ("eth_getBlockByHash", &["hash", "number", "parentHash"]),
+    ("eth_getTransactionByHash", &["hash", "from", "nonce"]),
+    (
+        "eth_getTransactionReceipt",
+        &["transactionHash", "blockNumber", "status"],
+    ),
+    ("eth_getLogs", &[]),
+    ("eth_feeHistory", &["baseFeePerGas", "oldestBlock"]),
+];
+
+pub fn builtin_schema(method: &str) -> Option<&'static [&'static str]> {
+    BUILTIN_SCHEMAS
+        .iter()
+        .find(|(name, _)| *name == method)
+        .map(|(_, fields)| *fields)
+}
+
+/// Body bytes of a JSON-RPC response scanned before it is accepted. Whatever
+/// comes after is scanned while it streams to the client.
+const SCANNED_AHEAD: usize = 64 * 1024;
+
+/// Checks run on a successful upstream response before it is accepted. A
+/// response failing them counts as a failed attempt of its backend.
+#[derive(Debug, Default)]
+pub struct ResponseChecks {
+    /// Reject bodies which are empty or not JSON, such as the HTML error
+    /// pages some providers serve with a `200`. See [`scan_json`].
+    pub json_rpc: bool,
+    /// Fields the `result` has to contain, see [`validate_shape`].
+    pub required_fields: Option<Vec<String>>,
+}
+
+impl ResponseChecks {
+    pub async fn inspect(&self, response: ReqwestResponse) -> Result<ReqwestResponse, String> {
+        let Some(required_fields) = &self.required_fields else {
+            if self.json_rpc {
+                return scan_json(response).await;
+            }
+            return Ok(response);
+        };
+
+        let (status, headers) = (response.status(), response.headers().clone());
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read upstream response: {}", e))?;
+        validate_shape(&body, required_fields)?;
+
+        // The body was consumed to look at it, hand on a response holding it.
+        let mut rebuilt = axum::http::Response::new(body);
+        *rebuilt.status_mut() = status;
+        *rebuilt.headers_mut() = headers;
+        Ok(ReqwestResponse::from(rebuilt))
+    }
+}
+
+/// Scans the first [`SCANNED_AHEAD`] bytes of the body with a [`JsonScanner`]
+/// and hands on a response streaming the whole body. The rest is scanned as
+/// it streams, a body turning out not to be JSON is cut off there rather than
+/// buffered to the end, which also drops the backend's connection.
+async fn scan_json(mut response: ReqwestResponse) -> Result<ReqwestResponse, String> {
+    let (status, headers) = (response.status(), response.headers().clone());
+    let mut scanner = JsonScanner::default();
+    let mut read = Vec::new();
+    let mut scanned = 0;
+    while scanned < SCANNED_AHEAD {
+        let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to read upstream response: {}", e))?
+        else {
+            scanner.finish()?;
+            break;
+        };
+        scanner.feed(&chunk)?;
+        scanned += chunk.len();
+        read.push(chunk);
+    }
+
+    let rest = response.bytes_stream().map(move |chunk| {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        scanner.feed(&chunk).map_err(|e| {
+            println!("Cut off upstream response: {}", e);
+            e
+        })?;
+        Ok::<_, String>(chunk)
+    });
+    let read = futures_util::stream::iter(read.into_iter().map(Ok::<_, String>));
+    let body = reqwest::Body::wrap_stream(read.chain(rest));
+    let mut rebuilt = axum::http::Response::new(body);
+    *rebuilt.status_mut() = status;
+    *rebuilt.headers_mut() = headers;
+    Ok(ReqwestResponse::from(rebuilt))
+}
+
+/// Checks a JSON document chunk by chunk without parsing it: brackets have to
+/// match, strings to be closed and everything else outside strings to be
+/// made of the characters of numbers, literals and separators. That is
+/// enough to tell HTML, plain text or a truncated body from JSON.
+#[derive(Debug, Default)]
+pub struct JsonScanner {
+    /// Open objects and arrays, by their opening byte.
+    open: Vec<u8>,
+    in_string: bool,
+    escaped: bool,
+    started: bool,
+    offset: usize,
+}
+
+impl JsonScanner {
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), String> {
+        for &byte in chunk {
+            self.byte(byte)?;
+            self.offset += 1;
+        }
+        Ok(())
+    }
+
+    /// Checks the document is complete once the body ended.
+    pub fn finish(&self) -> Result<(), String> {
+        match (self.started, self.open.is_empty()) {
+            (false, _) => Err("response body is empty".to_string()),
+            (true, false) => Err(format!(
+                "response is not JSON, it ends after {} bytes with {} unclosed",
+                self.offset,
+                self.open.len()
+            )),
+            (true, true) => Ok(()),
+        }
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), String> {
+        if self.in_string {
+            match byte {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => self.in_string = false,
+                0..=0x1f => return Err(self.unexpected(byte)),
+                _ => {}
+            }
+            return Ok(());
+        }
+        if byte.is_ascii_whitespace() {
+            return Ok(());
+        }
+        if !self.started {
+            if byte != b'{' && byte != b'[' {
+                return Err(format!(
+                    "response is not JSON, it starts with `{}`",
+                    byte.escape_ascii()
+                ));
+            }
+            self.started = true;
+        } else if self.open.is_empty() {
+            return Err(format!(
+                "response is not JSON, `{}` follows the document at byte {}",
+                byte.escape_ascii(),
+                self.offset
+            ));
+        }
+        match byte {
+            b'{' | b'[' => self.open.push(byte),
+            b'}' | b']' => {
+                let opening = if byte == b'}' { b'{' } else { b'[' };
+                if self.open.pop() != Some(opening) {
+                    return Err(self.unexpected(byte));
+                }
+            }
+            b'"' => self.in_string = true,
+            b',' | b':' | b'-' | b'+' | b'.' | b'0'..=b'9' => {}
+            b'a' | b'e' | b'f' | b'l' | b'n' | b'r' | b's' | b't' | b'u' | b'E' => {}
+            _ => return Err(self.unexpected(byte)),
+        }
+        Ok(())
+    }
+
+    fn unexpected(&self, byte: u8) -> String {
+        format!(
+            "response is not JSON, unexpected `{}` at byte {}",
+            byte.escape_ascii(),
+            self.offset
+        )
+    }
+}
+
+/// Accepts JSON-RPC error responses and `null` results as they are, any
+/// other `result` must be an object holding every field of `required_fields`.
+/// An empty list only requires the result to be present.
+pub fn validate_shape(body: &[u8], required_fields: &[String]) -> Result<(), String> {
+    let response: Value =
+        serde_json::from_slice(body).map_err(|_| "response is not valid JSON".to_string())?;
+    if response.get("error").is_some() {
+        return Ok(());
+    }
+
+    let result = response
+        .get("result")
+        .ok_or_else(|| "response has neither result nor error".to_string())?;
+    if result.is_null() || required_fields.is_empty() {
+        return Ok(());
+    }
+
+    let missing: Vec<&str> = required_fields
+        .iter()
+        .filter(|field| result.get(field.as_str()).is_none())
+        .map(String::as_str)
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("result is missing {}", missing.join(", ")))
+    }
+}
+
+/// Headers describing a single connection, which a proxy must not pass on.
+const HOP_BY_HOP: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Copies the upstream headers worth passing to the client. Hop-by-hop
+/// headers, including the ones named in `Connection`, are dropped, as are
+/// the content type and length which the balancer sets itself.
+pub fn end_to_end_headers(upstream: &HeaderMap) -> HeaderMap {
+    let connection_listed: Vec<String> = upstream
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+
+    upstream
+        .iter()
+        .filter(|(name, _)| {
+            !HOP_BY_HOP.contains(name)
+                && **name != header::CONTENT_TYPE
+                && **name != header::CONTENT_LENGTH
+                && !connection_listed
+                    .iter()
+                    .any(|listed| listed == name.as_str())
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+/// Whether the upstream labelled its response as JSON.
+pub fn claims_json(upstream: &HeaderMap) -> bool {
+    upstream
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("json"))
+}
+
+/// Returns an error when the declared length is already over `max_bytes`.
+pub fn check_declared_size(
+    response: &ReqwestResponse,
+    max_bytes: Option<usize>,
+) -> Result<(), String> {
+    // Read from the header, the body of an inspected response is re-streamed.
+    let declared = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+    match (declared, max_bytes) {
+        (Some(length), Some(max_bytes)) if length > max_bytes as u64 => Err(too_large(max_bytes)),
+        _ => Ok(()),
+    }
+}
+
+/// Streams the response body, aborting it once more than `max_bytes` came in.
+pub fn limited_body(response: ReqwestResponse, max_bytes: Option<usize>) -> Body {
+    let mut received = 0;
+    Body::from_stream(response.bytes_stream().map(move |chunk| {
+        let chunk = chunk.map_err(|e| e.to_string())?;
+        received += chunk.len();
+        match max_bytes {
+            Some(max_bytes) if received > max_bytes => Err(too_large(max_bytes)),
+            _ => Ok(chunk),
+        }
+    }))
+}
+
+/// Buffers the response body, giving up once more than `max_bytes` came in.
+pub async fn read_limited(
+    mut response: ReqwestResponse,
+    max_bytes: Option<usize>,
+) -> Result<Bytes, String> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to read upstream response: {}", e))?
+    {
+        body.extend_from_slice(&chunk);
+        if let Some(max_bytes) = max_bytes.filter(|&max_bytes| body.len() > max_bytes) {
+            return Err(too_large(max_bytes));
+        }
+    }
+    Ok(Bytes::from(body))
+}
+
+fn too_large(max_bytes: usize) -> String {
+    format!("Upstream response exceeds the limit of {} bytes", max_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_validate_shape() {
+        let fields = vec!["hash".to_string(), "number".to_string()];
+        assert!(validate_shape(br#"{"result":{"hash":"0x1","number":"0x2"}}"#, &fields).is_ok());
+        assert!(validate_shape(br#"{"result":null}"#, &fields).is_ok());
+        assert!(validate_shape(br#"{"error":{"code":-32000}}"#, &fields).is_ok());
+        assert_eq!(
+            validate_shape(br#"{"result":{"hash":"0x1"}}"#, &fields),
+            Err("result is missing number".to_string())
+        );
+        assert!(validate_shape(b"<html>Bad gateway</html>", &fields).is_err());
+        assert!(validate_shape(br#"{"id":1}"#, &[]).is_err());
+    }
+
+    #[test]
+    fn test_json_scanner() {
+        let scan = |chunks: &[&str]| {
+            let mut scanner = JsonScanner::default();
+            for chunk in chunks {
+                scanner.feed(chunk.as_bytes())?;
+            }
+            scanner.finish()
+        };
+        assert!(scan(&[r#" {"id":1,"result":{"a":[true,null,-1.5e3]}}"#, "\n"]).is_ok());
+        assert!(scan(&[r#"{"result":"<html> \"quoted\" ]}"}"#]).is_ok());
+        assert!(scan(&[r#"[{"id":"#, r#"1},{"id":2}]"#]).is_ok());
+        assert_eq!(
+            scan(&["<html>"]),
+            Err("response is not JSON, it starts with `<`".to_string())
+        );
+        assert!(scan(&[""]).is_err());
+        assert!(scan(&[r#"{"result":"0x1""#]).is_err());
+        assert!(scan(&[r#"{"result":[1}"#]).is_err());
+        assert!(scan(&[r#"{"result":1}<br>"#]).is_err());
+        assert!(scan(&["{\"result\":\"line\nbreak\"}"]).is_err());
+    }
+
+    fn streamed(chunks: Vec<String>) -> ReqwestResponse {
+        let stream = futures_util::stream::iter(chunks.into_iter().map(Ok::<_, String>));
+        ReqwestResponse::from(axum::http::Response::new(reqwest::Body::wrap_stream(
+            stream,
+        )))
+    }
+
+    #[tokio::test]
+    async fn test_scan_json() {
+        let valid = format!(r#"{{"result":"{}"}}"#, "a".repeat(SCANNED_AHEAD));
+        let response = scan_json(streamed(vec![valid.clone()])).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), valid);
+
+        let error = scan_json(streamed(vec!["<html>".to_string()])).await;
+        assert!(error.is_err());
+
+        // Past the scanned bytes the response is accepted, and cut off once
+        // it turns out not to be JSON.
+        let chunks = vec![
+            format!("[{}", "1,".repeat(SCANNED_AHEAD)),
+            "<html>".to_string(),
+            "1]".to_string(),
+        ];
+        let response = scan_json(streamed(chunks)).await.unwrap();
+        assert!(response.bytes().await.is_err());
+    }
+
+    #[test]
+    fn test_hop_by_hop_headers_are_stripped() {
+        let mut upstream = HeaderMap::new();
+        upstream.insert(header::CONNECTION, HeaderValue::from_static("x-trace"));
+        upstream.insert("x-trace", HeaderValue::from_static("1"));
+        upstream.insert(
+            header::TRANSFER_ENCODING,
+            HeaderValue::from_static("chunked"),
+        );
+        upstream.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        upstream.insert("x-ratelimit-remaining", HeaderValue::from_static("10"));
+
+        let headers = end_to_end_headers(&upstream);
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers["x-ratelimit-remaining"], "10");
+        assert!(claims_json(&upstream));
+    }
+}
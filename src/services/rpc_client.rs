@@ -0,0 +1,88 @@
+use serde_json::{json, Value};
+
+use crate::{
+    algorithms::round_robin::RoundRobin,
+    transport::{Sent, UpstreamRequest},
+};
+
+/// Sends a single JSON-RPC call to `url` and returns the `result` field.
+///
+/// Used by background services and aggregation endpoints which talk to
+/// backends directly instead of going through the round robin.
+pub async fn call(
+    client: &reqwest::Client,
+    url: &str,
+    method: &str,
+    params: Value,
+) -> Result<Value, String> {
+    let response = client
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(payload(method, params).to_string())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    result_of(response).await
+}
+
+/// Sends a single JSON-RPC call to the server at `url` of `round_robin`
+/// through the server's own transport, so websocket and IPC backends,
+/// `host_header` and `connect_to` apply as for forwarded requests. Takes no
+/// request token.
+pub async fn call_server(
+    round_robin: &RoundRobin,
+    url: &str,
+    method: &str,
+    params: Value,
+) -> Result<Value, String> {
+    let transport = round_robin
+        .transport_for(url)
+        .ok_or_else(|| "unknown backend".to_string())?;
+    let request = UpstreamRequest::json(payload(method, params).to_string());
+    let response = transport
+        .send(
+            url,
+            &request,
+            round_robin.timeout_for(url),
+            &Sent::default(),
+        )
+        .await
+        .map_err(|e| e.message)?;
+    result_of(response).await
+}
+
+fn payload(method: &str, params: Value) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+        "id": 1,
+    })
+}
+
+async fn result_of(response: reqwest::Response) -> Result<Value, String> {
+    let status = response.status();
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    let body: Value = serde_json::from_slice(&bytes).map_err(|e| e.to_string())?;
+
+    if let Some(error) = body.get("error") {
+        return Err(error.to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+
+    Ok(body.get("result").cloned().unwrap_or(Value::Null))
+}
+
+/// Whether an error of [`call`] or [`call_server`] is the error object a
+/// backend answered with, rather than a transport or HTTP failure.
+pub fn is_rpc_error(error: &str) -> bool {
+    serde_json::from_str::<Value>(error).is_ok_and(|error| error.is_object())
+}
+
+/// Parses a hex quantity such as `"0x1b4"` into a `u64`.
+pub fn parse_quantity(value: &Value) -> Option<u64> {
+    let hex = value.as_str()?.strip_prefix("0x")?;
+    u64::from_str_radix(hex, 16).ok()
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use super::probe_report::table;
+use crate::{
+    algorithms::round_robin::{LoadBalancer, RoundRobin},
+    handlers::load_balancer::load_balancer,
+    transport::{self, Sent, TransportError, TransportFuture, UpstreamRequest, UpstreamTransport},
+};
+
+/// How long slow scripted backends take to answer, past usual timeouts.
+const SLOW: Duration = Duration::from_secs(5);
+
+/// What a scripted backend does with every request it gets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Behavior {
+    Healthy,
+    /// Answers with this HTTP status, e.g. `429`.
+    Status(u16),
+    /// Refuses the connection, the request never reaches it.
+    Down,
+    /// Answers successfully after [`SLOW`].
+    Slow,
+}
+
+/// A failure scenario, scripting the behavior of every backend of a chain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scenario {
+    Healthy,
+    AllBackends429,
+    AllBackends5xx,
+    AllBackendsDown,
+    AllBackendsSlow,
+    FirstBackend429,
+    FirstBackendDown,
+    FirstBackendSlow,
+}
+
+impl Scenario {
+    pub const NAMES: [&'static str; 8] = [
+        "healthy",
+        "all-backends-429",
+        "all-backends-5xx",
+        "all-backends-down",
+        "all-backends-slow",
+        "first-backend-429",
+        "first-backend-down",
+        "first-backend-slow",
+    ];
+
+    /// The behavior of backend `i` of the chain.
+    pub fn behavior(&self, i: usize) -> Behavior {
+        let failing = match self {
+            Scenario::Healthy => return Behavior::Healthy,
+            Scenario::AllBackends429 | Scenario::FirstBackend429 => Behavior::Status(429),
+            Scenario::AllBackends5xx => Behavior::Status(502),
+            Scenario::AllBackendsDown | Scenario::FirstBackendDown => Behavior::Down,
+            Scenario::AllBackendsSlow | Scenario::FirstBackendSlow => Behavior::Slow,
+        };
+        let first_only = matches!(
+            self,
+            Scenario::FirstBackend429 | Scenario::FirstBackendDown | Scenario::FirstBackendSlow
+        );
+        if first_only && i > 0 {
+            Behavior::Healthy
+        } else {
+            failing
+        }
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        let scenarios = [
+            Scenario::Healthy,
+            Scenario::AllBackends429,
+            Scenario::AllBackends5xx,
+            Scenario::AllBackendsDown,
+            Scenario::AllBackendsSlow,
+            Scenario::FirstBackend429,
+            Scenario::FirstBackendDown,
+            Scenario::FirstBackendSlow,
+        ];
+        Self::NAMES
+            .iter()
+            .position(|known| *known == name)
+            .map(|i| scenarios[i])
+            .ok_or_else(|| {
+                format!(
+                    "Unknown scenario {}, expected one of {}",
+                    name,
+                    Self::NAMES.join(", ")
+                )
+            })
+    }
+}
+
+/// Stands in for a backend, answering as its [`Behavior`] says without any
+/// network traffic.
+#[derive(Debug)]
+struct ScriptedTransport(Behavior);
+
+impl UpstreamTransport for ScriptedTransport {
+    fn send<'a>(
+        &'a self,
+        _url: &'a str,
+        request: &'a UpstreamRequest,
+        timeout: Option<Duration>,
+        sent: &'a Sent,
+    ) -> TransportFuture<'a> {
+        Box::pin(async move {
+            if self.0 == Behavior::Down {
+                return Err(TransportError::connect("connection refused"));
+            }
+            sent.mark();
+            let id = serde_json::from_slice::<Value>(&request.body)
+                .ok()
+                .and_then(|request| request.get("id").cloned())
+                .unwrap_or(Value::Null);
+            let (status, body) = match self.0 {
+                Behavior::Status(status) => (
+                    StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY),
+                    json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32005, "message": "scripted failure"}}),
+                ),
+                _ => (
+                    StatusCode::OK,
+                    json!({"jsonrpc": "2.0", "id": id, "result": "0x1"}),
+                ),
+            };
+            if self.0 == Behavior::Slow {
+                let slow = tokio::time::sleep(SLOW);
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, slow)
+                        .await
+                        .map_err(|_| TransportError::request("operation timed out"))?,
+                    None => slow.await,
+                }
+            }
+            Ok(transport::response_with(
+                status,
+                body.to_string().into_bytes(),
+            ))
+        })
+    }
+}
+
+/// How the balancer handled one simulated request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    pub status: u16,
+    /// Backend that served the request, none when it failed.
+    pub served_by: Option<String>,
+    pub attempts: usize,
+    pub elapsed: Duration,
+    /// The tries of a failed request and why it failed.
+    pub detail: String,
+}
+
+/// Sends `requests` calls to `chain` of `lb` through the regular forwarding,
+/// with every backend replaced by a scripted one behaving as `scenario`
+/// says. Limits, retries and timeouts are the chain's own.
+pub async fn simulate(
+    lb: &LoadBalancer,
+    chain: &str,
+    scenario: Scenario,
+    requests: usize,
+) -> Result<Vec<Decision>, String> {
+    let round_robin = lb
+        .load_balancers
+        .get(chain)
+        .ok_or_else(|| format!("Unknown chain {}", chain))?;
+    let mut scripted = RoundRobin::clone(round_robin);
+    scripted.transports = Arc::new(
+        (0..scripted.endpoints.len())
+            .map(|i| {
+                Arc::new(ScriptedTransport(scenario.behavior(i))) as Arc<dyn UpstreamTransport>
+            })
+            .collect(),
+    );
+    let mut settings = lb.chains.get(chain).cloned().unwrap_or_default();
+    settings.debug_headers = true;
+    settings.report_attempts = true;
+
+    let mut lb = lb.clone();
+    lb.load_balancers = Arc::new(HashMap::from([(chain.to_string(), Arc::new(scripted))]));
+    lb.chains = Arc::new(HashMap::from([(chain.to_string(), settings)]));
+    let lb = Arc::new(lb);
+
+    let mut decisions = Vec::new();
+    for id in 0..requests {
+        let body = json!({"jsonrpc": "2.0", "method": "eth_blockNumber", "params": [], "id": id});
+        let request = axum::http::Request::post(format!("/{}", chain))
+            .header("Content-Type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let started = Instant::now();
+        let Ok(response) = load_balancer(Path(chain.to_string()), State(lb.clone()), request).await;
+        let elapsed = started.elapsed();
+
+        let header = |name: &str| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let served_by = header("X-Served-By");
+        let served_attempts = header("X-Upstream-Attempts");
+        let status = response.status().as_u16();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|e| e.to_string())?;
+        let report: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        let tries = report["attempts"].as_array().cloned().unwrap_or_default();
+        let attempts = match served_attempts {
+            Some(attempts) => attempts.parse().unwrap_or_default(),
+            None => tries.iter().filter(|a| !a["backend"].is_null()).count(),
+        };
+        let detail = match served_by {
+            Some(_) => String::new(),
+            None => describe(&report, &tries, &body),
+        };
+        decisions.push(Decision {
+            status,
+            served_by,
+            attempts,
+            elapsed,
+            detail,
+        });
+    }
+    Ok(decisions)
+}
+
+/// Sums up a failed request: its failure reason and every try made.
+fn describe(report: &Value, tries: &[Value], body: &[u8]) -> String {
+    let Some(reason) = report["reason"].as_str() else {
+        return String::from_utf8_lossy(body).into_owned();
+    };
+    let tries: Vec<String> = tries
+        .iter()
+        .map(|attempt| {
+            let outcome = match (&attempt["status"], &attempt["error"]) {
+                (Value::Number(status), _) => status.to_string(),
+                (_, Value::String(error)) => error.clone(),
+                _ => "failed".to_string(),
+            };
+            match attempt["backend"].as_str() {
+                Some(backend) => format!("{} {}", backend, outcome),
+                None => outcome,
+            }
+        })
+        .collect();
+    format!("{}: {}", reason, tries.join(", "))
+}
+
+/// Lays the decisions out as a table for the terminal.
+pub fn render(decisions: &[Decision]) -> String {
+    let mut lines = vec![[
+        "REQUEST".to_string(),
+        "STATUS".to_string(),
+        "SERVED BY".to_string(),
+        "ATTEMPTS".to_string(),
+        "TIME".to_string(),
+        "DETAIL".to_string(),
+    ]];
+    for (i, decision) in decisions.iter().enumerate() {
+        lines.push([
+            (i + 1).to_string(),
+            decision.status.to_string(),
+            decision
+                .served_by
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+            decision.attempts.to_string(),
+            format!("{}ms", decision.elapsed.as_millis()),
+            decision.detail.clone(),
+        ]);
+    }
+    table(&lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::{Chains, RpcServer};
+
+    fn balancer() -> LoadBalancer {
+        let servers: Vec<_> = ["https://a.example.com", "https://b.example.com"]
+            .into_iter()
+            .map(|url| RpcServer {
+                url: url.to_string(),
+                request_limit: 10,
+                current_limit: 10,
+                ..Default::default()
+            })
+            .collect();
+        let mut lb = LoadBalancer::new(Arc::new(HashMap::from([(
+            "sepolia".to_string(),
+            Arc::new(RoundRobin::new(servers.clone())),
+        )])));
+        lb.chains = Arc::new(HashMap::from([(
+            "sepolia".to_string(),
+            Chains {
+                rpc_urls: servers,
+                ..Default::default()
+            },
+        )]));
+        lb
+    }
+
+    #[tokio::test]
+    async fn test_simulate() {
+        let lb = balancer();
+        let scenario: Scenario = "first-backend-down".parse().unwrap();
+        let decisions = simulate(&lb, "sepolia", scenario, 4).await.unwrap();
+        assert!(decisions.iter().all(|decision| decision.status == 200));
+        assert!(decisions
+            .iter()
+            .all(|decision| decision.served_by.as_deref() == Some("b.example.com")));
+
+        let decisions = simulate(&lb, "sepolia", Scenario::AllBackends429, 1)
+            .await
+            .unwrap();
+        assert_eq!(decisions[0].status, 503);
+        assert_eq!(decisions[0].attempts, 2);
+        assert!(decisions[0].detail.contains("a.example.com 429"));
+        assert!(render(&decisions).starts_with("REQUEST  STATUS"));
+
+        assert!("all-backends-418".parse::<Scenario>().is_err());
+    }
+}
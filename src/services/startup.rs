@@ -0,0 +1,161 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::{task::JoinSet, time};
+
+use crate::{
+    algorithms::round_robin::RoundRobin,
+    transport::{Sent, UpstreamRequest},
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Pause between probe rounds of a chain that is not ready yet.
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How the server treats chains whose backends can not be reached at startup.
+/// Without one, chains are served right away and never probed.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StartupMode {
+    /// Probe every backend first and refuse to start while a chain has none
+    /// usable.
+    FailFast,
+    /// Start right away, chains answer `503` until one of their backends
+    /// answered a probe.
+    Lazy,
+}
+
+/// Sends a JSON-RPC call to `url` through the backend's transport.
+///
+/// Any HTTP response counts, error responses included, since not every chain
+/// speaks the probed method. Only unreachable backends and statuses saying
+/// the url or its key is wrong make it unusable.
+pub async fn probe(round_robin: &RoundRobin, url: &str) -> Result<(), String> {
+    let transport = round_robin
+        .transport_for(url)
+        .ok_or_else(|| "unknown backend".to_string())?;
+    let request = UpstreamRequest::json(
+        json!({"jsonrpc": "2.0", "method": "eth_chainId", "params": [], "id": 1}).to_string(),
+    );
+
+    let status = transport
+        .send(url, &request, Some(PROBE_TIMEOUT), &Sent::default())
+        .await
+        .map_err(|e| e.message)?
+        .status();
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => {
+            Err(format!("HTTP {}", status))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Probes every backend of a chain concurrently and returns how many of them
+/// are usable. Probes do not take tokens from the request limits.
+pub async fn usable_backends(chain: &str, round_robin: &Arc<RoundRobin>) -> usize {
+    let mut probes = JoinSet::new();
+    for url in round_robin.server_urls() {
+        let round_robin = round_robin.clone();
+        probes.spawn(async move {
+            let result = probe(&round_robin, &url).await;
+            (url, result)
+        });
+    }
+
+    let mut usable = 0;
+    while let Some(result) = probes.join_next().await {
+        match result {
+            Ok((_, Ok(()))) => usable += 1,
+            Ok((url, Err(e))) => {
+                println!(
+                    "Backend {} of chain {} is unusable: {}",
+                    round_robin.label_of(&url),
+                    chain,
+                    e
+                )
+            }
+            Err(_) => {}
+        }
+    }
+    usable
+}
+
+/// Probes all chains and fails naming those without a usable backend.
+pub async fn check_all(load_balancers: &HashMap<String, Arc<RoundRobin>>) -> Result<(), String> {
+    let mut unusable = Vec::new();
+    for (chain, round_robin) in load_balancers {
+        if usable_backends(chain, round_robin).await == 0 {
+            unusable.push(chain.clone());
+        }
+    }
+    if unusable.is_empty() {
+        return Ok(());
+    }
+    unusable.sort();
+    Err(format!(
+        "No usable backend for chain(s) {}, refusing to start",
+        unusable.join(", ")
+    ))
+}
+
+/// Keeps probing a chain marked not ready until one of its backends is
+/// usable, then marks it ready.
+pub async fn wait_until_ready(chain: String, round_robin: Arc<RoundRobin>) {
+    while usable_backends(&chain, &round_robin).await == 0 {
+        time::sleep(PROBE_INTERVAL).await;
+    }
+    println!("Chain {} is ready", chain);
+    round_robin.set_ready(true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::RpcServer;
+    use axum::{http::StatusCode as AxumStatus, routing::post, Router};
+
+    async fn spawn_upstream(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    fn round_robin(urls: Vec<String>) -> Arc<RoundRobin> {
+        let servers = urls
+            .into_iter()
+            .map(|url| RpcServer {
+                url,
+                ..Default::default()
+            })
+            .collect();
+        Arc::new(RoundRobin::new(servers))
+    }
+
+    #[tokio::test]
+    async fn test_typoed_backends_are_unusable() {
+        // Method errors still prove the backend is there.
+        let usable = spawn_upstream(Router::new().route(
+            "/",
+            post(|| async { (AxumStatus::BAD_REQUEST, "unknown method") }),
+        ))
+        .await;
+        let missing = spawn_upstream(Router::new()).await;
+
+        let chains = HashMap::from([
+            (
+                "sepolia".to_string(),
+                round_robin(vec![usable, missing.clone()]),
+            ),
+            ("holesky".to_string(), round_robin(vec![missing])),
+        ]);
+        assert_eq!(usable_backends("sepolia", &chains["sepolia"]).await, 1);
+        assert_eq!(
+            check_all(&chains).await,
+            Err("No usable backend for chain(s) holesky, refusing to start".to_string())
+        );
+    }
+}
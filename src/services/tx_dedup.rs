@@ -0,0 +1,204 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::body::Bytes;
+use serde_json::Value;
+use tokio::sync::watch;
+
+use crate::services::tx_rebroadcast;
+
+type Key = (String, String);
+
+/// Responses to raw transaction submissions, kept for the chain's
+/// `tx_dedup_secs` so that resubmissions within it, e.g. of retrying
+/// clients, are answered with the original response instead of spending
+/// provider quota again.
+///
+/// Identical raw transactions are identical by hash, entries are keyed by
+/// the raw transaction so nothing needs decoding. Identical submissions
+/// arriving while the first is still being forwarded wait for its response.
+#[derive(Debug, Default)]
+pub struct TxDedup {
+    /// Expiry and response without `id`, by chain and raw transaction.
+    entries: Mutex<HashMap<Key, (Instant, Value)>>,
+    /// Submissions being forwarded, whose senders are dropped once they are
+    /// answered.
+    pending: Mutex<HashMap<Key, watch::Sender<()>>>,
+}
+
+/// What became of a submission passed to [`TxDedup::submit`].
+pub enum Submission {
+    /// The response an identical submission got.
+    Duplicate(Bytes),
+    /// The submission is to be forwarded. Identical ones wait until this is
+    /// dropped, after its response was passed to [`TxDedup::insert`].
+    First(Pending),
+}
+
+pub struct Pending {
+    dedup: Arc<TxDedup>,
+    key: Key,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.dedup.pending.lock().unwrap().remove(&self.key);
+    }
+}
+
+fn key(chain: &str, raw: &str) -> Key {
+    (chain.to_string(), raw.to_lowercase())
+}
+
+impl TxDedup {
+    /// Answers the submission of `raw` with the response an identical one
+    /// got, once that is forwarded if it still is. Submissions whose
+    /// response was not kept, e.g. as the backend failed, are forwarded
+    /// again, one at a time.
+    pub async fn submit(self: &Arc<Self>, chain: &str, raw: &str, id: &Value) -> Submission {
+        let key = key(chain, raw);
+        loop {
+            if let Some(body) = self.get(chain, raw, id) {
+                return Submission::Duplicate(body);
+            }
+            let mut answered = match self.pending.lock().unwrap().entry(key.clone()) {
+                Entry::Occupied(pending) => pending.get().subscribe(),
+                Entry::Vacant(pending) => {
+                    pending.insert(watch::Sender::new(()));
+                    return Submission::First(Pending {
+                        dedup: self.clone(),
+                        key,
+                    });
+                }
+            };
+            // Only ends once the sender is dropped.
+            let _ = answered.changed().await;
+        }
+    }
+
+    /// The response the submission of `raw` got, answering the request `id`.
+    pub fn get(&self, chain: &str, raw: &str, id: &Value) -> Option<Bytes> {
+        let entries = self.entries.lock().unwrap();
+        let (expires, response) = entries.get(&key(chain, raw))?;
+        if *expires <= Instant::now() {
+            return None;
+        }
+        let mut response = response.clone();
+        response["id"] = id.clone();
+        Some(Bytes::from(response.to_string()))
+    }
+
+    /// Keeps the response a backend gave to the submission of `raw` for
+    /// `window`, as long as it is a JSON-RPC result or the error of a node
+    /// already knowing the transaction. Other errors, e.g. of a rate limit or
+    /// a nonce gap, may not hold for a resubmission.
+    pub fn insert(&self, chain: &str, raw: &str, window: Duration, body: &[u8]) {
+        let Ok(response) = serde_json::from_slice::<Value>(body) else {
+            return;
+        };
+        let already_known = response["error"]["message"]
+            .as_str()
+            .is_some_and(tx_rebroadcast::is_already_known);
+        if response.get("result").is_none() && !already_known {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (expires, _)| *expires > now);
+        entries.insert(key(chain, raw), (now + window, response));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_dedup() {
+        let dedup = TxDedup::default();
+        let window = Duration::from_secs(60);
+        dedup.insert(
+            "sepolia",
+            "0x02F8",
+            window,
+            br#"{"jsonrpc":"2.0","id":1,"result":"0xabc"}"#,
+        );
+        dedup.insert("sepolia", "0x02f9", window, b"<html>");
+        dedup.insert(
+            "sepolia",
+            "0x02fb",
+            window,
+            br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32005,"message":"rate limited"}}"#,
+        );
+        dedup.insert(
+            "sepolia",
+            "0x02fc",
+            window,
+            br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"already known"}}"#,
+        );
+
+        let response = dedup.get("sepolia", "0x02f8", &json!(7)).unwrap();
+        let response: Value = serde_json::from_slice(&response).unwrap();
+        assert_eq!(
+            response,
+            json!({"jsonrpc": "2.0", "id": 7, "result": "0xabc"})
+        );
+        assert!(dedup.get("base", "0x02f8", &json!(7)).is_none());
+        assert!(dedup.get("sepolia", "0x02f9", &json!(7)).is_none());
+        assert!(dedup.get("sepolia", "0x02fb", &json!(7)).is_none());
+        assert!(dedup.get("sepolia", "0x02fc", &json!(7)).is_some());
+
+        dedup.insert(
+            "sepolia",
+            "0x02fa",
+            Duration::ZERO,
+            br#"{"jsonrpc":"2.0","id":1,"result":"0xdef"}"#,
+        );
+        assert!(dedup.get("sepolia", "0x02fa", &json!(1)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_submissions_wait_for_the_first() {
+        let dedup = Arc::new(TxDedup::default());
+        let Submission::First(first) = dedup.submit("sepolia", "0x02f8", &json!(1)).await else {
+            panic!("nothing was submitted yet");
+        };
+        let waiting = tokio::spawn({
+            let dedup = dedup.clone();
+            async move {
+                match dedup.submit("sepolia", "0x02f8", &json!(2)).await {
+                    Submission::Duplicate(body) => Some(body),
+                    Submission::First(_) => None,
+                }
+            }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        dedup.insert(
+            "sepolia",
+            "0x02f8",
+            Duration::from_secs(60),
+            br#"{"jsonrpc":"2.0","id":1,"result":"0xabc"}"#,
+        );
+        drop(first);
+        let body = waiting.await.unwrap().unwrap();
+        let response: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response["id"], json!(2));
+
+        // A submission whose response was not kept lets the next one through.
+        let Submission::First(failed) = dedup.submit("sepolia", "0x02f9", &json!(1)).await else {
+            panic!("nothing was submitted yet");
+        };
+        let next = tokio::spawn({
+            let dedup = dedup.clone();
+            async move { dedup.submit("sepolia", "0x02f9", &json!(2)).await }
+        });
+        drop(failed);
+        assert!(matches!(next.await.unwrap(), Submission::First(_)));
+    }
+}
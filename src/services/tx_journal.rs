@@ -0,0 +1,369 @@
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::time;
+
+use super::tx_rebroadcast;
+use crate::algorithms::round_robin::LoadBalancer;
+
+/// Numbers the entries written by this process, balancers of a reload share
+/// the journal.
+static NEXT_ENTRY: AtomicU64 = AtomicU64::new(0);
+
+/// The `[server.tx_journal]` section.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct JournalConfig {
+    /// Directory holding one file per transaction not yet acknowledged.
+    pub dir: String,
+    /// Seconds between replays of the entries left while running, which are
+    /// also how old an entry must be to be replayed, so submissions still in
+    /// flight are left alone.
+    #[serde(default = "default_retry_secs")]
+    pub retry_secs: u64,
+    /// Seconds after which an entry no backend took is dropped instead of
+    /// replayed again.
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+fn default_retry_secs() -> u64 {
+    60
+}
+
+fn default_max_age_secs() -> u64 {
+    24 * 60 * 60
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct Entry {
+    chain: String,
+    raw: String,
+}
+
+/// Raw transactions written to disk before they are forwarded, and removed
+/// once a backend answered their submission. Whatever is left after a crash
+/// is replayed at the next start, and submissions no backend took every
+/// `retry_secs` while running, so every transaction reaches a backend at
+/// least once. Entries still left after `max_age_secs` are dropped.
+#[derive(Debug, Default)]
+pub struct TxJournal {
+    dir: Option<PathBuf>,
+    retry: Duration,
+    max_age: Duration,
+}
+
+impl TxJournal {
+    pub fn new(config: Option<&JournalConfig>) -> Result<Self, String> {
+        let Some(config) = config else {
+            return Ok(Self::default());
+        };
+        if config.retry_secs == 0 {
+            return Err("tx_journal retry_secs should be positive".to_string());
+        }
+        if config.max_age_secs == 0 {
+            return Err("tx_journal max_age_secs should be positive".to_string());
+        }
+        fs::create_dir_all(&config.dir)
+            .map_err(|e| format!("Failed to create tx journal {}: {}", config.dir, e))?;
+        Ok(Self {
+            dir: Some(PathBuf::from(&config.dir)),
+            retry: Duration::from_secs(config.retry_secs),
+            max_age: Duration::from_secs(config.max_age_secs),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// Writes the submission of `raw` on `chain` to disk, returning the file
+    /// to [`acknowledge`](Self::acknowledge) once a backend answered it.
+    pub fn record(&self, chain: &str, raw: &str) -> Result<Option<PathBuf>, String> {
+        let Some(dir) = &self.dir else {
+            return Ok(None);
+        };
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let name = format!(
+            "{}-{}-{}",
+            nanos,
+            std::process::id(),
+            NEXT_ENTRY.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(format!("{}.json", name));
+        // Written aside and renamed, replays never see half an entry.
+        let partial = dir.join(format!("{}.partial", name));
+        let entry = Entry {
+            chain: chain.to_string(),
+            raw: raw.to_string(),
+        };
+        let write = || -> std::io::Result<()> {
+            let mut file = File::create(&partial)?;
+            file.write_all(&serde_json::to_vec(&entry)?)?;
+            file.sync_all()?;
+            fs::rename(&partial, &path)
+        };
+        write().map_err(|e| format!("Failed to journal transaction: {}", e))?;
+        Ok(Some(path))
+    }
+
+    pub fn acknowledge(&self, entry: &Path) {
+        if let Err(e) = fs::remove_file(entry) {
+            println!(
+                "Failed to remove tx journal entry {}: {}",
+                entry.display(),
+                e
+            );
+        }
+    }
+
+    /// The entries left in the journal written at least `min_age` ago,
+    /// oldest first, with their age.
+    fn unacknowledged(&self, min_age: Duration) -> Vec<(PathBuf, Entry, Duration)> {
+        let Some(entries) = self.dir.as_ref().and_then(|dir| fs::read_dir(dir).ok()) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<(PathBuf, Duration)> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .filter_map(|path| {
+                let modified = fs::metadata(&path).and_then(|metadata| metadata.modified());
+                let age = modified.ok()?.elapsed().unwrap_or_default();
+                (age >= min_age).then_some((path, age))
+            })
+            .collect();
+        paths.sort();
+        paths
+            .into_iter()
+            .filter_map(|(path, age)| {
+                let entry = fs::read(&path)
+                    .ok()
+                    .and_then(|content| serde_json::from_slice(&content).ok());
+                if entry.is_none() {
+                    println!("Skipping unreadable tx journal entry {}", path.display());
+                }
+                Some((path, entry?, age))
+            })
+            .collect()
+    }
+}
+
+/// Submits every transaction left in the journal of `lb` at least `min_age`
+/// ago to all backends of its chain, through their transports, dropping the
+/// entries at least one backend accepted or rejected, and those older than
+/// `max_age_secs`.
+pub async fn replay(lb: &LoadBalancer, min_age: Duration) {
+    for (path, entry, age) in lb.tx_journal.unacknowledged(min_age) {
+        if age > lb.tx_journal.max_age {
+            println!(
+                "Dropping journaled transaction on {} no backend took in {}s",
+                entry.chain,
+                age.as_secs()
+            );
+            lb.tx_journal.acknowledge(&path);
+            continue;
+        }
+        let Some(round_robin) = lb.load_balancers.get(&entry.chain) else {
+            println!(
+                "Keeping journaled transaction of unknown chain {}",
+                entry.chain
+            );
+            continue;
+        };
+        let outcome = tx_rebroadcast::broadcast(round_robin, &entry.raw).await;
+        println!(
+            "Replayed journaled transaction on {}, accepted by {} and rejected by {} of {} backends",
+            entry.chain,
+            outcome.accepted,
+            outcome.rejected,
+            round_robin.endpoints.len()
+        );
+        // A rejection, e.g. "nonce too low", won't change with more replays.
+        if outcome.accepted > 0 || outcome.rejected > 0 {
+            lb.tx_journal.acknowledge(&path);
+        }
+    }
+}
+
+/// Replays the entries left while `lb` runs, every `retry_secs`, e.g. those
+/// of a submission every backend failed. The whole journal is replayed once
+/// at startup, see [`replay`].
+pub async fn run(lb: Arc<LoadBalancer>) {
+    let retry = lb.tx_journal.retry;
+    loop {
+        time::sleep(retry).await;
+        replay(&lb, retry).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        algorithms::round_robin::{RoundRobin, RpcServer},
+        transport::{self, Sent, TransportFuture, UpstreamRequest, UpstreamTransport},
+    };
+    use reqwest::StatusCode;
+    use std::{collections::HashMap, sync::Mutex};
+
+    /// Answers every transaction with `answer`, keeping their bodies.
+    #[derive(Debug)]
+    struct Answering {
+        answer: &'static [u8],
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl Answering {
+        fn new(answer: &'static [u8]) -> Arc<Self> {
+            Arc::new(Self {
+                answer,
+                sent: Mutex::default(),
+            })
+        }
+    }
+
+    impl UpstreamTransport for Answering {
+        fn send<'a>(
+            &'a self,
+            _url: &'a str,
+            request: &'a UpstreamRequest,
+            _timeout: Option<Duration>,
+            sent: &'a Sent,
+        ) -> TransportFuture<'a> {
+            sent.mark();
+            let body = String::from_utf8_lossy(&request.body).to_string();
+            self.sent.lock().unwrap().push(body);
+            let answer = self.answer.to_vec();
+            Box::pin(async { Ok(transport::response_with(StatusCode::OK, answer)) })
+        }
+    }
+
+    /// A balancer for sepolia with one backend behind `transport`.
+    fn balancer(journal: TxJournal, transport: Arc<Answering>) -> LoadBalancer {
+        let url = "wss://sepolia.example/ws";
+        let round_robin = RoundRobin::new(vec![RpcServer {
+            url: url.to_string(),
+            ..Default::default()
+        }])
+        .with_transport(url, transport);
+        let mut lb = LoadBalancer::new(Arc::new(HashMap::from([(
+            "sepolia".to_string(),
+            Arc::new(round_robin),
+        )])));
+        lb.tx_journal = Arc::new(journal);
+        lb
+    }
+
+    fn journal(name: &str) -> (PathBuf, TxJournal) {
+        let dir = std::env::temp_dir().join(format!("rpc_lb_{}_{}", name, std::process::id()));
+        let journal = TxJournal::new(Some(&JournalConfig {
+            dir: dir.display().to_string(),
+            retry_secs: 60,
+            max_age_secs: 3600,
+        }))
+        .unwrap();
+        (dir, journal)
+    }
+
+    #[test]
+    fn test_journal() {
+        let (dir, journal) = journal("tx_journal");
+
+        let first = journal.record("sepolia", "0x01").unwrap().unwrap();
+        journal.record("base", "0x02").unwrap().unwrap();
+        journal.acknowledge(&first);
+        let left: Vec<Entry> = journal
+            .unacknowledged(Duration::ZERO)
+            .into_iter()
+            .map(|(_, entry, _)| entry)
+            .collect();
+        assert_eq!(
+            left,
+            vec![Entry {
+                chain: "base".to_string(),
+                raw: "0x02".to_string(),
+            }]
+        );
+
+        // Submissions of the last minute may still be in flight.
+        assert!(journal.unacknowledged(Duration::from_secs(60)).is_empty());
+
+        assert_eq!(TxJournal::default().record("sepolia", "0x01"), Ok(None));
+        assert!(TxJournal::new(Some(&JournalConfig {
+            dir: dir.display().to_string(),
+            retry_secs: 0,
+            max_age_secs: 3600,
+        }))
+        .is_err());
+        assert!(TxJournal::new(Some(&JournalConfig {
+            dir: dir.display().to_string(),
+            retry_secs: 60,
+            max_age_secs: 0,
+        }))
+        .is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_uses_backend_transports() {
+        let (dir, journal) = journal("tx_journal_replay");
+        journal.record("sepolia", "0x02f8").unwrap();
+        let accepting = Answering::new(br#"{"jsonrpc":"2.0","result":"0xabc","id":1}"#);
+        let lb = balancer(journal, accepting.clone());
+
+        replay(&lb, Duration::ZERO).await;
+        let sent = accepting.sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert!(sent[0].contains("0x02f8"));
+        assert!(lb.tx_journal.unacknowledged(Duration::ZERO).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_drops_rejected_and_expired() {
+        let (dir, rejected) = journal("tx_journal_rejected");
+        rejected.record("sepolia", "0x02f8").unwrap();
+        let rejecting = Answering::new(
+            br#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"nonce too low"},"id":1}"#,
+        );
+        let lb = balancer(rejected, rejecting.clone());
+        replay(&lb, Duration::ZERO).await;
+        assert_eq!(rejecting.sent.lock().unwrap().len(), 1);
+        assert!(lb.tx_journal.unacknowledged(Duration::ZERO).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+
+        // Backends answering no JSON-RPC at all leave the entry for later,
+        // until it is older than max_age_secs.
+        let failing = Answering::new(b"<html>Bad Gateway</html>");
+        let (dir, journal) = journal("tx_journal_expired");
+        let entry = journal.record("sepolia", "0x02f8").unwrap().unwrap();
+        let lb = balancer(journal, failing.clone());
+        replay(&lb, Duration::ZERO).await;
+        assert_eq!(lb.tx_journal.unacknowledged(Duration::ZERO).len(), 1);
+
+        File::options()
+            .write(true)
+            .open(&entry)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(7200))
+            .unwrap();
+        replay(&lb, Duration::ZERO).await;
+        assert_eq!(failing.sent.lock().unwrap().len(), 1);
+        assert!(lb.tx_journal.unacknowledged(Duration::ZERO).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::time;
+
+use super::rpc_client;
+use crate::algorithms::round_robin::RoundRobin;
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct RebroadcastConfig {
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+}
+
+fn default_interval_secs() -> u64 {
+    30
+}
+
+fn default_max_age_secs() -> u64 {
+    600
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxState {
+    Pending,
+    Included,
+    Expired,
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackedTx {
+    pub raw: String,
+    pub first_seen: Instant,
+    pub state: TxState,
+    pub block_number: Option<u64>,
+    pub rebroadcasts: u32,
+    pub backends_accepted: usize,
+    pub backends_total: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct TxStatus {
+    pub hash: String,
+    pub state: TxState,
+    pub age_secs: u64,
+    pub block_number: Option<u64>,
+    pub rebroadcasts: u32,
+    pub backends_accepted: usize,
+    pub backends_total: usize,
+}
+
+/// Keeps track of raw transactions proxied through the balancer so they can be
+/// rebroadcast until they show up in a block.
+///
+/// Only chains with a `rebroadcast` section in the config are tracked.
+#[derive(Debug, Default)]
+pub struct TxTracker {
+    chains: HashMap<String, RebroadcastConfig>,
+    txs: Mutex<HashMap<String, HashMap<String, TrackedTx>>>,
+}
+
+impl TxTracker {
+    pub fn new(chains: HashMap<String, RebroadcastConfig>) -> Self {
+        Self {
+            chains,
+            txs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self, chain: &str) -> bool {
+        self.chains.contains_key(chain)
+    }
+
+    pub fn enabled_chains(&self) -> Vec<String> {
+        self.chains.keys().cloned().collect()
+    }
+
+    pub fn track(&self, chain: &str, hash: &str, raw: &str) {
+        if !self.is_enabled(chain) {
+            return;
+        }
+
+        let mut txs = self.txs.lock().unwrap();
+        txs.entry(chain.to_string())
+            .or_default()
+            .entry(hash.to_lowercase())
+            .or_insert_with(|| TrackedTx {
+                raw: raw.to_string(),
+                first_seen: Instant::now(),
+                state: TxState::Pending,
+                block_number: None,
+                rebroadcasts: 0,
+                backends_accepted: 1,
+                backends_total: 1,
+            });
+    }
+
+    pub fn status(&self, chain: &str, hash: &str) -> Option<TxStatus> {
+        let hash = hash.to_lowercase();
+        let txs = self.txs.lock().unwrap();
+        let tx = txs.get(chain)?.get(&hash)?;
+
+        Some(TxStatus {
+            hash,
+            state: tx.state,
+            age_secs: tx.first_seen.elapsed().as_secs(),
+            block_number: tx.block_number,
+            rebroadcasts: tx.rebroadcasts,
+            backends_accepted: tx.backends_accepted,
+            backends_total: tx.backends_total,
+        })
+    }
+
+    fn pending(&self, chain: &str) -> Vec<(String, String, Instant)> {
+        let txs = self.txs.lock().unwrap();
+        txs.get(chain)
+            .map(|chain_txs| {
+                chain_txs
+                    .iter()
+                    .filter(|(_, tx)| tx.state == TxState::Pending)
+                    .map(|(hash, tx)| (hash.clone(), tx.raw.clone(), tx.first_seen))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn update<F: FnOnce(&mut TrackedTx)>(&self, chain: &str, hash: &str, f: F) {
+        let mut txs = self.txs.lock().unwrap();
+        if let Some(tx) = txs
+            .get_mut(chain)
+            .and_then(|chain_txs| chain_txs.get_mut(hash))
+        {
+            f(tx);
+        }
+    }
+
+    /// Drops settled transactions once they are older than twice the max age,
+    /// so their final state stays queryable for a while.
+    fn prune(&self, chain: &str, max_age: Duration) {
+        let mut txs = self.txs.lock().unwrap();
+        if let Some(chain_txs) = txs.get_mut(chain) {
+            chain_txs.retain(|_, tx| {
+                tx.state == TxState::Pending || tx.first_seen.elapsed() < max_age * 2
+            });
+        }
+    }
+
+    /// Periodically checks pending transactions of `chain` for inclusion and
+    /// rebroadcasts the ones not yet mined to every backend of the pool,
+    /// through each backend's transport.
+    ///
+    /// Rebroadcasts bypass the request limits, the volume is bounded by the
+    /// number of pending transactions and the configured interval.
+    pub async fn run(self: Arc<Self>, chain: String, round_robin: Arc<RoundRobin>) {
+        let Some(config) = self.chains.get(&chain).cloned() else {
+            return;
+        };
+        let interval = Duration::from_secs(config.interval_secs);
+        let max_age = Duration::from_secs(config.max_age_secs);
+
+        loop {
+            time::sleep(interval).await;
+
+            for (hash, raw, first_seen) in self.pending(&chain) {
+                if let Some(block_number) = find_inclusion(&round_robin, &hash).await {
+                    self.update(&chain, &hash, |tx| {
+                        tx.state = TxState::Included;
+                        tx.block_number = block_number;
+                    });
+                    continue;
+                }
+
+                if first_seen.elapsed() > max_age {
+                    println!(
+                        "Transaction {} on {} expired without inclusion",
+                        hash, chain
+                    );
+                    self.update(&chain, &hash, |tx| tx.state = TxState::Expired);
+                    continue;
+                }
+
+                let accepted = broadcast(&round_robin, &raw).await.accepted;
+                let total = round_robin.endpoints.len();
+                println!(
+                    "Rebroadcast {} on {} accepted by {}/{} backends",
+                    hash, chain, accepted, total
+                );
+                self.update(&chain, &hash, |tx| {
+                    tx.rebroadcasts += 1;
+                    tx.backends_accepted = accepted;
+                    tx.backends_total = total;
+                });
+            }
+
+            self.prune(&chain, max_age);
+        }
+    }
+}
+
+/// Returns `Some(block_number)` once any backend reports a receipt. A backend
+/// without one may just lag behind the others, so all of them are asked.
+async fn find_inclusion(round_robin: &RoundRobin, hash: &str) -> Option<Option<u64>> {
+    for url in round_robin.endpoints.iter() {
+        let receipt =
+            rpc_client::call_server(round_robin, url, "eth_getTransactionReceipt", json!([hash]))
+                .await;
+        match receipt {
+            Ok(Value::Null) | Err(_) => continue,
+            Ok(receipt) => return Some(rpc_client::parse_quantity(&receipt["blockNumber"])),
+        }
+    }
+    None
+}
+
+/// How the backends answered a [`broadcast`].
+#[derive(Debug, Default, PartialEq)]
+pub struct Broadcast {
+    /// Backends which took the transaction or already knew it.
+    pub accepted: usize,
+    /// Backends which answered with any other JSON-RPC error, e.g. "nonce too
+    /// low" or an invalid signature, which a resubmission won't change.
+    pub rejected: usize,
+}
+
+/// Submits `raw` to every backend of `round_robin`.
+pub async fn broadcast(round_robin: &RoundRobin, raw: &str) -> Broadcast {
+    let mut outcome = Broadcast::default();
+    for url in round_robin.endpoints.iter() {
+        match rpc_client::call_server(round_robin, url, "eth_sendRawTransaction", json!([raw]))
+            .await
+        {
+            Ok(_) => outcome.accepted += 1,
+            Err(e) if is_already_known(&e) => outcome.accepted += 1,
+            Err(e) if rpc_client::is_rpc_error(&e) => outcome.rejected += 1,
+            Err(_) => {}
+        }
+    }
+    outcome
+}
+
+/// Nodes that already hold the transaction in their mempool reject the
+/// resubmission, which still counts as the backend knowing about it.
+pub fn is_already_known(error: &str) -> bool {
+    let error = error.to_lowercase();
+    error.contains("already known") || error.contains("known transaction")
+}
+
+/// Extracts the raw transaction from an `eth_sendRawTransaction` request body.
+pub fn raw_transaction(request: &Value) -> Option<String> {
+    if request.get("method")?.as_str()? != "eth_sendRawTransaction" {
+        return None;
+    }
+    Some(request.get("params")?.get(0)?.as_str()?.to_string())
+}
+
+/// Extracts the transaction hash a backend returned for a raw submission.
+pub fn submitted_tx_hash(body: &[u8]) -> Option<String> {
+    let response: Value = serde_json::from_slice(body).ok()?;
+    Some(response.get("result")?.as_str()?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithms::round_robin::RpcServer;
+    use axum::{routing::post, Json, Router};
+
+    /// A backend answering every call with `result`.
+    async fn spawn_node(result: Value) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move || async move { Json(json!({"jsonrpc": "2.0", "id": 1, "result": result})) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    fn tracker() -> TxTracker {
+        let mut chains = HashMap::new();
+        chains.insert(
+            "sepolia".to_string(),
+            RebroadcastConfig {
+                interval_secs: 1,
+                max_age_secs: 60,
+            },
+        );
+        TxTracker::new(chains)
+    }
+
+    #[test]
+    fn test_track_only_enabled_chains() {
+        let tracker = tracker();
+        tracker.track("sepolia", "0xABC", "0x01");
+        tracker.track("bitcoin", "0xabc", "0x01");
+
+        let status = tracker.status("sepolia", "0xabc").unwrap();
+        assert_eq!(status.state, TxState::Pending);
+        assert_eq!(status.hash, "0xabc");
+        assert!(tracker.status("bitcoin", "0xabc").is_none());
+    }
+
+    #[test]
+    fn test_included_tx_is_not_pending() {
+        let tracker = tracker();
+        tracker.track("sepolia", "0xabc", "0x01");
+        assert_eq!(tracker.pending("sepolia").len(), 1);
+
+        tracker.update("sepolia", "0xabc", |tx| {
+            tx.state = TxState::Included;
+            tx.block_number = Some(42);
+        });
+
+        assert!(tracker.pending("sepolia").is_empty());
+        let status = tracker.status("sepolia", "0xabc").unwrap();
+        assert_eq!(status.state, TxState::Included);
+        assert_eq!(status.block_number, Some(42));
+    }
+
+    #[tokio::test]
+    async fn test_find_inclusion_asks_every_backend() {
+        let lagging = spawn_node(Value::Null).await;
+        let synced = spawn_node(json!({"blockNumber": "0x2a"})).await;
+        let round_robin = RoundRobin::new(
+            [lagging, synced]
+                .into_iter()
+                .map(|url| RpcServer {
+                    url,
+                    ..Default::default()
+                })
+                .collect(),
+        );
+
+        assert_eq!(find_inclusion(&round_robin, "0xabc").await, Some(Some(42)));
+        assert_eq!(
+            broadcast(&round_robin, "0x01").await,
+            Broadcast {
+                accepted: 2,
+                rejected: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_raw_transaction_extraction() {
+        let request = json!({"method": "eth_sendRawTransaction", "params": ["0xf86c"]});
+        assert_eq!(raw_transaction(&request), Some("0xf86c".to_string()));
+
+        let request = json!({"method": "eth_blockNumber", "params": []});
+        assert_eq!(raw_transaction(&request), None);
+
+        let body = br#"{"jsonrpc":"2.0","result":"0xabc","id":1}"#;
+        assert_eq!(submitted_tx_hash(body), Some("0xabc".to_string()));
+    }
+
+    #[test]
+    fn test_already_known_errors() {
+        assert!(is_already_known(
+            r#"{"code":-32000,"message":"already known"}"#
+        ));
+        assert!(!is_already_known(
+            r#"{"code":-32000,"message":"nonce too low"}"#
+        ));
+    }
+}
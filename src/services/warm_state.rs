@@ -0,0 +1,189 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::algorithms::{key_health::KeyState, round_robin::LoadBalancer};
+
+/// What of a backend's state outlives a restart, so a quick one neither
+/// forgets a provider rate limited us nor hands out a fresh limit window.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct BackendSnapshot {
+    pub url: String,
+    /// State the key is quarantined in and when that ends, in unix seconds.
+    pub quarantine: Option<(KeyState, u64)>,
+    /// Health checks failed in a row.
+    pub failed_checks: u32,
+    pub unhealthy: bool,
+    /// Steady tokens spent in the limit window ending at `window_ends`.
+    pub used: u32,
+    /// End of the limit window, in unix seconds.
+    pub window_ends: Option<i64>,
+}
+
+/// The `[server] state_file`, by chain.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Snapshot {
+    chains: HashMap<String, Vec<BackendSnapshot>>,
+}
+
+/// Writes the state of every backend of `lb` to `path`, returning how many
+/// backends it holds.
+pub fn save(path: &str, lb: &LoadBalancer) -> Result<usize, String> {
+    let chains: HashMap<_, _> = lb
+        .load_balancers
+        .iter()
+        .map(|(chain, round_robin)| (chain.clone(), round_robin.snapshot()))
+        .collect();
+    let backends = chains.values().map(Vec::len).sum();
+    let body = serde_json::to_vec_pretty(&Snapshot { chains }).map_err(|e| e.to_string())?;
+    // Written aside and renamed, a restart never reads half a snapshot.
+    let partial = format!("{}.partial", path);
+    let write = || -> std::io::Result<()> {
+        // Backend urls may carry provider keys.
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&partial)?;
+        file.write_all(&body)?;
+        file.sync_all()?;
+        fs::rename(&partial, path)
+    };
+    write().map_err(|e| format!("Failed to write state snapshot {}: {}", path, e))?;
+    Ok(backends)
+}
+
+/// Carries the snapshot at `path` over to the backends of `lb` with the
+/// same chain and url, returning how many were restored. None are without
+/// a snapshot yet.
+pub fn restore(path: &str, lb: &LoadBalancer) -> Result<usize, String> {
+    let body = match fs::read(path) {
+        Ok(body) => body,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(format!("Failed to read state snapshot {}: {}", path, e)),
+    };
+    let snapshot: Snapshot = serde_json::from_slice(&body)
+        .map_err(|e| format!("Invalid state snapshot {}: {}", path, e))?;
+    let mut restored = 0;
+    for (chain, backends) in &snapshot.chains {
+        let (Some(round_robin), Some(settings)) =
+            (lb.load_balancers.get(chain), lb.chains.get(chain))
+        else {
+            continue;
+        };
+        // Only health checks bring an unhealthy backend back.
+        restored += round_robin.restore(backends, settings.health_check.is_some());
+    }
+    Ok(restored)
+}
+
+/// Resolves once the process is asked to stop, by `SIGINT` or `SIGTERM`.
+pub async fn shutdown_signal() {
+    let mut terminate = signal(SignalKind::terminate()).ok();
+    let terminated = async {
+        match terminate.as_mut() {
+            Some(terminate) => {
+                terminate.recv().await;
+            }
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminated => {}
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use reqwest::StatusCode;
+
+    use super::*;
+    use crate::algorithms::round_robin::{Chains, RoundRobin, RpcServer};
+
+    fn balancer(health_checked: bool) -> LoadBalancer {
+        let servers = ["https://a.example", "https://b.example"]
+            .into_iter()
+            .map(|url| RpcServer {
+                url: url.to_string(),
+                request_limit: 10,
+                current_limit: 10,
+                ..Default::default()
+            })
+            .collect();
+        let round_robin = Arc::new(RoundRobin::new(servers));
+        let mut chain = Chains::default();
+        if health_checked {
+            chain.health_check = Some(toml::from_str("").unwrap());
+        }
+        LoadBalancer {
+            chains: Arc::new(HashMap::from([("sepolia".to_string(), chain)])),
+            ..LoadBalancer::new(Arc::new(HashMap::from([(
+                "sepolia".to_string(),
+                round_robin,
+            )])))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warm_restart() {
+        let path = std::env::temp_dir().join(format!("rpc_lb_state_{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let before = balancer(true);
+        assert_eq!(restore(&path, &before), Ok(0));
+
+        let round_robin = &before.load_balancers["sepolia"];
+        let (a, b) = (&round_robin.endpoints[0], &round_robin.endpoints[1]);
+        tokio::spawn({
+            let round_robin = round_robin.clone();
+            async move { round_robin.refill_limits(Duration::from_secs(60)).await }
+        });
+        tokio::task::yield_now().await;
+        round_robin.record_status(
+            a,
+            StatusCode::TOO_MANY_REQUESTS,
+            Some(Duration::from_secs(600)),
+        );
+        for _ in 0..4 {
+            assert_eq!(round_robin.get_next().as_ref(), Some(b));
+        }
+        round_robin.record_health(b, false, 1);
+        assert_eq!(save(&path, &before), Ok(2));
+
+        let after = balancer(true);
+        assert_eq!(restore(&path, &after), Ok(2));
+        let round_robin = &after.load_balancers["sepolia"];
+        assert_eq!(round_robin.get_next(), None);
+        round_robin.record_health(b, true, 1);
+        // The tokens spent before the restart stay spent.
+        let usage = round_robin.window_usage();
+        assert_eq!((usage[1].1, usage[1].2), (4, 10));
+
+        // Without health checks nothing would bring it back.
+        let unchecked = balancer(false);
+        restore(&path, &unchecked).unwrap();
+        let round_robin = &unchecked.load_balancers["sepolia"];
+        assert_eq!(
+            round_robin.get_next().as_ref(),
+            Some(&round_robin.endpoints[1])
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
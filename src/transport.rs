@@ -0,0 +1,190 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use axum::body::Bytes;
+use futures_util::future::BoxFuture;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+    Method, Response as ReqwestResponse, StatusCode,
+};
+
+use crate::algorithms::round_robin::RpcServer;
+
+pub mod http;
+#[cfg(unix)]
+pub mod ipc;
+pub mod websocket;
+
+/// The request sent upstream on every attempt. It is built once per client
+/// request and shared between attempts, `Bytes` and `HeaderValue` clones only
+/// bump a reference count.
+#[derive(Debug, Clone)]
+pub struct UpstreamRequest {
+    pub method: Method,
+    /// Inbound headers forwarded under the chain's `[headers]` policy.
+    pub headers: HeaderMap,
+    pub content_type: HeaderValue,
+    pub body: Bytes,
+}
+
+impl UpstreamRequest {
+    /// A JSON-RPC call made by the balancer itself, e.g. a health check.
+    pub fn json(body: impl Into<Bytes>) -> Self {
+        Self {
+            method: Method::POST,
+            headers: HeaderMap::new(),
+            content_type: HeaderValue::from_static("application/json"),
+            body: body.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransportError {
+    pub message: String,
+    /// The request never reached the backend, e.g. the connection was refused.
+    pub connect: bool,
+}
+
+impl TransportError {
+    pub fn connect(message: impl fmt::Display) -> Self {
+        Self {
+            message: message.to_string(),
+            connect: true,
+        }
+    }
+
+    pub fn request(message: impl fmt::Display) -> Self {
+        Self {
+            message: message.to_string(),
+            connect: false,
+        }
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Set by a transport once the request was written to the backend's
+/// connection. Until then the request can be given up without the backend
+/// ever seeing it.
+#[derive(Debug, Clone, Default)]
+pub struct Sent(Arc<AtomicBool>);
+
+impl Sent {
+    pub fn mark(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_marked(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+pub type TransportFuture<'a> = BoxFuture<'a, Result<ReqwestResponse, TransportError>>;
+
+/// Carries a request to a backend and its response back.
+///
+/// Backend selection only deals in urls, the transport of the chosen backend
+/// then sends the request. Responses are handed on as reqwest responses
+/// whatever the transport, so response checks and streaming work the same
+/// for all of them.
+pub trait UpstreamTransport: Send + Sync + fmt::Debug {
+    /// Sends `request` to `url`, marking `sent` once it was written.
+    fn send<'a>(
+        &'a self,
+        url: &'a str,
+        request: &'a UpstreamRequest,
+        timeout: Option<Duration>,
+        sent: &'a Sent,
+    ) -> TransportFuture<'a>;
+}
+
+/// The transport for a backend, picked by the scheme of its url: `ws://` and
+/// `wss://` for websockets, `ipc://` for a local socket and HTTP otherwise.
+pub fn for_server(server: &RpcServer) -> Arc<dyn UpstreamTransport> {
+    let scheme = server.url.split("://").next().unwrap_or_default();
+    match scheme {
+        "ws" | "wss" => Arc::new(websocket::WebSocketTransport::new(
+            server.host_header.clone(),
+            server.connect_to,
+        )),
+        #[cfg(unix)]
+        "ipc" => Arc::new(ipc::IpcTransport::default()),
+        _ => Arc::new(
+            http::HttpTransport::new(server.host_header.clone(), server.connect_to, &server.url)
+                .with_compression(server.compress_requests_over),
+        ),
+    }
+}
+
+/// Schemes backend urls may use.
+pub const SCHEMES: [&str; 5] = ["http", "https", "ws", "wss", "ipc"];
+
+/// A `200` JSON response holding `body`, for transports without HTTP
+/// responses of their own.
+fn json_response(body: Vec<u8>) -> ReqwestResponse {
+    response_with(StatusCode::OK, body)
+}
+
+/// A JSON response made up by the balancer rather than received from a
+/// backend, e.g. an injected or scripted failure.
+pub fn response_with(status: StatusCode, body: Vec<u8>) -> ReqwestResponse {
+    let mut response = axum::http::Response::new(body);
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    ReqwestResponse::from(response)
+}
+
+/// Idle connections of a backend, reused by later requests.
+struct Idle<T>(Mutex<Vec<T>>);
+
+/// Connections kept open per backend while not in use.
+const MAX_IDLE: usize = 16;
+
+impl<T> Default for Idle<T> {
+    fn default() -> Self {
+        Self(Mutex::new(Vec::new()))
+    }
+}
+
+impl<T> Idle<T> {
+    fn take(&self) -> Option<T> {
+        self.0.lock().unwrap().pop()
+    }
+
+    fn put(&self, connection: T) {
+        let mut idle = self.0.lock().unwrap();
+        if idle.len() < MAX_IDLE {
+            idle.push(connection);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+/// Runs `future` within `timeout`, if any.
+async fn within<T>(
+    timeout: Option<Duration>,
+    future: impl std::future::Future<Output = Result<T, TransportError>>,
+) -> Result<T, TransportError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| TransportError::request("operation timed out"))?,
+        None => future.await,
+    }
+}
@@ -0,0 +1,326 @@
+use std::{
+    fs,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use std::convert::Infallible;
+
+use axum::body::Bytes;
+use flate2::{write::GzEncoder, Compression};
+use futures_util::stream;
+use reqwest::{
+    header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, HOST},
+    Body, Certificate, Identity, Proxy,
+};
+use serde::Deserialize;
+
+use super::{Sent, TransportError, TransportFuture, UpstreamRequest, UpstreamTransport};
+use crate::services::head::host_of;
+
+/// `User-Agent` of upstream requests, unless a chain's `[headers]` sets one.
+pub const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// The client of every HTTP backend without `connect_to`, so upstream
+/// connections are pooled across chains.
+pub fn shared_client() -> &'static reqwest::Client {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap()
+    })
+}
+
+/// The `http` section of a chain: connection settings of a client used by
+/// its HTTP backends alone, so tuning them leaves other chains untouched.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct HttpClientConfig {
+    /// Idle connections kept per backend host.
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Seconds an idle connection is kept before it is closed.
+    pub pool_idle_timeout_secs: Option<u64>,
+    pub connect_timeout_ms: Option<u64>,
+    pub tcp_keepalive_secs: Option<u64>,
+    /// Proxy every request goes through, e.g. `http://proxy.internal:3128`.
+    pub proxy: Option<String>,
+    /// PEM file of a CA trusted on top of the system's, e.g. of self-hosted
+    /// nodes.
+    pub ca_file: Option<String>,
+    /// PEM files of the client certificate and its PKCS#8 key for backends
+    /// requiring mutual TLS.
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    /// Skip certificate checks, for staging backends with self-signed ones.
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+}
+
+impl HttpClientConfig {
+    /// Builds a client with these settings, resolving `resolve.0` to
+    /// `resolve.1` when given, as for backends with `connect_to`.
+    pub fn build(&self, resolve: Option<(&str, IpAddr)>) -> Result<reqwest::Client, String> {
+        let read =
+            |path: &String| fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e));
+        let mut builder = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .danger_accept_invalid_certs(self.accept_invalid_certs);
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(secs) = self.pool_idle_timeout_secs {
+            builder = builder.pool_idle_timeout(Duration::from_secs(secs));
+        }
+        if let Some(ms) = self.connect_timeout_ms {
+            builder = builder.connect_timeout(Duration::from_millis(ms));
+        }
+        if let Some(secs) = self.tcp_keepalive_secs {
+            builder = builder.tcp_keepalive(Duration::from_secs(secs));
+        }
+        if let Some(proxy) = &self.proxy {
+            let proxy = Proxy::all(proxy).map_err(|e| format!("Invalid proxy {}: {}", proxy, e))?;
+            builder = builder.proxy(proxy);
+        }
+        if let Some(path) = &self.ca_file {
+            let ca = Certificate::from_pem(&read(path)?)
+                .map_err(|e| format!("Invalid CA in {}: {}", path, e))?;
+            builder = builder.add_root_certificate(ca);
+        }
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                let identity = Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+                    .map_err(|e| format!("Invalid client certificate {}: {}", cert, e))?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => return Err("client_cert and client_key go together".to_string()),
+        }
+        if let Some((host, address)) = resolve {
+            builder = builder.resolve(host, SocketAddr::new(address, 0));
+        }
+        builder.build().map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpTransport {
+    /// Dedicated client resolving the backend's host to `connect_to`, or of
+    /// the backend's chain.
+    client: Option<reqwest::Client>,
+    host_header: Option<String>,
+    /// Bodies of at least this many bytes are sent gzipped.
+    compress_over: Option<usize>,
+}
+
+impl HttpTransport {
+    pub fn new(host_header: Option<String>, connect_to: Option<IpAddr>, url: &str) -> Self {
+        let client = connect_to.map(|address| {
+            reqwest::Client::builder()
+                .user_agent(USER_AGENT)
+                .resolve(&host_of(url), SocketAddr::new(address, 0))
+                .build()
+                .unwrap()
+        });
+        Self {
+            client,
+            host_header,
+            compress_over: None,
+        }
+    }
+
+    /// A transport sending through `client` rather than the shared one.
+    pub fn with_client(client: reqwest::Client, host_header: Option<String>) -> Self {
+        Self {
+            client: Some(client),
+            host_header,
+            compress_over: None,
+        }
+    }
+
+    /// Gzips request bodies of at least `min_bytes`, for backends accepting
+    /// `Content-Encoding: gzip`.
+    pub fn with_compression(mut self, min_bytes: Option<usize>) -> Self {
+        self.compress_over = min_bytes;
+        self
+    }
+}
+
+impl UpstreamTransport for HttpTransport {
+    fn send<'a>(
+        &'a self,
+        url: &'a str,
+        request: &'a UpstreamRequest,
+        timeout: Option<Duration>,
+        sent: &'a Sent,
+    ) -> TransportFuture<'a> {
+        let mut forwarded_request = self
+            .client
+            .as_ref()
+            .unwrap_or(shared_client())
+            .request(request.method.clone(), url)
+            .headers(request.headers.clone())
+            .header(CONTENT_TYPE, request.content_type.clone());
+        // The body is only polled once the connection is up and the head
+        // written, which is when the request counts as sent.
+        if !request.body.is_empty() {
+            let mut body = request.body.clone();
+            if self.compress_over.is_some_and(|min| body.len() >= min) {
+                body = gzip(&body);
+                forwarded_request = forwarded_request.header(CONTENT_ENCODING, "gzip");
+            }
+            let body_sent = sent.clone();
+            forwarded_request =
+                forwarded_request
+                    .header(CONTENT_LENGTH, body.len())
+                    .body(Body::wrap_stream(stream::once(async move {
+                        body_sent.mark();
+                        Ok::<_, Infallible>(body)
+                    })));
+        }
+        if let Some(host) = &self.host_header {
+            forwarded_request = forwarded_request.header(HOST, host);
+        }
+        if let Some(timeout) = timeout {
+            forwarded_request = forwarded_request.timeout(timeout);
+        }
+
+        let bodyless = request.body.is_empty();
+        Box::pin(async move {
+            let result = forwarded_request.send().await.map_err(|e| TransportError {
+                message: e.to_string(),
+                connect: e.is_connect(),
+            });
+            if bodyless && !result.as_ref().is_err_and(|e| e.connect) {
+                sent.mark();
+            }
+            result
+        })
+    }
+}
+
+fn gzip(body: &[u8]) -> Bytes {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+    // Writing to a `Vec` can not fail.
+    encoder.write_all(body).unwrap();
+    Bytes::from(encoder.finish().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+
+    #[tokio::test]
+    async fn test_body_is_sent_with_its_length() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new().route(
+            "/",
+            post(|headers: axum::http::HeaderMap| async move {
+                format!(
+                    "{:?} {:?}",
+                    headers.get(CONTENT_LENGTH),
+                    headers.get("transfer-encoding")
+                )
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let transport = HttpTransport::new(None, None, &url);
+        let sent = Sent::default();
+        let response = transport
+            .send(&url, &UpstreamRequest::json("{\"id\":1}"), None, &sent)
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "Some(\"8\") None");
+        assert!(sent.is_marked());
+
+        let sent = Sent::default();
+        let error = transport
+            .send(
+                "http://127.0.0.1:1",
+                &UpstreamRequest::json("{}"),
+                None,
+                &sent,
+            )
+            .await
+            .unwrap_err();
+        assert!(error.connect);
+        assert!(!sent.is_marked());
+    }
+
+    #[tokio::test]
+    async fn test_request_compression() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new().route(
+            "/",
+            post(|headers: axum::http::HeaderMap, body: Bytes| async move {
+                let mut decoded = String::new();
+                match headers.get(CONTENT_ENCODING) {
+                    Some(encoding) if encoding == "gzip" => {
+                        std::io::Read::read_to_string(
+                            &mut flate2::read::GzDecoder::new(&body[..]),
+                            &mut decoded,
+                        )
+                        .unwrap();
+                        format!("gzip {}", decoded)
+                    }
+                    _ => format!("plain {}", String::from_utf8_lossy(&body)),
+                }
+            }),
+        );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let transport = HttpTransport::new(None, None, &url).with_compression(Some(16));
+        let batch = format!("[{}]", ["{\"id\":1}"; 4].join(","));
+        for (body, expected) in [
+            ("{\"id\":1}".to_string(), "plain {\"id\":1}".to_string()),
+            (batch.clone(), format!("gzip {}", batch)),
+        ] {
+            let response = transport
+                .send(&url, &UpstreamRequest::json(body), None, &Sent::default())
+                .await
+                .unwrap();
+            assert_eq!(response.text().await.unwrap(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_client() {
+        // Answers whatever a client sends through it as a proxy.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new().route("/", post(|| async { "proxied" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config: HttpClientConfig = toml::from_str(&format!(
+            "proxy = {:?}\npool_max_idle_per_host = 4\nconnect_timeout_ms = 500",
+            proxy
+        ))
+        .unwrap();
+        let url = "http://node.invalid/";
+        let round_robin = crate::algorithms::round_robin::RoundRobin::new(vec![
+            crate::algorithms::round_robin::RpcServer {
+                url: url.to_string(),
+                ..Default::default()
+            },
+        ])
+        .with_http_client(&config)
+        .unwrap();
+        let response = round_robin
+            .send(url, &UpstreamRequest::json("{}"), None)
+            .await
+            .unwrap();
+        assert_eq!(response.text().await.unwrap(), "proxied");
+
+        let config = HttpClientConfig {
+            client_cert: Some("cert.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(config.build(None).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::{fmt, time::Duration};
+
+use serde::de::IgnoredAny;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+use super::{
+    json_response, within, Idle, Sent, TransportError, TransportFuture, UpstreamRequest,
+    UpstreamTransport,
+};
+
+/// Largest response accepted from a node.
+const MAX_RESPONSE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Sends requests over the local socket of a node, as in
+/// `ipc:///var/run/geth.ipc`.
+///
+/// The socket carries a stream of JSON values without framing, a response
+/// ends where one complete JSON value does.
+#[derive(Default)]
+pub struct IpcTransport {
+    idle: Idle<UnixStream>,
+}
+
+impl fmt::Debug for IpcTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IpcTransport")
+            .field("idle", &self.idle.len())
+            .finish()
+    }
+}
+
+impl IpcTransport {
+    async fn exchange(
+        &self,
+        mut stream: UnixStream,
+        body: &[u8],
+        sent: &Sent,
+    ) -> Result<Vec<u8>, String> {
+        stream.write_all(body).await.map_err(|e| e.to_string())?;
+        sent.mark();
+
+        let mut response = Vec::new();
+        let mut buffer = [0u8; 8192];
+        loop {
+            let read = stream.read(&mut buffer).await.map_err(|e| e.to_string())?;
+            if read == 0 {
+                return Err("socket closed by the node".to_string());
+            }
+            response.extend_from_slice(&buffer[..read]);
+            if is_complete(&response) {
+                break;
+            }
+            if response.len() > MAX_RESPONSE_BYTES {
+                return Err("response is too large".to_string());
+            }
+        }
+        self.idle.put(stream);
+        Ok(response)
+    }
+}
+
+/// Whether `response` holds a whole JSON value.
+fn is_complete(response: &[u8]) -> bool {
+    matches!(
+        serde_json::Deserializer::from_slice(response)
+            .into_iter::<IgnoredAny>()
+            .next(),
+        Some(Ok(_))
+    )
+}
+
+impl UpstreamTransport for IpcTransport {
+    fn send<'a>(
+        &'a self,
+        url: &'a str,
+        request: &'a UpstreamRequest,
+        timeout: Option<Duration>,
+        sent: &'a Sent,
+    ) -> TransportFuture<'a> {
+        Box::pin(within(timeout, async move {
+            if let Some(stream) = self.idle.take() {
+                if let Ok(response) = self.exchange(stream, &request.body, sent).await {
+                    return Ok(json_response(response));
+                }
+            }
+            let path = url.trim_start_matches("ipc://");
+            let stream = UnixStream::connect(path)
+                .await
+                .map_err(TransportError::connect)?;
+            let response = self
+                .exchange(stream, &request.body, sent)
+                .await
+                .map_err(TransportError::request)?;
+            Ok(json_response(response))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixListener;
+
+    #[tokio::test]
+    async fn test_request_over_ipc() {
+        let path = std::env::temp_dir().join(format!("rpc-lb-{}.ipc", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 1024];
+            loop {
+                let read = stream.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                // Answer in two writes to exercise reading a partial value.
+                stream.write_all(br#"{"jsonrpc":"2.0","#).await.unwrap();
+                stream.flush().await.unwrap();
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                stream
+                    .write_all(br#""result":"0x1","id":1}"#)
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let transport = IpcTransport::default();
+        let url = format!("ipc://{}", path.display());
+        for _ in 0..2 {
+            let response = transport
+                .send(
+                    &url,
+                    &UpstreamRequest::json("{}"),
+                    Some(Duration::from_secs(5)),
+                    &Sent::default(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                response.text().await.unwrap(),
+                r#"{"jsonrpc":"2.0","result":"0x1","id":1}"#
+            );
+        }
+        assert_eq!(transport.idle.len(), 1);
+        let _ = std::fs::remove_file(&path);
+
+        let error = IpcTransport::default()
+            .send(
+                "ipc:///nonexistent/node.ipc",
+                &UpstreamRequest::json("{}"),
+                None,
+                &Sent::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.connect);
+    }
+}
@@ -0,0 +1,295 @@
+use std::{fmt, net::IpAddr, time::Duration};
+
+use axum::body::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use reqwest::header::{HeaderValue, HOST};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    client_async_tls_with_config,
+    tungstenite::{client::IntoClientRequest, protocol::WebSocketConfig, Message, Utf8Bytes},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use super::{
+    json_response, within, Idle, Sent, TransportError, TransportFuture, UpstreamRequest,
+    UpstreamTransport,
+};
+
+/// Largest message accepted from a backend.
+const MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+type Connection = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Sends each request as a text message over a websocket and answers with
+/// the next message the backend sends back.
+///
+/// A connection carries one request at a time and is kept open for the next
+/// one once answered, so no request ids have to be rewritten.
+pub struct WebSocketTransport {
+    host_header: Option<String>,
+    connect_to: Option<IpAddr>,
+    idle: Idle<Connection>,
+}
+
+impl fmt::Debug for WebSocketTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocketTransport")
+            .field("host_header", &self.host_header)
+            .field("connect_to", &self.connect_to)
+            .field("idle", &self.idle.len())
+            .finish()
+    }
+}
+
+impl WebSocketTransport {
+    pub fn new(host_header: Option<String>, connect_to: Option<IpAddr>) -> Self {
+        Self {
+            host_header,
+            connect_to,
+            idle: Idle::default(),
+        }
+    }
+
+    /// Opens a connection to `url`, TLS for `wss`. The handshake, including
+    /// the check of the backend's `Sec-WebSocket-Accept`, is tungstenite's.
+    async fn connect(&self, url: &str) -> Result<Connection, TransportError> {
+        let mut request = url.into_client_request().map_err(TransportError::connect)?;
+        if let Some(host_header) = &self.host_header {
+            let host_header =
+                HeaderValue::from_str(host_header).map_err(TransportError::connect)?;
+            request.headers_mut().insert(HOST, host_header);
+        }
+        let host = request
+            .uri()
+            .host()
+            .ok_or_else(|| TransportError::connect("url has no host"))?
+            .to_string();
+        let port = request
+            .uri()
+            .port_u16()
+            .unwrap_or(match request.uri().scheme_str() {
+                Some("wss") => 443,
+                _ => 80,
+            });
+
+        let tcp = match self.connect_to {
+            Some(address) => TcpStream::connect((address, port)).await,
+            None => TcpStream::connect((host.as_str(), port)).await,
+        }
+        .map_err(TransportError::connect)?;
+        let config = WebSocketConfig::default().max_message_size(Some(MAX_MESSAGE_BYTES));
+        let (connection, _) = client_async_tls_with_config(request, tcp, Some(config), None)
+            .await
+            .map_err(TransportError::connect)?;
+        Ok(connection)
+    }
+
+    /// Sends `body` and reads the answer. The connection goes back to the
+    /// idle ones only when the exchange completed.
+    async fn exchange(
+        &self,
+        mut connection: Connection,
+        body: &Bytes,
+        sent: &Sent,
+    ) -> Result<Vec<u8>, String> {
+        let text = Utf8Bytes::try_from(body.clone()).map_err(|e| e.to_string())?;
+        connection
+            .send(Message::Text(text))
+            .await
+            .map_err(|e| e.to_string())?;
+        sent.mark();
+        let message = read_message(&mut connection).await?;
+        self.idle.put(connection);
+        Ok(message)
+    }
+}
+
+impl UpstreamTransport for WebSocketTransport {
+    fn send<'a>(
+        &'a self,
+        url: &'a str,
+        request: &'a UpstreamRequest,
+        timeout: Option<Duration>,
+        sent: &'a Sent,
+    ) -> TransportFuture<'a> {
+        Box::pin(within(timeout, async move {
+            // An idle connection may have been closed by the backend in the
+            // meantime, the request is then sent over a new one. Once sent it
+            // is not sent again, the backend may have acted on it.
+            if let Some(connection) = self.idle.take() {
+                match self.exchange(connection, &request.body, sent).await {
+                    Ok(message) => return Ok(json_response(message)),
+                    Err(e) if sent.is_marked() => return Err(TransportError::request(e)),
+                    Err(_) => {}
+                }
+            }
+            let connection = self.connect(url).await?;
+            let message = self
+                .exchange(connection, &request.body, sent)
+                .await
+                .map_err(TransportError::request)?;
+            Ok(json_response(message))
+        }))
+    }
+}
+
+/// Reads the next data message. Pings are answered by tungstenite on the way.
+async fn read_message(connection: &mut Connection) -> Result<Vec<u8>, String> {
+    loop {
+        let message = connection
+            .next()
+            .await
+            .ok_or_else(|| "websocket closed by the backend".to_string())?
+            .map_err(|e| e.to_string())?;
+        match message {
+            Message::Text(text) => return Ok(Bytes::from(text).into()),
+            Message::Binary(data) => return Ok(data.into()),
+            Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => {}
+            Message::Close(_) => return Err("websocket closed by the backend".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    /// Accepts websocket connections and answers every message with
+    /// `{"result":<message>}`, after a ping.
+    async fn spawn_backend() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/rpc", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (tcp, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut connection = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    while let Some(Ok(message)) = connection.next().await {
+                        let Message::Text(text) = message else {
+                            continue;
+                        };
+                        let answer = format!(r#"{{"result":{}}}"#, text);
+                        connection.send(Message::Ping(Bytes::new())).await.unwrap();
+                        connection.send(Message::text(answer)).await.unwrap();
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_request_over_websocket() {
+        let url = spawn_backend().await;
+        let transport = WebSocketTransport::new(None, None);
+
+        for id in 1..=2 {
+            let request = UpstreamRequest::json(format!(r#"{{"id":{}}}"#, id));
+            let response = transport
+                .send(
+                    &url,
+                    &request,
+                    Some(Duration::from_secs(5)),
+                    &Sent::default(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                response.text().await.unwrap(),
+                format!(r#"{{"result":{{"id":{}}}}}"#, id)
+            );
+        }
+        // The second request reused the connection of the first.
+        assert_eq!(transport.idle.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_request_is_not_resent() {
+        // Answers the first message and drops the connection on the second.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let received = Arc::new(AtomicUsize::new(0));
+        let counter = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let mut connection = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                while let Some(Ok(Message::Text(text))) = connection.next().await {
+                    if counter.fetch_add(1, Ordering::SeqCst) > 0 {
+                        break;
+                    }
+                    connection.send(Message::Text(text)).await.unwrap();
+                }
+            }
+        });
+
+        let transport = WebSocketTransport::new(None, None);
+        let request = UpstreamRequest::json("{}");
+        transport
+            .send(&url, &request, None, &Sent::default())
+            .await
+            .unwrap();
+        let sent = Sent::default();
+        let error = transport
+            .send(&url, &request, None, &sent)
+            .await
+            .unwrap_err();
+        assert!(!error.connect);
+        assert!(sent.is_marked());
+        assert_eq!(received.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_without_valid_accept_is_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut connection = BufReader::new(tcp);
+            loop {
+                let mut line = String::new();
+                connection.read_line(&mut line).await.unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            connection
+                .get_mut()
+                .write_all(b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: bm90IHRoZSBrZXk=\r\n\r\n")
+                .await
+                .unwrap();
+        });
+
+        let transport = WebSocketTransport::new(None, None);
+        let sent = Sent::default();
+        let error = transport
+            .send(&url, &UpstreamRequest::json("{}"), None, &sent)
+            .await
+            .unwrap_err();
+        assert!(error.connect);
+        assert!(!sent.is_marked());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_backend_is_a_connect_error() {
+        let transport = WebSocketTransport::new(None, None);
+        let error = transport
+            .send(
+                "ws://127.0.0.1:1",
+                &UpstreamRequest::json("{}"),
+                None,
+                &Sent::default(),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.connect);
+    }
+}